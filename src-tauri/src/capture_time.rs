@@ -0,0 +1,418 @@
+use crate::{ExifData, Photo};
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, NaiveDateTime, TimeDelta, Timelike, Utc,
+};
+use std::collections::HashMap;
+
+/// Settle `date_taken` once every source has been merged. A camera's local
+/// capture time is placed on the timeline with its recorded UTC offset;
+/// without one it is read as UTC, as the camera clock is all there is.
+pub fn resolve(exif: &mut ExifData) {
+    let Some(local) = exif.date_taken_local else {
+        return;
+    };
+    exif.date_taken = Some(match exif.offset_time.as_deref().and_then(parse_offset) {
+        Some(offset) => (local - Duration::seconds(i64::from(offset.local_minus_utc()))).and_utc(),
+        None => local.and_utc(),
+    });
+}
+
+/// An EXIF `OffsetTime*` value such as `"+02:00"` or `"-05:30"`.
+pub fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let value = value.trim();
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = rest.split_once(':')?;
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 14 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// The wall-clock time a photo was taken where it was taken, which dated
+/// export folders and `{date}` renames are named after. Video containers
+/// record UTC, shown in this computer's zone; photos without a capture
+/// time fall back to the file's modification time.
+pub fn local_capture_time(photo: &Photo) -> NaiveDateTime {
+    if let Some(local) = photo.exif.date_taken_local {
+        return local;
+    }
+    match photo.exif.date_taken {
+        Some(taken) if photo.exif.video.is_some() => taken.with_timezone(&Local).naive_local(),
+        Some(taken) => taken.naive_utc(),
+        None => photo.modified_at.with_timezone(&Local).naive_local(),
+    }
+}
+
+/// The capture instant, for comparing photos across cameras. Photos without
+/// a capture time use the file's modification time.
+pub fn capture_instant(photo: &Photo) -> DateTime<Utc> {
+    photo.exif.date_taken.unwrap_or(photo.modified_at)
+}
+
+/// The sun's elevation in degrees above the horizon when `photo` was taken
+/// at `latitude`, `longitude`, by NOAA's approximation. Where the camera
+/// recorded no UTC offset, the time zone is estimated from the longitude.
+pub fn sun_elevation(photo: &Photo, latitude: f64, longitude: f64) -> Option<f64> {
+    let taken = photo.exif.date_taken?;
+    let has_offset = photo.exif.video.is_some()
+        || photo
+            .exif
+            .offset_time
+            .as_deref()
+            .and_then(parse_offset)
+            .is_some();
+    let utc = if has_offset {
+        taken
+    } else {
+        taken - Duration::hours((longitude / 15.0).round() as i64)
+    };
+
+    let hours =
+        f64::from(utc.hour()) + f64::from(utc.minute()) / 60.0 + f64::from(utc.second()) / 3600.0;
+    let gamma =
+        2.0 * std::f64::consts::PI / 365.0 * (f64::from(utc.ordinal0()) + (hours - 12.0) / 24.0);
+    let equation_of_time = 229.18
+        * (0.000075 + 0.001868 * gamma.cos()
+            - 0.032077 * gamma.sin()
+            - 0.014615 * (2.0 * gamma).cos()
+            - 0.040849 * (2.0 * gamma).sin());
+    let declination = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin()
+        - 0.006758 * (2.0 * gamma).cos()
+        + 0.000907 * (2.0 * gamma).sin()
+        - 0.002697 * (3.0 * gamma).cos()
+        + 0.00148 * (3.0 * gamma).sin();
+    let solar_minutes = hours * 60.0 + equation_of_time + 4.0 * longitude;
+    let hour_angle = (solar_minutes / 4.0 - 180.0).to_radians();
+    let latitude = latitude.to_radians();
+    let cos_zenith =
+        latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    Some(90.0 - cos_zenith.clamp(-1.0, 1.0).acos().to_degrees())
+}
+
+/// The capture time as an XMP date, with the UTC offset where known.
+pub fn xmp_date(exif: &ExifData) -> Option<String> {
+    let offset = exif.offset_time.as_deref().and_then(parse_offset);
+    match (exif.date_taken_local, offset) {
+        (Some(local), Some(offset)) => local
+            .and_local_timezone(offset)
+            .single()
+            .map(|datetime| datetime.format("%Y-%m-%dT%H:%M:%S%.f%:z").to_string()),
+        (Some(local), None) => Some(local.format("%Y-%m-%dT%H:%M:%S%.f").to_string()),
+        (None, _) => exif
+            .date_taken
+            .map(|taken| taken.format("%Y-%m-%dT%H:%M:%S%.fZ").to_string()),
+    }
+}
+
+/// Move a photo's capture time by `seconds`, recording the total correction.
+/// A shift that would move the time out of range leaves `exif` untouched.
+pub fn shift(exif: &mut ExifData, seconds: i64) -> Result<(), String> {
+    let out_of_range =
+        || format!("Shifting by {seconds} seconds moves the capture time out of range");
+    let delta = TimeDelta::try_seconds(seconds).ok_or_else(out_of_range)?;
+    let date_taken = match exif.date_taken {
+        Some(taken) => Some(taken.checked_add_signed(delta).ok_or_else(out_of_range)?),
+        None => None,
+    };
+    let date_taken_local = match exif.date_taken_local {
+        Some(local) => Some(local.checked_add_signed(delta).ok_or_else(out_of_range)?),
+        None => None,
+    };
+    let total = exif
+        .capture_time_shift
+        .unwrap_or(0)
+        .checked_add(seconds)
+        .ok_or_else(out_of_range)?;
+    exif.date_taken = date_taken;
+    exif.date_taken_local = date_taken_local;
+    exif.capture_time_shift = (total != 0).then_some(total);
+    Ok(())
+}
+
+/// `sidecar` with its capture date set to `date`, leaving every other
+/// property as it was. Dates written as attributes or elements are replaced
+/// in place; a sidecar without them gets them on its first description.
+pub fn with_xmp_date(sidecar: &str, date: &str) -> String {
+    let mut contents = sidecar.to_string();
+    for (name, namespace) in [
+        ("exif:DateTimeOriginal", "http://ns.adobe.com/exif/1.0/"),
+        (
+            "photoshop:DateCreated",
+            "http://ns.adobe.com/photoshop/1.0/",
+        ),
+    ] {
+        contents = replace_xmp_value(&contents, name, date).unwrap_or_else(|| {
+            insert_xmp_attribute(&contents, name, namespace, date).unwrap_or(contents)
+        });
+    }
+    contents
+}
+
+/// Replace the value of `name` written as an attribute or a simple element.
+fn replace_xmp_value(contents: &str, name: &str, value: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        let needle = format!("{name}={quote}");
+        if let Some(start) = contents.find(&needle) {
+            let value_start = start + needle.len();
+            let value_end = contents[value_start..].find(quote)? + value_start;
+            return Some(format!(
+                "{}{value}{}",
+                &contents[..value_start],
+                &contents[value_end..]
+            ));
+        }
+    }
+    let open = format!("<{name}>");
+    let start = contents.find(&open)? + open.len();
+    let end = contents[start..].find(&format!("</{name}>"))? + start;
+    Some(format!("{}{value}{}", &contents[..start], &contents[end..]))
+}
+
+/// Add `name` as an attribute of the first `rdf:Description`, declaring its
+/// namespace prefix if the sidecar does not yet.
+fn insert_xmp_attribute(
+    contents: &str,
+    name: &str,
+    namespace: &str,
+    value: &str,
+) -> Option<String> {
+    let start = contents.find("<rdf:Description")?;
+    let mut end = contents[start..].find('>')? + start;
+    if contents[..end].ends_with('/') {
+        end -= 1;
+    }
+    let prefix = name.split_once(':')?.0;
+    let declaration = if contents.contains(&format!("xmlns:{prefix}=")) {
+        String::new()
+    } else {
+        format!(" xmlns:{prefix}=\"{namespace}\"")
+    };
+    Some(format!(
+        "{}{declaration} {name}=\"{value}\"{}",
+        &contents[..end],
+        &contents[end..]
+    ))
+}
+
+/// Corrections made in this catalog, in seconds by photo ID.
+pub fn load_shifts(conn: &rusqlite::Connection) -> HashMap<String, i64> {
+    let Ok(mut statement) = conn.prepare("SELECT photo_id, shift_seconds FROM capture_time_shifts")
+    else {
+        return HashMap::new();
+    };
+    statement
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Apply the catalog's clock corrections to freshly scanned photos. The
+/// scan index keeps the times as the camera recorded them.
+pub fn apply_shifts(conn: &rusqlite::Connection, photos: &mut [Photo]) {
+    let shifts = load_shifts(conn);
+    if shifts.is_empty() {
+        return;
+    }
+    for photo in photos {
+        if let Some(seconds) = shifts.get(&photo.id) {
+            if let Err(error) = shift(&mut photo.exif, *seconds) {
+                eprintln!(
+                    "Skipping the clock correction of {}: {error}",
+                    photo.file_path
+                );
+            }
+        }
+    }
+}
+
+/// Add `seconds` to the correction stored for each of `photo_ids` within
+/// `tx`. Nothing is stored if any total would overflow. The caller commits,
+/// so the shift can wait for the sidecars carrying it to be written.
+pub fn add_shift(
+    tx: &rusqlite::Transaction,
+    photo_ids: &[&str],
+    seconds: i64,
+) -> Result<(), String> {
+    let shifts = load_shifts(tx);
+    for photo_id in photo_ids {
+        let total = shifts
+            .get(*photo_id)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(seconds)
+            .ok_or_else(|| format!("The clock correction of {photo_id} is out of range"))?;
+        tx.execute(
+            "INSERT INTO capture_time_shifts (photo_id, shift_seconds) VALUES (?1, ?2)
+             ON CONFLICT(photo_id) DO UPDATE SET shift_seconds = excluded.shift_seconds",
+            rusqlite::params![photo_id, total],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "DELETE FROM capture_time_shifts WHERE shift_seconds = 0",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn local(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_utc_offsets() {
+        assert_eq!(parse_offset("+02:00"), FixedOffset::east_opt(7200));
+        assert_eq!(parse_offset(" -05:30 "), FixedOffset::west_opt(19800));
+        assert_eq!(parse_offset("+00:00"), FixedOffset::east_opt(0));
+        for invalid in ["", "02:00", "+2", "+15:00", "+01:60", "+aa:00", "   "] {
+            assert_eq!(parse_offset(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn resolves_the_local_time_with_its_offset() {
+        let mut exif = ExifData {
+            date_taken_local: Some(local(14, 30)),
+            offset_time: Some("+02:00".to_string()),
+            ..ExifData::default()
+        };
+        resolve(&mut exif);
+        assert_eq!(exif.date_taken, Some(local(12, 30).and_utc()));
+
+        exif.offset_time = Some("garbage".to_string());
+        resolve(&mut exif);
+        assert_eq!(exif.date_taken, Some(local(14, 30).and_utc()));
+
+        let mut undated = ExifData::default();
+        resolve(&mut undated);
+        assert_eq!(undated.date_taken, None);
+    }
+
+    #[test]
+    fn formats_xmp_dates_with_what_is_known_of_the_zone() {
+        let mut exif = ExifData {
+            date_taken_local: Some(local(14, 30)),
+            offset_time: Some("-05:30".to_string()),
+            ..ExifData::default()
+        };
+        assert_eq!(
+            xmp_date(&exif).as_deref(),
+            Some("2024-06-01T14:30:00-05:30")
+        );
+        exif.offset_time = None;
+        assert_eq!(xmp_date(&exif).as_deref(), Some("2024-06-01T14:30:00"));
+        exif.date_taken_local = None;
+        exif.date_taken = Some(local(9, 5).and_utc());
+        assert_eq!(xmp_date(&exif).as_deref(), Some("2024-06-01T09:05:00Z"));
+        assert_eq!(xmp_date(&ExifData::default()), None);
+    }
+
+    #[test]
+    fn shifts_accumulate_and_refuse_to_overflow() {
+        let mut exif = ExifData {
+            date_taken: Some(local(12, 0).and_utc()),
+            date_taken_local: Some(local(14, 0)),
+            ..ExifData::default()
+        };
+        shift(&mut exif, 3600).unwrap();
+        assert_eq!(exif.date_taken_local, Some(local(15, 0)));
+        assert_eq!(exif.capture_time_shift, Some(3600));
+        shift(&mut exif, -3600).unwrap();
+        assert_eq!(exif.date_taken, Some(local(12, 0).and_utc()));
+        assert_eq!(exif.capture_time_shift, None);
+
+        let before = exif.clone();
+        assert!(shift(&mut exif, i64::MAX).is_err());
+        assert!(shift(&mut exif, i64::MAX / 1000).is_err());
+        exif.capture_time_shift = Some(i64::MAX);
+        assert!(shift(&mut exif, 1).is_err());
+        assert_eq!(exif.date_taken, before.date_taken);
+        assert_eq!(exif.date_taken_local, before.date_taken_local);
+    }
+
+    /// A photo taken in San Francisco at `hour`:`minute` local time on
+    /// the winter solstice, with or without the camera's UTC offset.
+    fn san_francisco(hour: u32, minute: u32, offset: Option<&str>) -> f64 {
+        let local = NaiveDate::from_ymd_opt(2024, 12, 21)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap();
+        let mut exif = ExifData {
+            date_taken_local: Some(local),
+            offset_time: offset.map(str::to_string),
+            ..ExifData::default()
+        };
+        resolve(&mut exif);
+        let photo = crate::photo_from_exif(
+            std::path::Path::new("/shoot/IMG_0001.JPG"),
+            "IMG_0001".to_string(),
+            0,
+            local.and_utc(),
+            exif,
+        );
+        sun_elevation(&photo, 37.77, -122.42).unwrap()
+    }
+
+    #[test]
+    fn finds_the_sun_at_known_times() {
+        // The sun culminates at 28.8° around 12:08 and sets at 16:54 PST.
+        let noon = san_francisco(12, 8, Some("-08:00"));
+        assert!((noon - 28.8).abs() < 0.5, "{noon}");
+        let golden = san_francisco(16, 30, Some("-08:00"));
+        assert!((-4.0..6.0).contains(&golden), "{golden}");
+        assert!(golden > 0.0, "{golden}");
+        let blue = san_francisco(17, 20, Some("-08:00"));
+        assert!((-6.0..-4.0).contains(&blue), "{blue}");
+        let night = san_francisco(19, 0, Some("-08:00"));
+        assert!(night < -6.0, "{night}");
+
+        // Without an offset the zone is guessed from the longitude, which
+        // here is PST.
+        assert_eq!(san_francisco(16, 30, None), golden);
+        // Undated photos have no sun.
+        let undated = crate::photo_from_exif(
+            std::path::Path::new("/shoot/IMG_0002.JPG"),
+            "IMG_0002".to_string(),
+            0,
+            Utc::now(),
+            ExifData::default(),
+        );
+        assert_eq!(sun_elevation(&undated, 37.77, -122.42), None);
+    }
+
+    #[test]
+    fn merges_the_date_into_an_existing_sidecar() {
+        let sidecar = r#"<x:xmpmeta><rdf:RDF><rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="3" exif:DateTimeOriginal='2020-01-01T00:00:00'>
+<photoshop:DateCreated>2020-01-01T00:00:00</photoshop:DateCreated>
+<dc:subject><rdf:Bag><rdf:li>kept</rdf:li></rdf:Bag></dc:subject>
+</rdf:Description></rdf:RDF></x:xmpmeta>"#;
+        let merged = with_xmp_date(sidecar, "2024-06-01T14:30:00");
+        assert!(merged.contains("exif:DateTimeOriginal='2024-06-01T14:30:00'"));
+        assert!(
+            merged.contains("<photoshop:DateCreated>2024-06-01T14:30:00</photoshop:DateCreated>")
+        );
+        assert!(merged.contains(r#"xmp:Rating="3""#));
+        assert!(merged.contains("<rdf:li>kept</rdf:li>"));
+
+        let bare = r#"<rdf:RDF><rdf:Description rdf:about="" xmp:Rating="1"/></rdf:RDF>"#;
+        let merged = with_xmp_date(bare, "2024-06-01T14:30:00");
+        assert!(merged.contains(r#"xmlns:exif="http://ns.adobe.com/exif/1.0/""#));
+        assert!(merged.contains(r#"exif:DateTimeOriginal="2024-06-01T14:30:00""#));
+        assert!(merged.contains(r#"photoshop:DateCreated="2024-06-01T14:30:00"/>"#));
+    }
+}
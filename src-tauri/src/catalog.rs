@@ -0,0 +1,308 @@
+use crate::volumes;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+
+/// A catalog is a directory holding `hologram.sql` and `hologram-cache.sql`
+/// whose library spans any number of root folders, e.g. every card and drive
+/// used on one shoot. Metadata and caches for all roots live in the one
+/// catalog database, so the whole project is culled as a single library.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalog {
+    pub catalog_path: String,
+    pub roots: Vec<CatalogRoot>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogRoot {
+    pub root_path: String,
+    /// Whether the folder is currently reachable (its drive is mounted).
+    /// Photos under an offline root are browsed from the catalog.
+    pub online: bool,
+    /// Identifier from the root's volume marker, used to find the folder
+    /// again when its drive is remounted elsewhere.
+    pub volume_id: Option<String>,
+    /// Photos indexed under this root by the last scan.
+    pub photo_count: usize,
+    pub added_at: String,
+}
+
+pub fn root_paths(conn: &rusqlite::Connection) -> Result<Vec<String>, String> {
+    let mut statement = conn
+        .prepare("SELECT root_path FROM catalog_roots ORDER BY added_at, root_path")
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    Ok(rows.flatten().collect())
+}
+
+pub fn load(conn: &rusqlite::Connection, catalog_path: &str) -> Result<Catalog, String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    {
+        let mut statement = conn
+            .prepare("SELECT root_path, COUNT(*) FROM scan_index GROUP BY root_path")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, usize>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        counts.extend(rows.flatten());
+    }
+
+    let mut statement = conn
+        .prepare(
+            "SELECT root_path, added_at, volume_id FROM catalog_roots
+             ORDER BY added_at, root_path",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let roots = rows
+        .flatten()
+        .map(|(root_path, added_at, volume_id)| CatalogRoot {
+            online: Path::new(&root_path).is_dir(),
+            photo_count: counts.get(&root_path).copied().unwrap_or(0),
+            volume_id,
+            root_path,
+            added_at,
+        })
+        .collect();
+
+    Ok(Catalog {
+        catalog_path: catalog_path.to_string(),
+        roots,
+    })
+}
+
+/// Register `root_path` with the catalog. A volume marker is only written
+/// into the folder when `mark_volume` is set; one already there is always
+/// picked up.
+pub fn add_root(
+    conn: &rusqlite::Connection,
+    root_path: &str,
+    mark_volume: bool,
+) -> Result<(), String> {
+    if !Path::new(root_path).is_dir() {
+        return Err(format!("Folder does not exist: {root_path}"));
+    }
+    let existing = root_paths(conn)?;
+    if let Some(overlap) = existing.iter().find(|existing| {
+        Path::new(root_path).starts_with(existing) || Path::new(existing).starts_with(root_path)
+    }) {
+        if overlap != root_path {
+            return Err(format!("{root_path} overlaps catalog folder {overlap}"));
+        }
+    }
+    conn.execute(
+        "INSERT INTO catalog_roots (root_path, added_at, volume_id) VALUES (?1, ?2, ?3)
+         ON CONFLICT(root_path) DO NOTHING",
+        rusqlite::params![
+            root_path,
+            Utc::now().to_rfc3339(),
+            if mark_volume {
+                volumes::ensure_marker(Path::new(root_path))
+            } else {
+                volumes::read_marker(Path::new(root_path))
+            },
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Forget a root and its scan index. Ratings, tags and notes stay in the
+/// catalog, so adding the folder back restores them.
+pub fn remove_root(conn: &mut rusqlite::Connection, root_path: &str) -> Result<(), String> {
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    transaction
+        .execute(
+            "DELETE FROM catalog_roots WHERE root_path = ?1",
+            [root_path],
+        )
+        .map_err(|e| e.to_string())?;
+    transaction
+        .execute("DELETE FROM scan_index WHERE root_path = ?1", [root_path])
+        .map_err(|e| e.to_string())?;
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+/// A root folder whose drive came back under a different mount point.
+pub struct Relocation {
+    pub old_root: String,
+    pub new_root: String,
+}
+
+/// Look for offline roots by volume marker and move any that reappeared
+/// elsewhere, together with their scan index and queued sidecars. Indexed
+/// photo IDs are kept, so ratings and tags stay attached.
+pub fn relocate_offline_roots(conn: &mut rusqlite::Connection) -> Result<Vec<Relocation>, String> {
+    relocate_roots(conn, volumes::relocate)
+}
+
+/// `relocate_offline_roots`, finding each root's new place with `find`.
+fn relocate_roots(
+    conn: &mut rusqlite::Connection,
+    find: impl Fn(&str, &Path) -> Option<PathBuf>,
+) -> Result<Vec<Relocation>, String> {
+    let offline: Vec<(String, String)> = {
+        let mut statement = conn
+            .prepare("SELECT root_path, volume_id FROM catalog_roots WHERE volume_id IS NOT NULL")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.flatten()
+            .filter(|(root_path, _)| !Path::new(root_path).is_dir())
+            .collect()
+    };
+
+    let mut relocations = Vec::new();
+    for (old_root, volume_id) in offline {
+        let Some(new_root) = find(&volume_id, Path::new(&old_root)) else {
+            continue;
+        };
+        let new_root = new_root.to_string_lossy().to_string();
+        let transaction = conn.transaction().map_err(|e| e.to_string())?;
+        let moved = transaction.execute(
+            "UPDATE catalog_roots SET root_path = ?2 WHERE root_path = ?1",
+            [&old_root, &new_root],
+        );
+        // Another root already lives there; leave both alone.
+        if moved.is_err() {
+            continue;
+        }
+        transaction
+            .execute(
+                "UPDATE scan_index SET file_path = ?2 || substr(file_path, length(?1) + 1),
+                   root_path = ?2
+                 WHERE root_path = ?1",
+                [&old_root, &new_root],
+            )
+            .map_err(|e| e.to_string())?;
+        transaction
+            .execute(
+                "UPDATE pending_sidecars
+                 SET sidecar_path = ?2 || substr(sidecar_path, length(?1) + 1)
+                 WHERE substr(sidecar_path, 1, length(?3)) = ?3",
+                [&old_root, &new_root, &format!("{old_root}{MAIN_SEPARATOR}")],
+            )
+            .map_err(|e| e.to_string())?;
+        transaction.commit().map_err(|e| e.to_string())?;
+        relocations.push(Relocation { old_root, new_root });
+    }
+    Ok(relocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hologram-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn nested_and_overlapping_roots_are_refused() {
+        let conn = catalog();
+        let shoot = temp_dir("catalog-overlap");
+        let card = shoot.join("card");
+        let raw = card.join("raw");
+        let neighbour = shoot.join("card-2");
+        for dir in [&raw, &neighbour] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let path = |dir: &Path| dir.to_string_lossy().to_string();
+
+        add_root(&conn, &path(&card), false).unwrap();
+        assert!(add_root(&conn, &path(&raw), false).is_err());
+        assert!(add_root(&conn, &path(&shoot), false).is_err());
+        // Adding a root again is a no-op, and a sibling sharing a name
+        // prefix does not overlap.
+        add_root(&conn, &path(&card), false).unwrap();
+        add_root(&conn, &path(&neighbour), false).unwrap();
+        assert_eq!(root_paths(&conn).unwrap().len(), 2);
+        assert!(add_root(&conn, &path(&shoot.join("missing")), false).is_err());
+        // Nothing is written into the folder unless asked.
+        assert!(volumes::read_marker(&card).is_none());
+        std::fs::remove_dir_all(shoot).unwrap();
+    }
+
+    #[test]
+    fn relocating_a_root_moves_its_index_and_queued_sidecars() {
+        let mut conn = catalog();
+        let old_root = "/Volumes/hologram-missing-card/DCIM";
+        let new_root = temp_dir("catalog-relocated");
+        let new_root_path = new_root.to_string_lossy().to_string();
+        conn.execute(
+            "INSERT INTO catalog_roots (root_path, added_at, volume_id)
+             VALUES (?1, '2024-01-01T00:00:00+00:00', 'card')",
+            [old_root],
+        )
+        .unwrap();
+        let file = |root: &str, name: &str| format!("{root}{MAIN_SEPARATOR}{name}");
+        conn.execute(
+            "INSERT INTO scan_index
+             (file_path, root_path, photo_id, file_size, modified_at, exif_json, scanned_at)
+             VALUES (?1, ?2, 'a', 1, '', '{}', '')",
+            [file(old_root, "IMG_0001.JPG"), old_root.to_string()],
+        )
+        .unwrap();
+        let sidecar = file(old_root, "IMG_0001.xmp");
+        let elsewhere = format!("{old_root}-2{MAIN_SEPARATOR}IMG_0002.xmp");
+        for path in [&sidecar, &elsewhere] {
+            volumes::queue_sidecar(&conn, Path::new(path), "queued").unwrap();
+        }
+
+        let relocations = relocate_roots(&mut conn, |volume_id, root| {
+            (volume_id == "card" && root == Path::new(old_root)).then(|| new_root.clone())
+        })
+        .unwrap();
+        assert_eq!(relocations.len(), 1);
+        assert_eq!(relocations[0].new_root, new_root_path);
+        assert_eq!(
+            root_paths(&conn).unwrap(),
+            std::slice::from_ref(&new_root_path)
+        );
+        let (file_path, root_path): (String, String) = conn
+            .query_row("SELECT file_path, root_path FROM scan_index", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .unwrap();
+        assert_eq!(file_path, file(&new_root_path, "IMG_0001.JPG"));
+        assert_eq!(root_path, new_root_path);
+        let moved = file(&new_root_path, "IMG_0001.xmp");
+        assert!(volumes::queued_sidecar(&conn, Path::new(&moved)).is_some());
+        assert!(volumes::queued_sidecar(&conn, Path::new(&sidecar)).is_none());
+        // A folder merely sharing the prefix stays where it was.
+        assert!(volumes::queued_sidecar(&conn, Path::new(&elsewhere)).is_some());
+
+        // Online roots are left alone.
+        assert!(
+            relocate_roots(&mut conn, |_, _| Some(PathBuf::from("/elsewhere")))
+                .unwrap()
+                .is_empty()
+        );
+        std::fs::remove_dir_all(new_root).unwrap();
+    }
+}
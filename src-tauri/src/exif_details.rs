@@ -0,0 +1,1089 @@
+use crate::ExifData;
+use exif::{Context, Tag};
+use serde::{Deserialize, Serialize};
+
+const TAG_MAKE: u16 = 0x010F;
+const TAG_ARTIST: u16 = 0x013B;
+const TAG_COPYRIGHT: u16 = 0x8298;
+const TAG_RATING: u16 = 0x4746;
+pub const TAG_XMP: u16 = 0x02BC;
+pub const TAG_IPTC_NAA: u16 = 0x83BB;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_OFFSET_TIME: u16 = 0x9010;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TAG_METERING_MODE: u16 = 0x9207;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+const TAG_SUBSEC_TIME_ORIGINAL: u16 = 0x9291;
+const TAG_BODY_SERIAL: u16 = 0xA431;
+const TAG_LENS_SERIAL: u16 = 0xA435;
+
+/// Longest value rendered in a tag dump; maker notes carry kilobytes of
+/// binary data.
+const MAX_DISPLAY_VALUES: usize = 64;
+
+/// One EXIF tag of a file, as `get_full_metadata` lists it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExifTag {
+    /// `IFD0`, `IFD1`, `Exif`, `GPS`, `Interop` or `MakerNote`.
+    pub ifd: String,
+    pub tag_id: u16,
+    pub name: String,
+    /// The stored values: numbers, rationals as `n/d`, text, or hex bytes.
+    pub raw: String,
+    /// The values as a person reads them, with names for coded values and
+    /// units where known.
+    pub value: String,
+}
+
+/// One IFD entry with its value bytes resolved.
+#[derive(Clone, Copy)]
+struct Entry<'a> {
+    tag: u16,
+    kind: u16,
+    count: usize,
+    data: &'a [u8],
+    /// Where the value starts in the data the IFD was read from.
+    value_offset: usize,
+    little_endian: bool,
+}
+
+impl Entry<'_> {
+    /// The `index`th value of an integer entry. Signed types are returned
+    /// as their raw bits, as vendors mix signed and unsigned freely.
+    fn uint(&self, index: usize) -> Option<u32> {
+        match self.kind {
+            1 | 6 | 7 => self.data.get(index).copied().map(u32::from),
+            3 | 8 => {
+                let bytes: [u8; 2] = self.data.get(index * 2..index * 2 + 2)?.try_into().ok()?;
+                Some(u32::from(if self.little_endian {
+                    u16::from_le_bytes(bytes)
+                } else {
+                    u16::from_be_bytes(bytes)
+                }))
+            }
+            4 | 9 | 13 => {
+                let bytes: [u8; 4] = self.data.get(index * 4..index * 4 + 4)?.try_into().ok()?;
+                Some(if self.little_endian {
+                    u32::from_le_bytes(bytes)
+                } else {
+                    u32::from_be_bytes(bytes)
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn text(&self) -> Option<String> {
+        if !matches!(self.kind, 2 | 7) {
+            return None;
+        }
+        let end = self
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(self.data.len());
+        crate::clean_metadata_string(String::from_utf8_lossy(&self.data[..end]))
+    }
+
+    fn display(&self) -> String {
+        let truncated = if self.count > MAX_DISPLAY_VALUES {
+            " …"
+        } else {
+            ""
+        };
+        let values: Vec<String> = match self.kind {
+            2 => return self.text().unwrap_or_default(),
+            5 | 10 => {
+                let words = Entry { kind: 4, ..*self };
+                (0..self.count.min(MAX_DISPLAY_VALUES))
+                    .filter_map(|index| {
+                        let (numerator, denominator) =
+                            (words.uint(index * 2)?, words.uint(index * 2 + 1)?);
+                        Some(if self.kind == 10 {
+                            format!("{}/{}", numerator as i32, denominator as i32)
+                        } else {
+                            format!("{numerator}/{denominator}")
+                        })
+                    })
+                    .collect()
+            }
+            11 => {
+                let words = Entry { kind: 4, ..*self };
+                (0..self.count.min(MAX_DISPLAY_VALUES))
+                    .filter_map(|index| Some(f32::from_bits(words.uint(index)?).to_string()))
+                    .collect()
+            }
+            7 => self
+                .data
+                .iter()
+                .take(MAX_DISPLAY_VALUES)
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+            _ => (0..self.count.min(MAX_DISPLAY_VALUES))
+                .filter_map(|index| self.uint(index).map(|value| value.to_string()))
+                .collect(),
+        };
+        format!("{}{truncated}", values.join(" "))
+    }
+}
+
+fn value_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 | 13 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+fn read_u16(data: &[u8], at: usize, little_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(at..at + 2)?.try_into().ok()?;
+    Some(if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], at: usize, little_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(at..at + 4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+/// Byte order and first IFD offset of a TIFF structure.
+fn tiff_header(tiff: &[u8]) -> Option<(bool, usize)> {
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    Some((little_endian, read_u32(tiff, 4, little_endian)? as usize))
+}
+
+/// Entries of the IFD at `offset`, with value offsets relative to `base`.
+/// Entries whose values fall outside `base` are dropped.
+fn read_ifd(base: &[u8], offset: usize, little_endian: bool) -> Vec<Entry<'_>> {
+    let Some(count) = read_u16(base, offset, little_endian) else {
+        return Vec::new();
+    };
+    (0..usize::from(count))
+        .filter_map(|index| {
+            let at = offset + 2 + index * 12;
+            let kind = read_u16(base, at + 2, little_endian)?;
+            let count = read_u32(base, at + 4, little_endian)? as usize;
+            let size = value_size(kind)?.checked_mul(count)?;
+            let value_offset = if size <= 4 {
+                at + 8
+            } else {
+                read_u32(base, at + 8, little_endian)? as usize
+            };
+            Some(Entry {
+                tag: read_u16(base, at, little_endian)?,
+                kind,
+                count,
+                data: base.get(value_offset..value_offset.checked_add(size)?)?,
+                value_offset,
+                little_endian,
+            })
+        })
+        .collect()
+}
+
+fn find<'a, 'b>(entries: &'b [Entry<'a>], tag: u16) -> Option<&'b Entry<'a>> {
+    entries.iter().find(|entry| entry.tag == tag)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Vendor {
+    Canon,
+    Nikon,
+    Sony,
+    Fujifilm,
+}
+
+impl Vendor {
+    fn from_make(make: &str) -> Option<Self> {
+        let make = make.to_lowercase();
+        if make.starts_with("canon") {
+            Some(Self::Canon)
+        } else if make.starts_with("nikon") {
+            Some(Self::Nikon)
+        } else if make.starts_with("sony") {
+            Some(Self::Sony)
+        } else if make.starts_with("fujifilm") {
+            Some(Self::Fujifilm)
+        } else {
+            None
+        }
+    }
+}
+
+/// A decoded vendor MakerNote IFD.
+struct MakerNote<'a> {
+    vendor: Vendor,
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> MakerNote<'a> {
+    /// Find the maker note IFD at `offset` in `tiff`. Each vendor has its
+    /// own header and idea of what offsets are relative to.
+    fn locate(make: &str, tiff: &'a [u8], offset: usize, little_endian: bool) -> Option<Self> {
+        let vendor = Vendor::from_make(make)?;
+        let note = tiff.get(offset..)?;
+        let entries = match vendor {
+            Vendor::Canon => read_ifd(tiff, offset, little_endian),
+            // Type 3 notes embed a TIFF structure of their own after a
+            // ten-byte header; older ones are a plain IFD.
+            Vendor::Nikon if note.starts_with(b"Nikon\0\x02") => {
+                let inner = note.get(10..)?;
+                let (little_endian, ifd) = tiff_header(inner)?;
+                read_ifd(inner, ifd, little_endian)
+            }
+            Vendor::Nikon => read_ifd(tiff, offset, little_endian),
+            Vendor::Sony
+                if note.starts_with(b"SONY DSC \0\0\0") || note.starts_with(b"SONY CAM \0\0\0") =>
+            {
+                read_ifd(tiff, offset + 12, little_endian)
+            }
+            Vendor::Sony => read_ifd(tiff, offset, little_endian),
+            // Always little-endian, with offsets from the start of the note.
+            Vendor::Fujifilm if note.starts_with(b"FUJIFILM") => {
+                read_ifd(note, read_u32(note, 8, true)? as usize, true)
+            }
+            Vendor::Fujifilm => return None,
+        };
+        (!entries.is_empty()).then_some(Self { vendor, entries })
+    }
+
+    /// A maker note stored as a complete TIFF structure, as in CR3 files.
+    fn from_tiff(vendor: Vendor, tiff: &'a [u8]) -> Option<Self> {
+        let (little_endian, ifd) = tiff_header(tiff)?;
+        let entries = read_ifd(tiff, ifd, little_endian);
+        (!entries.is_empty()).then_some(Self { vendor, entries })
+    }
+
+    fn get(&self, tag: u16) -> Option<&Entry<'a>> {
+        find(&self.entries, tag)
+    }
+
+    fn uint(&self, tag: u16) -> Option<u32> {
+        self.get(tag)?.uint(0)
+    }
+
+    fn fill(&self, exif_data: &mut ExifData) {
+        let mut decoded = ExifData::default();
+        match self.vendor {
+            Vendor::Canon => {
+                if let Some(settings) = self.get(0x0001) {
+                    decoded.drive_mode = settings.uint(5).and_then(canon_drive_mode);
+                    decoded.focus_mode = settings.uint(7).and_then(canon_focus_mode);
+                    decoded.af_point = settings.uint(19).and_then(canon_af_point);
+                    decoded.image_stabilization =
+                        settings.uint(34).and_then(canon_image_stabilization);
+                }
+                decoded.camera_serial = self
+                    .uint(0x000C)
+                    .filter(|serial| *serial != 0)
+                    .map(|serial| format!("{serial:010}"));
+            }
+            Vendor::Nikon => {
+                decoded.shutter_count = self.uint(0x00A7);
+                decoded.camera_serial = self.get(0x001D).and_then(Entry::text);
+                decoded.focus_mode = self.get(0x0007).and_then(Entry::text);
+                decoded.af_point = self
+                    .get(0x0088)
+                    .and_then(|af_info| af_info.uint(1))
+                    .and_then(nikon_af_point);
+                decoded.drive_mode = self.uint(0x0089).map(nikon_drive_mode);
+                decoded.image_stabilization = self
+                    .get(0x001F)
+                    .and_then(|vr_info| vr_info.uint(4))
+                    .and_then(|vr| match vr {
+                        1 => Some("On".to_string()),
+                        2 => Some("Off".to_string()),
+                        _ => None,
+                    });
+            }
+            Vendor::Sony => {
+                decoded.focus_mode = self
+                    .uint(0x201B)
+                    .and_then(sony_focus_mode)
+                    .or_else(|| self.uint(0xB042).and_then(sony_legacy_focus_mode));
+                decoded.af_point = self.uint(0x201E).and_then(sony_af_point);
+                decoded.drive_mode = self.uint(0xB049).and_then(sony_release_mode);
+                decoded.image_stabilization = self.uint(0xB026).and_then(on_off);
+            }
+            Vendor::Fujifilm => {
+                decoded.focus_mode = self
+                    .uint(0x102B)
+                    .and_then(|settings| fujifilm_focus_mode(settings & 0x0F))
+                    .or_else(|| match self.uint(0x1021)? {
+                        0 => Some("Auto".to_string()),
+                        1 => Some("Manual".to_string()),
+                        _ => None,
+                    });
+                decoded.af_point = self.get(0x1023).and_then(|pixel| {
+                    Some(format!(
+                        "Focus pixel {}, {}",
+                        pixel.uint(0)?,
+                        pixel.uint(1)?
+                    ))
+                });
+                decoded.drive_mode = self
+                    .uint(0x1103)
+                    .and_then(|settings| fujifilm_drive_mode(settings & 0xFF));
+                decoded.image_stabilization = self
+                    .get(0x1422)
+                    .and_then(|stabilization| stabilization.uint(1))
+                    .and_then(fujifilm_image_stabilization);
+                decoded.shutter_count = self.uint(0x1438).map(|count| count & 0x7FFF);
+                decoded.camera_rating = self
+                    .uint(0x1431)
+                    .and_then(|rating| u8::try_from(rating).ok());
+            }
+        }
+        crate::merge_exif_data(exif_data, decoded);
+    }
+
+    /// What the decoded fields make of a single entry, e.g. `"AF-C"` for a
+    /// focus mode code. Entries packing several settings list each.
+    fn describe(&self, entry: &Entry<'a>) -> Option<String> {
+        let mut decoded = ExifData::default();
+        MakerNote {
+            vendor: self.vendor,
+            entries: vec![*entry],
+        }
+        .fill(&mut decoded);
+        let parts: Vec<(&str, String)> = [
+            ("Drive mode", decoded.drive_mode),
+            ("Focus mode", decoded.focus_mode),
+            ("AF point", decoded.af_point),
+            ("Stabilization", decoded.image_stabilization),
+            ("Serial number", decoded.camera_serial),
+            (
+                "Shutter count",
+                decoded.shutter_count.map(|count| count.to_string()),
+            ),
+            (
+                "Rating",
+                decoded.camera_rating.map(|rating| rating.to_string()),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(label, value)| Some((label, value?)))
+        .collect();
+        match parts.as_slice() {
+            [] => None,
+            [(_, value)] => Some(value.clone()),
+            _ => Some(
+                parts
+                    .iter()
+                    .map(|(label, value)| format!("{label}: {value}"))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        }
+    }
+
+    fn tags(&self) -> Vec<ExifTag> {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let raw = entry.display();
+                ExifTag {
+                    ifd: "MakerNote".to_string(),
+                    tag_id: entry.tag,
+                    name: maker_note_tag_name(self.vendor, entry.tag)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("{:?} 0x{:04x}", self.vendor, entry.tag)),
+                    value: self.describe(entry).unwrap_or_else(|| raw.clone()),
+                    raw,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Standard tags `ExifData`'s basic fields leave out, read from the IFD0
+/// and Exif IFD entries, completed from the maker note.
+fn details_from_ifds(
+    ifd0: &[Entry],
+    exif_ifd: &[Entry],
+    maker_note: Option<&MakerNote>,
+) -> ExifData {
+    let text = |entries: &[Entry], tag| find(entries, tag).and_then(Entry::text);
+    let mut exif_data = ExifData {
+        artist: text(ifd0, TAG_ARTIST),
+        copyright: text(ifd0, TAG_COPYRIGHT),
+        camera_rating: find(ifd0, TAG_RATING)
+            .and_then(|rating| rating.uint(0))
+            .and_then(|rating| u8::try_from(rating).ok())
+            .filter(|rating| *rating <= 5),
+        camera_serial: text(exif_ifd, TAG_BODY_SERIAL),
+        lens_serial: text(exif_ifd, TAG_LENS_SERIAL),
+        metering_mode: find(exif_ifd, TAG_METERING_MODE)
+            .and_then(|mode| mode.uint(0))
+            .and_then(metering_mode),
+        subsec_time: text(exif_ifd, TAG_SUBSEC_TIME_ORIGINAL),
+        offset_time: text(exif_ifd, TAG_OFFSET_TIME_ORIGINAL)
+            .or_else(|| text(exif_ifd, TAG_OFFSET_TIME)),
+        ..ExifData::default()
+    };
+    if let Some(maker_note) = maker_note {
+        maker_note.fill(&mut exif_data);
+    }
+    exif_data
+}
+
+/// The IFD0 and Exif IFD entries of a TIFF structure.
+fn main_ifds(tiff: &[u8]) -> Option<(bool, Vec<Entry<'_>>, Vec<Entry<'_>>)> {
+    let (little_endian, ifd0_offset) = tiff_header(tiff)?;
+    let ifd0 = read_ifd(tiff, ifd0_offset, little_endian);
+    let exif_ifd = find(&ifd0, TAG_EXIF_IFD)
+        .and_then(|pointer| pointer.uint(0))
+        .map(|offset| read_ifd(tiff, offset as usize, little_endian))
+        .unwrap_or_default();
+    Some((little_endian, ifd0, exif_ifd))
+}
+
+fn locate_maker_note<'a>(
+    tiff: &'a [u8],
+    little_endian: bool,
+    ifd0: &[Entry],
+    exif_ifd: &[Entry],
+) -> Option<MakerNote<'a>> {
+    let make = find(ifd0, TAG_MAKE)?.text()?;
+    let note = find(exif_ifd, TAG_MAKER_NOTE)?;
+    MakerNote::locate(&make, tiff, note.value_offset, little_endian)
+}
+
+/// Extended camera details from the TIFF structure behind an EXIF block
+/// (`Exif::buf`): serial numbers, drive, focus and metering modes, AF
+/// point, stabilization, shutter count, artist, copyright, the camera's
+/// rating and the original time's sub-seconds and UTC offset. Canon,
+/// Nikon, Sony and Fujifilm maker notes are decoded where the layout is
+/// known.
+pub fn from_tiff(tiff: &[u8]) -> ExifData {
+    let Some((little_endian, ifd0, exif_ifd)) = main_ifds(tiff) else {
+        return ExifData::default();
+    };
+    let maker_note = locate_maker_note(tiff, little_endian, &ifd0, &exif_ifd);
+    details_from_ifds(&ifd0, &exif_ifd, maker_note.as_ref())
+}
+
+/// The decoded maker note entries of a TIFF structure, for tag dumps.
+pub fn maker_note_tags(tiff: &[u8]) -> Vec<ExifTag> {
+    main_ifds(tiff)
+        .and_then(|(little_endian, ifd0, exif_ifd)| {
+            locate_maker_note(tiff, little_endian, &ifd0, &exif_ifd)
+        })
+        .map(|maker_note| maker_note.tags())
+        .unwrap_or_default()
+}
+
+/// The IPTC-IIM block a TIFF structure stores in IFD0, as TIFF files and
+/// TIFF-based RAW formats do instead of Photoshop resources.
+pub fn iptc_naa(tiff: &[u8]) -> Option<&[u8]> {
+    let (_, ifd0, _) = main_ifds(tiff)?;
+    find(&ifd0, TAG_IPTC_NAA).map(|entry| entry.data)
+}
+
+/// Where IFD0 stores `tag`'s value, as (offset, length) in the file, for
+/// values past the part of the TIFF structure that was read.
+pub fn ifd0_value_range(header: &[u8], tag: u16) -> Option<(u64, u64)> {
+    let (little_endian, offset) = tiff_header(header)?;
+    let count = read_u16(header, offset, little_endian)?;
+    (0..usize::from(count)).find_map(|index| {
+        let at = offset + 2 + index * 12;
+        if read_u16(header, at, little_endian)? != tag {
+            return None;
+        }
+        let kind = read_u16(header, at + 2, little_endian)?;
+        let count = u64::from(read_u32(header, at + 4, little_endian)?);
+        let size = (value_size(kind)? as u64).checked_mul(count)?;
+        let value_offset = if size <= 4 {
+            (at + 8) as u64
+        } else {
+            u64::from(read_u32(header, at + 8, little_endian)?)
+        };
+        Some((value_offset, size))
+    })
+}
+
+/// Canon CR3 files keep IFD0, the Exif IFD, the maker note and the GPS IFD
+/// as separate TIFF structures (the `CMT1`-`CMT4` boxes).
+pub struct Cr3Metadata<'a> {
+    pub ifd0: &'a [u8],
+    pub exif: &'a [u8],
+    pub maker_note: Option<&'a [u8]>,
+    pub gps: Option<&'a [u8]>,
+}
+
+impl Cr3Metadata<'_> {
+    fn first_ifd(tiff: &[u8]) -> Vec<Entry<'_>> {
+        tiff_header(tiff)
+            .map(|(little_endian, offset)| read_ifd(tiff, offset, little_endian))
+            .unwrap_or_default()
+    }
+
+    pub fn exif_data(&self) -> ExifData {
+        let ifd0 = Self::first_ifd(self.ifd0);
+        let exif_ifd = Self::first_ifd(self.exif);
+        let maker_note = self
+            .maker_note
+            .and_then(|tiff| MakerNote::from_tiff(Vendor::Canon, tiff));
+        let mut exif_data = details_from_ifds(&ifd0, &exif_ifd, maker_note.as_ref());
+        exif_data.date_taken_local = find(&exif_ifd, 0x9003)
+            .and_then(Entry::text)
+            .and_then(|datetime| exif::DateTime::from_ascii(datetime.as_bytes()).ok())
+            .and_then(|mut datetime| {
+                if let Some(subsec) = &exif_data.subsec_time {
+                    let _ = datetime.parse_subsec(subsec.as_bytes());
+                }
+                crate::naive_exif_datetime(&datetime)
+            });
+        exif_data
+    }
+
+    pub fn tags(&self) -> Vec<ExifTag> {
+        let standard = [
+            ("IFD0", Context::Tiff, Some(self.ifd0)),
+            ("Exif", Context::Exif, Some(self.exif)),
+            ("GPS", Context::Gps, self.gps),
+        ];
+        let mut tags: Vec<ExifTag> = standard
+            .into_iter()
+            .filter_map(|(ifd, context, tiff)| Some((ifd, context, tiff?)))
+            .flat_map(|(ifd, context, tiff)| {
+                Self::first_ifd(tiff).into_iter().map(move |entry| ExifTag {
+                    ifd: ifd.to_string(),
+                    tag_id: entry.tag,
+                    name: Tag(context, entry.tag).to_string(),
+                    raw: entry.display(),
+                    value: entry.display(),
+                })
+            })
+            .collect();
+        if let Some(maker_note) = self
+            .maker_note
+            .and_then(|tiff| MakerNote::from_tiff(Vendor::Canon, tiff))
+        {
+            tags.extend(maker_note.tags());
+        }
+        tags
+    }
+}
+
+fn label(value: u32, labels: &[(u32, &str)]) -> Option<String> {
+    labels
+        .iter()
+        .find(|(known, _)| *known == value)
+        .map(|(_, name)| (*name).to_string())
+}
+
+fn on_off(value: u32) -> Option<String> {
+    label(value, &[(0, "Off"), (1, "On")])
+}
+
+fn metering_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (1, "Average"),
+            (2, "Center-weighted average"),
+            (3, "Spot"),
+            (4, "Multi-spot"),
+            (5, "Pattern"),
+            (6, "Partial"),
+            (255, "Other"),
+        ],
+    )
+}
+
+fn canon_drive_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Single"),
+            (1, "Continuous"),
+            (2, "Movie"),
+            (3, "Continuous, speed priority"),
+            (4, "Continuous, low"),
+            (5, "Continuous, high"),
+            (6, "Silent single"),
+            (9, "Single, silent"),
+            (10, "Continuous, silent"),
+        ],
+    )
+}
+
+fn canon_focus_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "One-shot AF"),
+            (1, "AI Servo AF"),
+            (2, "AI Focus AF"),
+            (3, "Manual"),
+            (4, "Single"),
+            (5, "Continuous"),
+            (6, "Manual"),
+            (16, "Pan focus"),
+            (256, "One-shot AF (live view)"),
+            (257, "AI Servo AF (live view)"),
+            (258, "AI Focus AF (live view)"),
+            (512, "Movie snap focus"),
+            (519, "Movie servo AF"),
+        ],
+    )
+}
+
+fn canon_af_point(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0x2005, "Manual selection"),
+            (0x3000, "None (MF)"),
+            (0x3001, "Auto"),
+            (0x3002, "Right"),
+            (0x3003, "Center"),
+            (0x3004, "Left"),
+            (0x4001, "Auto"),
+            (0x4006, "Face detect"),
+        ],
+    )
+}
+
+fn canon_image_stabilization(value: u32) -> Option<String> {
+    label(
+        value & 0xFF,
+        &[
+            (0, "Off"),
+            (1, "On"),
+            (2, "Shoot only"),
+            (3, "Panning"),
+            (4, "Dynamic"),
+        ],
+    )
+}
+
+fn nikon_af_point(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Center"),
+            (1, "Top"),
+            (2, "Bottom"),
+            (3, "Mid-left"),
+            (4, "Mid-right"),
+            (5, "Upper-left"),
+            (6, "Upper-right"),
+            (7, "Lower-left"),
+            (8, "Lower-right"),
+            (9, "Far left"),
+            (10, "Far right"),
+        ],
+    )
+}
+
+/// Nikon's shooting mode is a bit field; bit 0 is continuous release and
+/// bit 3 the self-timer.
+fn nikon_drive_mode(value: u32) -> String {
+    if value & 0b1 != 0 {
+        "Continuous".to_string()
+    } else if value & 0b1000 != 0 {
+        "Self-timer".to_string()
+    } else {
+        "Single".to_string()
+    }
+}
+
+fn sony_focus_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Manual"),
+            (2, "AF-S"),
+            (3, "AF-C"),
+            (4, "AF-A"),
+            (6, "DMF"),
+        ],
+    )
+}
+
+fn sony_legacy_focus_mode(value: u32) -> Option<String> {
+    label(value, &[(1, "AF-S"), (2, "AF-C"), (4, "Permanent AF")])
+}
+
+fn sony_af_point(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Auto"),
+            (1, "Center"),
+            (2, "Top"),
+            (3, "Upper-right"),
+            (4, "Right"),
+            (5, "Lower-right"),
+            (6, "Bottom"),
+            (7, "Lower-left"),
+            (8, "Left"),
+            (9, "Upper-left"),
+            (10, "Far right"),
+            (11, "Far left"),
+        ],
+    )
+}
+
+fn sony_release_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Single"),
+            (2, "Continuous"),
+            (5, "Exposure bracketing"),
+            (6, "White balance bracketing"),
+            (8, "DRO bracketing"),
+        ],
+    )
+}
+
+fn fujifilm_focus_mode(value: u32) -> Option<String> {
+    label(value, &[(0, "Manual"), (1, "AF-S"), (2, "AF-C")])
+}
+
+fn fujifilm_drive_mode(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Single"),
+            (1, "Continuous, low"),
+            (2, "Continuous, high"),
+        ],
+    )
+}
+
+fn fujifilm_image_stabilization(value: u32) -> Option<String> {
+    label(
+        value,
+        &[
+            (0, "Off"),
+            (1, "On (continuous)"),
+            (2, "On (shooting only)"),
+        ],
+    )
+}
+
+fn maker_note_tag_name(vendor: Vendor, tag: u16) -> Option<&'static str> {
+    let names: &[(u16, &str)] = match vendor {
+        Vendor::Canon => &[
+            (0x0001, "CameraSettings"),
+            (0x0006, "ImageType"),
+            (0x0007, "FirmwareVersion"),
+            (0x000C, "SerialNumber"),
+            (0x0095, "LensModel"),
+            (0x0096, "InternalSerialNumber"),
+        ],
+        Vendor::Nikon => &[
+            (0x0007, "FocusMode"),
+            (0x001D, "SerialNumber"),
+            (0x001F, "VRInfo"),
+            (0x0084, "Lens"),
+            (0x0088, "AFInfo"),
+            (0x0089, "ShootingMode"),
+            (0x00A7, "ShutterCount"),
+        ],
+        Vendor::Sony => &[
+            (0x201B, "FocusMode"),
+            (0x201E, "AFPointSelected"),
+            (0xB026, "ImageStabilization"),
+            (0xB042, "FocusMode"),
+            (0xB049, "ReleaseMode"),
+        ],
+        Vendor::Fujifilm => &[
+            (0x0010, "InternalSerialNumber"),
+            (0x1021, "FocusMode"),
+            (0x1023, "FocusPixel"),
+            (0x102B, "FocusSettings"),
+            (0x1103, "DriveSettings"),
+            (0x1422, "ImageStabilization"),
+            (0x1431, "Rating"),
+            (0x1438, "ImageCount"),
+        ],
+    };
+    names
+        .iter()
+        .find(|(known, _)| *known == tag)
+        .map(|(_, name)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One IFD entry to lay out: tag, type, count and the value's bytes.
+    type Field = (u16, u16, u32, Vec<u8>);
+
+    fn short(value: u16, little_endian: bool) -> Vec<u8> {
+        if little_endian {
+            value.to_le_bytes().to_vec()
+        } else {
+            value.to_be_bytes().to_vec()
+        }
+    }
+
+    fn long(value: u32, little_endian: bool) -> Vec<u8> {
+        if little_endian {
+            value.to_le_bytes().to_vec()
+        } else {
+            value.to_be_bytes().to_vec()
+        }
+    }
+
+    fn ascii(tag: u16, text: &str) -> Field {
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.push(0);
+        (tag, 2, bytes.len() as u32, bytes)
+    }
+
+    /// The IFD at `start`, followed by the values too long to fit inline.
+    fn ifd(fields: &[Field], start: usize, little_endian: bool) -> Vec<u8> {
+        let mut out = short(fields.len() as u16, little_endian);
+        let mut values: Vec<u8> = Vec::new();
+        let values_start = start + 2 + fields.len() * 12 + 4;
+        for (tag, kind, count, bytes) in fields {
+            out.extend(short(*tag, little_endian));
+            out.extend(short(*kind, little_endian));
+            out.extend(long(*count, little_endian));
+            if bytes.len() <= 4 {
+                let mut inline = bytes.clone();
+                inline.resize(4, 0);
+                out.extend(inline);
+            } else {
+                out.extend(long((values_start + values.len()) as u32, little_endian));
+                values.extend(bytes);
+            }
+        }
+        out.extend([0; 4]);
+        out.extend(values);
+        out
+    }
+
+    /// A TIFF structure with IFD0 at offset 8 and the Exif IFD after it.
+    fn tiff(ifd0: &[Field], exif: &[Field], little_endian: bool) -> Vec<u8> {
+        let mut out = if little_endian {
+            b"II*\0".to_vec()
+        } else {
+            b"MM\0*".to_vec()
+        };
+        out.extend(long(8, little_endian));
+        let mut ifd0 = ifd0.to_vec();
+        ifd0.push((TAG_EXIF_IFD, 4, 1, long(0, little_endian)));
+        let exif_start = 8 + ifd(&ifd0, 8, little_endian).len();
+        ifd0.last_mut().unwrap().3 = long(exif_start as u32, little_endian);
+        out.extend(ifd(&ifd0, 8, little_endian));
+        out.extend(ifd(exif, exif_start, little_endian));
+        out
+    }
+
+    /// Where the maker note's value lands in `tiff`, so notes whose offsets
+    /// are relative to the TIFF structure can be laid out in place.
+    fn maker_note_offset(tiff: &[u8]) -> usize {
+        let (_, _, exif_ifd) = main_ifds(tiff).unwrap();
+        find(&exif_ifd, TAG_MAKER_NOTE).unwrap().value_offset
+    }
+
+    fn with_maker_note(make: &str, exif: &[Field], note: impl Fn(usize) -> Vec<u8>) -> Vec<u8> {
+        let ifd0 = [ascii(TAG_MAKE, make)];
+        let mut exif = exif.to_vec();
+        let placeholder = note(0);
+        exif.push((TAG_MAKER_NOTE, 7, placeholder.len() as u32, placeholder));
+        let offset = maker_note_offset(&tiff(&ifd0, &exif, true));
+        let note = note(offset);
+        exif.last_mut().unwrap().3 = note;
+        tiff(&ifd0, &exif, true)
+    }
+
+    #[test]
+    fn reads_standard_details_in_either_byte_order() {
+        for little_endian in [true, false] {
+            let data = tiff(
+                &[
+                    ascii(TAG_MAKE, "Leica"),
+                    ascii(TAG_ARTIST, "Ada Lovelace"),
+                    ascii(TAG_COPYRIGHT, "CC BY 4.0"),
+                    (TAG_RATING, 3, 1, short(4, little_endian)),
+                ],
+                &[
+                    ascii(TAG_BODY_SERIAL, "5012345"),
+                    ascii(TAG_LENS_SERIAL, "LN-998877"),
+                    (TAG_METERING_MODE, 3, 1, short(5, little_endian)),
+                    ascii(TAG_SUBSEC_TIME_ORIGINAL, "42"),
+                    ascii(TAG_OFFSET_TIME_ORIGINAL, "+02:00"),
+                ],
+                little_endian,
+            );
+            let details = from_tiff(&data);
+            assert_eq!(details.artist.as_deref(), Some("Ada Lovelace"));
+            assert_eq!(details.copyright.as_deref(), Some("CC BY 4.0"));
+            assert_eq!(details.camera_rating, Some(4));
+            assert_eq!(details.camera_serial.as_deref(), Some("5012345"));
+            assert_eq!(details.lens_serial.as_deref(), Some("LN-998877"));
+            assert_eq!(details.metering_mode.as_deref(), Some("Pattern"));
+            assert_eq!(details.subsec_time.as_deref(), Some("42"));
+            assert_eq!(details.offset_time.as_deref(), Some("+02:00"));
+            assert!(maker_note_tags(&data).is_empty());
+        }
+    }
+
+    #[test]
+    fn decodes_canon_camera_settings_and_serial() {
+        let data = with_maker_note("Canon", &[], |offset| {
+            let mut settings = [0u16; 40];
+            settings[5] = 1;
+            settings[7] = 1;
+            settings[19] = 0x3001;
+            settings[34] = 2;
+            let settings = settings
+                .iter()
+                .flat_map(|value| value.to_le_bytes())
+                .collect();
+            ifd(
+                &[
+                    (0x0001, 3, 40, settings),
+                    (0x000C, 4, 1, long(123456, true)),
+                ],
+                offset,
+                true,
+            )
+        });
+        let details = from_tiff(&data);
+        assert_eq!(details.drive_mode.as_deref(), Some("Continuous"));
+        assert_eq!(details.focus_mode.as_deref(), Some("AI Servo AF"));
+        assert_eq!(details.af_point.as_deref(), Some("Auto"));
+        assert_eq!(details.image_stabilization.as_deref(), Some("Shoot only"));
+        assert_eq!(details.camera_serial.as_deref(), Some("0000123456"));
+
+        let tags = maker_note_tags(&data);
+        let names: Vec<&str> = tags.iter().map(|tag| tag.name.as_str()).collect();
+        assert_eq!(names, ["CameraSettings", "SerialNumber"]);
+        assert!(tags[0].value.contains("Drive mode: Continuous"));
+        assert_eq!(tags[1].raw, "123456");
+        assert_eq!(tags[1].value, "0000123456");
+    }
+
+    #[test]
+    fn decodes_nikon_type_3_notes_with_their_own_byte_order() {
+        // The note's inner TIFF is big-endian inside a little-endian file,
+        // and its offsets count from the inner header.
+        let data = with_maker_note("NIKON CORPORATION", &[], |_| {
+            let mut note = b"Nikon\0\x02\x10\0\0MM\0*".to_vec();
+            note.extend(long(8, false));
+            note.extend(ifd(
+                &[
+                    ascii(0x001D, "3012345"),
+                    (0x0089, 3, 1, short(0b1, false)),
+                    (0x00A7, 4, 1, long(48213, false)),
+                ],
+                8,
+                false,
+            ));
+            note
+        });
+        let details = from_tiff(&data);
+        assert_eq!(details.camera_serial.as_deref(), Some("3012345"));
+        assert_eq!(details.drive_mode.as_deref(), Some("Continuous"));
+        assert_eq!(details.shutter_count, Some(48213));
+    }
+
+    #[test]
+    fn decodes_sony_and_fujifilm_notes() {
+        let sony = with_maker_note("SONY", &[], |offset| {
+            let mut note = b"SONY DSC \0\0\0".to_vec();
+            note.extend(ifd(
+                &[(0x201B, 1, 1, vec![3]), (0xB049, 3, 1, short(2, true))],
+                offset + 12,
+                true,
+            ));
+            note
+        });
+        let details = from_tiff(&sony);
+        assert_eq!(details.focus_mode.as_deref(), Some("AF-C"));
+        assert_eq!(details.drive_mode.as_deref(), Some("Continuous"));
+
+        // Fujifilm offsets count from the start of the note.
+        let fujifilm = with_maker_note("FUJIFILM", &[], |_| {
+            let mut note = b"FUJIFILM".to_vec();
+            note.extend(long(12, true));
+            note.extend(ifd(
+                &[
+                    (0x1431, 4, 1, long(3, true)),
+                    (0x1438, 4, 1, long(0x8000 | 1234, true)),
+                ],
+                12,
+                true,
+            ));
+            note
+        });
+        let details = from_tiff(&fujifilm);
+        assert_eq!(details.camera_rating, Some(3));
+        assert_eq!(details.shutter_count, Some(1234));
+    }
+
+    #[test]
+    fn ignores_entries_outside_the_data() {
+        let mut data = tiff(
+            &[ascii(TAG_ARTIST, "Ada Lovelace"), ascii(TAG_COPYRIGHT, "x")],
+            &[],
+            true,
+        );
+        // Point the artist's value far past the end of the structure.
+        let artist_value = 8 + 2 + 8;
+        data[artist_value..artist_value + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let details = from_tiff(&data);
+        assert_eq!(details.artist, None);
+        assert_eq!(details.copyright.as_deref(), Some("x"));
+
+        for truncated in [&data[..3], &data[..12], b"not a tiff".as_slice()] {
+            let details = from_tiff(truncated);
+            assert_eq!(details.artist, None);
+            assert!(maker_note_tags(truncated).is_empty());
+        }
+
+        let fujifilm = with_maker_note("FUJIFILM", &[], |_| {
+            let mut note = b"FUJIFILM".to_vec();
+            note.extend(long(u32::MAX, true));
+            note
+        });
+        assert!(maker_note_tags(&fujifilm).is_empty());
+    }
+
+    #[test]
+    fn displays_rationals_and_truncates_long_values() {
+        let data = tiff(
+            &[
+                (0x011A, 5, 1, [long(300, true), long(1, true)].concat()),
+                (
+                    0x9204,
+                    10,
+                    1,
+                    [long(-1i32 as u32, true), long(3, true)].concat(),
+                ),
+                (0x0102, 3, 100, vec![8; 200]),
+            ],
+            &[],
+            true,
+        );
+        let (_, ifd0, _) = main_ifds(&data).unwrap();
+        assert_eq!(find(&ifd0, 0x011A).unwrap().display(), "300/1");
+        assert_eq!(find(&ifd0, 0x9204).unwrap().display(), "-1/3");
+        let long_value = find(&ifd0, 0x0102).unwrap().display();
+        assert!(long_value.ends_with(" …"));
+        assert_eq!(long_value.split(' ').count(), MAX_DISPLAY_VALUES + 1);
+    }
+}
@@ -0,0 +1,352 @@
+use crate::exif_details::{self, ExifTag};
+use crate::iptc;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// How far into a file IPTC and XMP blocks are looked for. JPEG and PNG
+/// keep both in their first segments; TIFF-based RAW files point to them
+/// from IFD0, and blocks past the header are read from there.
+const MAX_HEADER_BYTES: u64 = 256 * 1024;
+/// Largest IPTC or XMP block read from where IFD0 points.
+const MAX_BLOCK_BYTES: u64 = 4 * 1024 * 1024;
+
+/// One field as listed by `get_full_metadata`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataField {
+    /// The tag number for EXIF (`0x829A`), record and dataset for IPTC
+    /// (`2:105`), and the qualified property path for XMP (`dc:subject`).
+    pub id: String,
+    pub name: String,
+    /// The value as stored.
+    pub raw: String,
+    /// The value as a person reads it.
+    pub value: String,
+}
+
+/// The fields of one IFD (`IFD0`, `Exif`, `GPS`, `MakerNote`, ...), IPTC
+/// record (`IPTC Envelope`, `IPTC Application`) or XMP namespace (`XMP-dc`,
+/// `XMP-photoshop`, ...), in file order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataGroup {
+    pub namespace: String,
+    pub fields: Vec<MetadataField>,
+}
+
+/// Every metadata field of the file at `path`: its `exif_tags`, followed by
+/// the IPTC datasets and XMP properties found in its header.
+pub fn read(path: &Path, exif_tags: Vec<ExifTag>) -> Result<Vec<MetadataGroup>> {
+    let mut groups = Vec::new();
+    for tag in exif_tags {
+        let field = MetadataField {
+            id: format!("0x{:04X}", tag.tag_id),
+            name: tag.name,
+            raw: tag.raw,
+            value: tag.value,
+        };
+        push(&mut groups, &tag.ifd, field);
+    }
+
+    let mut file = File::open(path)?;
+    let mut header = Vec::new();
+    (&mut file)
+        .take(MAX_HEADER_BYTES)
+        .read_to_end(&mut header)?;
+    let iptc_block = read_ifd0_block(&mut file, &header, exif_details::TAG_IPTC_NAA);
+    let xmp_block = read_ifd0_block(&mut file, &header, exif_details::TAG_XMP);
+
+    let iim = iptc::find_iim(&header)
+        .or_else(|| exif_details::iptc_naa(&header))
+        .or(iptc_block.as_deref());
+    if let Some(iim) = iim {
+        let datasets = iptc::datasets(iim);
+        let utf8 = iptc::is_utf8(&datasets);
+        for dataset in &datasets {
+            let namespace = match dataset.record {
+                1 => "IPTC Envelope".to_string(),
+                2 => "IPTC Application".to_string(),
+                record => format!("IPTC Record {record}"),
+            };
+            let field = MetadataField {
+                id: format!("{}:{}", dataset.record, dataset.number),
+                name: iptc::dataset_name(dataset.record, dataset.number)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("Dataset {}:{}", dataset.record, dataset.number)),
+                raw: iptc::raw(dataset, utf8),
+                value: iptc::readable(dataset, utf8),
+            };
+            push(&mut groups, &namespace, field);
+        }
+    }
+
+    if let Some(packet) = xmp_packet(&header).or_else(|| xmp_packet(xmp_block.as_deref()?)) {
+        for (path, values) in xmp_properties(&packet) {
+            let prefix = path
+                .split_once(':')
+                .map_or("", |(prefix, _)| prefix)
+                .to_string();
+            let name = path
+                .split('/')
+                .map(|segment| segment.split_once(':').map_or(segment, |(_, name)| name))
+                .collect::<Vec<_>>()
+                .join("/");
+            let field = MetadataField {
+                raw: values.join("; "),
+                value: values.join(", "),
+                id: path,
+                name,
+            };
+            push(&mut groups, &format!("XMP-{prefix}"), field);
+        }
+    }
+    Ok(groups)
+}
+
+fn push(groups: &mut Vec<MetadataGroup>, namespace: &str, field: MetadataField) {
+    match groups.iter_mut().find(|group| group.namespace == namespace) {
+        Some(group) => group.fields.push(field),
+        None => groups.push(MetadataGroup {
+            namespace: namespace.to_string(),
+            fields: vec![field],
+        }),
+    }
+}
+
+/// The value of IFD0's `tag` when the header holds a TIFF structure whose
+/// value lies beyond it.
+fn read_ifd0_block(file: &mut File, header: &[u8], tag: u16) -> Option<Vec<u8>> {
+    let (offset, length) = exif_details::ifd0_value_range(header, tag)?;
+    if offset.checked_add(length)? <= header.len() as u64 || length > MAX_BLOCK_BYTES {
+        return None;
+    }
+    file.seek(SeekFrom::Start(offset)).ok()?;
+    let mut block = Vec::new();
+    file.take(length).read_to_end(&mut block).ok()?;
+    Some(block)
+}
+
+/// The first XMP packet embedded in `data`.
+fn xmp_packet(data: &[u8]) -> Option<String> {
+    let (open, close): (&[u8], &[u8]) = (b"<x:xmpmeta", b"</x:xmpmeta>");
+    let start = data.windows(open.len()).position(|window| window == open)?;
+    let end = data[start..]
+        .windows(close.len())
+        .position(|window| window == close)?;
+    Some(String::from_utf8_lossy(&data[start..start + end + close.len()]).into_owned())
+}
+
+/// The properties of an XMP packet as (qualified path, values), in packet
+/// order. Struct fields are named `parent/field`; array items and language
+/// alternatives become the property's values.
+fn xmp_properties(packet: &str) -> Vec<(String, Vec<String>)> {
+    let mut properties: Vec<(String, Vec<String>)> = Vec::new();
+    let is_property = |name: &str| !name.starts_with("rdf:") && !name.starts_with("x:");
+
+    let mut path: Vec<&str> = Vec::new();
+    let mut rest = packet;
+    while let Some(open) = rest.find('<') {
+        let text = rest[..open].trim();
+        if !path.is_empty() && !text.is_empty() {
+            add_value(&mut properties, path.join("/"), crate::xml_unescape(text));
+        }
+        rest = &rest[open..];
+        let skip_to = ["<?", "<!--", "<!["]
+            .iter()
+            .zip(["?>", "-->", "]]>"])
+            .find(|(start, _)| rest.starts_with(**start));
+        if let Some((_, end)) = skip_to {
+            let Some(close) = rest.find(end) else {
+                break;
+            };
+            rest = &rest[close + end.len()..];
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            if is_property(name.trim()) {
+                path.pop();
+            }
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/').trim();
+        let (name, attributes) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let property = is_property(name);
+        if property {
+            path.push(name);
+        }
+        for (attribute, value) in xml_attributes(attributes) {
+            if attribute == "rdf:resource" && !path.is_empty() {
+                add_value(&mut properties, path.join("/"), value);
+            } else if !attribute.starts_with("xmlns")
+                && !attribute.starts_with("xml:")
+                && !attribute.starts_with("rdf:")
+            {
+                let mut attribute_path = path.clone();
+                attribute_path.push(attribute);
+                add_value(&mut properties, attribute_path.join("/"), value);
+            }
+        }
+        if property && self_closing {
+            path.pop();
+        }
+    }
+    properties
+}
+
+fn add_value(properties: &mut Vec<(String, Vec<String>)>, path: String, value: String) {
+    match properties.iter_mut().find(|(known, _)| *known == path) {
+        Some((_, values)) => values.push(value),
+        None => properties.push((path, vec![value])),
+    }
+}
+
+/// `name="value"` pairs of an element, values unescaped.
+fn xml_attributes(mut rest: &str) -> Vec<(&str, String)> {
+    let mut attributes = Vec::new();
+    while let Some(equals) = rest.find('=') {
+        let name = rest[..equals].trim();
+        let after = rest[equals + 1..].trim_start();
+        let Some(quote) = after.chars().next().filter(|ch| matches!(ch, '"' | '\'')) else {
+            break;
+        };
+        let Some(end) = after[1..].find(quote) else {
+            break;
+        };
+        attributes.push((name, crate::xml_unescape(&after[1..1 + end])));
+        rest = &after[end + 2..];
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property<'a>(properties: &'a [(String, Vec<String>)], path: &str) -> Option<&'a [String]> {
+        properties
+            .iter()
+            .find(|(known, _)| known == path)
+            .map(|(_, values)| values.as_slice())
+    }
+
+    #[test]
+    fn reads_attribute_element_and_array_properties() {
+        let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <!-- a comment with <tags> inside -->
+    <rdf:Description rdf:about="" xmp:Rating='4' photoshop:City="Caf&#233; &amp; Bar">
+      <dc:subject>
+        <rdf:Bag>
+          <rdf:li>Fish &lt;&gt; Chips</rdf:li>
+          <rdf:li>&quot;Quoted&quot; &#x2022; &apos;single&apos;</rdf:li>
+        </rdf:Bag>
+      </dc:subject>
+      <dc:title>
+        <rdf:Alt>
+          <rdf:li xml:lang="x-default">Harbour</rdf:li>
+          <rdf:li xml:lang="de">Hafen</rdf:li>
+        </rdf:Alt>
+      </dc:title>
+      <Iptc4xmpCore:CreatorContactInfo Iptc4xmpCore:CiEmailWork="a@b.c"/>
+      <xmpMM:History>
+        <rdf:Seq>
+          <rdf:li rdf:parseType="Resource">
+            <stEvt:action>saved</stEvt:action>
+          </rdf:li>
+        </rdf:Seq>
+      </xmpMM:History>
+      <xmp:CreatorTool rdf:resource="https://example.com/tool"/>
+      <xmp:Label>Red</xmp:Label>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+        let properties = xmp_properties(packet);
+        assert_eq!(
+            property(&properties, "xmp:Rating"),
+            Some(&["4".to_string()][..])
+        );
+        assert_eq!(
+            property(&properties, "photoshop:City"),
+            Some(&["Café & Bar".to_string()][..])
+        );
+        assert_eq!(
+            property(&properties, "dc:subject"),
+            Some(
+                &[
+                    "Fish <> Chips".to_string(),
+                    "\"Quoted\" • 'single'".to_string()
+                ][..]
+            )
+        );
+        assert_eq!(
+            property(&properties, "dc:title"),
+            Some(&["Harbour".to_string(), "Hafen".to_string()][..])
+        );
+        assert_eq!(
+            property(
+                &properties,
+                "Iptc4xmpCore:CreatorContactInfo/Iptc4xmpCore:CiEmailWork"
+            ),
+            Some(&["a@b.c".to_string()][..])
+        );
+        assert_eq!(
+            property(&properties, "xmpMM:History/stEvt:action"),
+            Some(&["saved".to_string()][..])
+        );
+        assert_eq!(
+            property(&properties, "xmp:CreatorTool"),
+            Some(&["https://example.com/tool".to_string()][..])
+        );
+        assert_eq!(
+            property(&properties, "xmp:Label"),
+            Some(&["Red".to_string()][..])
+        );
+        assert!(properties
+            .iter()
+            .all(|(path, _)| !path.contains("rdf:") && !path.contains("xmlns")));
+    }
+
+    #[test]
+    fn splits_attributes_with_either_quote() {
+        assert_eq!(
+            xml_attributes(r#"a="1" b = '2 "two"' c="&lt;3&gt;" broken="#),
+            [
+                ("a", "1".to_string()),
+                ("b", "2 \"two\"".to_string()),
+                ("c", "<3>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_ifd0_to_an_xmp_packet_past_the_header() {
+        let packet =
+            br#"<x:xmpmeta><rdf:RDF><rdf:Description xmp:Rating="5"/></rdf:RDF></x:xmpmeta>"#;
+        let offset = MAX_HEADER_BYTES as u32 + 1000;
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend(exif_details::TAG_XMP.to_le_bytes());
+        tiff.extend(7u16.to_le_bytes());
+        tiff.extend((packet.len() as u32).to_le_bytes());
+        tiff.extend(offset.to_le_bytes());
+        tiff.extend([0; 4]);
+        tiff.resize(offset as usize, 0);
+        tiff.extend(packet);
+
+        let path = std::env::temp_dir().join(format!("hologram-xmp-{}.tif", std::process::id()));
+        std::fs::write(&path, &tiff).unwrap();
+        let groups = read(&path, Vec::new()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].namespace, "XMP-xmp");
+        assert_eq!(groups[0].fields[0].id, "xmp:Rating");
+        assert_eq!(groups[0].fields[0].value, "5");
+    }
+}
@@ -0,0 +1,517 @@
+use crate::raw_preview::{is_raw_file, is_video_file};
+use crate::Photo;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Companion files that are not library photos themselves but belong to a
+/// shot: XMP sidecars.
+const SIDECAR_EXTENSIONS: &[&str] = &["xmp"];
+const PAIRING_OPTIONS_KEY: &str = "pairing_options";
+
+/// What a file contributes to its group. The order is the preference for
+/// the group's representative: the camera JPEG previews fastest, then HEIF,
+/// then the RAW itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupRole {
+    CameraJpeg,
+    Heif,
+    PrimaryRaw,
+    Tiff,
+    Image,
+    /// Further RAW files of the same shot, e.g. a DNG next to the original.
+    Raw,
+    VideoClip,
+    XmpSidecar,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub role: GroupRole,
+    /// Library ID for photo members; companion files have none.
+    pub photo_id: Option<String>,
+    pub file_path: String,
+}
+
+/// Files written by the camera for one shot (same folder and file stem).
+/// Every photo in the group carries the full member list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhotoGroup {
+    /// The member shown for the whole group: the camera JPEG if there is
+    /// one, otherwise the HEIF, otherwise the primary RAW.
+    pub representative_id: String,
+    pub members: Vec<GroupMember>,
+    /// Members were matched across folders by capture time and camera
+    /// rather than by file name.
+    #[serde(default)]
+    pub inferred: bool,
+}
+
+impl PhotoGroup {
+    pub fn member(&self, role: GroupRole) -> Option<&GroupMember> {
+        self.members.iter().find(|member| member.role == role)
+    }
+
+    /// IDs of the photo members other than `photo_id`.
+    pub fn other_photo_ids<'a>(&'a self, photo_id: &'a str) -> impl Iterator<Item = &'a str> {
+        self.members
+            .iter()
+            .filter_map(|member| member.photo_id.as_deref())
+            .filter(move |id| *id != photo_id)
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn photo_role(path: &Path) -> GroupRole {
+    if is_raw_file(path) {
+        return GroupRole::PrimaryRaw;
+    }
+    if is_video_file(path) {
+        return GroupRole::VideoClip;
+    }
+    match extension(path).as_str() {
+        "jpg" | "jpeg" => GroupRole::CameraJpeg,
+        "heic" | "heif" | "hif" => GroupRole::Heif,
+        "tif" | "tiff" => GroupRole::Tiff,
+        _ => GroupRole::Image,
+    }
+}
+
+/// Group key for a file: its folder and stem. Sidecars named after the full
+/// file name (`IMG_0001.CR2.xmp`) belong to the same shot as `IMG_0001.xmp`.
+fn group_key(path: &Path) -> Option<(PathBuf, String)> {
+    let mut stem = path.file_stem()?.to_str()?;
+    if SIDECAR_EXTENSIONS.contains(&extension(path).as_str()) {
+        stem = Path::new(stem)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(stem);
+    }
+    Some((path.parent()?.to_path_buf(), stem.to_string()))
+}
+
+/// Video clips and sidecars in `dir`, by group key.
+fn companion_files(dir: &Path) -> Vec<(String, GroupMember)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            if !SIDECAR_EXTENSIONS.contains(&extension(&path).as_str()) {
+                return None;
+            }
+            let (_, stem) = group_key(&path)?;
+            Some((
+                stem,
+                GroupMember {
+                    role: GroupRole::XmpSidecar,
+                    photo_id: None,
+                    file_path: path.to_string_lossy().to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Assign every photo to the group of files sharing its folder and stem,
+/// replacing any previous grouping. Photos with no other file alongside
+/// them are left ungrouped.
+fn group_photos(photos: &mut [Photo]) {
+    let mut groups: HashMap<(PathBuf, String), Vec<usize>> = HashMap::new();
+    for (index, photo) in photos.iter_mut().enumerate() {
+        photo.group = None;
+        photo.paired_raw_embedded_jpeg_preview = None;
+        if let Some(key) = group_key(Path::new(&photo.file_path)) {
+            groups.entry(key).or_default().push(index);
+        }
+    }
+
+    let mut companions: HashMap<PathBuf, HashMap<String, Vec<GroupMember>>> = HashMap::new();
+    for (dir, _) in groups.keys() {
+        companions.entry(dir.clone()).or_insert_with(|| {
+            let mut by_stem: HashMap<String, Vec<GroupMember>> = HashMap::new();
+            for (stem, member) in companion_files(dir) {
+                by_stem.entry(stem).or_default().push(member);
+            }
+            by_stem
+        });
+    }
+
+    for ((dir, stem), indices) in groups {
+        let companions = companions
+            .get_mut(&dir)
+            .and_then(|by_stem| by_stem.remove(&stem))
+            .unwrap_or_default();
+        if indices.len() + companions.len() >= 2 {
+            assign_group(photos, indices, companions, false);
+        }
+    }
+}
+
+/// Make the photos at `indices` plus `companions` one group, choosing roles
+/// and the representative.
+fn assign_group(
+    photos: &mut [Photo],
+    mut indices: Vec<usize>,
+    companions: Vec<GroupMember>,
+    inferred: bool,
+) {
+    // Several files of one kind: the original RAW wins over a DNG
+    // conversion, otherwise the first by path.
+    indices.sort_by(|&a, &b| {
+        let rank = |index: usize| {
+            let path = Path::new(&photos[index].file_path);
+            (
+                photo_role(path),
+                extension(path) == "dng",
+                photos[index].file_path.clone(),
+            )
+        };
+        rank(a).cmp(&rank(b))
+    });
+    let mut members: Vec<GroupMember> = Vec::new();
+    for &index in &indices {
+        let mut role = photo_role(Path::new(&photos[index].file_path));
+        if members.iter().any(|member| member.role == role) {
+            role = match role {
+                GroupRole::PrimaryRaw => GroupRole::Raw,
+                GroupRole::VideoClip => GroupRole::VideoClip,
+                _ => GroupRole::Image,
+            };
+        }
+        members.push(GroupMember {
+            role,
+            photo_id: Some(photos[index].id.clone()),
+            file_path: photos[index].file_path.clone(),
+        });
+    }
+    members.sort_by_key(|member| member.role);
+    let Some(representative_id) = members.first().and_then(|member| member.photo_id.clone()) else {
+        return;
+    };
+    members.extend(companions);
+
+    let raw_embedded_jpeg_preview = indices
+        .iter()
+        .find(|&&index| is_raw_file(Path::new(&photos[index].file_path)))
+        .and_then(|&raw| photos[raw].embedded_jpeg_preview.clone());
+    let group = PhotoGroup {
+        representative_id,
+        members,
+        inferred,
+    };
+    for &index in &indices {
+        let path = Path::new(&photos[index].file_path);
+        if !is_raw_file(path) && !is_video_file(path) {
+            photos[index].paired_raw_embedded_jpeg_preview = raw_embedded_jpeg_preview.clone();
+        }
+        photos[index].group = Some(group.clone());
+    }
+}
+
+/// Group a library: files of one shot by name, then, when `options` allow,
+/// RAW and rendered files across folders by capture metadata. Returns the
+/// pairs made across folders.
+pub fn group_library(photos: &mut [Photo], options: &PairingOptions) -> Vec<InferredPair> {
+    group_photos(photos);
+    pair_across_folders(photos, options)
+}
+
+fn default_tolerance_ms() -> u64 {
+    1000
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// How RAW and JPEG files written to different folders (e.g. the two card
+/// slots of one camera) are matched into one group. Stored per catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingOptions {
+    /// Match files across folders by capture metadata. Files in one folder
+    /// are always grouped by name.
+    #[serde(default)]
+    pub cross_folder: bool,
+    /// Largest capture-time difference still treated as the same shot.
+    #[serde(default = "default_tolerance_ms")]
+    pub tolerance_ms: u64,
+    /// Also require the same file number (`DSC_1234` and `_DSC1234` share
+    /// 1234) when both names have one.
+    #[serde(default = "default_true")]
+    pub match_file_number: bool,
+}
+
+impl Default for PairingOptions {
+    fn default() -> Self {
+        Self {
+            cross_folder: false,
+            tolerance_ms: default_tolerance_ms(),
+            match_file_number: true,
+        }
+    }
+}
+
+pub fn load_pairing_options(conn: &rusqlite::Connection) -> PairingOptions {
+    conn.query_row(
+        "SELECT value FROM catalog_settings WHERE key = ?1",
+        [PAIRING_OPTIONS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+pub fn save_pairing_options(
+    conn: &rusqlite::Connection,
+    options: &PairingOptions,
+) -> Result<(), String> {
+    let json = serde_json::to_string(options).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO catalog_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [PAIRING_OPTIONS_KEY, &json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A RAW and a rendered file grouped by capture metadata instead of name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InferredPair {
+    pub raw_id: String,
+    pub photo_id: String,
+    pub raw_path: String,
+    pub photo_path: String,
+    /// Difference between the two capture times.
+    pub time_delta_ms: u64,
+}
+
+/// Trailing digits of the file stem: the camera's file counter.
+fn file_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = &stem[stem.trim_end_matches(|c: char| c.is_ascii_digit()).len()..];
+    digits.parse().ok()
+}
+
+/// Whether two photos can come from the same camera: equal body serials
+/// when both are known, otherwise equal models.
+fn same_camera(a: &Photo, b: &Photo) -> bool {
+    match (&a.exif.camera_serial, &b.exif.camera_serial) {
+        (Some(a), Some(b)) => a == b,
+        _ => match (&a.exif.camera_model, &b.exif.camera_model) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        },
+    }
+}
+
+fn has_member(photo: &Photo, raw: bool) -> bool {
+    photo.group.as_ref().is_some_and(|group| {
+        group.members.iter().any(|member| {
+            member.photo_id.is_some() && is_raw_file(Path::new(&member.file_path)) == raw
+        })
+    })
+}
+
+/// Group RAW files that have no rendered counterpart with JPEG/HEIF files
+/// in other folders that have no RAW, matching capture time (within the
+/// tolerance), camera and file number. Each file joins at most one pair;
+/// the closest capture times win. Returns the pairs made.
+fn pair_across_folders(photos: &mut [Photo], options: &PairingOptions) -> Vec<InferredPair> {
+    if !options.cross_folder {
+        return Vec::new();
+    }
+    let tolerance = options.tolerance_ms as i64;
+    let mut raws = Vec::new();
+    let mut renditions = Vec::new();
+    for (index, photo) in photos.iter().enumerate() {
+        let Some(taken) = photo.exif.date_taken else {
+            continue;
+        };
+        let path = Path::new(&photo.file_path);
+        if is_raw_file(path) {
+            if !has_member(photo, false) {
+                raws.push((index, taken));
+            }
+        } else if matches!(photo_role(path), GroupRole::CameraJpeg | GroupRole::Heif)
+            && !has_member(photo, true)
+        {
+            renditions.push((index, taken));
+        }
+    }
+    renditions.sort_by_key(|(_, taken)| *taken);
+
+    let mut candidates = Vec::new();
+    for &(raw, raw_taken) in &raws {
+        let raw_path = Path::new(&photos[raw].file_path);
+        let start = renditions
+            .partition_point(|(_, taken)| (raw_taken - *taken).num_milliseconds() > tolerance);
+        for &(rendition, taken) in &renditions[start..] {
+            let delta = (taken - raw_taken).num_milliseconds();
+            if delta > tolerance {
+                break;
+            }
+            let path = Path::new(&photos[rendition].file_path);
+            if path.parent() == raw_path.parent() || !same_camera(&photos[raw], &photos[rendition])
+            {
+                continue;
+            }
+            if options.match_file_number {
+                if let (Some(a), Some(b)) = (file_number(raw_path), file_number(path)) {
+                    if a != b {
+                        continue;
+                    }
+                }
+            }
+            candidates.push((delta.unsigned_abs(), raw, rendition));
+        }
+    }
+    candidates.sort();
+
+    let index_by_id: HashMap<String, usize> = photos
+        .iter()
+        .enumerate()
+        .map(|(index, photo)| (photo.id.clone(), index))
+        .collect();
+    let mut used = HashSet::new();
+    let mut inferred = Vec::new();
+    for (delta, raw, rendition) in candidates {
+        let mut indices = Vec::new();
+        let mut companions = Vec::new();
+        for index in [raw, rendition] {
+            match &photos[index].group {
+                Some(group) => {
+                    for member in &group.members {
+                        match &member.photo_id {
+                            Some(id) => indices.extend(index_by_id.get(id)),
+                            None => companions.push(member.clone()),
+                        }
+                    }
+                }
+                None => indices.push(index),
+            }
+        }
+        // Every member of both groups joins at most one pair.
+        if indices.iter().any(|index| used.contains(index)) {
+            continue;
+        }
+        used.extend(indices.iter().copied());
+        inferred.push(InferredPair {
+            raw_id: photos[raw].id.clone(),
+            photo_id: photos[rendition].id.clone(),
+            raw_path: photos[raw].file_path.clone(),
+            photo_path: photos[rendition].file_path.clone(),
+            time_delta_ms: delta,
+        });
+        assign_group(photos, indices, companions, true);
+    }
+    inferred
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{photo_from_exif, ExifData};
+    use chrono::{DateTime, Duration, Utc};
+
+    fn shot(path: &str, taken: DateTime<Utc>) -> Photo {
+        let exif = ExifData {
+            date_taken: Some(taken),
+            camera_model: Some("X-T5".to_string()),
+            ..ExifData::default()
+        };
+        photo_from_exif(Path::new(path), path.to_string(), 0, taken, exif)
+    }
+
+    fn pairs(photos: &mut [Photo], options: &PairingOptions) -> Vec<(String, String)> {
+        group_library(photos, options)
+            .into_iter()
+            .map(|pair| (pair.raw_id, pair.photo_id))
+            .collect()
+    }
+
+    #[test]
+    fn file_number_is_the_trailing_counter() {
+        assert_eq!(file_number(Path::new("/a/DSC_1234.NEF")), Some(1234));
+        assert_eq!(file_number(Path::new("/b/_DSC1234.JPG")), Some(1234));
+        assert_eq!(file_number(Path::new("/b/IMG_0007-edit.JPG")), None);
+        assert_eq!(file_number(Path::new("/b/cover.jpg")), None);
+    }
+
+    #[test]
+    fn pairs_across_folders_within_the_tolerance() {
+        let taken = Utc::now();
+        let options = PairingOptions {
+            cross_folder: true,
+            ..PairingOptions::default()
+        };
+        let raw = "/slot1/DSCF0001.RAF";
+        let jpeg = "/slot2/DSCF0001.JPG";
+
+        let mut photos = vec![
+            shot(raw, taken),
+            shot(jpeg, taken + Duration::milliseconds(900)),
+        ];
+        assert_eq!(
+            pairs(&mut photos, &options),
+            vec![(raw.to_string(), jpeg.to_string())]
+        );
+        assert!(photos[0].group.as_ref().is_some_and(|group| group.inferred));
+
+        let mut photos = vec![
+            shot(raw, taken),
+            shot(jpeg, taken - Duration::milliseconds(1001)),
+        ];
+        assert!(pairs(&mut photos, &options).is_empty());
+        let wide = PairingOptions {
+            tolerance_ms: 2000,
+            ..options.clone()
+        };
+        assert_eq!(pairs(&mut photos, &wide).len(), 1);
+
+        // Pairing is off unless chosen, and never pairs within one folder.
+        assert!(pairs(&mut photos, &PairingOptions::default()).is_empty());
+        let mut photos = vec![shot(raw, taken), shot("/slot1/DSCF0001b.JPG", taken)];
+        assert!(pairs(&mut photos, &options).is_empty());
+    }
+
+    #[test]
+    fn file_numbers_and_closest_times_decide_between_candidates() {
+        let taken = Utc::now();
+        let options = PairingOptions {
+            cross_folder: true,
+            ..PairingOptions::default()
+        };
+        let raw = "/slot1/DSCF0002.RAF";
+        let mut photos = vec![
+            shot(raw, taken),
+            shot("/slot2/DSCF0003.JPG", taken),
+            shot("/slot2/DSCF0002.JPG", taken + Duration::milliseconds(500)),
+        ];
+        assert_eq!(
+            pairs(&mut photos, &options),
+            vec![(raw.to_string(), "/slot2/DSCF0002.JPG".to_string())]
+        );
+
+        let any_number = PairingOptions {
+            match_file_number: false,
+            ..options
+        };
+        assert_eq!(
+            pairs(&mut photos, &any_number),
+            vec![(raw.to_string(), "/slot2/DSCF0003.JPG".to_string())]
+        );
+    }
+}
@@ -0,0 +1,324 @@
+use crate::raw_preview::is_raw_file;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes read from the start of a file: enough for container headers and
+/// the XMP packet that flags a motion photo.
+const HEAD_BYTES: u64 = 64 * 1024;
+/// Bytes read from the end of a file when looking for its end marker.
+/// Some cameras pad files after the marker.
+const TAIL_BYTES: u64 = 4 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthStatus {
+    #[default]
+    Ok,
+    /// The file ends early, typically an interrupted copy off the card.
+    Truncated,
+    /// The file is complete but its contents are not a valid image.
+    Corrupt,
+    /// The file could not be read at all.
+    Unreadable,
+}
+
+/// Result of validating a file during scan. Damaged files stay in the
+/// library so they can be found and re-copied, but are kept out of
+/// thumbnail and render pipelines.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct FileHealth {
+    pub status: HealthStatus,
+    pub reason: Option<String>,
+}
+
+impl FileHealth {
+    pub fn damaged(status: HealthStatus, reason: impl Into<String>) -> Self {
+        Self {
+            status,
+            reason: Some(reason.into()),
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status == HealthStatus::Ok
+    }
+}
+
+/// A damaged file as listed for re-copying.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DamagedFile {
+    pub photo_id: String,
+    pub file_path: String,
+    pub file_size: u64,
+    pub health: FileHealth,
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn read_head_and_tail(path: &Path) -> std::io::Result<(u64, Vec<u8>, Vec<u8>)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(HEAD_BYTES).read_to_end(&mut head)?;
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    file.read_to_end(&mut tail)?;
+    Ok((len, head, tail))
+}
+
+/// Check a file's container structure from its first and last bytes: JPEG
+/// start/end markers, the PNG signature and end chunk, WebP and HEIF
+/// headers, and TIFF headers
+/// (which most RAW formats share). RAW files are additionally opened with
+/// LibRaw while their metadata is read.
+pub fn check(path: &Path) -> FileHealth {
+    let (len, head, tail) = match read_head_and_tail(path) {
+        Ok(contents) => contents,
+        Err(error) => return FileHealth::damaged(HealthStatus::Unreadable, error.to_string()),
+    };
+    if len == 0 {
+        return FileHealth::damaged(HealthStatus::Truncated, "File is empty");
+    }
+    if head.iter().take(512).all(|byte| *byte == 0) {
+        return FileHealth::damaged(HealthStatus::Corrupt, "File starts with zeroed data");
+    }
+
+    match extension(path).as_str() {
+        "jpg" | "jpeg" => check_jpeg(&head, &tail),
+        "png" => check_png(&head, &tail),
+        "webp" => check_webp(&head, len),
+        "heic" | "heif" | "hif" | "avif" => check_heif(&head),
+        "tif" | "tiff" => check_tiff(&head, len)
+            .unwrap_or_else(|| FileHealth::damaged(HealthStatus::Corrupt, "Missing TIFF header")),
+        // RAW formats without a TIFF header (CR3, RAF, X3F, ...) are left
+        // to LibRaw.
+        _ if is_raw_file(path) => check_tiff(&head, len).unwrap_or_default(),
+        _ => FileHealth::default(),
+    }
+}
+
+fn check_jpeg(head: &[u8], tail: &[u8]) -> FileHealth {
+    if !head.starts_with(&[0xFF, 0xD8]) {
+        return FileHealth::damaged(HealthStatus::Corrupt, "Missing JPEG start-of-image marker");
+    }
+    // Motion photos append a video after the image, so their last bytes are
+    // not the JPEG end marker.
+    let motion_photo = contains(head, b"MotionPhoto") || contains(head, b"MicroVideo");
+    if !motion_photo && !contains(tail, &[0xFF, 0xD9]) {
+        return FileHealth::damaged(HealthStatus::Truncated, "Missing JPEG end-of-image marker");
+    }
+    FileHealth::default()
+}
+
+fn check_png(head: &[u8], tail: &[u8]) -> FileHealth {
+    if !head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return FileHealth::damaged(HealthStatus::Corrupt, "Missing PNG signature");
+    }
+    if !contains(tail, b"IEND") {
+        return FileHealth::damaged(HealthStatus::Truncated, "Missing PNG end chunk");
+    }
+    FileHealth::default()
+}
+
+fn check_webp(head: &[u8], len: u64) -> FileHealth {
+    if !head.starts_with(b"RIFF") || head.get(8..12) != Some(b"WEBP".as_slice()) {
+        return FileHealth::damaged(HealthStatus::Corrupt, "Missing WebP header");
+    }
+    // The RIFF size covers everything after the first eight bytes.
+    let declared = head[4..8].try_into().map(u32::from_le_bytes).unwrap_or(0);
+    if u64::from(declared) + 8 > len {
+        return FileHealth::damaged(HealthStatus::Truncated, "WebP data ends early");
+    }
+    FileHealth::default()
+}
+
+fn check_heif(head: &[u8]) -> FileHealth {
+    if head.get(4..8) != Some(b"ftyp".as_slice()) {
+        return FileHealth::damaged(HealthStatus::Corrupt, "Missing HEIF file type box");
+    }
+    FileHealth::default()
+}
+
+/// `None` when the file has no TIFF header; otherwise whether the first IFD
+/// it points to lies within the file.
+fn check_tiff(head: &[u8], len: u64) -> Option<FileHealth> {
+    let offset_bytes: [u8; 4] = head.get(4..8)?.try_into().ok()?;
+    let first_ifd = match head.get(..4)? {
+        b"II*\0" => u32::from_le_bytes(offset_bytes),
+        b"MM\0*" => u32::from_be_bytes(offset_bytes),
+        _ => return None,
+    };
+    let health = if first_ifd < 8 {
+        FileHealth::damaged(
+            HealthStatus::Corrupt,
+            format!("Invalid first IFD offset {first_ifd}"),
+        )
+    } else if u64::from(first_ifd) >= len {
+        FileHealth::damaged(
+            HealthStatus::Truncated,
+            format!("First IFD at offset {first_ifd} lies beyond the end of the file"),
+        )
+    } else {
+        FileHealth::default()
+    };
+    Some(health)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Every file indexed as damaged across all roots of the catalog.
+pub fn damaged_files(conn: &rusqlite::Connection) -> Result<Vec<DamagedFile>, String> {
+    let mut statement = conn
+        .prepare(
+            "SELECT photo_id, file_path, file_size, health_json FROM scan_index
+             WHERE health_json IS NOT NULL ORDER BY file_path",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u64>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .flatten()
+        .filter_map(|(photo_id, file_path, file_size, health_json)| {
+            let health: FileHealth = serde_json::from_str(&health_json).ok()?;
+            (!health.is_ok()).then_some(DamagedFile {
+                photo_id,
+                file_path,
+                file_size,
+                health,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hologram-health-{}-{name}", std::process::id()))
+    }
+
+    /// The status `check` gives a file `name` holding `bytes`.
+    fn status(name: &str, bytes: &[u8]) -> HealthStatus {
+        let path = temp_path(name);
+        std::fs::write(&path, bytes).unwrap();
+        let health = check(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(health.reason.is_some(), !health.is_ok(), "{name}");
+        health.status
+    }
+
+    fn bytes(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn unreadable_empty_and_zeroed_files_are_damaged() {
+        assert_eq!(
+            check(&temp_path("missing.jpg")).status,
+            HealthStatus::Unreadable
+        );
+        assert_eq!(status("empty.jpg", b""), HealthStatus::Truncated);
+        let zeroed = bytes(&[&[0; 512], b"\xFF\xD8 data \xFF\xD9"]);
+        assert_eq!(status("zeroed.jpg", &zeroed), HealthStatus::Corrupt);
+        let late_zeroes = bytes(&[b"\xFF\xD8", &[0; 1024], b"\xFF\xD9"]);
+        assert_eq!(status("late-zeroes.jpg", &late_zeroes), HealthStatus::Ok);
+        // Formats without rules pass.
+        assert_eq!(status("clip.mov", b"anything"), HealthStatus::Ok);
+    }
+
+    #[test]
+    fn jpegs_need_both_markers_unless_a_video_follows() {
+        assert_eq!(
+            status("ok.jpg", b"\xFF\xD8 data \xFF\xD9"),
+            HealthStatus::Ok
+        );
+        // Padding after the end marker is fine.
+        let padded = bytes(&[b"\xFF\xD8 data \xFF\xD9", &[0; 100]]);
+        assert_eq!(status("padded.JPEG", &padded), HealthStatus::Ok);
+        assert_eq!(status("cut.jpg", b"\xFF\xD8 data"), HealthStatus::Truncated);
+        assert_eq!(status("bad.jpg", b"GIF89a \xFF\xD9"), HealthStatus::Corrupt);
+        for motion in ["MotionPhoto", "MicroVideo"] {
+            let photo = bytes(&[b"\xFF\xD8 ", motion.as_bytes(), b" \xFF\xD9 ftypmp42 video"]);
+            assert_eq!(status("motion.jpg", &photo), HealthStatus::Ok, "{motion}");
+        }
+    }
+
+    #[test]
+    fn pngs_need_their_signature_and_end_chunk() {
+        let signature: &[u8] = b"\x89PNG\r\n\x1a\n";
+        let complete = bytes(&[signature, b"IHDR data IEND\xAEB`\x82"]);
+        assert_eq!(status("ok.png", &complete), HealthStatus::Ok);
+        let cut = bytes(&[signature, b"IHDR data"]);
+        assert_eq!(status("cut.png", &cut), HealthStatus::Truncated);
+        assert_eq!(status("bad.png", b"PNG IEND"), HealthStatus::Corrupt);
+    }
+
+    #[test]
+    fn webp_data_must_reach_the_riff_size() {
+        let webp = |declared: u32, payload: &[u8]| {
+            bytes(&[b"RIFF", &declared.to_le_bytes(), b"WEBP", payload])
+        };
+        assert_eq!(status("ok.webp", &webp(8, b"VP8 data")), HealthStatus::Ok);
+        assert_eq!(
+            status("cut.webp", &webp(100, b"VP8 data")),
+            HealthStatus::Truncated
+        );
+        let not_webp = bytes(&[b"RIFF", &8u32.to_le_bytes(), b"WAVE", b"fmt data"]);
+        assert_eq!(status("bad.webp", &not_webp), HealthStatus::Corrupt);
+    }
+
+    #[test]
+    fn heif_needs_a_file_type_box() {
+        let heif = bytes(&[&24u32.to_be_bytes(), b"ftypheic", &[0; 16]]);
+        assert_eq!(status("ok.heic", &heif), HealthStatus::Ok);
+        assert_eq!(status("ok.avif", &heif), HealthStatus::Ok);
+        let bad = bytes(&[&24u32.to_be_bytes(), b"moovheic", &[0; 16]]);
+        assert_eq!(status("bad.heic", &bad), HealthStatus::Corrupt);
+    }
+
+    #[test]
+    fn the_first_tiff_ifd_must_lie_in_the_file() {
+        let little = bytes(&[b"II*\0", &8u32.to_le_bytes(), &[1; 32]]);
+        assert_eq!(status("ok.tif", &little), HealthStatus::Ok);
+        let big = bytes(&[b"MM\0*", &8u32.to_be_bytes(), &[1; 32]]);
+        assert_eq!(status("ok.tiff", &big), HealthStatus::Ok);
+        let beyond = bytes(&[b"MM\0*", &4096u32.to_be_bytes(), &[1; 32]]);
+        assert_eq!(status("cut.tif", &beyond), HealthStatus::Truncated);
+        let inside_header = bytes(&[b"II*\0", &4u32.to_le_bytes(), &[1; 32]]);
+        assert_eq!(status("bad.tif", &inside_header), HealthStatus::Corrupt);
+        assert_eq!(
+            status("none.tif", b"not a tiff at all"),
+            HealthStatus::Corrupt
+        );
+
+        // RAW files share the rule, but ones without a TIFF header are left
+        // to LibRaw.
+        assert_eq!(status("ok.nef", &little), HealthStatus::Ok);
+        assert_eq!(status("cut.nef", &beyond), HealthStatus::Truncated);
+        assert_eq!(
+            status("ok.cr3", b"\0\0\0\x18ftypcrx data"),
+            HealthStatus::Ok
+        );
+    }
+}
@@ -0,0 +1,262 @@
+use crate::jobs::{JobHandle, JOB_CANCELLED};
+use crate::metadata_history;
+use crate::{open_cache_db, stable_photo_id};
+use exif::{In, Reader, Tag};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+/// Bytes hashed from each end of a file for its content key. Enough to cover
+/// the EXIF header and the tail of the image data without reading whole RAWs.
+const CONTENT_SAMPLE_LEN: u64 = 64 * 1024;
+const IDENTITY_SCHEME_KEY: &str = "identity_scheme";
+
+/// How photo IDs are derived for a catalog.
+/// - `path`: UUIDv5 of the canonical path (the original scheme); files are
+///   not sampled, so a moved file is indexed as a new photo
+/// - `content`: UUIDv5 of the file's content key, so IDs survive renames
+///   and moves
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentityScheme {
+    #[default]
+    Path,
+    Content,
+}
+
+/// A photo whose metadata moved from `old_id` to `new_id` because the same
+/// image reappeared at `file_path`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relink {
+    pub old_id: String,
+    pub new_id: String,
+    pub file_path: String,
+}
+
+pub fn load_scheme(conn: &rusqlite::Connection) -> IdentityScheme {
+    conn.query_row(
+        "SELECT value FROM catalog_settings WHERE key = ?1",
+        [IDENTITY_SCHEME_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .map(|value| match value.as_str() {
+        "content" => IdentityScheme::Content,
+        _ => IdentityScheme::Path,
+    })
+    .unwrap_or_default()
+}
+
+pub fn save_scheme(conn: &rusqlite::Connection, scheme: IdentityScheme) -> Result<(), String> {
+    let value = match scheme {
+        IdentityScheme::Path => "path",
+        IdentityScheme::Content => "content",
+    };
+    conn.execute(
+        "INSERT INTO catalog_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [IDENTITY_SCHEME_KEY, value],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Fast partial fingerprint of a file: its size, the first and last
+/// `CONTENT_SAMPLE_LEN` bytes, and the capture time and body serial when the
+/// file has EXIF. Only depends on the bytes, so it is the same wherever the
+/// file is moved.
+pub fn content_key(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let file_size = file.metadata().ok()?.len();
+    let mut hasher = Sha256::new();
+    hasher.update(file_size.to_le_bytes());
+
+    let mut head = Vec::new();
+    (&mut file)
+        .take(CONTENT_SAMPLE_LEN)
+        .read_to_end(&mut head)
+        .ok()?;
+    hasher.update(&head);
+    if file_size > CONTENT_SAMPLE_LEN {
+        let tail_start = (file_size - CONTENT_SAMPLE_LEN).max(CONTENT_SAMPLE_LEN);
+        file.seek(SeekFrom::Start(tail_start)).ok()?;
+        let mut tail = Vec::new();
+        file.read_to_end(&mut tail).ok()?;
+        hasher.update(&tail);
+    }
+
+    // Two bursts from identical bodies can share a size and sampled bytes;
+    // the capture time and serial tell them apart.
+    file.seek(SeekFrom::Start(0)).ok()?;
+    if let Ok(exif) = Reader::new().read_from_container(&mut std::io::BufReader::new(&file)) {
+        for tag in [Tag::DateTimeOriginal, Tag::BodySerialNumber] {
+            if let Some(field) = exif.get_field(tag, In::PRIMARY) {
+                hasher.update(field.display_value().to_string().as_bytes());
+            }
+            hasher.update([0]);
+        }
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// The ID `scheme` assigns to the file at `path` with `content_key`. Files
+/// whose content could not be read fall back to the path ID.
+pub fn photo_id(scheme: IdentityScheme, path: &Path, content_key: Option<&str>) -> String {
+    match (scheme, content_key) {
+        (IdentityScheme::Content, Some(key)) => {
+            Uuid::new_v5(&Uuid::NAMESPACE_OID, format!("hologram:{key}").as_bytes()).to_string()
+        }
+        _ => stable_photo_id(path),
+    }
+}
+
+/// Move everything the catalog stores under `old_id` to `new_id`, including
+/// the undo journal. Rows that already exist for `new_id` win.
+pub fn migrate_photo_id(
+    conn: &rusqlite::Connection,
+    old_id: &str,
+    new_id: &str,
+) -> Result<(), String> {
+    // Tags belong to the metadata row: when `new_id` keeps its own, the old
+    // tags go with the old row.
+    let new_has_metadata = conn
+        .prepare("SELECT 1 FROM photo_metadata WHERE photo_id = ?1")
+        .and_then(|mut statement| statement.exists([new_id]))
+        .map_err(|e| e.to_string())?;
+    if new_has_metadata {
+        conn.execute("DELETE FROM photo_tags WHERE photo_id = ?1", [old_id])
+            .map_err(|e| e.to_string())?;
+    }
+    for table in ["photo_metadata", "photo_tags", "capture_time_shifts"] {
+        conn.execute(
+            &format!("UPDATE OR IGNORE {table} SET photo_id = ?2 WHERE photo_id = ?1"),
+            [old_id, new_id],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            &format!("DELETE FROM {table} WHERE photo_id = ?1"),
+            [old_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    metadata_history::rename_photo(conn, old_id, new_id)
+}
+
+/// Switch the catalog to `scheme` and re-derive the ID of every indexed
+/// file, migrating its metadata to the new ID. Files indexed before content
+/// keys existed are sampled now. Nothing changes if the job is cancelled.
+pub fn switch_scheme(
+    conn: &mut rusqlite::Connection,
+    scheme: IdentityScheme,
+    job: &JobHandle,
+) -> Result<Vec<Relink>, String> {
+    let rows: Vec<(String, String, Option<String>)> = {
+        let mut statement = conn
+            .prepare("SELECT file_path, photo_id, content_key FROM scan_index ORDER BY file_path")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| e.to_string())?;
+        rows.flatten().collect()
+    };
+
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    save_scheme(&transaction, scheme)?;
+    let total = rows.len();
+    let mut assigned = HashSet::new();
+    let mut relinks = Vec::new();
+    for (idx, (file_path, old_id, key)) in rows.into_iter().enumerate() {
+        if job.is_cancelled() {
+            return Err(JOB_CANCELLED.to_string());
+        }
+        job.progress(idx, total);
+        let path = Path::new(&file_path);
+        let key = key.or_else(|| content_key(path));
+        let mut new_id = photo_id(scheme, path, key.as_deref());
+        // Duplicate copies keep path IDs, as during a scan.
+        if !assigned.insert(new_id.clone()) {
+            new_id = stable_photo_id(path);
+        }
+        transaction
+            .execute(
+                "UPDATE scan_index SET photo_id = ?2, content_key = ?3 WHERE file_path = ?1",
+                rusqlite::params![file_path, new_id, key],
+            )
+            .map_err(|e| e.to_string())?;
+        if new_id != old_id {
+            migrate_photo_id(&transaction, &old_id, &new_id)?;
+            relinks.push(Relink {
+                old_id,
+                new_id,
+                file_path,
+            });
+        }
+    }
+    transaction.commit().map_err(|e| e.to_string())?;
+    Ok(relinks)
+}
+
+/// Carry cached thumbnails and offline previews over to relinked photos so
+/// a moved folder does not regenerate them. The caches check the file path,
+/// so it moves too.
+pub fn relink_thumbnail_cache(app: &AppHandle, folder_path: Option<&str>, relinks: &[Relink]) {
+    if relinks.is_empty() {
+        return;
+    }
+    let Ok(mut conn) = open_cache_db(app, folder_path) else {
+        return;
+    };
+    let Ok(transaction) = conn.transaction() else {
+        return;
+    };
+    for relink in relinks {
+        for table in ["thumbnail_cache", "preview_cache"] {
+            let _ = transaction.execute(
+                &format!(
+                    "UPDATE OR IGNORE {table} SET photo_id = ?2, file_path = ?3
+                     WHERE photo_id = ?1"
+                ),
+                [&relink.old_id, &relink.new_id, &relink.file_path],
+            );
+        }
+    }
+    let _ = transaction.commit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata_history::MetadataPatch;
+    use crate::{read_photo_metadata, write_photo_metadata, PhotoMetadata};
+
+    #[test]
+    fn undo_after_a_relink_restores_the_new_id() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        let rated = PhotoMetadata {
+            rating: 1,
+            ..PhotoMetadata::default()
+        };
+        write_photo_metadata(&conn, "old", &rated).unwrap();
+        let patch = MetadataPatch {
+            rating: Some(4),
+            ..MetadataPatch::default()
+        };
+        metadata_history::apply_batch(&mut conn, &["old".to_string()], &patch, "Rate").unwrap();
+
+        migrate_photo_id(&conn, "old", "new").unwrap();
+        let step = metadata_history::undo(&mut conn).unwrap().unwrap();
+        assert!(step.metadata.contains_key("new"));
+        let metadata = read_photo_metadata(&conn, &["old".to_string(), "new".to_string()]);
+        assert_eq!(metadata.get("new").map(|metadata| metadata.rating), Some(1));
+        assert!(!metadata.contains_key("old"));
+
+        metadata_history::redo(&mut conn).unwrap().unwrap();
+        let metadata = read_photo_metadata(&conn, &["new".to_string()]);
+        assert_eq!(metadata["new"].rating, 4);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::jobs::{JobHandle, JOB_CANCELLED};
+use crate::{
+    collect_photo_metadata, discover_supported_files, export_file_name, export_relative_dir,
+    open_db, unique_path, ScanProgress,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestOptions {
+    pub source_path: String,
+    pub destination_path: String,
+    pub backup_path: Option<String>,
+    pub organize_by: String,
+    pub rename_pattern: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestFailure {
+    pub file_path: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestResult {
+    pub copied_count: usize,
+    pub skipped_count: usize,
+    pub failed: Vec<IngestFailure>,
+    pub destination_path: String,
+    pub backup_path: Option<String>,
+}
+
+const COPY_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// Feed everything `reader` yields to `sink` while hashing it, one buffer at a
+/// time, and return the hex SHA-256 of what was read.
+fn stream_hashed<R: Read>(
+    mut reader: R,
+    mut sink: impl FnMut(&[u8]) -> std::io::Result<()>,
+) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; COPY_BUFFER_SIZE];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..read]);
+        sink(&buffer[..read])?;
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn hash_file(path: &Path) -> Result<String, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    stream_hashed(BufReader::new(file), |_| Ok(())).map_err(|e| e.to_string())
+}
+
+/// Stream `source` to `destination` with the source's modification time,
+/// hashing as it goes, then read the copy back and compare checksums. A copy
+/// that fails verification is removed so it can never be mistaken for a good
+/// one.
+fn copy_verified(
+    source: &Path,
+    hash: &str,
+    destination: &Path,
+    modified: Option<std::time::SystemTime>,
+) -> Result<(), String> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let reader = BufReader::new(fs::File::open(source).map_err(|e| e.to_string())?);
+    let mut file = fs::File::create(destination).map_err(|e| e.to_string())?;
+    let copied = stream_hashed(reader, |chunk| file.write_all(chunk));
+    let copied = copied.and_then(|copied| file.sync_all().map(|_| copied));
+    if let Some(modified) = modified {
+        let _ = file.set_modified(modified);
+    }
+    drop(file);
+
+    // A streamed hash that differs means the source changed since it was
+    // checked against the ingest history; otherwise read the copy back.
+    let verified = copied.map_err(|e| e.to_string()).and_then(|copied| {
+        if copied == hash {
+            hash_file(destination)
+        } else {
+            Ok(copied)
+        }
+    });
+    if verified.as_deref() != Ok(hash) {
+        let _ = fs::remove_file(destination);
+        return Err(verified.err().unwrap_or_else(|| {
+            format!(
+                "Checksum mismatch after copying to {}",
+                destination.display()
+            )
+        }));
+    }
+    Ok(())
+}
+
+fn ingested_hashes(conn: &rusqlite::Connection) -> HashSet<String> {
+    let Ok(mut statement) = conn.prepare("SELECT content_hash FROM ingest_history") else {
+        return HashSet::new();
+    };
+    statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map(|rows| rows.flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Copy every supported file under `source_path` into the destination and,
+/// concurrently, the optional backup, organized and renamed like an export.
+/// Files whose content hash was ingested before, by this or an earlier run,
+/// are skipped.
+pub fn ingest(
+    app: &AppHandle,
+    options: &IngestOptions,
+    job: &JobHandle,
+) -> Result<IngestResult, String> {
+    let destination = PathBuf::from(&options.destination_path);
+    fs::create_dir_all(&destination).map_err(|e| e.to_string())?;
+    let backup = options
+        .backup_path
+        .as_deref()
+        .filter(|path| !path.trim().is_empty())
+        .map(PathBuf::from);
+    if let Some(backup) = &backup {
+        fs::create_dir_all(backup).map_err(|e| e.to_string())?;
+    }
+
+    let conn = open_db(app, Some(&options.destination_path))?;
+    let mut known_hashes = ingested_hashes(&conn);
+    let sources = discover_supported_files(&options.source_path);
+    let total = sources.len();
+
+    let mut result = IngestResult {
+        copied_count: 0,
+        skipped_count: 0,
+        failed: Vec::new(),
+        destination_path: options.destination_path.clone(),
+        backup_path: backup
+            .as_ref()
+            .map(|path| path.to_string_lossy().to_string()),
+    };
+
+    for (idx, source) in sources.iter().enumerate() {
+        if job.is_cancelled() {
+            return Err(JOB_CANCELLED.to_string());
+        }
+        job.progress(idx, total);
+        let source_display = source.to_string_lossy().to_string();
+        let _ = app.emit(
+            "ingest-progress",
+            &ScanProgress {
+                current: idx + 1,
+                total,
+                percentage: (idx + 1) as f64 / total.max(1) as f64 * 100.0,
+                current_file: Some(source_display.clone()),
+            },
+        );
+
+        // Hash the card file first so already ingested files are skipped
+        // without copying; the copies are hashed again as they stream.
+        let hash = match hash_file(source) {
+            Ok(hash) => hash,
+            Err(error) => {
+                result.failed.push(IngestFailure {
+                    file_path: source_display,
+                    error,
+                });
+                continue;
+            }
+        };
+        if known_hashes.contains(&hash) {
+            result.skipped_count += 1;
+            continue;
+        }
+        let Some(photo) = collect_photo_metadata(source) else {
+            result.failed.push(IngestFailure {
+                file_path: source_display,
+                error: "Could not read file metadata".to_string(),
+            });
+            continue;
+        };
+
+        let relative = export_relative_dir(&photo, &options.organize_by).join(export_file_name(
+            &photo,
+            idx,
+            options.rename_pattern.as_deref(),
+        ));
+        let modified = fs::metadata(source)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let primary = unique_path(destination.join(&relative));
+        let backup_copy = backup
+            .as_ref()
+            .map(|backup| unique_path(backup.join(&relative)));
+        let copied = std::thread::scope(|scope| {
+            let backup_job = backup_copy
+                .as_ref()
+                .map(|path| scope.spawn(|| copy_verified(source, &hash, path, modified)));
+            let primary_copy = copy_verified(source, &hash, &primary, modified);
+            let backup_copy = backup_job.map_or(Ok(()), |job| {
+                job.join()
+                    .unwrap_or_else(|_| Err("Backup copy failed".to_string()))
+            });
+            primary_copy.and(backup_copy)
+        });
+        if let Err(error) = copied {
+            // Leave no half-ingested file behind so a rerun starts clean.
+            let _ = fs::remove_file(&primary);
+            if let Some(path) = &backup_copy {
+                let _ = fs::remove_file(path);
+            }
+            result.failed.push(IngestFailure {
+                file_path: source_display,
+                error,
+            });
+            continue;
+        }
+
+        // The copy is already verified on disk, so a history failure only
+        // means a later run may copy this file again; keep going.
+        if let Err(e) = conn.execute(
+            "INSERT INTO ingest_history (content_hash, source_path, destination_path, ingested_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(content_hash) DO NOTHING",
+            rusqlite::params![
+                hash,
+                source_display,
+                primary.to_string_lossy().to_string(),
+                Utc::now().to_rfc3339(),
+            ],
+        ) {
+            eprintln!("Failed to record ingest history for {source_display}: {e}");
+        }
+        known_hashes.insert(hash);
+        result.copied_count += 1;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streams_a_verified_copy() {
+        let dir = std::env::temp_dir().join(format!("hologram-ingest-{}", std::process::id()));
+        let source = dir.join("card").join("IMG_0001.JPG");
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        // Larger than one buffer so the copy takes several reads.
+        let data: Vec<u8> = (0..COPY_BUFFER_SIZE * 2 + 17).map(|i| i as u8).collect();
+        fs::write(&source, &data).unwrap();
+
+        let hash = hash_file(&source).unwrap();
+        assert_eq!(hash, format!("{:x}", Sha256::digest(&data)));
+
+        let destination = dir.join("library").join("2024").join("IMG_0001.JPG");
+        copy_verified(&source, &hash, &destination, None).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), data);
+
+        let stale = dir.join("library").join("stale.JPG");
+        assert!(copy_verified(&source, "0", &stale, None).is_err());
+        assert!(!stale.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
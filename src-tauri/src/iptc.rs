@@ -0,0 +1,426 @@
+use serde::{Deserialize, Serialize};
+
+/// Photoshop image resource holding the IPTC-IIM block.
+const IPTC_RESOURCE_ID: u16 = 0x0404;
+/// Envelope dataset 1:90 value declaring UTF-8 text (ESC % G).
+const UTF8_CHARSET: &[u8] = b"\x1b%G";
+
+/// IPTC Core and Extension fields kept in the catalog for each photo and
+/// written to XMP sidecars.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IptcMetadata {
+    pub headline: String,
+    pub caption: String,
+    pub creator: String,
+    /// The creator's work contact details (IPTC Creator's Contact Info).
+    pub contact_email: String,
+    pub contact_phone: String,
+    pub contact_url: String,
+    pub credit_line: String,
+    pub copyright_notice: String,
+    pub usage_terms: String,
+    pub city: String,
+    pub state: String,
+    pub country: String,
+    pub event: String,
+    pub person_shown: Vec<String>,
+}
+
+/// A batch edit of `IptcMetadata`: only the fields present are changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IptcPatch {
+    pub headline: Option<String>,
+    pub caption: Option<String>,
+    pub creator: Option<String>,
+    pub contact_email: Option<String>,
+    pub contact_phone: Option<String>,
+    pub contact_url: Option<String>,
+    pub credit_line: Option<String>,
+    pub copyright_notice: Option<String>,
+    pub usage_terms: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub country: Option<String>,
+    pub event: Option<String>,
+    pub person_shown: Option<Vec<String>>,
+}
+
+impl IptcPatch {
+    /// The patch with `f` applied to each value it sets.
+    pub fn map_text(mut self, f: impl Fn(&str) -> String) -> Self {
+        let fields = [
+            &mut self.headline,
+            &mut self.caption,
+            &mut self.creator,
+            &mut self.contact_email,
+            &mut self.contact_phone,
+            &mut self.contact_url,
+            &mut self.credit_line,
+            &mut self.copyright_notice,
+            &mut self.usage_terms,
+            &mut self.city,
+            &mut self.state,
+            &mut self.country,
+            &mut self.event,
+        ];
+        for value in fields.into_iter().flatten() {
+            *value = f(value);
+        }
+        for person in self.person_shown.iter_mut().flatten() {
+            *person = f(person);
+        }
+        self
+    }
+}
+
+impl IptcMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&mut self, patch: IptcPatch) {
+        let fields = [
+            (&mut self.headline, patch.headline),
+            (&mut self.caption, patch.caption),
+            (&mut self.creator, patch.creator),
+            (&mut self.contact_email, patch.contact_email),
+            (&mut self.contact_phone, patch.contact_phone),
+            (&mut self.contact_url, patch.contact_url),
+            (&mut self.credit_line, patch.credit_line),
+            (&mut self.copyright_notice, patch.copyright_notice),
+            (&mut self.usage_terms, patch.usage_terms),
+            (&mut self.city, patch.city),
+            (&mut self.state, patch.state),
+            (&mut self.country, patch.country),
+            (&mut self.event, patch.event),
+        ];
+        for (field, value) in fields {
+            if let Some(value) = value {
+                *field = value.trim().to_string();
+            }
+        }
+        if let Some(people) = patch.person_shown {
+            self.person_shown = people
+                .into_iter()
+                .map(|person| person.trim().to_string())
+                .filter(|person| !person.is_empty())
+                .collect();
+        }
+    }
+}
+
+/// One IPTC-IIM dataset: record, dataset number and value bytes.
+pub struct Dataset<'a> {
+    pub record: u8,
+    pub number: u8,
+    pub data: &'a [u8],
+}
+
+/// The IIM block of the Photoshop image resources (`8BIM` blocks) found in
+/// `data`, which is where JPEG APP13 segments and TIFF files keep it.
+pub fn find_iim(data: &[u8]) -> Option<&[u8]> {
+    let marker = [
+        b'8',
+        b'B',
+        b'I',
+        b'M',
+        (IPTC_RESOURCE_ID >> 8) as u8,
+        IPTC_RESOURCE_ID as u8,
+    ];
+    let start = data
+        .windows(marker.len())
+        .position(|window| window == marker)?;
+    let resource = &data[start + marker.len()..];
+    // A Pascal-string name, padded to an even length, then the size.
+    let name_len = usize::from(*resource.first()?);
+    let size_at = (name_len + 2) & !1;
+    let size = u32::from_be_bytes(resource.get(size_at..size_at + 4)?.try_into().ok()?) as usize;
+    resource.get(size_at + 4..size_at + 4 + size)
+}
+
+/// The datasets of an IIM block, in file order. Parsing stops at the first
+/// byte that is not a dataset tag marker.
+pub fn datasets(iim: &[u8]) -> Vec<Dataset<'_>> {
+    let mut datasets = Vec::new();
+    let mut at = 0;
+    while let [0x1C, record, number, high, low, ..] = iim[at..] {
+        let mut length = usize::from(u16::from_be_bytes([high, low]));
+        let mut data_at = at + 5;
+        // Extended datasets store the length in the next `length & 0x7FFF`
+        // bytes.
+        if length & 0x8000 != 0 {
+            let count = length & 0x7FFF;
+            let Some(bytes) = iim.get(data_at..data_at + count) else {
+                break;
+            };
+            let Some(extended) = bytes.iter().try_fold(0usize, |total, byte| {
+                total.checked_mul(256)?.checked_add(usize::from(*byte))
+            }) else {
+                break;
+            };
+            length = extended;
+            data_at += count;
+        }
+        // Lengths come from the file, so the end may lie past any buffer.
+        let Some(end) = data_at.checked_add(length) else {
+            break;
+        };
+        let Some(data) = iim.get(data_at..end) else {
+            break;
+        };
+        datasets.push(Dataset {
+            record,
+            number,
+            data,
+        });
+        at = end;
+    }
+    datasets
+}
+
+/// Whether the envelope declares UTF-8 text; older files are Latin-1.
+pub fn is_utf8(datasets: &[Dataset]) -> bool {
+    datasets
+        .iter()
+        .any(|dataset| dataset.record == 1 && dataset.number == 90 && dataset.data == UTF8_CHARSET)
+}
+
+/// The text of a dataset.
+pub fn text(data: &[u8], utf8: bool) -> String {
+    let text = if utf8 {
+        String::from_utf8_lossy(data).into_owned()
+    } else {
+        match std::str::from_utf8(data) {
+            Ok(text) => text.to_string(),
+            Err(_) => data.iter().map(|byte| char::from(*byte)).collect(),
+        }
+    };
+    text.trim_matches(char::from(0)).trim().to_string()
+}
+
+/// The IIM specification's name for a dataset.
+pub fn dataset_name(record: u8, number: u8) -> Option<&'static str> {
+    Some(match (record, number) {
+        (1, 0) => "EnvelopeRecordVersion",
+        (1, 5) => "Destination",
+        (1, 20) => "FileFormat",
+        (1, 22) => "FileVersion",
+        (1, 30) => "ServiceIdentifier",
+        (1, 40) => "EnvelopeNumber",
+        (1, 50) => "ProductID",
+        (1, 60) => "EnvelopePriority",
+        (1, 70) => "DateSent",
+        (1, 80) => "TimeSent",
+        (1, 90) => "CodedCharacterSet",
+        (1, 100) => "UniqueObjectName",
+        (2, 0) => "ApplicationRecordVersion",
+        (2, 3) => "ObjectTypeReference",
+        (2, 4) => "ObjectAttributeReference",
+        (2, 5) => "ObjectName",
+        (2, 7) => "EditStatus",
+        (2, 10) => "Urgency",
+        (2, 12) => "SubjectReference",
+        (2, 15) => "Category",
+        (2, 20) => "SupplementalCategories",
+        (2, 22) => "FixtureIdentifier",
+        (2, 25) => "Keywords",
+        (2, 26) => "ContentLocationCode",
+        (2, 27) => "ContentLocationName",
+        (2, 30) => "ReleaseDate",
+        (2, 35) => "ReleaseTime",
+        (2, 37) => "ExpirationDate",
+        (2, 38) => "ExpirationTime",
+        (2, 40) => "SpecialInstructions",
+        (2, 42) => "ActionAdvised",
+        (2, 45) => "ReferenceService",
+        (2, 47) => "ReferenceDate",
+        (2, 50) => "ReferenceNumber",
+        (2, 55) => "DateCreated",
+        (2, 60) => "TimeCreated",
+        (2, 62) => "DigitalCreationDate",
+        (2, 63) => "DigitalCreationTime",
+        (2, 65) => "OriginatingProgram",
+        (2, 70) => "ProgramVersion",
+        (2, 75) => "ObjectCycle",
+        (2, 80) => "By-line",
+        (2, 85) => "By-lineTitle",
+        (2, 90) => "City",
+        (2, 92) => "Sub-location",
+        (2, 95) => "Province-State",
+        (2, 100) => "Country-PrimaryLocationCode",
+        (2, 101) => "Country-PrimaryLocationName",
+        (2, 103) => "OriginalTransmissionReference",
+        (2, 105) => "Headline",
+        (2, 110) => "Credit",
+        (2, 115) => "Source",
+        (2, 116) => "CopyrightNotice",
+        (2, 118) => "Contact",
+        (2, 120) => "Caption-Abstract",
+        (2, 121) => "LocalCaption",
+        (2, 122) => "Writer-Editor",
+        (2, 130) => "ImageType",
+        (2, 131) => "ImageOrientation",
+        (2, 135) => "LanguageIdentifier",
+        (2, 184) => "JobID",
+        (2, 187) => "MasterDocumentID",
+        (2, 221) => "Prefs",
+        (2, 228) => "ClassifyState",
+        (2, 230) => "DocumentNotes",
+        (2, 231) => "DocumentHistory",
+        _ => return None,
+    })
+}
+
+/// A dataset value as a person reads it: dates as `YYYY-MM-DD`, times as
+/// `HH:MM:SS±HH:MM`, version numbers as integers and text decoded.
+pub fn readable(dataset: &Dataset, utf8: bool) -> String {
+    let data = dataset.data;
+    match (dataset.record, dataset.number) {
+        (1, 0) | (2, 0) | (1, 22) | (1, 20) if data.len() == 2 => {
+            u16::from_be_bytes([data[0], data[1]]).to_string()
+        }
+        (1, 90) if data == UTF8_CHARSET => "UTF-8".to_string(),
+        (1, 70) | (2, 30) | (2, 37) | (2, 47) | (2, 55) | (2, 62)
+            if data.len() == 8 && data.iter().all(u8::is_ascii_digit) =>
+        {
+            let date = std::str::from_utf8(data).unwrap_or_default();
+            format!("{}-{}-{}", &date[..4], &date[4..6], &date[6..])
+        }
+        (1, 80) | (2, 35) | (2, 38) | (2, 60) | (2, 63) if data.len() == 11 && data.is_ascii() => {
+            let time = std::str::from_utf8(data).unwrap_or_default();
+            format!(
+                "{}:{}:{}{}:{}",
+                &time[..2],
+                &time[2..4],
+                &time[4..6],
+                &time[6..9],
+                &time[9..]
+            )
+        }
+        _ => text(data, utf8),
+    }
+}
+
+/// A dataset value as stored: text where it decodes, hex otherwise.
+pub fn raw(dataset: &Dataset, utf8: bool) -> String {
+    let data = dataset.data;
+    if data
+        .iter()
+        .all(|byte| *byte >= 0x20 || matches!(byte, b'\t' | b'\n' | b'\r'))
+    {
+        text(data, utf8)
+    } else {
+        data.iter()
+            .take(64)
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dataset(record: u8, number: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0x1C, record, number];
+        bytes.extend((data.len() as u16).to_be_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn resource(name: &[u8], iim: &[u8]) -> Vec<u8> {
+        let mut bytes = b"Photoshop 3.0\08BIM".to_vec();
+        bytes.extend(IPTC_RESOURCE_ID.to_be_bytes());
+        bytes.push(name.len() as u8);
+        bytes.extend(name);
+        if name.len().is_multiple_of(2) {
+            bytes.push(0);
+        }
+        bytes.extend((iim.len() as u32).to_be_bytes());
+        bytes.extend(iim);
+        bytes
+    }
+
+    #[test]
+    fn finds_the_iim_block_after_a_padded_name() {
+        let iim = dataset(2, 5, b"Title");
+        assert_eq!(find_iim(&resource(b"", &iim)), Some(iim.as_slice()));
+        assert_eq!(find_iim(&resource(b"IPTC", &iim)), Some(iim.as_slice()));
+        assert_eq!(find_iim(&resource(b"odd", &iim)), Some(iim.as_slice()));
+
+        let mut truncated = resource(b"", &iim);
+        truncated.pop();
+        assert_eq!(find_iim(&truncated), None);
+        assert_eq!(find_iim(b"8BIM\x04\x05 other resource"), None);
+        assert_eq!(find_iim(b"8BIM\x04\x04"), None);
+    }
+
+    #[test]
+    fn reads_plain_and_extended_datasets() {
+        let mut iim = dataset(2, 5, b"Title");
+        // An extended dataset with its length in the next two bytes.
+        iim.extend([0x1C, 2, 120, 0x80, 0x02, 0x00, 0x03]);
+        iim.extend(b"abc");
+        iim.extend(dataset(2, 25, b"tag"));
+        let datasets = datasets(&iim);
+        let read: Vec<(u8, u8, &[u8])> = datasets
+            .iter()
+            .map(|dataset| (dataset.record, dataset.number, dataset.data))
+            .collect();
+        assert_eq!(
+            read,
+            [
+                (2, 5, b"Title".as_slice()),
+                (2, 120, b"abc".as_slice()),
+                (2, 25, b"tag".as_slice())
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_at_truncated_or_oversized_datasets() {
+        let mut iim = dataset(2, 5, b"Title");
+        iim.extend(dataset(2, 25, b"tag"));
+        iim.truncate(iim.len() - 1);
+        assert_eq!(datasets(&iim).len(), 1);
+
+        // Stops at bytes that are not a dataset.
+        let mut iim = dataset(2, 5, b"Title");
+        iim.extend(b"\0\0\0\0\0");
+        iim.extend(dataset(2, 25, b"tag"));
+        assert_eq!(datasets(&iim).len(), 1);
+
+        // An extended length that overflows must not panic.
+        let mut iim = vec![0x1C, 2, 5, 0x80, 0x08];
+        iim.extend([0xFF; 8]);
+        iim.extend(b"data");
+        assert!(datasets(&iim).is_empty());
+        let mut iim = vec![0x1C, 2, 5, 0x80, 0x10];
+        iim.extend([0xFF; 16]);
+        assert!(datasets(&iim).is_empty());
+        let iim = [0x1C, 2, 5, 0x80, 0x04, 0x00];
+        assert!(datasets(&iim).is_empty());
+        assert!(datasets(&[0x1C, 2]).is_empty());
+        assert!(datasets(&[]).is_empty());
+    }
+
+    #[test]
+    fn decodes_latin1_unless_the_envelope_says_utf8() {
+        let mut iim = dataset(1, 90, UTF8_CHARSET);
+        iim.extend(dataset(2, 90, "Zürich".as_bytes()));
+        let utf8 = datasets(&iim);
+        assert!(is_utf8(&utf8));
+        assert_eq!(text(utf8[1].data, true), "Zürich");
+
+        let latin1 = dataset(2, 90, b"Z\xfcrich\0");
+        let latin1 = datasets(&latin1);
+        assert!(!is_utf8(&latin1));
+        assert_eq!(text(latin1[0].data, false), "Zürich");
+        // Undeclared UTF-8 is still read as UTF-8.
+        assert_eq!(text("Zürich ".as_bytes(), false), "Zürich");
+        // Declared UTF-8 with broken bytes is read lossily.
+        assert_eq!(text(b"Z\xfcrich", true), "Z\u{fffd}rich");
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+/// Error returned by commands whose job was cancelled before producing a
+/// result. Background pipelines without a result simply stop and return Ok.
+pub const JOB_CANCELLED: &str = "Job cancelled";
+
+/// Finished jobs kept around for `list_jobs`.
+const FINISHED_JOBS_RETAINED: usize = 50;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: String,
+    pub status: JobStatus,
+    pub current: usize,
+    pub total: usize,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+struct JobEntry {
+    seq: u64,
+    info: JobInfo,
+    cancel: Arc<AtomicBool>,
+}
+
+/// Managed state tracking every long-running command. Jobs announce
+/// themselves with "job-started", report "job-progress" (throttled) and end
+/// with "job-finished"; each event carries the job's `JobInfo`.
+#[derive(Default)]
+pub struct JobRegistry {
+    next_seq: AtomicU64,
+    jobs: Arc<Mutex<HashMap<String, JobEntry>>>,
+}
+
+impl JobRegistry {
+    /// Register a new running job of `kind`.
+    pub fn start(&self, app: &AppHandle, kind: &str) -> JobHandle {
+        self.register(Some(app), kind)
+    }
+
+    /// Register a job whose events go to `app`, or nowhere without one.
+    fn register(&self, app: Option<&AppHandle>, kind: &str) -> JobHandle {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed) + 1;
+        let id = format!("job-{seq}");
+        let cancel = Arc::new(AtomicBool::new(false));
+        let info = JobInfo {
+            id: id.clone(),
+            kind: kind.to_string(),
+            status: JobStatus::Running,
+            current: 0,
+            total: 0,
+            error: None,
+            started_at: Utc::now(),
+            finished_at: None,
+        };
+        if let Ok(mut jobs) = self.jobs.lock() {
+            jobs.insert(
+                id.clone(),
+                JobEntry {
+                    seq,
+                    info: info.clone(),
+                    cancel: Arc::clone(&cancel),
+                },
+            );
+        }
+        emit(app, "job-started", &info);
+
+        JobHandle {
+            id,
+            app: app.cloned(),
+            jobs: Arc::clone(&self.jobs),
+            cancel,
+            last_progress: Mutex::new(
+                Instant::now()
+                    .checked_sub(PROGRESS_INTERVAL)
+                    .unwrap_or_else(Instant::now),
+            ),
+        }
+    }
+
+    /// Like `start`, but first cancels running jobs of the same kind whose
+    /// work the new job makes pointless (e.g. pre-rendering a folder that is
+    /// no longer open).
+    pub fn start_exclusive(&self, app: &AppHandle, kind: &str) -> JobHandle {
+        self.cancel_kind(kind);
+        self.start(app, kind)
+    }
+
+    fn cancel_kind(&self, kind: &str) {
+        if let Ok(jobs) = self.jobs.lock() {
+            for entry in jobs.values() {
+                if entry.info.kind == kind && entry.info.status == JobStatus::Running {
+                    entry.cancel.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        let Ok(jobs) = self.jobs.lock() else {
+            return Vec::new();
+        };
+        let mut entries: Vec<&JobEntry> = jobs.values().collect();
+        entries.sort_by_key(|entry| entry.seq);
+        entries
+            .into_iter()
+            .map(|entry| entry.info.clone())
+            .collect()
+    }
+
+    pub fn cancel(&self, job_id: &str) -> Result<(), String> {
+        let jobs = self.jobs.lock().map_err(|e| e.to_string())?;
+        let entry = jobs
+            .get(job_id)
+            .ok_or_else(|| format!("Unknown job: {job_id}"))?;
+        entry.cancel.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// A running job's view of the registry. Pipelines poll `is_cancelled`
+/// between files and call `finish` with their outcome; a handle dropped
+/// without finishing marks the job failed.
+pub struct JobHandle {
+    id: String,
+    app: Option<AppHandle>,
+    jobs: Arc<Mutex<HashMap<String, JobEntry>>>,
+    cancel: Arc<AtomicBool>,
+    last_progress: Mutex<Instant>,
+}
+
+impl JobHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Record progress; events are throttled, the stored state is not.
+    pub fn progress(&self, current: usize, total: usize) {
+        let info = {
+            let Ok(mut jobs) = self.jobs.lock() else {
+                return;
+            };
+            let Some(entry) = jobs.get_mut(&self.id) else {
+                return;
+            };
+            entry.info.current = current;
+            entry.info.total = total;
+            entry.info.clone()
+        };
+        let Ok(mut last_progress) = self.last_progress.lock() else {
+            return;
+        };
+        if last_progress.elapsed() >= PROGRESS_INTERVAL || current == total {
+            *last_progress = Instant::now();
+            emit(self.app.as_ref(), "job-progress", &info);
+        }
+    }
+
+    pub fn finish<T>(&self, result: &Result<T, String>) {
+        match result {
+            Ok(_) => self.set_status(JobStatus::Completed, None),
+            Err(error) => self.set_status(JobStatus::Failed, Some(error.clone())),
+        }
+    }
+
+    fn set_status(&self, status: JobStatus, error: Option<String>) {
+        let info = {
+            let Ok(mut jobs) = self.jobs.lock() else {
+                return;
+            };
+            let Some(entry) = jobs.get_mut(&self.id) else {
+                return;
+            };
+            if entry.info.status != JobStatus::Running {
+                return;
+            }
+            // A cancelled job that stopped early reports as cancelled even
+            // though its pipeline returned normally or with JOB_CANCELLED.
+            let status = if self.is_cancelled() {
+                JobStatus::Cancelled
+            } else {
+                status
+            };
+            entry.info.status = status;
+            entry.info.error = if status == JobStatus::Failed {
+                error
+            } else {
+                None
+            };
+            entry.info.finished_at = Some(Utc::now());
+            let info = entry.info.clone();
+            prune_finished(&mut jobs);
+            info
+        };
+        emit(self.app.as_ref(), "job-finished", &info);
+    }
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        self.set_status(
+            JobStatus::Failed,
+            Some("Job stopped unexpectedly".to_string()),
+        );
+    }
+}
+
+fn emit(app: Option<&AppHandle>, event: &str, info: &JobInfo) {
+    if let Some(app) = app {
+        let _ = app.emit(event, info);
+    }
+}
+
+fn prune_finished(jobs: &mut HashMap<String, JobEntry>) {
+    let mut finished: Vec<(u64, String)> = jobs
+        .iter()
+        .filter(|(_, entry)| entry.info.status != JobStatus::Running)
+        .map(|(id, entry)| (entry.seq, id.clone()))
+        .collect();
+    if finished.len() <= FINISHED_JOBS_RETAINED {
+        return;
+    }
+    finished.sort();
+    let excess = finished.len() - FINISHED_JOBS_RETAINED;
+    for (_, id) in finished.into_iter().take(excess) {
+        jobs.remove(&id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(registry: &JobRegistry, job: &JobHandle) -> JobStatus {
+        registry
+            .list()
+            .into_iter()
+            .find(|info| info.id == job.id)
+            .unwrap()
+            .status
+    }
+
+    #[test]
+    fn exclusive_jobs_cancel_running_jobs_of_their_kind() {
+        let registry = JobRegistry::default();
+        let prerender = registry.register(None, "prerender");
+        let scan = registry.register(None, "scan");
+        registry.cancel_kind("prerender");
+        let next = registry.register(None, "prerender");
+        assert!(prerender.is_cancelled());
+        assert!(!scan.is_cancelled());
+        assert!(!next.is_cancelled());
+
+        // Finished jobs of the kind are left as they were.
+        next.finish(&Ok::<_, String>(()));
+        registry.cancel_kind("prerender");
+        assert_eq!(status(&registry, &next), JobStatus::Completed);
+    }
+
+    #[test]
+    fn cancelled_jobs_finish_as_cancelled() {
+        let registry = JobRegistry::default();
+        let job = registry.register(None, "export");
+        job.progress(3, 10);
+        registry.cancel(&job.id).unwrap();
+        job.finish(&Err::<(), _>(JOB_CANCELLED.to_string()));
+        let info = registry.list().pop().unwrap();
+        assert_eq!(info.status, JobStatus::Cancelled);
+        assert_eq!((info.current, info.total), (3, 10));
+        assert_eq!(info.error, None);
+        assert!(info.finished_at.is_some());
+        assert!(registry.cancel("job-unknown").is_err());
+    }
+
+    #[test]
+    fn failures_are_kept_and_dropped_handles_fail() {
+        let registry = JobRegistry::default();
+        let failed = registry.register(None, "ingest");
+        failed.finish(&Err::<(), _>("Disk full".to_string()));
+        assert_eq!(status(&registry, &failed), JobStatus::Failed);
+
+        let dropped = registry.register(None, "ingest");
+        let id = dropped.id.clone();
+        drop(dropped);
+        let info = registry
+            .list()
+            .into_iter()
+            .find(|info| info.id == id)
+            .unwrap();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.error.as_deref(), Some("Job stopped unexpectedly"));
+
+        // Dropping a finished handle keeps its outcome.
+        let done = registry.register(None, "ingest");
+        let id = done.id.clone();
+        done.finish(&Ok::<_, String>(()));
+        drop(done);
+        let info = registry
+            .list()
+            .into_iter()
+            .find(|info| info.id == id)
+            .unwrap();
+        assert_eq!(info.status, JobStatus::Completed);
+    }
+
+    #[test]
+    fn only_the_newest_finished_jobs_are_kept() {
+        let registry = JobRegistry::default();
+        let running = registry.register(None, "scan");
+        for _ in 0..FINISHED_JOBS_RETAINED + 5 {
+            registry.register(None, "thumbnail");
+        }
+        let jobs = registry.list();
+        assert_eq!(jobs.len(), FINISHED_JOBS_RETAINED + 1);
+        // Running jobs are never pruned, and the oldest finished go first.
+        assert_eq!(jobs[0].id, running.id);
+        assert_eq!(jobs[1].id, "job-7");
+        assert_eq!(
+            jobs.last().unwrap().id,
+            format!("job-{}", FINISHED_JOBS_RETAINED + 6)
+        );
+    }
+}
@@ -0,0 +1,752 @@
+use crate::metadata_history::{self, Change, KeywordChange};
+use crate::{read_photo_metadata, write_photo_metadata};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Separates the levels of a keyword path, as in Lightroom's
+/// `lr:hierarchicalSubject`: `Places|USA|California`.
+pub const SEPARATOR: char = '|';
+
+/// One node of the catalog's keyword tree. Photos store keywords by path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keyword {
+    pub id: i64,
+    pub parent_id: Option<i64>,
+    pub name: String,
+    pub path: String,
+    /// Other words for the keyword; exported with it and matched when
+    /// tagging.
+    pub synonyms: Vec<String>,
+    /// Whether the keyword is written to sidecars and exports. Off for
+    /// levels that only organize the tree, like Lightroom's bracketed
+    /// `[Places]`.
+    pub export: bool,
+}
+
+/// A new keyword (no `id`) or changes to an existing one. Renaming or
+/// moving a keyword retags the photos that have it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeywordEdit {
+    pub id: Option<i64>,
+    pub parent_id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+    #[serde(default = "default_export")]
+    pub export: bool,
+}
+
+fn default_export() -> bool {
+    true
+}
+
+/// A row of the `keywords` table, as journaled for undo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeywordRow {
+    pub parent_id: Option<i64>,
+    pub name: String,
+    /// The synonyms as stored, a JSON array.
+    pub synonyms: String,
+    pub export: bool,
+}
+
+pub fn read_row(conn: &rusqlite::Connection, id: i64) -> Result<Option<KeywordRow>, String> {
+    match conn.query_row(
+        "SELECT parent_id, name, synonyms, export FROM keywords WHERE id = ?1",
+        [id],
+        |row| {
+            Ok(KeywordRow {
+                parent_id: row.get(0)?,
+                name: row.get(1)?,
+                synonyms: row.get(2)?,
+                export: row.get(3)?,
+            })
+        },
+    ) {
+        Ok(row) => Ok(Some(row)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Put keyword `id` back as `row`, or remove it for `None`.
+pub fn write_row(
+    conn: &rusqlite::Connection,
+    id: i64,
+    row: Option<&KeywordRow>,
+) -> Result<(), String> {
+    match row {
+        Some(row) => conn.execute(
+            "INSERT INTO keywords (id, parent_id, name, synonyms, export) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(id) DO UPDATE SET parent_id=excluded.parent_id, name=excluded.name,
+               synonyms=excluded.synonyms, export=excluded.export",
+            rusqlite::params![id, row.parent_id, row.name, row.synonyms, row.export],
+        ),
+        None => conn.execute("DELETE FROM keywords WHERE id = ?1", [id]),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The catalog's keywords, sorted by path.
+#[derive(Default)]
+pub struct KeywordTree {
+    keywords: Vec<Keyword>,
+    by_path: HashMap<String, usize>,
+}
+
+/// `tag` with its levels trimmed and empty levels dropped.
+pub fn normalize(tag: &str) -> String {
+    tag.split(SEPARATOR)
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+fn path_key(path: &str) -> String {
+    path.to_lowercase()
+}
+
+/// Whether `tag` is `path` or lies under it.
+fn is_under(tag: &str, path: &str) -> bool {
+    let (tag, path) = (path_key(tag), path_key(path));
+    tag == path
+        || tag
+            .strip_prefix(&path)
+            .is_some_and(|rest| rest.starts_with(SEPARATOR))
+}
+
+pub fn load(conn: &rusqlite::Connection) -> KeywordTree {
+    let rows: Vec<(i64, Option<i64>, String, String, bool)> = conn
+        .prepare("SELECT id, parent_id, name, synonyms, export FROM keywords")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get(0)?,
+                        row.get(1)?,
+                        row.get(2)?,
+                        row.get(3)?,
+                        row.get(4)?,
+                    ))
+                })
+                .map(|rows| rows.flatten().collect())
+        })
+        .unwrap_or_default();
+    let parents: HashMap<i64, (Option<i64>, &str)> = rows
+        .iter()
+        .map(|(id, parent_id, name, _, _)| (*id, (*parent_id, name.as_str())))
+        .collect();
+    let mut keywords: Vec<Keyword> = rows
+        .iter()
+        .map(|(id, parent_id, name, synonyms, export)| {
+            let mut levels = vec![name.as_str()];
+            let mut parent = *parent_id;
+            // The depth bound stops at a cycle left by a broken catalog.
+            while let Some((grandparent, parent_name)) = parent
+                .and_then(|id| parents.get(&id))
+                .filter(|_| levels.len() <= rows.len())
+            {
+                levels.push(parent_name);
+                parent = *grandparent;
+            }
+            levels.reverse();
+            Keyword {
+                id: *id,
+                parent_id: *parent_id,
+                name: name.clone(),
+                path: levels.join(&SEPARATOR.to_string()),
+                synonyms: serde_json::from_str(synonyms).unwrap_or_default(),
+                export: *export,
+            }
+        })
+        .collect();
+    // By level, so each keyword directly follows its parent.
+    keywords.sort_by_cached_key(|keyword| {
+        path_key(&keyword.path)
+            .split(SEPARATOR)
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    });
+    let by_path = keywords
+        .iter()
+        .enumerate()
+        .map(|(index, keyword)| (path_key(&keyword.path), index))
+        .collect();
+    KeywordTree { keywords, by_path }
+}
+
+impl KeywordTree {
+    pub fn into_keywords(self) -> Vec<Keyword> {
+        self.keywords
+    }
+
+    pub fn find(&self, path: &str) -> Option<&Keyword> {
+        self.by_path
+            .get(&path_key(path))
+            .map(|index| &self.keywords[*index])
+    }
+
+    fn get(&self, id: i64) -> Option<&Keyword> {
+        self.keywords.iter().find(|keyword| keyword.id == id)
+    }
+
+    /// The keyword path `tag` stands for: the path itself, or for a single
+    /// word the one keyword with that name or synonym. Words matching no
+    /// keyword, or several, are kept as typed.
+    pub fn resolve(&self, tag: &str) -> String {
+        let tag = normalize(tag);
+        if let Some(keyword) = self.find(&tag) {
+            return keyword.path.clone();
+        }
+        if tag.contains(SEPARATOR) {
+            return tag;
+        }
+        let unique = |matches: Vec<&Keyword>| match matches.as_slice() {
+            [keyword] => Some(keyword.path.clone()),
+            _ => None,
+        };
+        unique(
+            self.keywords
+                .iter()
+                .filter(|keyword| keyword.name.eq_ignore_ascii_case(&tag))
+                .collect(),
+        )
+        .or_else(|| {
+            unique(
+                self.keywords
+                    .iter()
+                    .filter(|keyword| {
+                        keyword
+                            .synonyms
+                            .iter()
+                            .any(|synonym| synonym.eq_ignore_ascii_case(&tag))
+                    })
+                    .collect(),
+            )
+        })
+        .unwrap_or(tag)
+    }
+
+    /// Photo tags as XMP writes them: `dc:subject` gets each exported level
+    /// of every path, plus the synonyms of the tagged keyword, and
+    /// `lr:hierarchicalSubject` the full path of each exported keyword.
+    pub fn xmp_subjects(&self, tags: &[String]) -> (Vec<String>, Vec<String>) {
+        let mut flat: Vec<String> = Vec::new();
+        let mut hierarchical = Vec::new();
+        let mut push = |word: &str| {
+            if !flat.iter().any(|known| known.eq_ignore_ascii_case(word)) {
+                flat.push(word.to_string());
+            }
+        };
+        for tag in tags {
+            let levels: Vec<&str> = tag.split(SEPARATOR).collect();
+            for depth in 1..=levels.len() {
+                let keyword = self.find(&levels[..depth].join(&SEPARATOR.to_string()));
+                if keyword.is_some_and(|keyword| !keyword.export) {
+                    continue;
+                }
+                push(levels[depth - 1]);
+                if depth == levels.len() {
+                    for synonym in keyword
+                        .map(|keyword| keyword.synonyms.as_slice())
+                        .unwrap_or_default()
+                    {
+                        push(synonym);
+                    }
+                    hierarchical.push(tag.clone());
+                }
+            }
+        }
+        (flat, hierarchical)
+    }
+
+    /// Photo tags from XMP: each `lr:hierarchicalSubject` path, and the
+    /// `dc:subject` words that are not levels or synonyms of one of them.
+    pub fn tags_from_xmp(&self, subjects: Vec<String>, hierarchical: Vec<String>) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        for path in hierarchical {
+            let path = self.resolve(&path);
+            if !path.is_empty() && !tags.contains(&path) {
+                tags.push(path);
+            }
+        }
+        for subject in subjects {
+            let covered = tags.iter().any(|path| {
+                path.split(SEPARATOR)
+                    .any(|level| level.eq_ignore_ascii_case(subject.trim()))
+                    || self.find(path).is_some_and(|keyword| {
+                        keyword
+                            .synonyms
+                            .iter()
+                            .any(|synonym| synonym.eq_ignore_ascii_case(subject.trim()))
+                    })
+            });
+            let tag = self.resolve(&subject);
+            if !covered && !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+}
+
+fn child_id(
+    conn: &rusqlite::Connection,
+    parent_id: Option<i64>,
+    name: &str,
+) -> Result<Option<i64>, String> {
+    match conn.query_row(
+        "SELECT id FROM keywords WHERE parent_id IS ?1 AND name = ?2 COLLATE NOCASE",
+        rusqlite::params![parent_id, name],
+        |row| row.get(0),
+    ) {
+        Ok(id) => Ok(Some(id)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The ID of the keyword at `path`, creating it and any missing parents.
+fn ensure_path(conn: &rusqlite::Connection, path: &str) -> Result<Option<i64>, String> {
+    let mut parent_id = None;
+    for level in normalize(path)
+        .split(SEPARATOR)
+        .filter(|level| !level.is_empty())
+    {
+        parent_id = Some(match child_id(conn, parent_id, level)? {
+            Some(id) => id,
+            None => {
+                conn.execute(
+                    "INSERT INTO keywords (parent_id, name) VALUES (?1, ?2)",
+                    rusqlite::params![parent_id, level],
+                )
+                .map_err(|e| e.to_string())?;
+                conn.last_insert_rowid()
+            }
+        });
+    }
+    Ok(parent_id)
+}
+
+/// Add the paths of `tags` the tree does not have yet, so every tag a photo
+/// carries is part of the vocabulary.
+pub fn ensure(conn: &rusqlite::Connection, tags: &[String]) -> Result<(), String> {
+    for tag in tags {
+        ensure_path(conn, tag)?;
+    }
+    Ok(())
+}
+
+/// `tag`, which lies under `old_path`, moved to `new_path`. Levels are
+/// counted rather than bytes, as the case-insensitive match may differ in
+/// length.
+fn reparent(tag: &str, old_path: &str, new_path: &str) -> String {
+    let depth = old_path.split(SEPARATOR).count();
+    std::iter::once(new_path)
+        .chain(tag.split(SEPARATOR).skip(depth))
+        .collect::<Vec<_>>()
+        .join(&SEPARATOR.to_string())
+}
+
+/// Rewrite the tags of every photo with a tag under `path` through
+/// `retag`, journaled under `label` for undo together with the keyword
+/// rows the edit changed.
+fn retag_photos(
+    conn: &rusqlite::Connection,
+    path: &str,
+    label: &str,
+    keyword_changes: &[KeywordChange],
+    retag: impl Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    // Paths under `path` sort between `path|` and `path}`, the character
+    // after the separator.
+    let photo_ids: Vec<String> = conn
+        .prepare(
+            "SELECT DISTINCT photo_id FROM photo_tags
+             WHERE tag = ?1 COLLATE NOCASE OR (tag >= ?2 COLLATE NOCASE AND tag < ?3 COLLATE NOCASE)",
+        )
+        .and_then(|mut statement| {
+            statement
+                .query_map(
+                    [path.to_string(), format!("{path}{SEPARATOR}"), format!("{path}}}")],
+                    |row| row.get(0),
+                )
+                .map(|rows| rows.flatten().collect())
+        })
+        .map_err(|e| e.to_string())?;
+    let mut changes = Vec::new();
+    for (photo_id, before) in read_photo_metadata(conn, &photo_ids) {
+        if !before.tags.iter().any(|tag| is_under(tag, path)) {
+            continue;
+        }
+        let mut after = before.clone();
+        after.tags = Vec::new();
+        for tag in &before.tags {
+            let tag = if is_under(tag, path) {
+                retag(tag)
+            } else {
+                Some(tag.clone())
+            };
+            if let Some(tag) = tag.filter(|tag| !after.tags.contains(tag)) {
+                after.tags.push(tag);
+            }
+        }
+        write_photo_metadata(conn, &photo_id, &after)?;
+        changes.push(Change {
+            photo_id,
+            before: Some(before),
+            after,
+        });
+    }
+    metadata_history::record_with_keywords(conn, label, &changes, keyword_changes)
+}
+
+/// Create or update a keyword, moving the photos tagged with it (or with
+/// keywords under it) to its new path.
+pub fn save(conn: &mut rusqlite::Connection, edit: KeywordEdit) -> Result<(), String> {
+    let name = edit.name.trim().to_string();
+    if name.is_empty() {
+        return Err("Keywords need a name".to_string());
+    }
+    if name.contains(SEPARATOR) {
+        return Err(format!("Keyword names cannot contain {SEPARATOR}"));
+    }
+    let synonyms: Vec<String> = edit
+        .synonyms
+        .iter()
+        .map(|synonym| synonym.trim().to_string())
+        .filter(|synonym| !synonym.is_empty())
+        .collect();
+    let synonyms_json = serde_json::to_string(&synonyms).map_err(|e| e.to_string())?;
+    let tree = load(conn);
+    let parent = match edit.parent_id {
+        Some(parent_id) => Some(tree.get(parent_id).ok_or("Parent keyword not found")?),
+        None => None,
+    };
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let sibling = child_id(&tx, edit.parent_id, &name)?;
+    match edit.id {
+        None => {
+            if sibling.is_some() {
+                return Err(format!("There is already a keyword named {name} here"));
+            }
+            tx.execute(
+                "INSERT INTO keywords (parent_id, name, synonyms, export) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![edit.parent_id, name, synonyms_json, edit.export],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        Some(id) => {
+            let keyword = tree.get(id).ok_or("Keyword not found")?;
+            if sibling.is_some_and(|sibling| sibling != id) {
+                return Err(format!("There is already a keyword named {name} here"));
+            }
+            if parent.is_some_and(|parent| is_under(&parent.path, &keyword.path)) {
+                return Err("A keyword cannot be moved under itself".to_string());
+            }
+            let before = read_row(&tx, id)?;
+            let after = KeywordRow {
+                parent_id: edit.parent_id,
+                name: name.clone(),
+                synonyms: synonyms_json,
+                export: edit.export,
+            };
+            write_row(&tx, id, Some(&after))?;
+            let keyword_changes = [KeywordChange {
+                id,
+                before,
+                after: Some(after),
+            }];
+            let new_path = match parent {
+                Some(parent) => format!("{}{SEPARATOR}{name}", parent.path),
+                None => name.clone(),
+            };
+            let old_path = keyword.path.as_str();
+            if new_path != keyword.path {
+                retag_photos(
+                    &tx,
+                    old_path,
+                    &format!("Rename keyword {old_path}"),
+                    &keyword_changes,
+                    |tag| Some(reparent(tag, old_path, &new_path)),
+                )?;
+            } else {
+                metadata_history::record_with_keywords(
+                    &tx,
+                    &format!("Edit keyword {old_path}"),
+                    &[],
+                    &keyword_changes,
+                )?;
+            }
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Delete a keyword and the keywords under it, removing them from photos.
+pub fn delete(conn: &mut rusqlite::Connection, id: i64) -> Result<(), String> {
+    let tree = load(conn);
+    let keyword = tree.get(id).ok_or("Keyword not found")?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut keyword_changes = Vec::new();
+    for doomed in tree
+        .keywords
+        .iter()
+        .filter(|other| is_under(&other.path, &keyword.path))
+    {
+        keyword_changes.push(KeywordChange {
+            id: doomed.id,
+            before: read_row(&tx, doomed.id)?,
+            after: None,
+        });
+        write_row(&tx, doomed.id, None)?;
+    }
+    retag_photos(
+        &tx,
+        &keyword.path,
+        &format!("Delete keyword {}", keyword.path),
+        &keyword_changes,
+        |_| None,
+    )?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Merge a Lightroom keyword list (tab-indented levels, `[Name]` for
+/// keywords not exported, `{Synonym}` one level under its keyword) into the
+/// tree. Returns how many keywords the list names.
+pub fn import_list(conn: &mut rusqlite::Connection, text: &str) -> Result<usize, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut stack: Vec<(String, i64)> = Vec::new();
+    let mut synonyms: HashMap<i64, Vec<String>> = HashMap::new();
+    let mut count = 0;
+    for line in text.lines() {
+        let depth = line.chars().take_while(|ch| *ch == '\t').count();
+        let entry = line.trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if let Some(synonym) = entry
+            .strip_prefix('{')
+            .and_then(|rest| rest.strip_suffix('}'))
+        {
+            if let Some((_, id)) = stack.get(depth.saturating_sub(1)) {
+                synonyms
+                    .entry(*id)
+                    .or_default()
+                    .push(synonym.trim().to_string());
+            }
+            continue;
+        }
+        let (name, export) = match entry
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            Some(name) => (name.trim(), false),
+            None => (entry, true),
+        };
+        let name = name.replace(SEPARATOR, " ");
+        stack.truncate(depth);
+        let path = match stack.last() {
+            Some((parent, _)) => format!("{parent}{SEPARATOR}{name}"),
+            None => name,
+        };
+        let Some(id) = ensure_path(&tx, &path)? else {
+            continue;
+        };
+        tx.execute(
+            "UPDATE keywords SET export = ?1 WHERE id = ?2",
+            rusqlite::params![export, id],
+        )
+        .map_err(|e| e.to_string())?;
+        stack.push((path, id));
+        count += 1;
+    }
+    for (id, mut added) in synonyms {
+        let known: String = tx
+            .query_row("SELECT synonyms FROM keywords WHERE id = ?1", [id], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        let mut merged: Vec<String> = serde_json::from_str(&known).unwrap_or_default();
+        added.retain(|synonym| !synonym.is_empty());
+        for synonym in added {
+            if !merged.contains(&synonym) {
+                merged.push(synonym);
+            }
+        }
+        let json = serde_json::to_string(&merged).map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE keywords SET synonyms = ?1 WHERE id = ?2",
+            rusqlite::params![json, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+/// The tree as a Lightroom keyword list.
+pub fn export_list(tree: &KeywordTree) -> String {
+    let mut text = String::new();
+    for keyword in &tree.keywords {
+        let depth = keyword.path.matches(SEPARATOR).count();
+        let indent = "\t".repeat(depth);
+        if keyword.export {
+            text.push_str(&format!("{indent}{}\n", keyword.name));
+        } else {
+            text.push_str(&format!("{indent}[{}]\n", keyword.name));
+        }
+        for synonym in &keyword.synonyms {
+            text.push_str(&format!("{indent}\t{{{synonym}}}\n"));
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metadata_history, PhotoMetadata};
+
+    fn catalog() -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    fn tag_photo(conn: &rusqlite::Connection, photo_id: &str, tags: &[&str]) {
+        let metadata = PhotoMetadata {
+            tags: strings(tags),
+            ..PhotoMetadata::default()
+        };
+        ensure(conn, &metadata.tags).unwrap();
+        write_photo_metadata(conn, photo_id, &metadata).unwrap();
+    }
+
+    fn tags_of(conn: &rusqlite::Connection, photo_id: &str) -> Vec<String> {
+        read_photo_metadata(conn, &[photo_id.to_string()])
+            .remove(photo_id)
+            .map(|metadata| metadata.tags)
+            .unwrap_or_default()
+    }
+
+    const LIST: &str = "People\n\tAda\n[Places]\n\tUSA\n\t\tCalifornia\n\t\t\t{CA}\n";
+
+    #[test]
+    fn keyword_lists_round_trip() {
+        let mut conn = catalog();
+        assert_eq!(import_list(&mut conn, LIST).unwrap(), 5);
+        let tree = load(&conn);
+        let california = tree.find("places|usa|california").unwrap();
+        assert_eq!(california.path, "Places|USA|California");
+        assert_eq!(california.synonyms, ["CA"]);
+        assert!(!tree.find("Places").unwrap().export);
+        assert_eq!(export_list(&tree), LIST);
+
+        // Importing again merges instead of duplicating.
+        import_list(&mut conn, "People\n\tAda\n\t\t{Lovelace}\n").unwrap();
+        let tree = load(&conn);
+        assert_eq!(tree.into_keywords().len(), 5);
+        assert_eq!(
+            load(&conn).find("People|Ada").unwrap().synonyms,
+            ["Lovelace"]
+        );
+    }
+
+    #[test]
+    fn xmp_subjects_skip_organizing_levels_and_add_synonyms() {
+        let mut conn = catalog();
+        import_list(&mut conn, LIST).unwrap();
+        let tree = load(&conn);
+        let (flat, hierarchical) =
+            tree.xmp_subjects(&strings(&["Places|USA|California", "beach", "People|Ada"]));
+        assert_eq!(flat, ["USA", "California", "CA", "beach", "People", "Ada"]);
+        assert_eq!(
+            hierarchical,
+            ["Places|USA|California", "beach", "People|Ada"]
+        );
+    }
+
+    #[test]
+    fn tags_from_xmp_prefer_paths_over_their_words() {
+        let mut conn = catalog();
+        import_list(&mut conn, LIST).unwrap();
+        let tree = load(&conn);
+        let tags = tree.tags_from_xmp(
+            strings(&["USA", "California", "CA", "Ada", "sunset"]),
+            strings(&["places|usa|california"]),
+        );
+        // `Ada` resolves to its one keyword; `sunset` is kept as written.
+        assert_eq!(tags, ["Places|USA|California", "People|Ada", "sunset"]);
+        assert_eq!(
+            tree.tags_from_xmp(strings(&["ca"]), Vec::new()),
+            ["Places|USA|California"]
+        );
+    }
+
+    #[test]
+    fn renames_keep_the_rest_of_each_path() {
+        assert_eq!(
+            reparent("places|usa|CA", "Places|USA", "Places|US"),
+            "Places|US|CA"
+        );
+        // Lowercasing `İ` changes its length in bytes.
+        assert_eq!(
+            reparent("i̇stanbul|Old town", "İstanbul", "Istanbul"),
+            "Istanbul|Old town"
+        );
+    }
+
+    #[test]
+    fn undo_puts_renamed_and_deleted_keywords_back() {
+        let mut conn = catalog();
+        tag_photo(&conn, "a", &["Places|USA|California", "beach"]);
+        let usa = load(&conn).find("Places|USA").unwrap().clone();
+        let places = usa.parent_id;
+
+        save(
+            &mut conn,
+            KeywordEdit {
+                id: Some(usa.id),
+                parent_id: places,
+                name: "United States".to_string(),
+                synonyms: Vec::new(),
+                export: true,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            tags_of(&conn, "a"),
+            ["Places|United States|California", "beach"]
+        );
+
+        let step = metadata_history::undo(&mut conn).unwrap().unwrap();
+        assert!(step.keywords_changed);
+        assert_eq!(tags_of(&conn, "a"), ["Places|USA|California", "beach"]);
+        assert!(load(&conn).find("Places|USA|California").is_some());
+        assert!(load(&conn).find("Places|United States").is_none());
+
+        delete(&mut conn, usa.id).unwrap();
+        assert_eq!(tags_of(&conn, "a"), ["beach"]);
+        assert!(load(&conn).find("Places|USA").is_none());
+        metadata_history::undo(&mut conn).unwrap().unwrap();
+        assert_eq!(tags_of(&conn, "a"), ["Places|USA|California", "beach"]);
+        let tree = load(&conn);
+        assert_eq!(
+            tree.find("Places|USA|California").unwrap().parent_id,
+            Some(usa.id)
+        );
+        assert_eq!(tree.into_keywords().len(), 4);
+
+        // Redoing the deletion removes the keywords again for good.
+        metadata_history::redo(&mut conn).unwrap().unwrap();
+        assert!(load(&conn).find("Places|USA").is_none());
+        assert_eq!(tags_of(&conn, "a"), ["beach"]);
+    }
+}
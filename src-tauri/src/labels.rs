@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const LABELS_KEY: &str = "labels";
+
+/// The color labels Lightroom and Bridge offer, in their order.
+pub const COLORS: [&str; 5] = ["red", "yellow", "green", "blue", "purple"];
+/// Flag states every catalog has.
+const BUILTIN_FLAGS: [&str; 3] = ["none", "pick", "reject"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ColorLabel {
+    pub color: String,
+    pub name: String,
+}
+
+/// A cull state besides pick and reject, such as "Client select" or
+/// "Album". `id` is what photos store; `name` is shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagState {
+    pub id: String,
+    pub name: String,
+}
+
+/// What a catalog calls its color labels and which extra flag states it
+/// culls with. `xmp:Label` holds the color label's name, as in Lightroom,
+/// so names matching a Lightroom label set show up there as colors.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LabelSet {
+    #[serde(default = "default_colors")]
+    pub colors: Vec<ColorLabel>,
+    #[serde(default)]
+    pub flags: Vec<FlagState>,
+}
+
+fn default_colors() -> Vec<ColorLabel> {
+    COLORS
+        .iter()
+        .map(|color| ColorLabel {
+            color: color.to_string(),
+            name: default_color_name(color),
+        })
+        .collect()
+}
+
+fn default_color_name(color: &str) -> String {
+    let mut chars = color.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+impl Default for LabelSet {
+    fn default() -> Self {
+        Self {
+            colors: default_colors(),
+            flags: Vec::new(),
+        }
+    }
+}
+
+/// `value` as a color label: one of `COLORS`, or empty for none.
+pub fn color(value: &str) -> String {
+    let value = value.trim().to_ascii_lowercase();
+    if COLORS.contains(&value.as_str()) {
+        value
+    } else {
+        String::new()
+    }
+}
+
+/// The identifier a flag state named `name` is stored under.
+pub fn flag_id(name: &str) -> String {
+    name.trim()
+        .to_lowercase()
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+impl LabelSet {
+    pub fn color_name(&self, color: &str) -> String {
+        self.colors
+            .iter()
+            .find(|label| label.color == color)
+            .map(|label| label.name.clone())
+            .unwrap_or_else(|| default_color_name(color))
+    }
+
+    /// The color an `xmp:Label` value stands for, by this catalog's names
+    /// or else Lightroom's default ones.
+    pub fn color_for_label(&self, label: &str) -> Option<&'static str> {
+        let label = label.trim();
+        let by_name = self
+            .colors
+            .iter()
+            .find(|known| known.name.eq_ignore_ascii_case(label))
+            .map(|known| known.color.as_str());
+        COLORS
+            .iter()
+            .find(|color| match by_name {
+                Some(by_name) => **color == by_name,
+                None => color.eq_ignore_ascii_case(label),
+            })
+            .copied()
+    }
+
+    /// `flag` when it is a built-in or one of this catalog's flag states,
+    /// otherwise `none`.
+    pub fn flag(&self, flag: &str) -> String {
+        let id = flag_id(flag);
+        if BUILTIN_FLAGS.contains(&id.as_str()) || self.flags.iter().any(|state| state.id == id) {
+            id
+        } else {
+            "none".to_string()
+        }
+    }
+}
+
+pub fn load(conn: &rusqlite::Connection) -> LabelSet {
+    conn.query_row(
+        "SELECT value FROM catalog_settings WHERE key = ?1",
+        [LABELS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+/// Store `labels`, giving unnamed colors their default name and each flag
+/// state an ID derived from its name. Returns what was stored.
+pub fn save(conn: &rusqlite::Connection, mut labels: LabelSet) -> Result<LabelSet, String> {
+    labels.colors = COLORS
+        .iter()
+        .map(|color| {
+            let name = labels
+                .colors
+                .iter()
+                .find(|label| label.color == *color)
+                .map(|label| label.name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .unwrap_or_else(|| default_color_name(color));
+            ColorLabel {
+                color: color.to_string(),
+                name,
+            }
+        })
+        .collect();
+    let mut ids: HashSet<String> = BUILTIN_FLAGS.iter().map(|id| id.to_string()).collect();
+    for state in &mut labels.flags {
+        state.name = state.name.trim().to_string();
+        state.id = flag_id(if state.id.trim().is_empty() {
+            &state.name
+        } else {
+            &state.id
+        });
+        if state.id.is_empty() {
+            return Err("Flag states need a name".to_string());
+        }
+        if !ids.insert(state.id.clone()) {
+            return Err(format!(
+                "There is already a flag state named {}",
+                state.name
+            ));
+        }
+    }
+    let json = serde_json::to_string(&labels).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO catalog_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [LABELS_KEY, &json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn flag_state(id: &str, name: &str) -> FlagState {
+        FlagState {
+            id: id.to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn labels_name_colors_by_the_catalog_then_lightroom() {
+        let defaults = LabelSet::default();
+        assert_eq!(defaults.color_for_label("Red"), Some("red"));
+        assert_eq!(defaults.color_for_label(" purple "), Some("purple"));
+        assert_eq!(defaults.color_for_label("Approved"), None);
+        assert_eq!(defaults.color_for_label(""), None);
+
+        let mut labels = LabelSet::default();
+        labels.colors[0].name = "Approved".to_string();
+        labels.colors[3].name = "Red".to_string();
+        assert_eq!(labels.color_for_label("approved"), Some("red"));
+        // A catalog name wins over a Lightroom default of the same name.
+        assert_eq!(labels.color_for_label("Red"), Some("blue"));
+        assert_eq!(labels.color_for_label("Yellow"), Some("yellow"));
+        assert_eq!(labels.color_name("red"), "Approved");
+        assert_eq!(labels.color_name("green"), "Green");
+    }
+
+    #[test]
+    fn unknown_flag_states_are_none() {
+        let labels = LabelSet {
+            flags: vec![flag_state("client-select", "Client select")],
+            ..LabelSet::default()
+        };
+        assert_eq!(labels.flag("Pick"), "pick");
+        assert_eq!(labels.flag("reject"), "reject");
+        assert_eq!(labels.flag("Client Select"), "client-select");
+        assert_eq!(labels.flag("album"), "none");
+        assert_eq!(labels.flag(""), "none");
+        assert_eq!(LabelSet::default().flag("client-select"), "none");
+    }
+
+    #[test]
+    fn saving_derives_flag_ids_and_refuses_duplicates() {
+        let conn = catalog();
+        let mut labels = LabelSet {
+            flags: vec![
+                flag_state("", " Client select "),
+                flag_state("Album", "For the album"),
+            ],
+            ..LabelSet::default()
+        };
+        labels.colors.retain(|label| label.color != "green");
+        labels.colors[0].name = "  ".to_string();
+        let saved = save(&conn, labels).unwrap();
+        let ids: Vec<&str> = saved.flags.iter().map(|state| state.id.as_str()).collect();
+        assert_eq!(ids, ["client-select", "album"]);
+        assert_eq!(saved.flags[0].name, "Client select");
+        // Every color is kept, unnamed ones under their default name.
+        let names: Vec<&str> = saved
+            .colors
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(names, ["Red", "Yellow", "Green", "Blue", "Purple"]);
+        assert_eq!(load(&conn).flags.len(), 2);
+
+        let duplicate = LabelSet {
+            flags: vec![flag_state("", "Album"), flag_state("", "album!")],
+            ..LabelSet::default()
+        };
+        assert!(save(&conn, duplicate).is_err());
+        let builtin = LabelSet {
+            flags: vec![flag_state("", "Pick")],
+            ..LabelSet::default()
+        };
+        assert!(save(&conn, builtin).is_err());
+        let unnamed = LabelSet {
+            flags: vec![flag_state("", " -- ")],
+            ..LabelSet::default()
+        };
+        assert!(save(&conn, unnamed).is_err());
+        // Failed saves leave the stored labels alone.
+        assert_eq!(load(&conn).flags.len(), 2);
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use exif as kamadak_exif;
 use image::ImageFormat;
 use kamadak_exif::{In, Reader};
@@ -12,20 +12,64 @@ use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
 use tauri::ipc::Response;
 use tauri::{AppHandle, Emitter, Manager};
 use uuid::Uuid;
-use walkdir::WalkDir;
 use zip::write::SimpleFileOptions;
 use zip::{CompressionMethod, ZipWriter};
 
+mod capture_time;
+mod catalog;
+mod exif_details;
+mod full_metadata;
+mod groups;
+mod health;
+mod identity;
+mod ingest;
+mod iptc;
+mod jobs;
+mod keywords;
+mod labels;
+mod metadata_history;
+mod metadata_templates;
+mod migrations;
+mod query;
+mod raw_container;
 mod raw_preview;
+mod scan_filter;
+mod scan_index;
+mod scan_progress;
+mod tokens;
+mod video;
+mod volumes;
+mod watcher;
+use catalog::Catalog;
+use exif_details::ExifTag;
+use full_metadata::MetadataGroup;
+use groups::{GroupRole, InferredPair, PairingOptions, PhotoGroup};
+use health::{DamagedFile, FileHealth};
+use identity::{IdentityScheme, Relink};
+use ingest::{IngestOptions, IngestResult};
+use iptc::{IptcMetadata, IptcPatch};
+use jobs::{JobHandle, JobInfo, JobRegistry, JOB_CANCELLED};
+use keywords::{Keyword, KeywordEdit, KeywordTree};
+use labels::LabelSet;
+use metadata_history::{HistoryEntry, HistoryStep, MetadataPatch};
+use metadata_templates::MetadataTemplate;
+use query::PhotoQuery;
+use raw_container::RawExif;
 use raw_preview::{
-    convert_raw_display_preview_to_jpeg, generate_embedded_thumbnail, generate_thumbnail_with_info,
-    is_raw_file, is_supported_file, orient_image_to_jpeg_if_needed, read_cached_raw_render,
-    render_raw_to_jpeg, EmbeddedJpegPreview,
+    convert_raw_display_preview_to_jpeg, generate_embedded_thumbnail, generate_preview_jpeg,
+    generate_thumbnail_with_info, heif_to_jpeg, is_heif_file, is_raw_file, is_supported_file,
+    is_video_file, orient_image_to_jpeg_if_needed, read_cached_raw_render, render_raw_to_jpeg,
+    EmbeddedJpegPreview,
 };
+use scan_filter::{ScanFilter, ScanOptions};
+use scan_progress::ScanReporter;
+use video::VideoMetadata;
+use watcher::FolderWatcher;
 const THUMBNAIL_CACHE_VERSION: &str = "thumbnail-v1-400px-jpeg90";
 // Higher than current still-camera dimensions, so cached RAW renders retain
 // their decoded resolution while still using the shared bounded API.
@@ -52,13 +96,25 @@ pub struct Photo {
     pub exif: ExifData,
     pub created_at: DateTime<Utc>,
     pub modified_at: DateTime<Utc>,
-    pub paired_with: Option<String>, // ID of paired RAW/JPEG
+    /// Other files of the same shot (RAW+JPEG, HEIF, Live Photo clip, ...).
+    pub group: Option<PhotoGroup>,
     pub embedded_jpeg_preview: Option<EmbeddedJpegPreview>,
     pub paired_raw_embedded_jpeg_preview: Option<EmbeddedJpegPreview>,
     pub tags: Option<Vec<String>>,
     pub notes: Option<String>,
     pub rating: Option<u8>,
     pub flag: Option<String>,
+    /// One of `labels::COLORS`, or empty.
+    #[serde(default)]
+    pub color_label: Option<String>,
+    #[serde(default)]
+    pub iptc: Option<IptcMetadata>,
+    /// The file's drive is unplugged; the photo is served from the catalog.
+    #[serde(default)]
+    pub offline: bool,
+    /// Whether the file passed validation when it was scanned.
+    #[serde(default)]
+    pub health: FileHealth,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -73,7 +129,31 @@ pub struct ExifData {
     pub exposure_mode: Option<String>,
     pub flash: Option<String>,
     pub white_balance: Option<String>,
+    /// The capture instant. Where the camera recorded no UTC offset, its
+    /// local time read as UTC.
     pub date_taken: Option<DateTime<Utc>>,
+    /// The camera's local capture time (DateTimeOriginal with subseconds).
+    pub date_taken_local: Option<NaiveDateTime>,
+    /// Clock correction in seconds applied to both capture times, set
+    /// through `shift_capture_time`.
+    pub capture_time_shift: Option<i64>,
+    pub camera_serial: Option<String>,
+    pub lens_serial: Option<String>,
+    /// Shutter actuations, from the maker note where the camera records it.
+    pub shutter_count: Option<u32>,
+    pub drive_mode: Option<String>,
+    pub focus_mode: Option<String>,
+    pub af_point: Option<String>,
+    pub metering_mode: Option<String>,
+    pub image_stabilization: Option<String>,
+    /// SubSecTimeOriginal digits, e.g. `"42"` for .42 s.
+    pub subsec_time: Option<String>,
+    /// OffsetTimeOriginal, the capture time's UTC offset, e.g. `"+02:00"`.
+    pub offset_time: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    /// Star rating (0-5) set in camera.
+    pub camera_rating: Option<u8>,
     pub exposure_bias: Option<f64>,
     pub ev100: Option<f64>,
     pub latitude: Option<f64>,
@@ -82,6 +162,8 @@ pub struct ExifData {
     pub width: Option<u32>,
     pub height: Option<u32>,
     pub orientation: Option<u16>,
+    /// Container metadata, for video clips only.
+    pub video: Option<VideoMetadata>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -94,6 +176,19 @@ pub struct PhotoFilter {
     pub iso_range: Option<(u32, u32)>,
     pub date_range: Option<(DateTime<Utc>, DateTime<Utc>)>,
     pub file_type: Option<String>,
+    /// A time of day as in the query syntax's `time:`, e.g. `14:00..15:30`
+    /// or `golden`.
+    pub time_of_day: Option<String>,
+    /// Capture weekdays, e.g. `["sat", "sun"]` or `["weekend"]`.
+    pub weekdays: Option<Vec<String>>,
+    pub near: Option<NearPhoto>,
+}
+
+/// Photos taken within `minutes` of the photo `photo_id`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NearPhoto {
+    pub photo_id: String,
+    pub minutes: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -130,6 +225,22 @@ struct ThumbnailCacheEntry {
 pub struct ScanResult {
     pub photos: Vec<Photo>,
     pub stats: PhotoStats,
+    pub inferred_pairs: Vec<InferredPair>,
+}
+
+/// Difference between the current contents of a folder and its last scan.
+/// `removed` holds photo IDs; `changed` includes unchanged files whose
+/// group moved because a neighbour was added or removed, members of groups
+/// inferred across folders, and files that moved without changing ID.
+/// `relinked` maps the old IDs of moved files to their new ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanDiff {
+    pub added: Vec<Photo>,
+    pub changed: Vec<Photo>,
+    pub removed: Vec<String>,
+    pub relinked: Vec<Relink>,
+    pub inferred_pairs: Vec<InferredPair>,
+    pub stats: PhotoStats,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -137,6 +248,7 @@ pub struct PhotoStats {
     pub total_photos: usize,
     pub raw_count: usize,
     pub jpeg_count: usize,
+    pub video_count: usize,
     pub paired_count: usize,
     pub raw_embedded_jpeg_preview_count: usize,
     pub raw_jpeg_redundancy_count: usize,
@@ -166,6 +278,12 @@ pub struct ExportResult {
 pub struct XmpSidecarResult {
     pub processed_count: usize,
     pub skipped_count: usize,
+    /// Sidecars for offline photos, written once their drive is back.
+    pub queued_count: usize,
+    /// `xmp:Label` values on import that name none of the catalog's color
+    /// labels. Photos carrying one keep their color label.
+    #[serde(default)]
+    pub unknown_labels: Vec<String>,
 }
 
 fn first_exif_u32(value: &kamadak_exif::Value) -> Option<u32> {
@@ -205,6 +323,54 @@ fn first_exif_ascii(value: &kamadak_exif::Value) -> Option<String> {
     }
 }
 
+fn exif_ascii(exif: &kamadak_exif::Exif, tag: kamadak_exif::Tag) -> Option<Vec<u8>> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        kamadak_exif::Value::Ascii(values) => values.first().cloned(),
+        _ => None,
+    }
+}
+
+/// DateTimeOriginal with its SubSecTimeOriginal, so shots from one burst
+/// (and the RAW and JPEG of one shot) can be told apart. Files written by
+/// software that drops DateTimeOriginal fall back to the digitized and
+/// modification times.
+fn exif_capture_time(exif: &kamadak_exif::Exif) -> Option<NaiveDateTime> {
+    [
+        (
+            kamadak_exif::Tag::DateTimeOriginal,
+            kamadak_exif::Tag::SubSecTimeOriginal,
+        ),
+        (
+            kamadak_exif::Tag::DateTimeDigitized,
+            kamadak_exif::Tag::SubSecTimeDigitized,
+        ),
+        (kamadak_exif::Tag::DateTime, kamadak_exif::Tag::SubSecTime),
+    ]
+    .into_iter()
+    .find_map(|(tag, subsec_tag)| {
+        let mut datetime = kamadak_exif::DateTime::from_ascii(&exif_ascii(exif, tag)?).ok()?;
+        if let Some(subsec) = exif_ascii(exif, subsec_tag) {
+            let _ = datetime.parse_subsec(&subsec);
+        }
+        naive_exif_datetime(&datetime)
+    })
+}
+
+/// An EXIF date and time, which is the camera's local time.
+fn naive_exif_datetime(datetime: &kamadak_exif::DateTime) -> Option<NaiveDateTime> {
+    chrono::NaiveDate::from_ymd_opt(
+        i32::from(datetime.year),
+        u32::from(datetime.month),
+        u32::from(datetime.day),
+    )?
+    .and_hms_nano_opt(
+        u32::from(datetime.hour),
+        u32::from(datetime.minute),
+        u32::from(datetime.second),
+        datetime.nanosecond.unwrap_or(0),
+    )
+}
+
 fn clean_metadata_string(value: impl AsRef<str>) -> Option<String> {
     let value = value
         .as_ref()
@@ -310,6 +476,23 @@ fn merge_exif_data(target: &mut ExifData, fallback: ExifData) {
     fill_missing(&mut target.flash, fallback.flash);
     fill_missing(&mut target.white_balance, fallback.white_balance);
     fill_missing(&mut target.date_taken, fallback.date_taken);
+    fill_missing(&mut target.date_taken_local, fallback.date_taken_local);
+    fill_missing(&mut target.camera_serial, fallback.camera_serial);
+    fill_missing(&mut target.lens_serial, fallback.lens_serial);
+    fill_missing(&mut target.shutter_count, fallback.shutter_count);
+    fill_missing(&mut target.drive_mode, fallback.drive_mode);
+    fill_missing(&mut target.focus_mode, fallback.focus_mode);
+    fill_missing(&mut target.af_point, fallback.af_point);
+    fill_missing(&mut target.metering_mode, fallback.metering_mode);
+    fill_missing(
+        &mut target.image_stabilization,
+        fallback.image_stabilization,
+    );
+    fill_missing(&mut target.subsec_time, fallback.subsec_time);
+    fill_missing(&mut target.offset_time, fallback.offset_time);
+    fill_missing(&mut target.artist, fallback.artist);
+    fill_missing(&mut target.copyright, fallback.copyright);
+    fill_missing(&mut target.camera_rating, fallback.camera_rating);
     fill_missing(&mut target.exposure_bias, fallback.exposure_bias);
     fill_missing(&mut target.ev100, fallback.ev100);
     fill_missing(&mut target.latitude, fallback.latitude);
@@ -355,116 +538,252 @@ fn exposure_ev100(
 fn extract_exif_data(file_path: &Path) -> Result<ExifData> {
     let file = fs::File::open(file_path)?;
     let mut bufreader = std::io::BufReader::new(&file);
-    let exifreader = Reader::new();
+    match Reader::new().read_from_container(&mut bufreader) {
+        Ok(exif) => Ok(exif_data_from(&exif)),
+        // RAF and CR3 keep their EXIF in containers of their own.
+        Err(_) if is_raw_file(file_path) => Ok(RawExif::read(file_path)
+            .map(|raw_exif| raw_exif.exif_data())
+            .unwrap_or_default()),
+        Err(_) => Ok(ExifData::default()),
+    }
+}
 
+fn exif_data_from(exif: &kamadak_exif::Exif) -> ExifData {
     let mut exif_data = ExifData::default();
 
-    if let Ok(exif) = exifreader.read_from_container(&mut bufreader) {
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::Make, In::PRIMARY) {
-            exif_data.camera_make = Some(field.display_value().to_string());
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::Model, In::PRIMARY) {
-            exif_data.camera_model = Some(field.display_value().to_string());
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::LensModel, In::PRIMARY) {
-            exif_data.lens_model = Some(field.display_value().to_string());
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::FocalLength, In::PRIMARY) {
-            if let kamadak_exif::Value::Rational(ref vec) = field.value {
-                if let Some(rational) = vec.first() {
-                    exif_data.focal_length = Some(rational.to_f64());
-                }
+    // `display_value` would quote ASCII values.
+    let text = |tag| {
+        exif_ascii(exif, tag)
+            .and_then(|value| clean_metadata_string(String::from_utf8_lossy(&value)))
+    };
+    exif_data.camera_make = text(kamadak_exif::Tag::Make);
+    exif_data.camera_model = text(kamadak_exif::Tag::Model);
+    exif_data.lens_model = text(kamadak_exif::Tag::LensModel);
+    exif_data.camera_serial = text(kamadak_exif::Tag::BodySerialNumber);
+    exif_data.date_taken_local = exif_capture_time(exif);
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::FocalLength, In::PRIMARY) {
+        if let kamadak_exif::Value::Rational(ref vec) = field.value {
+            if let Some(rational) = vec.first() {
+                exif_data.focal_length = Some(rational.to_f64());
             }
         }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::FNumber, In::PRIMARY) {
-            if let kamadak_exif::Value::Rational(ref vec) = field.value {
-                if let Some(rational) = vec.first() {
-                    exif_data.aperture = Some(rational.to_f64());
-                }
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::FNumber, In::PRIMARY) {
+        if let kamadak_exif::Value::Rational(ref vec) = field.value {
+            if let Some(rational) = vec.first() {
+                exif_data.aperture = Some(rational.to_f64());
             }
         }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::PhotographicSensitivity, In::PRIMARY)
-        {
-            exif_data.iso = first_exif_u32(&field.value);
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::ExposureTime, In::PRIMARY) {
-            exif_data.shutter_speed = Some(field.display_value().to_string());
-        }
-        let exposure_seconds = exif
-            .get_field(kamadak_exif::Tag::ExposureTime, In::PRIMARY)
-            .and_then(|field| first_exif_f64(&field.value));
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::ExposureBiasValue, In::PRIMARY) {
-            exif_data.exposure_bias = first_exif_f64(&field.value);
-        }
-        exif_data.ev100 = exposure_ev100(exif_data.aperture, exposure_seconds, exif_data.iso);
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::ImageWidth, In::PRIMARY) {
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::PhotographicSensitivity, In::PRIMARY) {
+        exif_data.iso = first_exif_u32(&field.value);
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::ExposureTime, In::PRIMARY) {
+        exif_data.shutter_speed = Some(field.display_value().to_string());
+    }
+    let exposure_seconds = exif
+        .get_field(kamadak_exif::Tag::ExposureTime, In::PRIMARY)
+        .and_then(|field| first_exif_f64(&field.value));
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::ExposureBiasValue, In::PRIMARY) {
+        exif_data.exposure_bias = first_exif_f64(&field.value);
+    }
+    exif_data.ev100 = exposure_ev100(exif_data.aperture, exposure_seconds, exif_data.iso);
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::ImageWidth, In::PRIMARY) {
+        exif_data.width = first_exif_u32(&field.value);
+    }
+    if exif_data.width.is_none() {
+        if let Some(field) = exif.get_field(kamadak_exif::Tag::PixelXDimension, In::PRIMARY) {
             exif_data.width = first_exif_u32(&field.value);
         }
-        if exif_data.width.is_none() {
-            if let Some(field) = exif.get_field(kamadak_exif::Tag::PixelXDimension, In::PRIMARY) {
-                exif_data.width = first_exif_u32(&field.value);
-            }
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::ImageLength, In::PRIMARY) {
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::ImageLength, In::PRIMARY) {
+        exif_data.height = first_exif_u32(&field.value);
+    }
+    if exif_data.height.is_none() {
+        if let Some(field) = exif.get_field(kamadak_exif::Tag::PixelYDimension, In::PRIMARY) {
             exif_data.height = first_exif_u32(&field.value);
         }
-        if exif_data.height.is_none() {
-            if let Some(field) = exif.get_field(kamadak_exif::Tag::PixelYDimension, In::PRIMARY) {
-                exif_data.height = first_exif_u32(&field.value);
-            }
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::Orientation, In::PRIMARY) {
-            exif_data.orientation =
-                first_exif_u32(&field.value).and_then(|value| u16::try_from(value).ok());
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSLatitude, In::PRIMARY) {
-            let ref_value = exif
-                .get_field(kamadak_exif::Tag::GPSLatitudeRef, In::PRIMARY)
-                .map(|field| &field.value);
-            exif_data.latitude = gps_coordinate(&field.value, ref_value);
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSLongitude, In::PRIMARY) {
-            let ref_value = exif
-                .get_field(kamadak_exif::Tag::GPSLongitudeRef, In::PRIMARY)
-                .map(|field| &field.value);
-            exif_data.longitude = gps_coordinate(&field.value, ref_value);
-        }
-        if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSAltitude, In::PRIMARY) {
-            exif_data.altitude = first_exif_f64(&field.value);
-            if let Some(altitude_ref) = exif
-                .get_field(kamadak_exif::Tag::GPSAltitudeRef, In::PRIMARY)
-                .and_then(|field| first_exif_u32(&field.value))
-            {
-                if altitude_ref == 1 {
-                    exif_data.altitude = exif_data.altitude.map(|value| -value);
-                }
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::Orientation, In::PRIMARY) {
+        exif_data.orientation =
+            first_exif_u32(&field.value).and_then(|value| u16::try_from(value).ok());
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSLatitude, In::PRIMARY) {
+        let ref_value = exif
+            .get_field(kamadak_exif::Tag::GPSLatitudeRef, In::PRIMARY)
+            .map(|field| &field.value);
+        exif_data.latitude = gps_coordinate(&field.value, ref_value);
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSLongitude, In::PRIMARY) {
+        let ref_value = exif
+            .get_field(kamadak_exif::Tag::GPSLongitudeRef, In::PRIMARY)
+            .map(|field| &field.value);
+        exif_data.longitude = gps_coordinate(&field.value, ref_value);
+    }
+    if let Some(field) = exif.get_field(kamadak_exif::Tag::GPSAltitude, In::PRIMARY) {
+        exif_data.altitude = first_exif_f64(&field.value);
+        if let Some(altitude_ref) = exif
+            .get_field(kamadak_exif::Tag::GPSAltitudeRef, In::PRIMARY)
+            .and_then(|field| first_exif_u32(&field.value))
+        {
+            if altitude_ref == 1 {
+                exif_data.altitude = exif_data.altitude.map(|value| -value);
             }
         }
     }
 
-    if is_raw_file(file_path) {
-        if let Ok(raw_exif_data) = extract_raw_metadata(file_path) {
-            merge_exif_data(&mut exif_data, raw_exif_data);
+    merge_exif_data(&mut exif_data, exif_details::from_tiff(exif.buf()));
+    exif_data
+}
+
+/// A field's values as stored, space-separated; text is unquoted and long
+/// binary values are cut short.
+fn exif_raw_value(value: &kamadak_exif::Value) -> String {
+    fn join<T: ToString>(values: &[T]) -> String {
+        values
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+    match value {
+        kamadak_exif::Value::Byte(values) => join(values),
+        kamadak_exif::Value::Ascii(values) => values
+            .iter()
+            .filter_map(|value| clean_metadata_string(String::from_utf8_lossy(value)))
+            .collect::<Vec<_>>()
+            .join("; "),
+        kamadak_exif::Value::Short(values) => join(values),
+        kamadak_exif::Value::Long(values) => join(values),
+        kamadak_exif::Value::Rational(values) => values
+            .iter()
+            .map(|value| format!("{}/{}", value.num, value.denom))
+            .collect::<Vec<_>>()
+            .join(" "),
+        kamadak_exif::Value::SByte(values) => join(values),
+        kamadak_exif::Value::Undefined(bytes, _) => {
+            let hex: String = bytes
+                .iter()
+                .take(64)
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            if bytes.len() > 64 {
+                format!("{hex} …")
+            } else {
+                hex
+            }
         }
+        kamadak_exif::Value::SShort(values) => join(values),
+        kamadak_exif::Value::SLong(values) => join(values),
+        kamadak_exif::Value::SRational(values) => values
+            .iter()
+            .map(|value| format!("{}/{}", value.num, value.denom))
+            .collect::<Vec<_>>()
+            .join(" "),
+        kamadak_exif::Value::Float(values) => join(values),
+        kamadak_exif::Value::Double(values) => join(values),
+        kamadak_exif::Value::Unknown(kind, count, _) => format!("{count} values of type {kind}"),
     }
+}
 
-    Ok(exif_data)
+/// Every field of an EXIF block, with the maker note decoded in place of
+/// its raw bytes.
+fn exif_tags_from(exif: &kamadak_exif::Exif) -> Vec<ExifTag> {
+    let mut tags: Vec<ExifTag> = exif
+        .fields()
+        .filter(|field| field.tag != kamadak_exif::Tag::MakerNote)
+        .map(|field| ExifTag {
+            ifd: match field.tag.context() {
+                kamadak_exif::Context::Tiff if field.ifd_num == In::PRIMARY => "IFD0",
+                kamadak_exif::Context::Tiff => "IFD1",
+                kamadak_exif::Context::Exif => "Exif",
+                kamadak_exif::Context::Gps => "GPS",
+                _ => "Interop",
+            }
+            .to_string(),
+            tag_id: field.tag.number(),
+            name: field.tag.to_string(),
+            raw: exif_raw_value(&field.value),
+            value: match field.value {
+                kamadak_exif::Value::Ascii(_) => exif_raw_value(&field.value),
+                _ => field.display_value().with_unit(exif).to_string(),
+            },
+        })
+        .collect();
+    tags.extend(exif_details::maker_note_tags(exif.buf()));
+    tags
 }
 
 /// Phase 1: Collect metadata + EXIF only (no image decoding). This is fast
-/// because it only reads file headers, not pixel data.
+/// because it only reads file headers, not pixel data. The file's structure
+/// is validated along the way and recorded as its `health`.
 fn collect_photo_metadata(path: &Path) -> Option<Photo> {
     if !is_supported_file(path) {
         return None;
     }
 
+    let (file_size, modified_at) = file_fingerprint(path)?;
+    let mut health = health::check(path);
+    let mut exif_data = if is_video_file(path) {
+        video::read_metadata(path).unwrap_or_else(|error| {
+            if health.is_ok() {
+                health = FileHealth::damaged(
+                    health::HealthStatus::Corrupt,
+                    format!("Could not read the video container: {error}"),
+                );
+            }
+            ExifData::default()
+        })
+    } else {
+        extract_exif_data(path).unwrap_or_default()
+    };
+    if is_raw_file(path) {
+        match extract_raw_metadata(path) {
+            Ok(raw_exif_data) => merge_exif_data(&mut exif_data, raw_exif_data),
+            Err(error) if health.is_ok() => {
+                health = FileHealth::damaged(
+                    health::HealthStatus::Corrupt,
+                    format!("LibRaw could not open the file: {error}"),
+                );
+            }
+            Err(_) => {}
+        }
+    }
+    capture_time::resolve(&mut exif_data);
+    let mut photo = photo_from_exif(
+        path,
+        stable_photo_id(path),
+        file_size,
+        modified_at,
+        exif_data,
+    );
+    photo.health = health;
+    if is_browser_preview_file(path) && photo.health.is_ok() {
+        photo.thumbnail = generate_embedded_thumbnail(path);
+    }
+    Some(photo)
+}
+
+/// Size and modification time, used to decide whether previously extracted
+/// metadata for a file is still current.
+fn file_fingerprint(path: &Path) -> Option<(u64, DateTime<Utc>)> {
     let metadata = fs::metadata(path).ok()?;
-    let file_size = metadata.len();
     let modified_at = metadata
         .modified()
         .map(|time| DateTime::<Utc>::from(time))
         .unwrap_or_else(|_| Utc::now());
+    Some((metadata.len(), modified_at))
+}
 
+/// Build a library entry from already-extracted EXIF without touching the
+/// file contents again.
+fn photo_from_exif(
+    path: &Path,
+    id: String,
+    file_size: u64,
+    modified_at: DateTime<Utc>,
+    exif_data: ExifData,
+) -> Photo {
     let file_name = path
         .file_name()
         .and_then(|name| name.to_str())
@@ -477,31 +796,28 @@ fn collect_photo_metadata(path: &Path) -> Option<Photo> {
         .unwrap_or("unknown")
         .to_uppercase();
 
-    let exif_data = extract_exif_data(path).unwrap_or_default();
-    let thumbnail = if is_browser_preview_file(path) {
-        generate_embedded_thumbnail(path)
-    } else {
-        None
-    };
-
-    Some(Photo {
-        id: stable_photo_id(path),
+    Photo {
+        id,
         file_path: path.to_string_lossy().to_string(),
         file_name,
         file_size,
         file_type,
-        thumbnail,
+        thumbnail: None,
         exif: exif_data,
         created_at: Utc::now(),
         modified_at,
-        paired_with: None,
+        group: None,
         embedded_jpeg_preview: None,
         paired_raw_embedded_jpeg_preview: None,
         tags: None,
         notes: None,
         rating: None,
         flag: None,
-    })
+        color_label: None,
+        iptc: None,
+        offline: false,
+        health: FileHealth::default(),
+    }
 }
 
 fn stable_photo_id(path: &Path) -> String {
@@ -515,6 +831,7 @@ fn stable_photo_id(path: &Path) -> String {
 
 fn compute_stats(photos: &[Photo]) -> PhotoStats {
     let mut raw_count = 0;
+    let mut video_count = 0;
     let mut paired_count = 0;
     let mut raw_embedded_jpeg_preview_count = 0;
     let mut raw_jpeg_redundancy_count = 0;
@@ -523,16 +840,26 @@ fn compute_stats(photos: &[Photo]) -> PhotoStats {
 
     for photo in photos {
         let is_raw = is_raw_file(Path::new(&photo.file_path));
+        if is_video_file(Path::new(&photo.file_path)) {
+            video_count += 1;
+        }
         if is_raw {
             raw_count += 1;
             if photo.embedded_jpeg_preview.is_some() {
                 raw_embedded_jpeg_preview_count += 1;
-                if photo.paired_with.is_some() {
+                if photo.group.as_ref().is_some_and(|group| {
+                    group.member(GroupRole::CameraJpeg).is_some()
+                        || group.member(GroupRole::Heif).is_some()
+                }) {
                     raw_jpeg_redundancy_count += 1;
                 }
             }
         }
-        if photo.paired_with.is_some() {
+        if photo
+            .group
+            .as_ref()
+            .is_some_and(|group| group.other_photo_ids(&photo.id).next().is_some())
+        {
             paired_count += 1;
         }
         if let Some(ref camera) = photo.exif.camera_model {
@@ -547,7 +874,8 @@ fn compute_stats(photos: &[Photo]) -> PhotoStats {
     PhotoStats {
         total_photos,
         raw_count,
-        jpeg_count: total_photos - raw_count,
+        jpeg_count: total_photos - raw_count - video_count,
+        video_count,
         paired_count,
         raw_embedded_jpeg_preview_count,
         raw_jpeg_redundancy_count,
@@ -556,52 +884,6 @@ fn compute_stats(photos: &[Photo]) -> PhotoStats {
     }
 }
 
-fn pair_raw_jpeg(photos: &mut Vec<Photo>) {
-    let mut base_names: HashMap<String, Vec<usize>> = HashMap::new();
-
-    for (i, photo) in photos.iter().enumerate() {
-        let path = Path::new(&photo.file_path);
-        if let Some(stem) = path.file_stem() {
-            if let Some(stem_str) = stem.to_str() {
-                let parent = path
-                    .parent()
-                    .map(|parent| parent.to_string_lossy().into_owned())
-                    .unwrap_or_default();
-                base_names
-                    .entry(format!("{parent}\0{stem_str}"))
-                    .or_insert_with(Vec::new)
-                    .push(i);
-            }
-        }
-    }
-
-    for indices in base_names.values() {
-        if indices.len() > 1 {
-            let mut raw_idx = None;
-            let mut jpeg_idx = None;
-
-            for &i in indices {
-                let path = Path::new(&photos[i].file_path);
-                if is_raw_file(path) {
-                    raw_idx = Some(i);
-                } else {
-                    jpeg_idx = Some(i);
-                }
-            }
-
-            if let (Some(raw), Some(jpeg)) = (raw_idx, jpeg_idx) {
-                let raw_id = photos[raw].id.clone();
-                let jpeg_id = photos[jpeg].id.clone();
-                let raw_embedded_jpeg_preview = photos[raw].embedded_jpeg_preview.clone();
-
-                photos[raw].paired_with = Some(jpeg_id);
-                photos[jpeg].paired_with = Some(raw_id);
-                photos[jpeg].paired_raw_embedded_jpeg_preview = raw_embedded_jpeg_preview;
-            }
-        }
-    }
-}
-
 fn is_browser_preview_file(path: &Path) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
@@ -635,6 +917,22 @@ fn load_full_resolution_image(file_path: &Path) -> Response {
         return tauri::ipc::Response::new(data);
     }
 
+    // Clips play from the file itself; this is only their poster frame.
+    if is_video_file(file_path) {
+        let data =
+            generate_preview_jpeg(file_path, RAW_VIEWER_PREVIEW_MAX_DIMENSION).unwrap_or_default();
+        return tauri::ipc::Response::new(data);
+    }
+
+    // WebViews cannot all display HEIC, so HEIF and AVIF files are always
+    // sent as JPEG.
+    if is_heif_file(file_path) {
+        return match heif_to_jpeg(file_path) {
+            Ok(data) => tauri::ipc::Response::new(data),
+            Err(error) => tauri::ipc::Response::new(format!("Could not decode image: {error}")),
+        };
+    }
+
     if is_jpeg_file(file_path) {
         if let Ok(Some(data)) = orient_image_to_jpeg_if_needed(file_path) {
             return tauri::ipc::Response::new(data);
@@ -647,44 +945,279 @@ fn load_full_resolution_image(file_path: &Path) -> Response {
     tauri::ipc::Response::new(data)
 }
 
-/// Phase 1: Fast scan — metadata + EXIF only, no image decoding.
-/// Returns photos (with thumbnail: None) and computed stats.
-#[tauri::command]
-async fn scan_folder_fast(folder_path: String, app: AppHandle) -> Result<ScanResult, String> {
-    let paths: Vec<std::path::PathBuf> = WalkDir::new(&folder_path)
-        .follow_links(true)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .map(|entry| entry.path().to_path_buf())
-        .filter(|path| is_supported_file(path))
-        .collect();
+/// Supported files under `folder_path` with the default scan options, for
+/// folders that are not a catalog root (e.g. a card being ingested).
+fn discover_supported_files(folder_path: &str) -> Vec<PathBuf> {
+    ScanFilter::new(folder_path, &ScanOptions::default()).walk(|_, _| true)
+}
+
+/// Walk every folder in `roots`, honoring the catalog's scan options, and
+/// reconcile each with its scan index in the catalog at `catalog_path` (a
+/// single folder is its own catalog). A catalog that cannot be opened (e.g. a
+/// read-only card) only disables the index; the scan itself still succeeds.
+/// Photos of the catalog's other roots (e.g. unplugged drives) join from the
+/// index before the library is grouped. With `stream_progress` the walk and
+/// metadata reads are reported as they happen (see `ScanReporter`). Runs as a
+/// cancellable "scan" job.
+async fn scan_roots_indexed(
+    catalog_path: String,
+    roots: Vec<String>,
+    app: AppHandle,
+    stream_progress: bool,
+) -> Result<scan_index::IndexedScan, String> {
+    let job = app.state::<JobRegistry>().start(&app, "scan");
+    tokio::task::spawn_blocking(move || {
+        let reporter = stream_progress.then(|| ScanReporter::new(app.clone()));
+        let mut conn = open_db(&app, Some(&catalog_path)).ok();
+        let options = conn
+            .as_ref()
+            .map(scan_filter::load_options)
+            .unwrap_or_default();
+        let mut total = 0;
+        let mut root_files = Vec::new();
+        for root in &roots {
+            if job.is_cancelled() {
+                break;
+            }
+            let paths = ScanFilter::new(root, &options).walk(|count, path| {
+                if let Some(reporter) = &reporter {
+                    reporter.discovered(total + count, path);
+                }
+                !job.is_cancelled()
+            });
+            total += paths.len();
+            root_files.push((root, paths));
+        }
+        if let Some(reporter) = &reporter {
+            reporter.start_reading(total);
+        }
 
-    let total_files = paths.len();
+        let read = AtomicUsize::new(0);
+        let on_photo = |photo: &Photo| {
+            if let Some(reporter) = &reporter {
+                reporter.photo_read(photo);
+            }
+            job.progress(read.fetch_add(1, Ordering::Relaxed) + 1, total);
+        };
+        let is_cancelled = || job.is_cancelled();
+        let hooks = scan_index::ScanHooks {
+            on_photo: &on_photo,
+            is_cancelled: &is_cancelled,
+        };
 
-    // Use rayon for parallel EXIF extraction (CPU-bound, no image decoding)
-    let mut photos: Vec<Photo> = tokio::task::spawn_blocking(move || {
-        paths
-            .par_iter()
-            .filter_map(|path| collect_photo_metadata(path))
-            .collect()
+        let mut scan = (!job.is_cancelled()).then(scan_index::IndexedScan::default);
+        for (root, paths) in root_files {
+            let Some(combined) = scan.as_mut() else {
+                break;
+            };
+            match scan_index::scan_paths(conn.as_mut(), root, paths, &hooks) {
+                Some(root_scan) => combined.extend(root_scan),
+                None => scan = None,
+            }
+            if let Some(conn) = &conn {
+                volumes::flush_pending_sidecars(conn, root);
+            }
+        }
+        if let Some(scan) = scan.as_mut() {
+            scan_index::group_catalog(conn.as_mut(), &roots, scan);
+            identity::relink_thumbnail_cache(&app, Some(&catalog_path), &scan.relinked);
+        }
+        if let Some(reporter) = &reporter {
+            reporter.finish();
+        }
+        let result = scan.ok_or_else(|| JOB_CANCELLED.to_string());
+        job.finish(&result);
+        result
     })
     .await
-    .map_err(|e| format!("Scan failed: {}", e))?;
+    .map_err(|e| format!("Scan failed: {}", e))?
+}
 
-    pair_raw_jpeg(&mut photos);
+fn complete_scan(scan: scan_index::IndexedScan, app: &AppHandle) -> ScanResult {
+    let photos = scan.photos;
     let stats = compute_stats(&photos);
 
     let _ = app.emit(
         "scan-complete",
         &ScanProgress {
-            current: total_files,
-            total: total_files,
+            current: photos.len(),
+            total: photos.len(),
             percentage: 100.0,
             current_file: None,
         },
     );
 
-    Ok(ScanResult { photos, stats })
+    ScanResult {
+        photos,
+        stats,
+        inferred_pairs: scan.inferred_pairs,
+    }
+}
+
+fn scan_diff(scan: scan_index::IndexedScan) -> ScanDiff {
+    let stats = compute_stats(&scan.photos);
+    let added: HashSet<String> = scan.added.into_iter().collect();
+    let changed: HashSet<String> = scan.changed.into_iter().collect();
+
+    let mut diff = ScanDiff {
+        added: Vec::new(),
+        changed: Vec::new(),
+        removed: scan.removed,
+        relinked: scan.relinked,
+        inferred_pairs: scan.inferred_pairs,
+        stats,
+    };
+    for photo in scan.photos {
+        if added.contains(&photo.id) {
+            diff.added.push(photo);
+        } else if changed.contains(&photo.id) {
+            diff.changed.push(photo);
+        }
+    }
+    diff
+}
+
+/// Phase 1: Fast scan — metadata + EXIF only, no image decoding.
+/// Returns photos (with thumbnail: None) and computed stats. Files unchanged
+/// since the last scan reuse their indexed EXIF instead of being re-read.
+/// Progress and partial batches stream while the scan runs.
+#[tauri::command]
+async fn scan_folder_fast(folder_path: String, app: AppHandle) -> Result<ScanResult, String> {
+    let roots = vec![folder_path.clone()];
+    let scan = scan_roots_indexed(folder_path, roots, app.clone(), true).await?;
+    Ok(complete_scan(scan, &app))
+}
+
+/// Rescan a folder that is already loaded and return only what changed since
+/// its previous scan, so the frontend can patch its library in place.
+#[tauri::command]
+async fn rescan_folder(folder_path: String, app: AppHandle) -> Result<ScanDiff, String> {
+    let roots = vec![folder_path.clone()];
+    let scan = scan_roots_indexed(folder_path, roots, app, false).await?;
+    Ok(scan_diff(scan))
+}
+
+/// Open a catalog's database, first moving roots whose drive came back
+/// under a different mount point.
+fn open_catalog_db(app: &AppHandle, catalog_path: &str) -> Result<rusqlite::Connection, String> {
+    let mut conn = open_db(app, Some(catalog_path))?;
+    let relocations = catalog::relocate_offline_roots(&mut conn)?;
+    volumes::relocate_cache_paths(app, Some(catalog_path), &relocations);
+    Ok(conn)
+}
+
+/// The catalog's root folders, split into those currently reachable and
+/// those whose drive is unplugged. Offline roots keep their index and
+/// metadata and are scanned again once mounted.
+fn catalog_roots_by_status(
+    app: &AppHandle,
+    catalog_path: &str,
+) -> Result<(Vec<String>, Vec<String>), String> {
+    let conn = open_catalog_db(app, catalog_path)?;
+    Ok(catalog::root_paths(&conn)?
+        .into_iter()
+        .partition(|root| Path::new(root).is_dir()))
+}
+
+/// Create (or open) a catalog directory and register `root_paths` with it.
+/// With `mark_volumes`, each root gets a volume marker file so it is found
+/// again when its drive is remounted elsewhere.
+#[tauri::command]
+fn create_catalog(
+    catalog_path: String,
+    root_paths: Vec<String>,
+    mark_volumes: Option<bool>,
+    app: AppHandle,
+) -> Result<Catalog, String> {
+    fs::create_dir_all(&catalog_path).map_err(|e| e.to_string())?;
+    let conn = open_db(&app, Some(&catalog_path))?;
+    for root_path in &root_paths {
+        catalog::add_root(&conn, root_path, mark_volumes.unwrap_or(false))?;
+    }
+    catalog::load(&conn, &catalog_path)
+}
+
+#[tauri::command]
+fn get_catalog(catalog_path: String, app: AppHandle) -> Result<Catalog, String> {
+    let conn = open_catalog_db(&app, &catalog_path)?;
+    catalog::load(&conn, &catalog_path)
+}
+
+#[tauri::command]
+fn add_catalog_root(
+    catalog_path: String,
+    root_path: String,
+    mark_volume: Option<bool>,
+    app: AppHandle,
+) -> Result<Catalog, String> {
+    let conn = open_db(&app, Some(&catalog_path))?;
+    catalog::add_root(&conn, &root_path, mark_volume.unwrap_or(false))?;
+    catalog::load(&conn, &catalog_path)
+}
+
+#[tauri::command]
+fn remove_catalog_root(
+    catalog_path: String,
+    root_path: String,
+    app: AppHandle,
+) -> Result<Catalog, String> {
+    let mut conn = open_db(&app, Some(&catalog_path))?;
+    catalog::remove_root(&mut conn, &root_path)?;
+    catalog::load(&conn, &catalog_path)
+}
+
+/// Scan every online root of a catalog as one library, streaming progress
+/// like `scan_folder_fast`. Photos under offline roots are included from the
+/// catalog with `offline` set. Pass the catalog path as `folder_path` to the
+/// metadata, thumbnail and export commands.
+#[tauri::command]
+async fn scan_catalog(catalog_path: String, app: AppHandle) -> Result<ScanResult, String> {
+    let (online, _) = catalog_roots_by_status(&app, &catalog_path)?;
+    let scan = scan_roots_indexed(catalog_path, online, app.clone(), true).await?;
+    Ok(complete_scan(scan, &app))
+}
+
+#[tauri::command]
+async fn rescan_catalog(catalog_path: String, app: AppHandle) -> Result<ScanDiff, String> {
+    let (roots, _) = catalog_roots_by_status(&app, &catalog_path)?;
+    let scan = scan_roots_indexed(catalog_path, roots, app, false).await?;
+    Ok(scan_diff(scan))
+}
+
+/// Live-watch the open library folder. New, modified and deleted files are
+/// reported through "photos-added", "photo-changed" and "photos-removed"
+/// events; opening another folder replaces the previous watch.
+#[tauri::command]
+fn watch_folder(folder_path: String, app: AppHandle) -> Result<(), String> {
+    let roots = vec![folder_path.clone()];
+    app.state::<FolderWatcher>()
+        .watch(app.clone(), folder_path, roots)
+}
+
+/// Live-watch every online root of a catalog, like `watch_folder`.
+#[tauri::command]
+fn watch_catalog(catalog_path: String, app: AppHandle) -> Result<(), String> {
+    let (roots, _) = catalog_roots_by_status(&app, &catalog_path)?;
+    app.state::<FolderWatcher>()
+        .watch(app.clone(), catalog_path, roots)
+}
+
+#[tauri::command]
+fn unwatch_folder(app: AppHandle) {
+    app.state::<FolderWatcher>().stop();
+}
+
+/// Long-running commands currently running or recently finished.
+#[tauri::command]
+fn list_jobs(app: AppHandle) -> Vec<JobInfo> {
+    app.state::<JobRegistry>().list()
+}
+
+/// Ask a running job to stop. Pipelines check between files, so the job
+/// finishes (with status "cancelled") shortly after, not immediately.
+#[tauri::command]
+fn cancel_job(job_id: String, app: AppHandle) -> Result<(), String> {
+    app.state::<JobRegistry>().cancel(&job_id)
 }
 
 /// Return the IDs of library entries whose original files no longer exist.
@@ -694,7 +1227,7 @@ async fn scan_folder_fast(folder_path: String, app: AppHandle) -> Result<ScanRes
 fn find_missing_photo_ids(photos: Vec<Photo>) -> Vec<String> {
     photos
         .into_iter()
-        .filter(|photo| !Path::new(&photo.file_path).is_file())
+        .filter(|photo| !photo.offline && !Path::new(&photo.file_path).is_file())
         .map(|photo| photo.id)
         .collect()
 }
@@ -712,82 +1245,106 @@ async fn generate_thumbnails(
         .filter(|p| p.thumbnail.is_none() || is_browser_preview_file(Path::new(&p.file_path)))
         .collect();
 
-    tokio::task::spawn_blocking(move || -> Result<(), String> {
-        let mut conn = open_cache_db(&app, folder_path.as_deref())?;
-        let mut cached = HashMap::new();
-        {
-            let mut statement = conn
-                .prepare(
-                    "SELECT file_path, file_size, modified_at, thumbnail, embedded_preview_json
-                     FROM thumbnail_cache WHERE photo_id = ?1 AND version = ?2",
-                )
-                .map_err(|e| e.to_string())?;
-            for photo in &items {
-                if let Ok(entry) = statement.query_row(
-                    rusqlite::params![photo.id, THUMBNAIL_CACHE_VERSION],
-                    |row| {
-                        let preview_json: Option<String> = row.get(4)?;
-                        Ok(ThumbnailCacheEntry {
-                            id: photo.id.clone(),
-                            file_path: row.get(0)?,
-                            file_size: row.get(1)?,
-                            modified_at: row.get(2)?,
-                            thumbnail: row.get(3)?,
-                            embedded_jpeg_preview: preview_json
-                                .and_then(|json| serde_json::from_str(&json).ok()),
-                        })
-                    },
-                ) {
-                    if entry.file_path == photo.file_path
-                        && entry.file_size == photo.file_size
-                        && entry.modified_at == photo.modified_at.to_rfc3339()
-                    {
-                        cached.insert(photo.id.clone(), entry);
-                    }
-                }
-            }
-        }
+    let job = app.state::<JobRegistry>().start(&app, "thumbnails");
+    tokio::task::spawn_blocking(move || {
+        let result = generate_thumbnails_blocking(&app, items, folder_path.as_deref(), &job);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Thumbnail generation failed: {}", e))??;
 
-        for entry in cached.values() {
-            let _ = app.emit(
-                "thumbnail-ready",
-                &ThumbnailReady {
-                    id: entry.id.clone(),
-                    thumbnail: entry.thumbnail.clone(),
-                    embedded_jpeg_preview: entry.embedded_jpeg_preview.clone(),
-                },
-            );
-        }
+    Ok(())
+}
 
-        let generated: Vec<ThumbnailCacheEntry> = items
-            .par_iter()
-            .filter(|photo| !cached.contains_key(&photo.id))
-            .filter_map(|photo| {
-                generate_thumbnail_with_info(Path::new(&photo.file_path))
-                    .ok()
-                    .map(|generated| ThumbnailCacheEntry {
+/// Serve cached thumbnails, then generate the rest in parallel. Cancelling
+/// the job stops generation between files; thumbnails finished so far are
+/// still written to the cache.
+fn generate_thumbnails_blocking(
+    app: &AppHandle,
+    items: Vec<Photo>,
+    folder_path: Option<&str>,
+    job: &JobHandle,
+) -> Result<(), String> {
+    let mut conn = open_cache_db(app, folder_path)?;
+    let mut cached = HashMap::new();
+    {
+        let mut statement = conn
+            .prepare(
+                "SELECT file_path, file_size, modified_at, thumbnail, embedded_preview_json
+                     FROM thumbnail_cache WHERE photo_id = ?1 AND version = ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        for photo in &items {
+            if let Ok(entry) = statement.query_row(
+                rusqlite::params![photo.id, THUMBNAIL_CACHE_VERSION],
+                |row| {
+                    let preview_json: Option<String> = row.get(4)?;
+                    Ok(ThumbnailCacheEntry {
                         id: photo.id.clone(),
-                        file_path: photo.file_path.clone(),
-                        file_size: photo.file_size,
-                        modified_at: photo.modified_at.to_rfc3339(),
-                        thumbnail: generated.thumbnail,
-                        embedded_jpeg_preview: generated.embedded_jpeg_preview,
+                        file_path: row.get(0)?,
+                        file_size: row.get(1)?,
+                        modified_at: row.get(2)?,
+                        thumbnail: row.get(3)?,
+                        embedded_jpeg_preview: preview_json
+                            .and_then(|json| serde_json::from_str(&json).ok()),
                     })
-            })
-            .collect();
+                },
+            ) {
+                if entry.file_path == photo.file_path
+                    && entry.file_size == photo.file_size
+                    && entry.modified_at == photo.modified_at.to_rfc3339()
+                {
+                    cached.insert(photo.id.clone(), entry);
+                }
+            }
+        }
+    }
 
-        for entry in &generated {
-                let event = ThumbnailReady {
+    for entry in cached.values() {
+        let _ = app.emit(
+            "thumbnail-ready",
+            &ThumbnailReady {
                 id: entry.id.clone(),
                 thumbnail: entry.thumbnail.clone(),
                 embedded_jpeg_preview: entry.embedded_jpeg_preview.clone(),
-                };
-                let _ = app.emit("thumbnail-ready", &event);
-        }
+            },
+        );
+    }
 
-        let transaction = conn.transaction().map_err(|e| e.to_string())?;
-        {
-            let mut statement = transaction
+    let total = items.len();
+    let done = AtomicUsize::new(cached.len());
+    job.progress(cached.len(), total);
+    let generated: Vec<ThumbnailCacheEntry> = items
+        .par_iter()
+        .filter(|photo| !cached.contains_key(&photo.id) && !photo.offline && photo.health.is_ok())
+        .filter(|_| !job.is_cancelled())
+        .filter_map(|photo| {
+            let generated = generate_thumbnail_with_info(Path::new(&photo.file_path));
+            job.progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+            generated.ok().map(|generated| ThumbnailCacheEntry {
+                id: photo.id.clone(),
+                file_path: photo.file_path.clone(),
+                file_size: photo.file_size,
+                modified_at: photo.modified_at.to_rfc3339(),
+                thumbnail: generated.thumbnail,
+                embedded_jpeg_preview: generated.embedded_jpeg_preview,
+            })
+        })
+        .collect();
+
+    for entry in &generated {
+        let event = ThumbnailReady {
+            id: entry.id.clone(),
+            thumbnail: entry.thumbnail.clone(),
+            embedded_jpeg_preview: entry.embedded_jpeg_preview.clone(),
+        };
+        let _ = app.emit("thumbnail-ready", &event);
+    }
+
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut statement = transaction
                 .prepare(
                     "INSERT INTO thumbnail_cache
                      (photo_id, version, cached_at, file_path, file_size, modified_at, thumbnail, embedded_preview_json)
@@ -798,33 +1355,28 @@ async fn generate_thumbnails(
                        thumbnail=excluded.thumbnail, embedded_preview_json=excluded.embedded_preview_json",
                 )
                 .map_err(|e| e.to_string())?;
-            let cached_at = Utc::now().to_rfc3339();
-            for entry in generated {
-                let preview_json = entry
-                    .embedded_jpeg_preview
-                    .map(|preview| serde_json::to_string(&preview))
-                    .transpose()
-                    .map_err(|e| e.to_string())?;
-                statement
-                    .execute(rusqlite::params![
-                        entry.id,
-                        THUMBNAIL_CACHE_VERSION,
-                        cached_at,
-                        entry.file_path,
-                        entry.file_size,
-                        entry.modified_at,
-                        entry.thumbnail,
-                        preview_json,
-                    ])
-                    .map_err(|e| e.to_string())?;
-            }
+        let cached_at = Utc::now().to_rfc3339();
+        for entry in generated {
+            let preview_json = entry
+                .embedded_jpeg_preview
+                .map(|preview| serde_json::to_string(&preview))
+                .transpose()
+                .map_err(|e| e.to_string())?;
+            statement
+                .execute(rusqlite::params![
+                    entry.id,
+                    THUMBNAIL_CACHE_VERSION,
+                    cached_at,
+                    entry.file_path,
+                    entry.file_size,
+                    entry.modified_at,
+                    entry.thumbnail,
+                    preview_json,
+                ])
+                .map_err(|e| e.to_string())?;
         }
-        transaction.commit().map_err(|e| e.to_string())?;
-        Ok(())
-    })
-    .await
-    .map_err(|e| format!("Thumbnail generation failed: {}", e))??;
-
+    }
+    transaction.commit().map_err(|e| e.to_string())?;
     Ok(())
 }
 
@@ -835,17 +1387,24 @@ async fn generate_thumbnails(
 async fn prerender_raws(photos: Vec<Photo>, app: AppHandle) -> Result<(), String> {
     let raws: VecDeque<Photo> = photos
         .into_iter()
-        .filter(|photo| is_raw_file(Path::new(&photo.file_path)))
+        .filter(|photo| {
+            !photo.offline && photo.health.is_ok() && is_raw_file(Path::new(&photo.file_path))
+        })
         .collect();
 
     if let Ok(mut priority) = raw_render_priority_queue().lock() {
         priority.clear();
     }
 
+    // A new folder's pre-render supersedes the previous one.
+    let job = app
+        .state::<JobRegistry>()
+        .start_exclusive(&app, "raw-prerender");
     tokio::task::spawn_blocking(move || {
+        let total = raws.len();
         let mut remaining = raws;
         let mut processed = HashSet::new();
-        loop {
+        while !job.is_cancelled() {
             let prioritized = raw_render_priority_queue()
                 .lock()
                 .ok()
@@ -861,7 +1420,9 @@ async fn prerender_raws(photos: Vec<Photo>, app: AppHandle) -> Result<(), String
             {
                 let _ = app.emit("raw-render-ready", RawRenderReady { id: photo.id });
             }
+            job.progress(processed.len().min(total), total);
         }
+        job.finish(&Ok::<(), String>(()));
     })
     .await
     .map_err(|e| format!("RAW pre-rendering failed: {e}"))?;
@@ -889,10 +1450,12 @@ fn prioritize_raw_renders(photos: Vec<Photo>) {
 
 #[tauri::command]
 async fn filter_photos(photos: Vec<Photo>, filter: PhotoFilter) -> Result<Vec<Photo>, String> {
+    let time_terms = query::filter_time_terms(&filter);
+    let context = query::Context::new(&photos);
     let filter = Arc::new(filter);
 
     let filtered: Vec<Photo> = photos
-        .into_par_iter()
+        .par_iter()
         .filter(|photo| {
             let filter = Arc::clone(&filter);
 
@@ -953,19 +1516,46 @@ async fn filter_photos(photos: Vec<Photo>, filter: PhotoFilter) -> Result<Vec<Ph
                     return false;
                 }
             }
+            if let Some((start, end)) = filter.date_range {
+                if let Some(taken) = photo.exif.date_taken {
+                    if taken < start || taken > end {
+                        return false;
+                    }
+                } else {
+                    return false;
+                }
+            }
             if let Some(ref file_type) = filter.file_type {
                 if photo.file_type != *file_type {
                     return false;
                 }
             }
 
-            true
+            time_terms.iter().all(|term| term.matches(photo, &context))
         })
+        .cloned()
         .collect();
 
     Ok(filtered)
 }
 
+/// Search the catalog's index with a structured query and/or the query
+/// syntax, e.g. `iso:>3200 lens:"24-70" rating:>=3 -flag:reject`. Only the
+/// matching IDs are returned, in `query.sort` order.
+#[tauri::command]
+async fn query_photos(
+    app: AppHandle,
+    folder_path: Option<String>,
+    query: PhotoQuery,
+) -> Result<Vec<String>, String> {
+    tokio::task::spawn_blocking(move || {
+        let conn = open_db(&app, folder_path.as_deref())?;
+        query::run(&query, scan_index::catalog_photos(&conn), &conn)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 async fn get_photo_stats(photos: Vec<Photo>) -> Result<PhotoStats, String> {
     Ok(compute_stats(&photos))
@@ -1297,9 +1887,15 @@ async fn apply_edits_and_save(
         return Err("File does not exist".to_string());
     }
 
+    if is_video_file(path) {
+        return Err("Video clips cannot be edited".to_string());
+    }
+
     // Load the full-res image (handle RAW via LibRaw conversion)
     let image_bytes = if is_raw_file(path) {
         render_raw_to_jpeg(path, 8192).map_err(|e| format!("RAW conversion failed: {}", e))?
+    } else if is_heif_file(path) {
+        heif_to_jpeg(path).map_err(|e| format!("HEIF decoding failed: {}", e))?
     } else {
         fs::read(path).map_err(|e| format!("Failed to read file: {}", e))?
     };
@@ -1372,12 +1968,26 @@ async fn apply_edits_and_save(
     Ok(output_path.to_string_lossy().to_string())
 }
 
+/// With `photo_id`, a photo whose drive is unplugged is served from the
+/// catalog's cached mid-size preview instead.
 #[tauri::command]
-async fn load_full_resolution_image_command(file_path: String) -> Response {
+async fn load_full_resolution_image_command(
+    file_path: String,
+    photo_id: Option<String>,
+    folder_path: Option<String>,
+    app: AppHandle,
+) -> Response {
     let path = Path::new(&file_path);
 
     if !path.exists() {
-        return Response::new("File does not exist".to_string());
+        let preview = photo_id.and_then(|photo_id| {
+            let conn = open_cache_db(&app, folder_path.as_deref()).ok()?;
+            volumes::read_offline_preview(&conn, &photo_id)
+        });
+        return match preview {
+            Some(data) => Response::new(data),
+            None => Response::new("File does not exist".to_string()),
+        };
     }
 
     if !is_supported_file(path) {
@@ -1387,12 +1997,29 @@ async fn load_full_resolution_image_command(file_path: String) -> Response {
     load_full_resolution_image(path)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PhotoMetadata {
     pub tags: Vec<String>,
     pub notes: String,
     pub rating: u8,
     pub flag: String,
+    #[serde(default)]
+    pub color_label: String,
+    #[serde(default)]
+    pub iptc: IptcMetadata,
+}
+
+impl Default for PhotoMetadata {
+    fn default() -> Self {
+        Self {
+            tags: Vec::new(),
+            notes: String::new(),
+            rating: 0,
+            flag: "none".to_string(),
+            color_label: String::new(),
+            iptc: IptcMetadata::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -1415,10 +2042,29 @@ pub struct AutoCullFeatureCacheEntry {
 
 #[derive(Debug, Clone, Default)]
 struct XmpMetadataPatch {
+    /// The flat `dc:subject` keywords.
     tags: Option<Vec<String>>,
+    /// The `lr:hierarchicalSubject` keyword paths.
+    hierarchical_tags: Option<Vec<String>>,
     notes: Option<String>,
     rating: Option<u8>,
     flag: Option<String>,
+    /// The `xmp:Label` value, a color label name.
+    label: Option<String>,
+    iptc: IptcPatch,
+}
+
+/// The catalog database inside a library folder or catalog directory.
+const CATALOG_FILE_NAME: &str = "hologram.sql";
+/// Thumbnails and previews, kept apart from the catalog.
+const CACHE_FILE_NAME: &str = "hologram-cache.sql";
+
+/// Whether `file_name` is one of Hologram's own files in a folder: the
+/// catalog, its cache, their SQLite journals, or a volume marker.
+fn is_hologram_file(file_name: &str) -> bool {
+    file_name.starts_with(CATALOG_FILE_NAME)
+        || file_name.starts_with(CACHE_FILE_NAME)
+        || file_name == volumes::VOLUME_MARKER
 }
 
 fn open_db(app: &AppHandle, folder_path: Option<&str>) -> Result<rusqlite::Connection, String> {
@@ -1427,7 +2073,7 @@ fn open_db(app: &AppHandle, folder_path: Option<&str>) -> Result<rusqlite::Conne
         if !folder.is_dir() {
             return Err(format!("Catalog folder does not exist: {folder_path}"));
         }
-        folder.join("hologram.sql")
+        folder.join(CATALOG_FILE_NAME)
     } else {
         app.path()
             .app_data_dir()
@@ -1437,25 +2083,8 @@ fn open_db(app: &AppHandle, folder_path: Option<&str>) -> Result<rusqlite::Conne
     if let Some(parent) = db_path.parent() {
         fs::create_dir_all(parent).map_err(|e| e.to_string())?;
     }
-    let conn = rusqlite::Connection::open(&db_path).map_err(|e| e.to_string())?;
-    conn.execute_batch(
-        "CREATE TABLE IF NOT EXISTS photo_metadata (
-            photo_id TEXT PRIMARY KEY,
-            tags TEXT NOT NULL DEFAULT '[]',
-            notes TEXT NOT NULL DEFAULT '',
-            rating INTEGER NOT NULL DEFAULT 0,
-            flag TEXT NOT NULL DEFAULT 'none'
-        )",
-    )
-    .map_err(|e| e.to_string())?;
-    let _ = conn.execute(
-        "ALTER TABLE photo_metadata ADD COLUMN rating INTEGER NOT NULL DEFAULT 0",
-        [],
-    );
-    let _ = conn.execute(
-        "ALTER TABLE photo_metadata ADD COLUMN flag TEXT NOT NULL DEFAULT 'none'",
-        [],
-    );
+    let mut conn = rusqlite::Connection::open(&db_path).map_err(|e| e.to_string())?;
+    migrations::migrate(&mut conn)?;
     Ok(conn)
 }
 
@@ -1468,12 +2097,12 @@ fn open_cache_db(
         if !folder.is_dir() {
             return Err(format!("Catalog folder does not exist: {folder_path}"));
         }
-        folder.join("hologram-cache.sql")
+        folder.join(CACHE_FILE_NAME)
     } else {
         app.path()
             .app_cache_dir()
             .map_err(|e| e.to_string())?
-            .join("hologram-cache.sql")
+            .join(CACHE_FILE_NAME)
     };
     if let Some(parent) = db_path.parent() {
         fs::create_dir_all(parent).map_err(|e| e.to_string())?;
@@ -1502,7 +2131,15 @@ fn open_cache_db(
             embedded_preview_json TEXT
         );
         CREATE INDEX IF NOT EXISTS thumbnail_cache_cached_at
-            ON thumbnail_cache(cached_at);",
+            ON thumbnail_cache(cached_at);
+        CREATE TABLE IF NOT EXISTS preview_cache (
+            photo_id TEXT PRIMARY KEY,
+            file_path TEXT NOT NULL,
+            file_size INTEGER NOT NULL,
+            modified_at TEXT NOT NULL,
+            preview BLOB NOT NULL,
+            cached_at TEXT NOT NULL
+        );",
     )
     .map_err(|e| e.to_string())?;
     Ok(conn)
@@ -1593,6 +2230,137 @@ fn write_autocull_feature_cache(
     Ok(())
 }
 
+/// Cache a mid-size preview of each photo so it stays viewable while its
+/// drive is unplugged. Returns the number of previews generated.
+#[tauri::command]
+async fn cache_offline_previews(
+    app: AppHandle,
+    photos: Vec<Photo>,
+    folder_path: Option<String>,
+) -> Result<usize, String> {
+    let job = app.state::<JobRegistry>().start(&app, "previews");
+    tokio::task::spawn_blocking(move || {
+        let result = volumes::cache_previews(&app, folder_path.as_deref(), &photos, &job);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Preview caching failed: {}", e))?
+}
+
+/// Every EXIF tag of a file, grouped by the IFD it was read from, including
+/// decoded maker note entries.
+fn read_exif_tags(path: &Path) -> Result<Vec<ExifTag>, String> {
+    let file = fs::File::open(path).map_err(|e| e.to_string())?;
+    match Reader::new().read_from_container(&mut std::io::BufReader::new(file)) {
+        Ok(exif) => Ok(exif_tags_from(&exif)),
+        Err(_) if is_raw_file(path) => RawExif::read(path)
+            .map(|raw_exif| raw_exif.tags())
+            .map_err(|e| e.to_string()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// Everything a file records about itself: its EXIF tags grouped by IFD
+/// (maker note included), then its IPTC datasets and embedded XMP
+/// properties grouped by namespace. Each field has its stored and readable
+/// value. A file without EXIF still lists its IPTC and XMP.
+#[tauri::command]
+async fn get_full_metadata(file_path: String) -> Result<Vec<MetadataGroup>, String> {
+    tokio::task::spawn_blocking(move || {
+        let path = Path::new(&file_path);
+        if !path.is_file() {
+            return Err(format!("File not found: {file_path}"));
+        }
+        let exif_tags = read_exif_tags(path).unwrap_or_default();
+        full_metadata::read(path, exif_tags).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Files the last scans found truncated or corrupt, so they can be copied
+/// off the card again before it is formatted.
+#[tauri::command]
+fn list_damaged_files(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<Vec<DamagedFile>, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    health::damaged_files(&conn)
+}
+
+#[tauri::command]
+fn get_pairing_options(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<PairingOptions, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(groups::load_pairing_options(&conn))
+}
+
+/// Change how RAW and JPEG files in different folders are paired. Takes
+/// effect on the next scan.
+#[tauri::command]
+fn set_pairing_options(
+    app: AppHandle,
+    folder_path: Option<String>,
+    options: PairingOptions,
+) -> Result<(), String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    groups::save_pairing_options(&conn, &options)
+}
+
+#[tauri::command]
+fn get_scan_options(app: AppHandle, folder_path: Option<String>) -> Result<ScanOptions, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(scan_filter::load_options(&conn))
+}
+
+/// Change which files scans of this catalog pick up. Takes effect on the
+/// next scan; files now excluded drop out of the library then.
+#[tauri::command]
+fn set_scan_options(
+    app: AppHandle,
+    folder_path: Option<String>,
+    options: ScanOptions,
+) -> Result<(), String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    scan_filter::save_options(&conn, &options)
+}
+
+#[tauri::command]
+fn get_identity_scheme(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<IdentityScheme, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(identity::load_scheme(&conn))
+}
+
+/// Switch a catalog between path- and content-derived photo IDs, carrying
+/// ratings, flags, tags, notes and cached thumbnails over to the new IDs.
+/// Returns the IDs that changed; rescan the folder afterwards.
+#[tauri::command]
+async fn set_identity_scheme(
+    app: AppHandle,
+    folder_path: Option<String>,
+    scheme: IdentityScheme,
+) -> Result<Vec<Relink>, String> {
+    let job = app.state::<JobRegistry>().start(&app, "relink");
+    tokio::task::spawn_blocking(move || {
+        let result = open_db(&app, folder_path.as_deref())
+            .and_then(|mut conn| identity::switch_scheme(&mut conn, scheme, &job));
+        if let Ok(relinks) = &result {
+            identity::relink_thumbnail_cache(&app, folder_path.as_deref(), relinks);
+        }
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Relink failed: {}", e))?
+}
+
 #[tauri::command]
 fn set_photo_metadata(
     app: AppHandle,
@@ -1603,59 +2371,151 @@ fn set_photo_metadata(
     rating: u8,
     flag: String,
 ) -> Result<(), String> {
-    let conn = open_db(&app, folder_path.as_deref())?;
-    let tags_json = serde_json::to_string(&tags).map_err(|e| e.to_string())?;
-    let rating = rating.min(5);
-    let flag = match flag.as_str() {
-        "pick" | "reject" => flag,
-        _ => "none".to_string(),
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    let vocabulary = keywords::load(&conn);
+    let existing = read_photo_metadata(&conn, std::slice::from_ref(&photo_id)).remove(&photo_id);
+    let metadata = PhotoMetadata {
+        tags: tags.iter().map(|tag| vocabulary.resolve(tag)).collect(),
+        notes,
+        rating: rating.min(5),
+        flag: labels::load(&conn).flag(&flag),
+        ..existing.clone().unwrap_or_default()
     };
-    conn.execute(
-        "INSERT INTO photo_metadata (photo_id, tags, notes, rating, flag) VALUES (?1, ?2, ?3, ?4, ?5)
-         ON CONFLICT(photo_id) DO UPDATE SET tags=excluded.tags, notes=excluded.notes, rating=excluded.rating, flag=excluded.flag",
-        rusqlite::params![photo_id, tags_json, notes, rating, flag],
-    )
-    .map_err(|e| e.to_string())?;
-    Ok(())
+    if existing.as_ref() == Some(&metadata) {
+        return Ok(());
+    }
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    keywords::ensure(&tx, &metadata.tags)?;
+    write_photo_metadata(&tx, &photo_id, &metadata)?;
+    metadata_history::record_edit(
+        &tx,
+        &format!("Edit {}", photo_count_label(1)),
+        metadata_history::Change {
+            photo_id,
+            before: existing,
+            after: metadata,
+        },
+    )?;
+    tx.commit().map_err(|e| e.to_string())
 }
 
+/// Photo IDs per bulk metadata query, well under SQLite's bound parameter
+/// limit.
+const METADATA_READ_CHUNK: usize = 500;
+
+/// The catalog metadata of `photo_ids`, read a chunk of photos per query.
+/// Photos without metadata are left out.
 fn read_photo_metadata(
     conn: &rusqlite::Connection,
     photo_ids: &[String],
 ) -> HashMap<String, PhotoMetadata> {
     let mut result = HashMap::new();
-    for photo_id in photo_ids {
-        if let Ok((tags_json, notes, rating, flag)) = conn.query_row(
-            "SELECT tags, notes, rating, flag FROM photo_metadata WHERE photo_id = ?1",
-            rusqlite::params![photo_id],
-            |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, u8>(2)?,
-                    row.get::<_, String>(3)?,
-                ))
-            },
-        ) {
-            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
-            let flag = match flag.as_str() {
-                "pick" | "reject" => flag,
-                _ => "none".to_string(),
+    for chunk in photo_ids.chunks(METADATA_READ_CHUNK) {
+        let placeholders = vec!["?"; chunk.len()].join(",");
+        let Ok(mut statement) = conn.prepare_cached(&format!(
+            "SELECT photo_id, notes, rating, flag, color_label, iptc FROM photo_metadata
+             WHERE photo_id IN ({placeholders})"
+        )) else {
+            continue;
+        };
+        let rows = statement.query_map(rusqlite::params_from_iter(chunk), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u8>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        });
+        for (photo_id, notes, rating, flag, color_label, iptc_json) in
+            rows.into_iter().flatten().flatten()
+        {
+            // Flag states removed from the catalog's label set are kept, so
+            // they come back if the state is added again.
+            let flag = if flag.is_empty() {
+                "none".to_string()
+            } else {
+                flag
             };
             result.insert(
-                photo_id.clone(),
+                photo_id,
                 PhotoMetadata {
-                    tags,
+                    tags: Vec::new(),
                     notes,
                     rating: rating.min(5),
                     flag,
+                    color_label,
+                    iptc: serde_json::from_str(&iptc_json).unwrap_or_default(),
                 },
             );
         }
+
+        let Ok(mut statement) = conn.prepare_cached(&format!(
+            "SELECT photo_id, tag FROM photo_tags WHERE photo_id IN ({placeholders})
+             ORDER BY photo_id, position"
+        )) else {
+            continue;
+        };
+        let rows = statement.query_map(rusqlite::params_from_iter(chunk), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        });
+        for (photo_id, tag) in rows.into_iter().flatten().flatten() {
+            if let Some(metadata) = result.get_mut(&photo_id) {
+                metadata.tags.push(tag);
+            }
+        }
     }
     result
 }
 
+/// Store a photo's metadata. Tags are stored as given; writers taking new
+/// tags add them to the keyword tree first with `keywords::ensure`, so
+/// tags restored by undo do not recreate keywords deleted since.
+fn write_photo_metadata(
+    conn: &rusqlite::Connection,
+    photo_id: &str,
+    metadata: &PhotoMetadata,
+) -> Result<(), String> {
+    let iptc_json = serde_json::to_string(&metadata.iptc).map_err(|e| e.to_string())?;
+    let flag = if metadata.flag.is_empty() {
+        "none"
+    } else {
+        metadata.flag.as_str()
+    };
+    conn.execute(
+        "INSERT INTO photo_metadata (photo_id, notes, rating, flag, color_label, iptc) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+         ON CONFLICT(photo_id) DO UPDATE SET notes=excluded.notes, rating=excluded.rating, flag=excluded.flag, color_label=excluded.color_label, iptc=excluded.iptc",
+        rusqlite::params![photo_id, metadata.notes, metadata.rating.min(5), flag, metadata.color_label, iptc_json],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM photo_tags WHERE photo_id = ?1", [photo_id])
+        .map_err(|e| e.to_string())?;
+    let mut insert = conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO photo_tags (photo_id, tag, position) VALUES (?1, ?2, ?3)",
+        )
+        .map_err(|e| e.to_string())?;
+    for (position, tag) in metadata.tags.iter().enumerate() {
+        insert
+            .execute(rusqlite::params![photo_id, tag, position])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Remove a photo's metadata from the catalog, tags included.
+fn delete_photo_metadata(conn: &rusqlite::Connection, photo_id: &str) -> Result<(), String> {
+    for table in ["photo_metadata", "photo_tags"] {
+        conn.execute(
+            &format!("DELETE FROM {table} WHERE photo_id = ?1"),
+            [photo_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
 #[tauri::command]
 fn get_photo_metadata(
     app: AppHandle,
@@ -1675,13 +2535,8 @@ fn get_photo_metadata(
             let fallback_conn = open_db(&app, None)?;
             let fallback_metadata = read_photo_metadata(&fallback_conn, &missing_ids);
             for (photo_id, metadata) in &fallback_metadata {
-                let tags_json = serde_json::to_string(&metadata.tags).map_err(|e| e.to_string())?;
-                conn.execute(
-                    "INSERT INTO photo_metadata (photo_id, tags, notes, rating, flag) VALUES (?1, ?2, ?3, ?4, ?5)
-                     ON CONFLICT(photo_id) DO UPDATE SET tags=excluded.tags, notes=excluded.notes, rating=excluded.rating, flag=excluded.flag",
-                    rusqlite::params![photo_id, tags_json, metadata.notes, metadata.rating.min(5), metadata.flag],
-                )
-                .map_err(|e| e.to_string())?;
+                keywords::ensure(&conn, &metadata.tags)?;
+                write_photo_metadata(&conn, photo_id, metadata)?;
             }
             result.extend(fallback_metadata);
         }
@@ -1690,10 +2545,223 @@ fn get_photo_metadata(
     Ok(result)
 }
 
+/// Edit the IPTC fields of several photos at once; fields left out of
+/// `patch` keep each photo's own value. Returns the resulting fields by
+/// photo ID.
+#[tauri::command]
+fn set_iptc_metadata(
+    app: AppHandle,
+    photo_ids: Vec<String>,
+    patch: IptcPatch,
+    folder_path: Option<String>,
+) -> Result<HashMap<String, IptcMetadata>, String> {
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    let label = format!("Edit IPTC of {}", photo_count_label(photo_ids.len()));
+    let result =
+        metadata_history::apply_batch(&mut conn, &photo_ids, &MetadataPatch::iptc(patch), &label)?;
+    Ok(result
+        .into_iter()
+        .map(|(photo_id, metadata)| (photo_id, metadata.iptc))
+        .collect())
+}
+
+fn photo_count_label(count: usize) -> String {
+    if count == 1 {
+        "1 photo".to_string()
+    } else {
+        format!("{count} photos")
+    }
+}
+
+/// Patch only the fields present in `patch` across `photo_ids` in one
+/// transaction, journaled so it can be undone. `label` names the batch in
+/// the history. Returns the resulting metadata by photo ID.
+#[tauri::command]
+async fn apply_metadata_batch(
+    app: AppHandle,
+    photo_ids: Vec<String>,
+    patch: MetadataPatch,
+    label: Option<String>,
+    folder_path: Option<String>,
+) -> Result<HashMap<String, PhotoMetadata>, String> {
+    tokio::task::spawn_blocking(move || {
+        let mut conn = open_db(&app, folder_path.as_deref())?;
+        let label = label
+            .filter(|label| !label.trim().is_empty())
+            .unwrap_or_else(|| format!("Edit {}", photo_count_label(photo_ids.len())));
+        metadata_history::apply_batch(&mut conn, &photo_ids, &patch, &label)
+    })
+    .await
+    .map_err(|e| format!("Metadata batch failed: {}", e))?
+}
+
+/// Revert the most recent metadata batch still applied. `None` when there
+/// is nothing to undo.
+#[tauri::command]
+fn undo_metadata_change(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<Option<HistoryStep>, String> {
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    metadata_history::undo(&mut conn)
+}
+
+/// Reapply the earliest undone metadata batch. `None` when there is
+/// nothing to redo.
+#[tauri::command]
+fn redo_metadata_change(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<Option<HistoryStep>, String> {
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    metadata_history::redo(&mut conn)
+}
+
+#[tauri::command]
+fn get_metadata_history(
+    app: AppHandle,
+    folder_path: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<HistoryEntry>, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    metadata_history::list(&conn, limit.unwrap_or(100))
+}
+
+#[tauri::command]
+fn get_label_set(app: AppHandle, folder_path: Option<String>) -> Result<LabelSet, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(labels::load(&conn))
+}
+
+/// Rename the catalog's color labels and replace its extra flag states.
+/// Photos keep flag states that are removed but show them as unflagged.
+#[tauri::command]
+fn set_label_set(
+    app: AppHandle,
+    folder_path: Option<String>,
+    labels: LabelSet,
+) -> Result<LabelSet, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    labels::save(&conn, labels)
+}
+
+#[tauri::command]
+fn get_keywords(app: AppHandle, folder_path: Option<String>) -> Result<Vec<Keyword>, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(keywords::load(&conn).into_keywords())
+}
+
+/// Create or change a keyword. Returns the updated tree; photos tagged
+/// with a renamed or moved keyword carry its new path.
+#[tauri::command]
+fn save_keyword(
+    app: AppHandle,
+    folder_path: Option<String>,
+    keyword: KeywordEdit,
+) -> Result<Vec<Keyword>, String> {
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    keywords::save(&mut conn, keyword)?;
+    Ok(keywords::load(&conn).into_keywords())
+}
+
+/// Delete a keyword and the keywords under it, untagging their photos.
+#[tauri::command]
+fn delete_keyword(
+    app: AppHandle,
+    folder_path: Option<String>,
+    keyword_id: i64,
+) -> Result<Vec<Keyword>, String> {
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    keywords::delete(&mut conn, keyword_id)?;
+    Ok(keywords::load(&conn).into_keywords())
+}
+
+/// Merge a Lightroom keyword list text file into the catalog's keywords.
+#[tauri::command]
+fn import_keyword_list(
+    app: AppHandle,
+    folder_path: Option<String>,
+    file_path: String,
+) -> Result<Vec<Keyword>, String> {
+    let text = fs::read_to_string(&file_path).map_err(|e| e.to_string())?;
+    let mut conn = open_db(&app, folder_path.as_deref())?;
+    keywords::import_list(&mut conn, text.trim_start_matches('\u{feff}'))?;
+    Ok(keywords::load(&conn).into_keywords())
+}
+
+/// Write the catalog's keywords as a Lightroom keyword list text file.
+#[tauri::command]
+fn export_keyword_list(
+    app: AppHandle,
+    folder_path: Option<String>,
+    file_path: String,
+) -> Result<(), String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    fs::write(&file_path, keywords::export_list(&keywords::load(&conn))).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
-async fn export_xmp_sidecars(photos: Vec<Photo>) -> Result<XmpSidecarResult, String> {
+fn get_metadata_templates(
+    app: AppHandle,
+    folder_path: Option<String>,
+) -> Result<Vec<MetadataTemplate>, String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    Ok(metadata_templates::load(&conn))
+}
+
+/// Replace the catalog's metadata templates. Names must be unique.
+#[tauri::command]
+fn set_metadata_templates(
+    app: AppHandle,
+    folder_path: Option<String>,
+    templates: Vec<MetadataTemplate>,
+) -> Result<(), String> {
+    let conn = open_db(&app, folder_path.as_deref())?;
+    metadata_templates::save(&conn, &templates)
+}
+
+/// Stamp the template named `template_name` onto `photos`, expanding its
+/// tokens for each photo. Returns the resulting metadata by photo ID.
+#[tauri::command]
+async fn apply_metadata_template(
+    app: AppHandle,
+    photos: Vec<Photo>,
+    template_name: String,
+    folder_path: Option<String>,
+) -> Result<HashMap<String, PhotoMetadata>, String> {
+    tokio::task::spawn_blocking(move || -> Result<HashMap<String, PhotoMetadata>, String> {
+        let mut conn = open_db(&app, folder_path.as_deref())?;
+        let template = metadata_templates::find(&conn, &template_name)?;
+        let photos: Vec<&Photo> = photos.iter().collect();
+        let label = format!(
+            "Apply {} to {}",
+            template.name,
+            photo_count_label(photos.len())
+        );
+        let applied =
+            metadata_templates::apply_to_photos(&mut conn, &photos, &[template], Some(&label))?;
+        Ok(applied.into_iter().collect())
+    })
+    .await
+    .map_err(|e| format!("Applying metadata template failed: {}", e))?
+}
+
+/// Sidecars of offline photos are queued in the catalog and written the
+/// next time their root is scanned with the drive attached.
+#[tauri::command]
+async fn export_xmp_sidecars(
+    app: AppHandle,
+    photos: Vec<Photo>,
+    folder_path: Option<String>,
+) -> Result<XmpSidecarResult, String> {
     tokio::task::spawn_blocking(move || -> Result<XmpSidecarResult, String> {
-        let results: Result<Vec<bool>, String> = photos
+        let conn = open_db(&app, folder_path.as_deref())?;
+        let labels = labels::load(&conn);
+        let keywords = keywords::load(&conn);
+        let (offline, online): (Vec<&Photo>, Vec<&Photo>) = photos
+            .iter()
+            .partition(|photo| photo.offline && !Path::new(&photo.file_path).is_file());
+        let results: Result<Vec<bool>, String> = online
             .par_iter()
             .map(|photo| {
                 let photo_path = Path::new(&photo.file_path);
@@ -1701,7 +2769,8 @@ async fn export_xmp_sidecars(photos: Vec<Photo>) -> Result<XmpSidecarResult, Str
                     return Ok(false);
                 }
                 let sidecar_path = xmp_sidecar_path(photo_path);
-                fs::write(&sidecar_path, lightroom_xmp(&photo)).map_err(|e| e.to_string())?;
+                fs::write(&sidecar_path, lightroom_xmp(photo, &labels, &keywords))
+                    .map_err(|e| e.to_string())?;
                 Ok(true)
             })
             .collect();
@@ -1709,9 +2778,20 @@ async fn export_xmp_sidecars(photos: Vec<Photo>) -> Result<XmpSidecarResult, Str
         let processed_count = results.iter().filter(|processed| **processed).count();
         let skipped_count = results.len() - processed_count;
 
+        for photo in &offline {
+            let sidecar_path = xmp_sidecar_path(Path::new(&photo.file_path));
+            volumes::queue_sidecar(
+                &conn,
+                &sidecar_path,
+                &lightroom_xmp(photo, &labels, &keywords),
+            )?;
+        }
+
         Ok(XmpSidecarResult {
             processed_count,
             skipped_count,
+            queued_count: offline.len(),
+            unknown_labels: Vec::new(),
         })
     })
     .await
@@ -1725,9 +2805,14 @@ async fn import_xmp_sidecars(
     folder_path: Option<String>,
 ) -> Result<XmpSidecarResult, String> {
     tokio::task::spawn_blocking(move || -> Result<XmpSidecarResult, String> {
-        let conn = open_db(&app, folder_path.as_deref())?;
+        let mut conn = open_db(&app, folder_path.as_deref())?;
+        let labels = labels::load(&conn);
+        let keywords = keywords::load(&conn);
         let mut processed_count = 0usize;
         let mut skipped_count = 0usize;
+        let mut unknown_labels = Vec::new();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut changes = Vec::new();
 
         for photo in photos {
             let sidecar_path = xmp_sidecar_path(Path::new(&photo.file_path));
@@ -1741,41 +2826,161 @@ async fn import_xmp_sidecars(
                 continue;
             };
 
-            let existing = read_photo_metadata(&conn, &[photo.id.clone()])
-                .remove(&photo.id)
-                .unwrap_or(PhotoMetadata {
-                    tags: photo.tags.clone().unwrap_or_default(),
-                    notes: photo.notes.clone().unwrap_or_default(),
-                    rating: photo.rating.unwrap_or(0).min(5),
-                    flag: photo.flag.clone().unwrap_or_else(|| "none".to_string()),
-                });
-            let tags = patch.tags.unwrap_or(existing.tags);
-            let notes = patch.notes.unwrap_or(existing.notes);
-            let rating = patch.rating.unwrap_or(existing.rating).min(5);
-            let flag = patch.flag.unwrap_or(existing.flag);
-            let flag = match flag.as_str() {
-                "pick" | "reject" => flag,
-                _ => "none".to_string(),
+            let before = read_photo_metadata(&tx, &[photo.id.clone()]).remove(&photo.id);
+            let existing = before.clone().unwrap_or(PhotoMetadata {
+                tags: photo.tags.clone().unwrap_or_default(),
+                notes: photo.notes.clone().unwrap_or_default(),
+                rating: photo.rating.unwrap_or(0).min(5),
+                flag: photo.flag.clone().unwrap_or_else(|| "none".to_string()),
+                color_label: photo.color_label.clone().unwrap_or_default(),
+                iptc: photo.iptc.clone().unwrap_or_default(),
+            });
+            let mut iptc = existing.iptc;
+            iptc.apply(patch.iptc);
+            let tags = match (patch.tags, patch.hierarchical_tags) {
+                (None, None) => existing.tags,
+                (subjects, hierarchical) => keywords.tags_from_xmp(
+                    subjects.unwrap_or_default(),
+                    hierarchical.unwrap_or_default(),
+                ),
             };
-            let tags_json = serde_json::to_string(&tags).map_err(|e| e.to_string())?;
-            conn.execute(
-                "INSERT INTO photo_metadata (photo_id, tags, notes, rating, flag) VALUES (?1, ?2, ?3, ?4, ?5)
-                 ON CONFLICT(photo_id) DO UPDATE SET tags=excluded.tags, notes=excluded.notes, rating=excluded.rating, flag=excluded.flag",
-                rusqlite::params![photo.id, tags_json, notes, rating, flag],
-            )
-            .map_err(|e| e.to_string())?;
+            let metadata = PhotoMetadata {
+                tags,
+                notes: patch.notes.unwrap_or(existing.notes),
+                rating: patch.rating.unwrap_or(existing.rating),
+                flag: patch
+                    .flag
+                    .map(|flag| labels.flag(&flag))
+                    .unwrap_or(existing.flag),
+                color_label: match patch.label {
+                    Some(label) if label.trim().is_empty() => String::new(),
+                    Some(label) => match labels.color_for_label(&label) {
+                        Some(color) => color.to_string(),
+                        None => {
+                            let label = label.trim().to_string();
+                            if !unknown_labels.contains(&label) {
+                                unknown_labels.push(label);
+                            }
+                            existing.color_label
+                        }
+                    },
+                    None => existing.color_label,
+                },
+                iptc,
+            };
+            if before.as_ref() != Some(&metadata) {
+                keywords::ensure(&tx, &metadata.tags)?;
+                write_photo_metadata(&tx, &photo.id, &metadata)?;
+                changes.push(metadata_history::Change {
+                    photo_id: photo.id.clone(),
+                    before,
+                    after: metadata,
+                });
+            }
             processed_count += 1;
         }
+        let label = format!("Import XMP of {}", photo_count_label(changes.len()));
+        metadata_history::record(&tx, &label, &changes)?;
+        tx.commit().map_err(|e| e.to_string())?;
 
         Ok(XmpSidecarResult {
             processed_count,
             skipped_count,
+            queued_count: 0,
+            unknown_labels,
         })
     })
     .await
     .map_err(|e| format!("XMP import failed: {}", e))?
 }
 
+/// Move the capture time of `photos` by `shift_seconds`, to correct a
+/// camera clock set wrong or to line up a second body with the first.
+/// Corrections accumulate in the catalog and are applied on every scan, and
+/// the corrected time is merged into each existing sidecar (a full sidecar
+/// is written where there is none, and queued for offline photos). Photos
+/// without a capture time are left out. Nothing is written if any photo's
+/// time would move out of range. Returns the corrected photos.
+#[tauri::command]
+async fn shift_capture_time(
+    app: AppHandle,
+    photos: Vec<Photo>,
+    shift_seconds: i64,
+    folder_path: Option<String>,
+) -> Result<Vec<Photo>, String> {
+    tokio::task::spawn_blocking(move || -> Result<Vec<Photo>, String> {
+        let mut photos: Vec<Photo> = photos
+            .into_iter()
+            .filter(|photo| photo.exif.date_taken.is_some())
+            .collect();
+        if photos.is_empty() || shift_seconds == 0 {
+            return Ok(photos);
+        }
+        for photo in &mut photos {
+            capture_time::shift(&mut photo.exif, shift_seconds)
+                .map_err(|error| format!("{}: {error}", photo.file_name))?;
+        }
+        let mut conn = open_db(&app, folder_path.as_deref())?;
+        let labels = labels::load(&conn);
+        let keywords = keywords::load(&conn);
+        let photo_ids: Vec<String> = photos.iter().map(|photo| photo.id.clone()).collect();
+        let mut metadata = read_photo_metadata(&conn, &photo_ids);
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let photo_ids: Vec<&str> = photo_ids.iter().map(String::as_str).collect();
+        capture_time::add_shift(&tx, &photo_ids, shift_seconds)?;
+
+        // The shift is committed once every sidecar has its date: writing a
+        // date again on retry is harmless, shifting the catalog twice is not.
+        // Sidecars only get the new date; new ones carry the catalog's
+        // metadata.
+        for photo in &photos {
+            let Some(date) = capture_time::xmp_date(&photo.exif) else {
+                continue;
+            };
+            let photo_path = Path::new(&photo.file_path);
+            let sidecar_path = xmp_sidecar_path(photo_path);
+            let new_sidecar = |metadata: Option<PhotoMetadata>| {
+                lightroom_xmp(&with_catalog_metadata(photo, metadata), &labels, &keywords)
+            };
+            if photo_path.is_file() {
+                let contents = if sidecar_path.exists() {
+                    let existing = fs::read_to_string(&sidecar_path)
+                        .map_err(|e| format!("{}: {e}", sidecar_path.display()))?;
+                    capture_time::with_xmp_date(&existing, &date)
+                } else {
+                    new_sidecar(metadata.remove(&photo.id))
+                };
+                fs::write(&sidecar_path, contents)
+                    .map_err(|e| format!("{}: {e}", sidecar_path.display()))?;
+            } else if photo.offline {
+                let contents = match volumes::queued_sidecar(&tx, &sidecar_path) {
+                    Some(queued) => capture_time::with_xmp_date(&queued, &date),
+                    None => new_sidecar(metadata.remove(&photo.id)),
+                };
+                volumes::queue_sidecar(&tx, &sidecar_path, &contents)?;
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(photos)
+    })
+    .await
+    .map_err(|e| format!("Capture time shift failed: {}", e))?
+}
+
+/// `photo` with the catalog's metadata in place of what the frontend sent.
+fn with_catalog_metadata(photo: &Photo, metadata: Option<PhotoMetadata>) -> Photo {
+    let metadata = metadata.unwrap_or_default();
+    Photo {
+        tags: Some(metadata.tags),
+        notes: Some(metadata.notes),
+        rating: Some(metadata.rating),
+        flag: Some(metadata.flag),
+        color_label: Some(metadata.color_label),
+        iptc: Some(metadata.iptc),
+        ..photo.clone()
+    }
+}
+
 fn sanitize_path_component(value: &str) -> String {
     let cleaned: String = value
         .chars()
@@ -1795,14 +3000,10 @@ fn sanitize_path_component(value: &str) -> String {
     }
 }
 
-fn photo_date(photo: &Photo) -> &DateTime<Utc> {
-    photo.exif.date_taken.as_ref().unwrap_or(&photo.modified_at)
-}
-
 fn export_relative_dir(photo: &Photo, organize_by: &str) -> PathBuf {
     match organize_by {
         "date" => {
-            let date = photo_date(photo);
+            let date = capture_time::local_capture_time(photo);
             PathBuf::from(date.format("%Y").to_string())
                 .join(date.format("%m").to_string())
                 .join(date.format("%d").to_string())
@@ -1830,24 +3031,9 @@ fn export_file_name(photo: &Photo, index: usize, pattern: Option<&str>) -> Strin
         .extension()
         .and_then(|value| value.to_str())
         .unwrap_or("");
-    let date = photo_date(photo).format("%Y%m%d").to_string();
-    let camera = photo
-        .exif
-        .camera_model
-        .as_deref()
-        .or(photo.exif.camera_make.as_deref())
-        .unwrap_or("camera");
-
     let base = pattern
         .filter(|value| !value.trim().is_empty())
-        .map(|value| {
-            value
-                .replace("{index}", &format!("{:04}", index + 1))
-                .replace("{name}", stem)
-                .replace("{date}", &date)
-                .replace("{camera}", camera)
-                .replace("{type}", &photo.file_type)
-        })
+        .map(|value| tokens::expand(value, photo, Some(index)))
         .unwrap_or_else(|| stem.to_string());
 
     let safe_base = sanitize_path_component(&base);
@@ -1904,30 +3090,55 @@ fn resolve_export_items(photos: Vec<Photo>, all_photos: Vec<Photo>, pair_mode: &
     };
 
     for photo in &photos {
-        let paired = photo.paired_with.as_ref().and_then(|id| by_id.get(id));
+        let members: Vec<&Photo> = photo
+            .group
+            .iter()
+            .flat_map(|group| group.other_photo_ids(&photo.id))
+            .filter_map(|id| by_id.get(id))
+            .collect();
+        // The RAW/JPEG choice is about stills; clips are always exported as
+        // themselves and never stand in for a still.
+        if is_video_file(Path::new(&photo.file_path)) {
+            add_photo(photo, &mut selected, &mut seen_paths);
+            if pair_mode == "both" {
+                for member in &members {
+                    add_photo(member, &mut selected, &mut seen_paths);
+                }
+            }
+            continue;
+        }
+        let members: Vec<&Photo> = match pair_mode {
+            "both" => members,
+            _ => members
+                .into_iter()
+                .filter(|item| !is_video_file(Path::new(&item.file_path)))
+                .collect(),
+        };
         match pair_mode {
             "both" => {
                 add_photo(photo, &mut selected, &mut seen_paths);
-                if let Some(pair) = paired {
-                    add_photo(pair, &mut selected, &mut seen_paths);
+                for member in &members {
+                    add_photo(member, &mut selected, &mut seen_paths);
                 }
             }
             "raw" => {
                 if is_raw_file(Path::new(&photo.file_path)) {
                     add_photo(photo, &mut selected, &mut seen_paths);
-                } else if let Some(pair) =
-                    paired.filter(|item| is_raw_file(Path::new(&item.file_path)))
+                } else if let Some(raw) = members
+                    .iter()
+                    .find(|item| is_raw_file(Path::new(&item.file_path)))
                 {
-                    add_photo(pair, &mut selected, &mut seen_paths);
+                    add_photo(raw, &mut selected, &mut seen_paths);
                 }
             }
             "jpeg" => {
                 if !is_raw_file(Path::new(&photo.file_path)) {
                     add_photo(photo, &mut selected, &mut seen_paths);
-                } else if let Some(pair) =
-                    paired.filter(|item| !is_raw_file(Path::new(&item.file_path)))
+                } else if let Some(rendered) = members
+                    .iter()
+                    .find(|item| !is_raw_file(Path::new(&item.file_path)))
                 {
-                    add_photo(pair, &mut selected, &mut seen_paths);
+                    add_photo(rendered, &mut selected, &mut seen_paths);
                 }
             }
             _ => add_photo(photo, &mut selected, &mut seen_paths),
@@ -1954,13 +3165,44 @@ fn xml_escape(value: &str) -> String {
         .replace('\'', "&apos;")
 }
 
+/// Resolve the predefined entities and numeric character references.
+/// Anything else is kept as written.
 fn xml_unescape(value: &str) -> String {
-    value
-        .replace("&apos;", "'")
-        .replace("&quot;", "\"")
-        .replace("&gt;", ">")
-        .replace("&lt;", "<")
-        .replace("&amp;", "&")
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let resolved = rest.find(';').and_then(|end| {
+            let character = match &rest[1..end] {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                entity => {
+                    let code = match entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => entity.strip_prefix('#')?.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((character, end))
+        });
+        match resolved {
+            Some((character, end)) => {
+                out.push(character);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
 }
 
 fn xmp_sidecar_path(path: &Path) -> PathBuf {
@@ -1991,6 +3233,20 @@ fn xmp_block<'a>(contents: &'a str, tag: &str) -> Option<&'a str> {
     Some(&contents[after_open..end])
 }
 
+/// A text property written as an attribute or an element, or the default
+/// item of a language alternative.
+fn xmp_text(contents: &str, name: &str) -> Option<String> {
+    if let Some(value) = xmp_attribute(contents, name) {
+        return Some(value);
+    }
+    let block = xmp_block(contents, name)?;
+    if block.contains("<rdf:li") {
+        rdf_li_values(block).into_iter().next()
+    } else {
+        Some(xml_unescape(block.trim()))
+    }
+}
+
 fn rdf_li_values(block: &str) -> Vec<String> {
     let mut values = Vec::new();
     let mut rest = block;
@@ -2023,12 +3279,23 @@ fn parse_xmp_sidecar(contents: &str) -> Option<XmpMetadataPatch> {
         touched = true;
     }
 
+    // `xmp:Label` is the color label. Hologram keeps its flag apart; older
+    // sidecars and other tools put Pick or Reject in the label instead.
+    let hologram_flag = xmp_attribute(contents, "hologram:Flag");
+    if let Some(flag) = &hologram_flag {
+        patch.flag = Some(flag.clone());
+        touched = true;
+    }
     if let Some(label) = xmp_attribute(contents, "xmp:Label") {
-        patch.flag = Some(match label.trim().to_ascii_lowercase().as_str() {
-            "pick" | "select" | "selected" => "pick".to_string(),
-            "reject" | "rejected" => "reject".to_string(),
-            _ => "none".to_string(),
-        });
+        match label.trim().to_ascii_lowercase().as_str() {
+            "pick" | "select" | "selected" if hologram_flag.is_none() => {
+                patch.flag = Some("pick".to_string())
+            }
+            "reject" | "rejected" if hologram_flag.is_none() => {
+                patch.flag = Some("reject".to_string())
+            }
+            _ => patch.label = Some(label),
+        }
         touched = true;
     }
 
@@ -2036,13 +3303,62 @@ fn parse_xmp_sidecar(contents: &str) -> Option<XmpMetadataPatch> {
         patch.tags = Some(rdf_li_values(subject));
         touched = true;
     }
+    if let Some(subject) = xmp_block(contents, "lr:hierarchicalSubject") {
+        patch.hierarchical_tags = Some(rdf_li_values(subject));
+        touched = true;
+    }
 
+    // Hologram's own sidecars carry notes separately from the caption and
+    // mark a description that is a caption; without the mark, one equal
+    // to the notes stands in for a missing caption. Elsewhere the
+    // description is taken as notes.
+    let hologram_notes = xmp_attribute(contents, "hologram:Notes");
+    let has_caption = xmp_attribute(contents, "hologram:HasCaption")
+        .is_some_and(|value| value.eq_ignore_ascii_case("true"));
     if let Some(description) = xmp_block(contents, "dc:description") {
-        if let Some(notes) = rdf_li_values(description).first() {
-            patch.notes = Some(notes.clone());
+        if let Some(description) = rdf_li_values(description).first() {
+            match &hologram_notes {
+                Some(notes) if notes == description && !has_caption => {
+                    patch.iptc.caption = Some(String::new())
+                }
+                Some(_) => patch.iptc.caption = Some(description.clone()),
+                None => patch.notes = Some(description.clone()),
+            }
+            touched = true;
+        }
+    }
+    if let Some(notes) = hologram_notes {
+        patch.notes = Some(notes);
+        touched = true;
+    }
+
+    let text_fields = [
+        ("photoshop:Headline", &mut patch.iptc.headline),
+        ("photoshop:Credit", &mut patch.iptc.credit_line),
+        ("photoshop:City", &mut patch.iptc.city),
+        ("photoshop:State", &mut patch.iptc.state),
+        ("photoshop:Country", &mut patch.iptc.country),
+        ("dc:rights", &mut patch.iptc.copyright_notice),
+        ("xmpRights:UsageTerms", &mut patch.iptc.usage_terms),
+        ("Iptc4xmpExt:Event", &mut patch.iptc.event),
+        ("Iptc4xmpCore:CiEmailWork", &mut patch.iptc.contact_email),
+        ("Iptc4xmpCore:CiTelWork", &mut patch.iptc.contact_phone),
+        ("Iptc4xmpCore:CiUrlWork", &mut patch.iptc.contact_url),
+    ];
+    for (name, field) in text_fields {
+        if let Some(value) = xmp_text(contents, name) {
+            *field = Some(value);
             touched = true;
         }
     }
+    if let Some(creator) = xmp_block(contents, "dc:creator") {
+        patch.iptc.creator = Some(rdf_li_values(creator).join("; "));
+        touched = true;
+    }
+    if let Some(people) = xmp_block(contents, "Iptc4xmpExt:PersonInImage") {
+        patch.iptc.person_shown = Some(rdf_li_values(people));
+        touched = true;
+    }
 
     if touched {
         Some(patch)
@@ -2082,23 +3398,90 @@ mod tests {
         );
         assert_eq!(patch.notes.as_deref(), Some("Hero frame"));
     }
+
+    #[test]
+    fn notes_and_caption_survive_a_sidecar_round_trip() {
+        let path = Path::new("/shoot/IMG_0001.JPG");
+        let mut photo = photo_from_exif(
+            path,
+            "IMG_0001".to_string(),
+            0,
+            Utc::now(),
+            ExifData::default(),
+        );
+        photo.rating = Some(3);
+        photo.flag = Some("pick".to_string());
+        photo.color_label = Some("red".to_string());
+        photo.tags = Some(vec!["portfolio".to_string()]);
+        let labels = LabelSet::default();
+        let keywords = KeywordTree::default();
+
+        for (notes, caption) in [
+            ("Hero frame", ""),
+            ("Hero frame", "Hero frame"),
+            ("Hero frame", "Harbour at dawn"),
+            ("", "Harbour at dawn"),
+        ] {
+            photo.notes = Some(notes.to_string());
+            photo.iptc = Some(IptcMetadata {
+                caption: caption.to_string(),
+                ..IptcMetadata::default()
+            });
+            let patch =
+                parse_xmp_sidecar(&lightroom_xmp(&photo, &labels, &keywords)).expect("xmp patch");
+            assert_eq!(
+                patch.notes.as_deref(),
+                Some(notes),
+                "{notes:?} / {caption:?}"
+            );
+            assert_eq!(
+                patch.iptc.caption.as_deref(),
+                Some(caption),
+                "{notes:?} / {caption:?}"
+            );
+            assert_eq!(patch.rating, Some(3));
+            assert_eq!(patch.flag.as_deref(), Some("pick"));
+            assert_eq!(patch.label.as_deref(), Some("Red"));
+            assert_eq!(patch.tags, Some(vec!["portfolio".to_string()]));
+        }
+    }
 }
 
 fn metadata_csv(rows: &[(Photo, String)]) -> String {
-    let mut csv = String::from("file_name,relative_path,original_path,rating,flag,tags,notes\n");
+    let mut csv = String::from(
+        "file_name,relative_path,original_path,rating,flag,color_label,tags,notes,headline,caption,creator,contact_email,contact_phone,contact_url,credit_line,copyright_notice,usage_terms,city,state,country,event,person_shown\n",
+    );
     for (photo, relative_path) in rows {
         let tags = photo.tags.clone().unwrap_or_default().join("|");
         let rating = photo.rating.unwrap_or(0).min(5).to_string();
         let flag = photo.flag.clone().unwrap_or_else(|| "none".to_string());
+        let color_label = photo.color_label.clone().unwrap_or_default();
         let notes = photo.notes.clone().unwrap_or_default();
+        let iptc = photo.iptc.clone().unwrap_or_default();
+        let person_shown = iptc.person_shown.join("|");
         let fields = [
             photo.file_name.as_str(),
             relative_path.as_str(),
             photo.file_path.as_str(),
             rating.as_str(),
             flag.as_str(),
+            color_label.as_str(),
             tags.as_str(),
             notes.as_str(),
+            iptc.headline.as_str(),
+            iptc.caption.as_str(),
+            iptc.creator.as_str(),
+            iptc.contact_email.as_str(),
+            iptc.contact_phone.as_str(),
+            iptc.contact_url.as_str(),
+            iptc.credit_line.as_str(),
+            iptc.copyright_notice.as_str(),
+            iptc.usage_terms.as_str(),
+            iptc.city.as_str(),
+            iptc.state.as_str(),
+            iptc.country.as_str(),
+            iptc.event.as_str(),
+            person_shown.as_str(),
         ];
         csv.push_str(
             &fields
@@ -2112,20 +3495,91 @@ fn metadata_csv(rows: &[(Photo, String)]) -> String {
     csv
 }
 
-fn lightroom_xmp(photo: &Photo) -> String {
+/// Namespace of the XMP properties only Hologram reads.
+const HOLOGRAM_XMP_NAMESPACE: &str = "http://ns.thatxliner.dev/hologram/1.0/";
+
+fn lightroom_xmp(photo: &Photo, labels: &LabelSet, keywords: &KeywordTree) -> String {
     let rating = photo.rating.unwrap_or(0).min(5);
     let flag = photo.flag.as_deref().unwrap_or("none");
-    let label = match flag {
-        "pick" => "Pick",
-        "reject" => "Reject",
-        _ => "",
+    let color = labels::color(photo.color_label.as_deref().unwrap_or_default());
+    let label = if color.is_empty() {
+        String::new()
+    } else {
+        labels.color_name(&color)
     };
-    let tags = photo.tags.clone().unwrap_or_default();
+    let (subjects, hierarchical) = keywords.xmp_subjects(photo.tags.as_deref().unwrap_or_default());
     let notes = photo.notes.clone().unwrap_or_default();
-    let tag_items = tags
-        .iter()
-        .map(|tag| format!("<rdf:li>{}</rdf:li>", xml_escape(tag)))
-        .collect::<String>();
+    let li_items = |values: &[String]| {
+        values
+            .iter()
+            .map(|value| format!("<rdf:li>{}</rdf:li>", xml_escape(value)))
+            .collect::<String>()
+    };
+    // Carries clock corrections to other applications reading the sidecar.
+    let date_attributes = capture_time::xmp_date(&photo.exif)
+        .map(|date| {
+            format!(
+                "\n      exif:DateTimeOriginal=\"{date}\"\n      photoshop:DateCreated=\"{date}\""
+            )
+        })
+        .unwrap_or_default();
+    let iptc = photo.iptc.clone().unwrap_or_default();
+    // The caption is the description other applications show; notes are
+    // kept apart so they survive a round trip alongside a caption.
+    let (description, caption_attribute) = if iptc.caption.is_empty() {
+        (&notes, "")
+    } else {
+        (&iptc.caption, "\n      hologram:HasCaption=\"True\"")
+    };
+    let iptc_attributes = [
+        ("photoshop:Headline", &iptc.headline),
+        ("photoshop:Credit", &iptc.credit_line),
+        ("photoshop:City", &iptc.city),
+        ("photoshop:State", &iptc.state),
+        ("photoshop:Country", &iptc.country),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| format!("\n      {name}=\"{}\"", xml_escape(value)))
+    .collect::<String>();
+    let iptc_elements = [
+        ("dc:creator", "Seq", vec![iptc.creator.clone()]),
+        ("dc:rights", "Alt", vec![iptc.copyright_notice.clone()]),
+        ("xmpRights:UsageTerms", "Alt", vec![iptc.usage_terms.clone()]),
+        ("Iptc4xmpExt:Event", "Alt", vec![iptc.event.clone()]),
+        ("Iptc4xmpExt:PersonInImage", "Bag", iptc.person_shown.clone()),
+    ]
+    .iter()
+    .filter_map(|(name, container, values)| {
+        let lang = if *container == "Alt" {
+            r#" xml:lang="x-default""#
+        } else {
+            ""
+        };
+        let items = values
+            .iter()
+            .filter(|value| !value.is_empty())
+            .map(|value| format!("<rdf:li{lang}>{}</rdf:li>", xml_escape(value)))
+            .collect::<String>();
+        (!items.is_empty()).then(|| {
+            format!("\n      <{name}>\n        <rdf:{container}>{items}</rdf:{container}>\n      </{name}>")
+        })
+    })
+    .collect::<String>();
+    let contact_attributes = [
+        ("Iptc4xmpCore:CiEmailWork", &iptc.contact_email),
+        ("Iptc4xmpCore:CiTelWork", &iptc.contact_phone),
+        ("Iptc4xmpCore:CiUrlWork", &iptc.contact_url),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| format!(" {name}=\"{}\"", xml_escape(value)))
+    .collect::<String>();
+    let contact_element = if contact_attributes.is_empty() {
+        String::new()
+    } else {
+        format!("\n      <Iptc4xmpCore:CreatorContactInfo{contact_attributes}/>")
+    };
 
     format!(
         r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
@@ -2134,24 +3588,44 @@ fn lightroom_xmp(photo: &Photo) -> String {
     <rdf:Description rdf:about=""
       xmlns:xmp="http://ns.adobe.com/xap/1.0/"
       xmlns:dc="http://purl.org/dc/elements/1.1/"
+      xmlns:exif="http://ns.adobe.com/exif/1.0/"
+      xmlns:photoshop="http://ns.adobe.com/photoshop/1.0/"
+      xmlns:xmpRights="http://ns.adobe.com/xap/1.0/rights/"
+      xmlns:Iptc4xmpCore="http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/"
+      xmlns:Iptc4xmpExt="http://iptc.org/std/Iptc4xmpExt/2008-02-29/"
+      xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+      xmlns:hologram="{HOLOGRAM_XMP_NAMESPACE}"
       xmp:Rating="{rating}"
-      xmp:Label="{label}">
+      xmp:Label="{label}"
+      hologram:Flag="{flag}"
+      hologram:Notes="{notes}"{caption_attribute}{date_attributes}{iptc_attributes}>
       <dc:subject>
         <rdf:Bag>{tag_items}</rdf:Bag>
       </dc:subject>
+      <lr:hierarchicalSubject>
+        <rdf:Bag>{hierarchical_items}</rdf:Bag>
+      </lr:hierarchicalSubject>
       <dc:description>
         <rdf:Alt>
-          <rdf:li xml:lang="x-default">{notes}</rdf:li>
+          <rdf:li xml:lang="x-default">{description}</rdf:li>
         </rdf:Alt>
-      </dc:description>
+      </dc:description>{iptc_elements}{contact_element}
     </rdf:Description>
   </rdf:RDF>
 </x:xmpmeta>
 <?xpacket end="w"?>"#,
         rating = rating,
-        label = xml_escape(label),
-        tag_items = tag_items,
-        notes = xml_escape(&notes)
+        label = xml_escape(&label),
+        flag = xml_escape(flag),
+        tag_items = li_items(&subjects),
+        hierarchical_items = li_items(&hierarchical),
+        notes = xml_escape(&notes),
+        description = xml_escape(description),
+        caption_attribute = caption_attribute,
+        date_attributes = date_attributes,
+        iptc_attributes = iptc_attributes,
+        iptc_elements = iptc_elements,
+        contact_element = contact_element
     )
 }
 
@@ -2167,80 +3641,68 @@ async fn export_photos(
     photos: Vec<Photo>,
     all_photos: Vec<Photo>,
     options: ExportOptions,
+    app: AppHandle,
+    folder_path: Option<String>,
 ) -> Result<ExportResult, String> {
-    tokio::task::spawn_blocking(move || -> Result<ExportResult, String> {
-        let selected = resolve_export_items(photos, all_photos, &options.pair_mode);
-        if selected.is_empty() {
-            return Err("No photos selected for export".to_string());
-        }
-
-        let destination = PathBuf::from(&options.destination_path);
-        let mode = options.mode.as_str();
-        let output_path = match mode {
-            "zip" => {
-                if destination.extension().and_then(|value| value.to_str()) == Some("zip") {
-                    destination
-                } else {
-                    destination.join("hologram-export.zip")
-                }
-            }
-            "lightroom" => destination.join("hologram-lightroom-export"),
-            _ => destination,
-        };
+    let job = app.state::<JobRegistry>().start(&app, "export");
+    let (labels, keywords) = match open_db(&app, folder_path.as_deref()) {
+        Ok(conn) => (labels::load(&conn), keywords::load(&conn)),
+        Err(_) => Default::default(),
+    };
+    tokio::task::spawn_blocking(move || {
+        let result = export_photos_blocking(photos, all_photos, &options, &labels, &keywords, &job);
+        job.finish(&result);
+        result
+    })
+    .await
+    .map_err(|e| format!("Export failed: {}", e))?
+}
 
-        let mut rows: Vec<(Photo, String)> = Vec::new();
-        let mut skipped_count = 0usize;
+fn export_photos_blocking(
+    photos: Vec<Photo>,
+    all_photos: Vec<Photo>,
+    options: &ExportOptions,
+    labels: &LabelSet,
+    keywords: &KeywordTree,
+    job: &JobHandle,
+) -> Result<ExportResult, String> {
+    let selected = resolve_export_items(photos, all_photos, &options.pair_mode);
+    if selected.is_empty() {
+        return Err("No photos selected for export".to_string());
+    }
 
-        if mode == "zip" {
-            if let Some(parent) = output_path.parent() {
-                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-            }
-            let file = fs::File::create(&output_path).map_err(|e| e.to_string())?;
-            let mut zip = ZipWriter::new(file);
-            let file_options = SimpleFileOptions::default()
-                .compression_method(CompressionMethod::Deflated)
-                .unix_permissions(0o644);
-
-            for (idx, photo) in selected.iter().enumerate() {
-                let src = Path::new(&photo.file_path);
-                if !src.is_file() {
-                    skipped_count += 1;
-                    continue;
-                }
-                let relative = export_relative_dir(photo, &options.organize_by).join(
-                    export_file_name(photo, idx, options.rename_pattern.as_deref()),
-                );
-                let relative_name = relative_zip_name(&relative);
-                zip.start_file(&relative_name, file_options)
-                    .map_err(|e| e.to_string())?;
-                let mut input = fs::File::open(src).map_err(|e| e.to_string())?;
-                io::copy(&mut input, &mut zip).map_err(|e| e.to_string())?;
-                rows.push((photo.clone(), relative_name));
+    let destination = PathBuf::from(&options.destination_path);
+    let mode = options.mode.as_str();
+    let output_path = match mode {
+        "zip" => {
+            if destination.extension().and_then(|value| value.to_str()) == Some("zip") {
+                destination
+            } else {
+                destination.join("hologram-export.zip")
             }
+        }
+        "lightroom" => destination.join("hologram-lightroom-export"),
+        _ => destination,
+    };
 
-            if options.include_metadata {
-                zip.start_file("hologram-metadata.csv", file_options)
-                    .map_err(|e| e.to_string())?;
-                zip.write_all(metadata_csv(&rows).as_bytes())
-                    .map_err(|e| e.to_string())?;
-            }
+    let mut rows: Vec<(Photo, String)> = Vec::new();
+    let mut skipped_count = 0usize;
 
-            zip.finish().map_err(|e| e.to_string())?;
-            return Ok(ExportResult {
-                exported_count: rows.len(),
-                skipped_count,
-                output_path: output_path.to_string_lossy().to_string(),
-                metadata_path: if options.include_metadata {
-                    Some("hologram-metadata.csv".to_string())
-                } else {
-                    None
-                },
-            });
+    if mode == "zip" {
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
         }
-
-        fs::create_dir_all(&output_path).map_err(|e| e.to_string())?;
+        let file = fs::File::create(&output_path).map_err(|e| e.to_string())?;
+        let mut zip = ZipWriter::new(file);
+        let file_options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(0o644);
 
         for (idx, photo) in selected.iter().enumerate() {
+            if job.is_cancelled() {
+                return Err(JOB_CANCELLED.to_string());
+            }
+            job.progress(idx, selected.len());
             let src = Path::new(&photo.file_path);
             if !src.is_file() {
                 skipped_count += 1;
@@ -2251,46 +3713,103 @@ async fn export_photos(
                 idx,
                 options.rename_pattern.as_deref(),
             ));
-            let dest = unique_path(output_path.join(&relative));
-            if let Some(parent) = dest.parent() {
-                fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-            }
-            fs::copy(src, &dest).map_err(|e| e.to_string())?;
-            let relative_to_output = dest
-                .strip_prefix(&output_path)
-                .unwrap_or(&dest)
-                .to_string_lossy()
-                .to_string();
-            rows.push((photo.clone(), relative_to_output.clone()));
-
-            if mode == "lightroom" {
-                let xmp_path = dest.with_extension(format!(
-                    "{}.xmp",
-                    dest.extension()
-                        .and_then(|value| value.to_str())
-                        .unwrap_or("photo")
-                ));
-                fs::write(xmp_path, lightroom_xmp(photo)).map_err(|e| e.to_string())?;
-            }
+            let relative_name = relative_zip_name(&relative);
+            zip.start_file(&relative_name, file_options)
+                .map_err(|e| e.to_string())?;
+            let mut input = fs::File::open(src).map_err(|e| e.to_string())?;
+            io::copy(&mut input, &mut zip).map_err(|e| e.to_string())?;
+            rows.push((photo.clone(), relative_name));
         }
 
-        let metadata_path = if options.include_metadata || mode == "lightroom" {
-            let csv_path = output_path.join("hologram-metadata.csv");
-            fs::write(&csv_path, metadata_csv(&rows)).map_err(|e| e.to_string())?;
-            Some(csv_path.to_string_lossy().to_string())
-        } else {
-            None
-        };
+        if options.include_metadata {
+            zip.start_file("hologram-metadata.csv", file_options)
+                .map_err(|e| e.to_string())?;
+            zip.write_all(metadata_csv(&rows).as_bytes())
+                .map_err(|e| e.to_string())?;
+        }
 
-        Ok(ExportResult {
+        zip.finish().map_err(|e| e.to_string())?;
+        return Ok(ExportResult {
             exported_count: rows.len(),
             skipped_count,
             output_path: output_path.to_string_lossy().to_string(),
-            metadata_path,
-        })
+            metadata_path: if options.include_metadata {
+                Some("hologram-metadata.csv".to_string())
+            } else {
+                None
+            },
+        });
+    }
+
+    fs::create_dir_all(&output_path).map_err(|e| e.to_string())?;
+
+    for (idx, photo) in selected.iter().enumerate() {
+        if job.is_cancelled() {
+            return Err(JOB_CANCELLED.to_string());
+        }
+        job.progress(idx, selected.len());
+        let src = Path::new(&photo.file_path);
+        if !src.is_file() {
+            skipped_count += 1;
+            continue;
+        }
+        let relative = export_relative_dir(photo, &options.organize_by).join(export_file_name(
+            photo,
+            idx,
+            options.rename_pattern.as_deref(),
+        ));
+        let dest = unique_path(output_path.join(&relative));
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        fs::copy(src, &dest).map_err(|e| e.to_string())?;
+        let relative_to_output = dest
+            .strip_prefix(&output_path)
+            .unwrap_or(&dest)
+            .to_string_lossy()
+            .to_string();
+        rows.push((photo.clone(), relative_to_output.clone()));
+
+        if mode == "lightroom" {
+            let xmp_path = dest.with_extension(format!(
+                "{}.xmp",
+                dest.extension()
+                    .and_then(|value| value.to_str())
+                    .unwrap_or("photo")
+            ));
+            fs::write(xmp_path, lightroom_xmp(photo, labels, keywords))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    let metadata_path = if options.include_metadata || mode == "lightroom" {
+        let csv_path = output_path.join("hologram-metadata.csv");
+        fs::write(&csv_path, metadata_csv(&rows)).map_err(|e| e.to_string())?;
+        Some(csv_path.to_string_lossy().to_string())
+    } else {
+        None
+    };
+
+    Ok(ExportResult {
+        exported_count: rows.len(),
+        skipped_count,
+        output_path: output_path.to_string_lossy().to_string(),
+        metadata_path,
+    })
+}
+
+/// Offload a memory card: copy, verify and organize every photo under
+/// `source_path`, streaming "ingest-progress" events as files complete.
+#[tauri::command]
+async fn ingest_card(options: IngestOptions, app: AppHandle) -> Result<IngestResult, String> {
+    let job = app.state::<JobRegistry>().start(&app, "ingest");
+    tokio::task::spawn_blocking(move || {
+        let result = ingest::ingest(&app, &options, &job);
+        job.finish(&result);
+        result
     })
     .await
-    .map_err(|e| format!("Export failed: {}", e))?
+    .map_err(|e| format!("Ingest failed: {}", e))?
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -2326,26 +3845,67 @@ pub fn run() {
             };
 
             app.manage(DenoiseModel(Arc::new(Mutex::new(session))));
+            app.manage(FolderWatcher::default());
+            app.manage(JobRegistry::default());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             scan_folder_fast,
+            rescan_folder,
+            create_catalog,
+            get_catalog,
+            add_catalog_root,
+            remove_catalog_root,
+            scan_catalog,
+            rescan_catalog,
+            watch_folder,
+            watch_catalog,
+            unwatch_folder,
+            list_jobs,
+            cancel_job,
             find_missing_photo_ids,
             generate_thumbnails,
             prerender_raws,
             prioritize_raw_renders,
             filter_photos,
+            query_photos,
             get_photo_stats,
             load_full_resolution_image_command,
             apply_edits_and_save,
             denoise_image,
+            cache_offline_previews,
+            get_full_metadata,
+            list_damaged_files,
+            get_pairing_options,
+            set_pairing_options,
+            get_scan_options,
+            set_scan_options,
+            get_identity_scheme,
+            set_identity_scheme,
             set_photo_metadata,
             get_photo_metadata,
+            set_iptc_metadata,
+            apply_metadata_batch,
+            undo_metadata_change,
+            redo_metadata_change,
+            get_metadata_history,
+            get_label_set,
+            set_label_set,
+            get_keywords,
+            save_keyword,
+            delete_keyword,
+            import_keyword_list,
+            export_keyword_list,
+            get_metadata_templates,
+            set_metadata_templates,
+            apply_metadata_template,
             read_autocull_feature_cache,
             write_autocull_feature_cache,
             export_xmp_sidecars,
             import_xmp_sidecars,
-            export_photos
+            shift_capture_time,
+            export_photos,
+            ingest_card
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,562 @@
+use crate::iptc::IptcPatch;
+use crate::{delete_photo_metadata, read_photo_metadata, write_photo_metadata, PhotoMetadata};
+use crate::{keywords, labels};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Batches kept for undo; older ones are dropped as new ones are recorded.
+const HISTORY_LIMIT: i64 = 500;
+/// Repeated edits of one photo this close together (typing notes, stepping
+/// a rating) are journaled as one batch.
+const COALESCE_SECONDS: i64 = 5;
+
+/// A batch edit of `PhotoMetadata`: only the fields present are changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetadataPatch {
+    /// Replaces each photo's tags.
+    pub tags: Option<Vec<String>>,
+    /// Added to each photo's tags, after `tags` is applied.
+    pub add_tags: Vec<String>,
+    pub remove_tags: Vec<String>,
+    pub notes: Option<String>,
+    pub rating: Option<u8>,
+    /// A built-in or catalog flag state; anything else clears the flag.
+    pub flag: Option<String>,
+    /// One of `labels::COLORS`, or empty to clear the color label.
+    pub color_label: Option<String>,
+    pub iptc: IptcPatch,
+}
+
+impl MetadataPatch {
+    pub fn iptc(iptc: IptcPatch) -> Self {
+        Self {
+            iptc,
+            ..Self::default()
+        }
+    }
+}
+
+impl PhotoMetadata {
+    pub fn apply(&mut self, patch: &MetadataPatch) {
+        if let Some(tags) = &patch.tags {
+            self.tags = tags.clone();
+        }
+        for tag in &patch.add_tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !self.tags.iter().any(|known| known == tag) {
+                self.tags.push(tag.to_string());
+            }
+        }
+        self.tags
+            .retain(|tag| !patch.remove_tags.iter().any(|removed| removed == tag));
+        if let Some(notes) = &patch.notes {
+            self.notes = notes.clone();
+        }
+        if let Some(rating) = patch.rating {
+            self.rating = rating.min(5);
+        }
+        if let Some(flag) = &patch.flag {
+            self.flag = flag.clone();
+        }
+        if let Some(color) = &patch.color_label {
+            self.color_label = labels::color(color);
+        }
+        self.iptc.apply(patch.iptc.clone());
+    }
+}
+
+/// One photo's metadata around a recorded batch. `before` is `None` when
+/// the catalog had no metadata for the photo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    pub photo_id: String,
+    pub before: Option<PhotoMetadata>,
+    pub after: PhotoMetadata,
+}
+
+/// A keyword row around a recorded batch, `None` on the side where the
+/// keyword did not exist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeywordChange {
+    pub id: i64,
+    pub before: Option<keywords::KeywordRow>,
+    pub after: Option<keywords::KeywordRow>,
+}
+
+/// A recorded batch, newest first in `get_metadata_history`. Undone
+/// batches stay listed until a new batch replaces them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub label: String,
+    pub created_at: String,
+    pub photo_count: usize,
+    pub undone: bool,
+}
+
+/// The batch an undo or redo stepped over, with the metadata it left on
+/// each photo.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryStep {
+    pub entry: HistoryEntry,
+    pub metadata: HashMap<String, PhotoMetadata>,
+    /// The step renamed, moved or deleted keywords.
+    pub keywords_changed: bool,
+}
+
+/// Journal `changes` as one undoable batch. Recording a batch discards the
+/// batches that were undone, as in an editor.
+pub fn record(conn: &rusqlite::Connection, label: &str, changes: &[Change]) -> Result<(), String> {
+    record_with_keywords(conn, label, changes, &[])
+}
+
+/// Journal `changes` together with the keyword rows the same edit changed,
+/// so undo puts the keyword tree back along with the photos' tags.
+pub fn record_with_keywords(
+    conn: &rusqlite::Connection,
+    label: &str,
+    changes: &[Change],
+    keyword_changes: &[KeywordChange],
+) -> Result<(), String> {
+    if changes.is_empty() && keyword_changes.is_empty() {
+        return Ok(());
+    }
+    let changes_json = serde_json::to_string(changes).map_err(|e| e.to_string())?;
+    let keywords_json = serde_json::to_string(keyword_changes).map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM metadata_history WHERE undone = 1", [])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO metadata_history (label, created_at, photo_count, changes, keyword_changes)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            label,
+            Utc::now().to_rfc3339(),
+            changes.len(),
+            changes_json,
+            keywords_json
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM metadata_history WHERE id <= last_insert_rowid() - ?1",
+        [HISTORY_LIMIT],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Journal a single photo's edit, folding it into the newest batch when
+/// that batch edited only the same photo under the same label moments ago.
+pub fn record_edit(conn: &rusqlite::Connection, label: &str, change: Change) -> Result<(), String> {
+    if change.before.as_ref() == Some(&change.after) {
+        return Ok(());
+    }
+    let newest = conn.query_row(
+        "SELECT id, label, created_at, photo_count, undone, changes FROM metadata_history
+         ORDER BY id DESC LIMIT 1",
+        [],
+        |row| Ok((entry_from_row(row)?, row.get::<_, String>(5)?)),
+    );
+    if let Ok((entry, changes_json)) = newest {
+        let recent = DateTime::parse_from_rfc3339(&entry.created_at).is_ok_and(|created_at| {
+            (Utc::now() - created_at.to_utc()).num_seconds() < COALESCE_SECONDS
+        });
+        let previous: Vec<Change> = serde_json::from_str(&changes_json).unwrap_or_default();
+        if let [previous] = previous.as_slice() {
+            if !entry.undone
+                && recent
+                && entry.label == label
+                && previous.photo_id == change.photo_id
+                && previous.after == change.before.clone().unwrap_or_default()
+            {
+                let merged = [Change {
+                    photo_id: change.photo_id,
+                    before: previous.before.clone(),
+                    after: change.after,
+                }];
+                let changes_json = serde_json::to_string(&merged).map_err(|e| e.to_string())?;
+                conn.execute(
+                    "UPDATE metadata_history SET changes = ?1, created_at = ?2 WHERE id = ?3",
+                    rusqlite::params![changes_json, Utc::now().to_rfc3339(), entry.id],
+                )
+                .map_err(|e| e.to_string())?;
+                return Ok(());
+            }
+        }
+    }
+    record(conn, label, &[change])
+}
+
+/// Patch the metadata of `photo_ids` in one transaction and journal it
+/// under `label`. Photos the patch leaves as they were are not journaled.
+/// Returns the resulting metadata by photo ID.
+pub fn apply_batch(
+    conn: &mut rusqlite::Connection,
+    photo_ids: &[String],
+    patch: &MetadataPatch,
+    label: &str,
+) -> Result<HashMap<String, PhotoMetadata>, String> {
+    let mut patch = patch.clone();
+    patch.flag = patch.flag.map(|flag| labels::load(conn).flag(&flag));
+    let vocabulary = keywords::load(conn);
+    let resolve = |tags: &mut Vec<String>| {
+        for tag in tags.iter_mut() {
+            *tag = vocabulary.resolve(tag);
+        }
+    };
+    if let Some(tags) = patch.tags.as_mut() {
+        resolve(tags);
+    }
+    resolve(&mut patch.add_tags);
+    let mut existing = read_photo_metadata(conn, photo_ids);
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut result = HashMap::new();
+    let mut changes = Vec::new();
+    for photo_id in photo_ids {
+        if result.contains_key(photo_id) {
+            continue;
+        }
+        let before = existing.remove(photo_id);
+        let mut after = before.clone().unwrap_or_default();
+        after.apply(&patch);
+        if before.as_ref() != Some(&after) {
+            keywords::ensure(&tx, &after.tags)?;
+            write_photo_metadata(&tx, photo_id, &after)?;
+            changes.push(Change {
+                photo_id: photo_id.clone(),
+                before,
+                after: after.clone(),
+            });
+        }
+        result.insert(photo_id.clone(), after);
+    }
+    record(&tx, label, &changes)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        label: row.get(1)?,
+        created_at: row.get(2)?,
+        photo_count: row.get(3)?,
+        undone: row.get(4)?,
+    })
+}
+
+/// Step over the newest batch still applied (`undo`) or the oldest one
+/// undone (`redo`). Each field the batch changed is taken back to the other
+/// side of it unless it was edited since, so later edits to other fields
+/// (or other tags) survive.
+fn step(conn: &mut rusqlite::Connection, undo: bool) -> Result<Option<HistoryStep>, String> {
+    let sql = if undo {
+        "SELECT id, label, created_at, photo_count, undone, changes, keyword_changes
+         FROM metadata_history WHERE undone = 0 ORDER BY id DESC LIMIT 1"
+    } else {
+        "SELECT id, label, created_at, photo_count, undone, changes, keyword_changes
+         FROM metadata_history WHERE undone = 1 ORDER BY id ASC LIMIT 1"
+    };
+    let Ok((mut entry, changes_json, keywords_json)) = conn.query_row(sql, [], |row| {
+        Ok((
+            entry_from_row(row)?,
+            row.get::<_, String>(5)?,
+            row.get::<_, String>(6)?,
+        ))
+    }) else {
+        return Ok(None);
+    };
+    let changes: Vec<Change> = serde_json::from_str(&changes_json).map_err(|e| e.to_string())?;
+    let keyword_changes: Vec<KeywordChange> =
+        serde_json::from_str(&keywords_json).map_err(|e| e.to_string())?;
+    let photo_ids: Vec<String> = changes
+        .iter()
+        .map(|change| change.photo_id.clone())
+        .collect();
+    let mut current = read_photo_metadata(conn, &photo_ids);
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    for change in &keyword_changes {
+        let restored = if undo { &change.before } else { &change.after };
+        keywords::write_row(&tx, change.id, restored.as_ref())?;
+    }
+    let mut metadata = HashMap::new();
+    for change in changes {
+        let before = change.before.clone().unwrap_or_default();
+        let (from, to) = if undo {
+            (&change.after, &before)
+        } else {
+            (&before, &change.after)
+        };
+        let current = current.remove(&change.photo_id).unwrap_or_default();
+        let restored = revert(&current, from, to);
+        // Undoing the batch that created a photo's metadata removes it again
+        // if nothing else was set since.
+        if undo && change.before.is_none() && restored == PhotoMetadata::default() {
+            delete_photo_metadata(&tx, &change.photo_id)?;
+        } else {
+            write_photo_metadata(&tx, &change.photo_id, &restored)?;
+        }
+        metadata.insert(change.photo_id, restored);
+    }
+    entry.undone = undo;
+    tx.execute(
+        "UPDATE metadata_history SET undone = ?1 WHERE id = ?2",
+        rusqlite::params![undo, entry.id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(Some(HistoryStep {
+        entry,
+        metadata,
+        keywords_changed: !keyword_changes.is_empty(),
+    }))
+}
+
+/// `current` with each field that still holds its `from` value set to its
+/// `to` value. Tags are reverted one by one: those `from` added are
+/// removed and those it removed are added back.
+fn revert(current: &PhotoMetadata, from: &PhotoMetadata, to: &PhotoMetadata) -> PhotoMetadata {
+    fn field<T: PartialEq + Clone>(current: &T, from: &T, to: &T) -> T {
+        if current == from {
+            to.clone()
+        } else {
+            current.clone()
+        }
+    }
+    let tags = if current.tags == from.tags {
+        to.tags.clone()
+    } else {
+        let mut tags: Vec<String> = current
+            .tags
+            .iter()
+            .filter(|tag| to.tags.contains(tag) || !from.tags.contains(tag))
+            .cloned()
+            .collect();
+        for tag in &to.tags {
+            if !from.tags.contains(tag) && !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags
+    };
+    // IPTC fields are reverted one by one as well.
+    let iptc = match (
+        serde_json::to_value(&current.iptc),
+        serde_json::to_value(&from.iptc),
+        serde_json::to_value(&to.iptc),
+    ) {
+        (Ok(Value::Object(mut current_iptc)), Ok(Value::Object(from)), Ok(Value::Object(to))) => {
+            for (key, value) in current_iptc.iter_mut() {
+                if let (Some(from), Some(to)) = (from.get(key), to.get(key)) {
+                    *value = field(value, from, to);
+                }
+            }
+            serde_json::from_value(Value::Object(current_iptc))
+                .unwrap_or_else(|_| current.iptc.clone())
+        }
+        _ => field(&current.iptc, &from.iptc, &to.iptc),
+    };
+    PhotoMetadata {
+        tags,
+        notes: field(&current.notes, &from.notes, &to.notes),
+        rating: field(&current.rating, &from.rating, &to.rating),
+        flag: field(&current.flag, &from.flag, &to.flag),
+        color_label: field(&current.color_label, &from.color_label, &to.color_label),
+        iptc,
+    }
+}
+
+pub fn undo(conn: &mut rusqlite::Connection) -> Result<Option<HistoryStep>, String> {
+    step(conn, true)
+}
+
+pub fn redo(conn: &mut rusqlite::Connection) -> Result<Option<HistoryStep>, String> {
+    step(conn, false)
+}
+
+/// Point the journaled changes of `old_id` at `new_id`, after the photo's
+/// metadata moved there, so undo and redo reach the photo again.
+pub fn rename_photo(conn: &rusqlite::Connection, old_id: &str, new_id: &str) -> Result<(), String> {
+    let rows: Vec<(i64, String)> = {
+        let mut statement = conn
+            .prepare("SELECT id, changes FROM metadata_history WHERE instr(changes, ?1) > 0")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([old_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())?
+    };
+    for (id, changes_json) in rows {
+        let mut changes: Vec<Change> =
+            serde_json::from_str(&changes_json).map_err(|e| e.to_string())?;
+        let mut renamed = false;
+        for change in changes
+            .iter_mut()
+            .filter(|change| change.photo_id == old_id)
+        {
+            change.photo_id = new_id.to_string();
+            renamed = true;
+        }
+        if renamed {
+            let changes_json = serde_json::to_string(&changes).map_err(|e| e.to_string())?;
+            conn.execute(
+                "UPDATE metadata_history SET changes = ?1 WHERE id = ?2",
+                rusqlite::params![changes_json, id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// The most recent `limit` batches, newest first.
+pub fn list(conn: &rusqlite::Connection, limit: usize) -> Result<Vec<HistoryEntry>, String> {
+    let mut statement = conn
+        .prepare(
+            "SELECT id, label, created_at, photo_count, undone FROM metadata_history
+             ORDER BY id DESC LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([limit as i64], entry_from_row)
+        .map_err(|e| e.to_string())?;
+    Ok(rows.flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn catalog() -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn metadata(conn: &rusqlite::Connection, photo_id: &str) -> Option<PhotoMetadata> {
+        read_photo_metadata(conn, &[photo_id.to_string()]).remove(photo_id)
+    }
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn undo_and_redo_step_through_batches() {
+        let mut conn = catalog();
+        let rate = MetadataPatch {
+            rating: Some(3),
+            add_tags: vec!["portfolio".to_string()],
+            ..MetadataPatch::default()
+        };
+        apply_batch(&mut conn, &ids(&["a", "b"]), &rate, "Rate").unwrap();
+        let note = MetadataPatch {
+            notes: Some("hero".to_string()),
+            ..MetadataPatch::default()
+        };
+        apply_batch(&mut conn, &ids(&["a"]), &note, "Note").unwrap();
+
+        let step = undo(&mut conn).unwrap().unwrap();
+        assert_eq!(step.entry.label, "Note");
+        assert_eq!(metadata(&conn, "a").unwrap().notes, "");
+        assert_eq!(metadata(&conn, "a").unwrap().rating, 3);
+
+        let step = undo(&mut conn).unwrap().unwrap();
+        assert_eq!(step.entry.label, "Rate");
+        // Metadata the batch created is removed again.
+        assert!(metadata(&conn, "b").is_none());
+        assert!(undo(&mut conn).unwrap().is_none());
+
+        redo(&mut conn).unwrap().unwrap();
+        redo(&mut conn).unwrap().unwrap();
+        let a = metadata(&conn, "a").unwrap();
+        assert_eq!((a.rating, a.notes.as_str()), (3, "hero"));
+        assert_eq!(a.tags, ["portfolio"]);
+        assert!(redo(&mut conn).unwrap().is_none());
+
+        // A new batch drops what was undone.
+        undo(&mut conn).unwrap();
+        apply_batch(&mut conn, &ids(&["b"]), &note, "Note b").unwrap();
+        assert!(redo(&mut conn).unwrap().is_none());
+        assert_eq!(list(&conn, 10).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn undo_keeps_edits_made_since() {
+        let mut conn = catalog();
+        let rate = MetadataPatch {
+            rating: Some(4),
+            add_tags: vec!["portfolio".to_string()],
+            ..MetadataPatch::default()
+        };
+        apply_batch(&mut conn, &ids(&["a"]), &rate, "Rate").unwrap();
+
+        // Edited in between by something outside this batch.
+        let mut edited = metadata(&conn, "a").unwrap();
+        edited.tags.insert(0, "client".to_string());
+        edited.color_label = "red".to_string();
+        edited.iptc.city = "Oslo".to_string();
+        write_photo_metadata(&conn, "a", &edited).unwrap();
+
+        undo(&mut conn).unwrap().unwrap();
+        let a = metadata(&conn, "a").unwrap();
+        assert_eq!(a.rating, 0);
+        assert_eq!(a.tags, ["client"]);
+        assert_eq!(a.color_label, "red");
+        assert_eq!(a.iptc.city, "Oslo");
+
+        redo(&mut conn).unwrap().unwrap();
+        let a = metadata(&conn, "a").unwrap();
+        assert_eq!(a.rating, 4);
+        assert_eq!(a.tags, ["client", "portfolio"]);
+        assert_eq!(a.color_label, "red");
+    }
+
+    #[test]
+    fn repeated_edits_of_one_photo_are_one_step() {
+        let conn = catalog();
+        let mut before = None;
+        for notes in ["h", "he", "hero"] {
+            let after = PhotoMetadata {
+                notes: notes.to_string(),
+                ..PhotoMetadata::default()
+            };
+            write_photo_metadata(&conn, "a", &after).unwrap();
+            record_edit(
+                &conn,
+                "Edit 1 photo",
+                Change {
+                    photo_id: "a".to_string(),
+                    before: before.replace(after.clone()),
+                    after,
+                },
+            )
+            .unwrap();
+        }
+        record_edit(
+            &conn,
+            "Edit 1 photo",
+            Change {
+                photo_id: "b".to_string(),
+                before: None,
+                after: PhotoMetadata {
+                    rating: 2,
+                    ..PhotoMetadata::default()
+                },
+            },
+        )
+        .unwrap();
+        assert_eq!(list(&conn, 10).unwrap().len(), 2);
+
+        let mut conn = conn;
+        undo(&mut conn).unwrap();
+        let step = undo(&mut conn).unwrap().unwrap();
+        assert_eq!(step.metadata["a"].notes, "");
+        assert!(metadata(&conn, "a").is_none());
+    }
+}
@@ -0,0 +1,201 @@
+use crate::iptc::IptcPatch;
+use crate::metadata_history::{self, Change};
+use crate::{keywords, read_photo_metadata, tokens, write_photo_metadata, Photo, PhotoMetadata};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const TEMPLATES_KEY: &str = "metadata_templates";
+
+/// A named set of metadata stamped onto many photos at once, such as the
+/// copyright, creator contact and base keywords shared by a whole job. Its
+/// text may contain tokens filled in per photo (see `tokens::expand`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataTemplate {
+    pub name: String,
+    /// Keywords added to each photo's tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// IPTC fields set on each photo; fields left out keep the photo's value.
+    #[serde(default)]
+    pub iptc: IptcPatch,
+    /// Applied by scans to files they find for the first time.
+    #[serde(default)]
+    pub apply_on_import: bool,
+}
+
+pub fn load(conn: &rusqlite::Connection) -> Vec<MetadataTemplate> {
+    conn.query_row(
+        "SELECT value FROM catalog_settings WHERE key = ?1",
+        [TEMPLATES_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+pub fn save(conn: &rusqlite::Connection, templates: &[MetadataTemplate]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for template in templates {
+        let name = template.name.trim();
+        if name.is_empty() {
+            return Err("Metadata templates need a name".to_string());
+        }
+        if !names.insert(name.to_lowercase()) {
+            return Err(format!("There is already a metadata template named {name}"));
+        }
+    }
+    let json = serde_json::to_string(templates).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO catalog_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [TEMPLATES_KEY, &json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn find(conn: &rusqlite::Connection, name: &str) -> Result<MetadataTemplate, String> {
+    load(conn)
+        .into_iter()
+        .find(|template| template.name.trim() == name.trim())
+        .ok_or_else(|| format!("No metadata template named {name}"))
+}
+
+impl MetadataTemplate {
+    /// Stamp this template onto the metadata of `photo`.
+    pub fn apply(&self, metadata: &mut PhotoMetadata, photo: &Photo) {
+        for tag in &self.tags {
+            let tag = tokens::expand(tag, photo, None).trim().to_string();
+            if !tag.is_empty() && !metadata.tags.contains(&tag) {
+                metadata.tags.push(tag);
+            }
+        }
+        metadata.iptc.apply(
+            self.iptc
+                .clone()
+                .map_text(|value| tokens::expand(value, photo, None)),
+        );
+    }
+}
+
+/// Apply `templates`, in order, to `photos` in one transaction, journaled
+/// for undo under `label` when given. Returns the resulting metadata by
+/// photo ID.
+pub fn apply_to_photos(
+    conn: &mut rusqlite::Connection,
+    photos: &[&Photo],
+    templates: &[MetadataTemplate],
+    label: Option<&str>,
+) -> Result<Vec<(String, PhotoMetadata)>, String> {
+    let photo_ids: Vec<String> = photos.iter().map(|photo| photo.id.clone()).collect();
+    let mut existing = read_photo_metadata(conn, &photo_ids);
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut result = Vec::with_capacity(photos.len());
+    let mut changes = Vec::new();
+    for photo in photos {
+        let before = existing.remove(&photo.id);
+        let mut metadata = before.clone().unwrap_or_else(|| PhotoMetadata {
+            tags: photo.tags.clone().unwrap_or_default(),
+            notes: photo.notes.clone().unwrap_or_default(),
+            rating: photo.rating.unwrap_or(0).min(5),
+            flag: photo.flag.clone().unwrap_or_else(|| "none".to_string()),
+            color_label: photo.color_label.clone().unwrap_or_default(),
+            iptc: photo.iptc.clone().unwrap_or_default(),
+        });
+        for template in templates {
+            template.apply(&mut metadata, photo);
+        }
+        if before.as_ref() != Some(&metadata) {
+            keywords::ensure(&tx, &metadata.tags)?;
+            write_photo_metadata(&tx, &photo.id, &metadata)?;
+            changes.push(Change {
+                photo_id: photo.id.clone(),
+                before,
+                after: metadata.clone(),
+            });
+        }
+        result.push((photo.id.clone(), metadata));
+    }
+    if let Some(label) = label {
+        metadata_history::record(&tx, label, &changes)?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Stamp the catalog's import templates onto the photos of a scan whose
+/// IDs are in `new_ids`.
+pub fn apply_on_import(conn: &mut rusqlite::Connection, photos: &[Photo], new_ids: &HashSet<&str>) {
+    if new_ids.is_empty() {
+        return;
+    }
+    let templates: Vec<MetadataTemplate> = load(conn)
+        .into_iter()
+        .filter(|template| template.apply_on_import)
+        .collect();
+    if templates.is_empty() {
+        return;
+    }
+    let photos: Vec<&Photo> = photos
+        .iter()
+        .filter(|photo| new_ids.contains(photo.id.as_str()))
+        .collect();
+    // The scan goes on without the templates, but the failure is reported.
+    if let Err(error) = apply_to_photos(conn, &photos, &templates, None) {
+        eprintln!("Failed to apply import templates: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::iptc::IptcMetadata;
+    use crate::{photo_from_exif, ExifData};
+    use chrono::Utc;
+    use std::path::Path;
+
+    #[test]
+    fn stamps_expanded_tags_and_iptc_fields() {
+        let exif = ExifData {
+            camera_model: Some("Z 8".to_string()),
+            camera_serial: Some("3012345".to_string()),
+            ..ExifData::default()
+        };
+        let photo = photo_from_exif(
+            Path::new("/job/DSC_0042.NEF"),
+            "DSC_0042".to_string(),
+            0,
+            Utc::now(),
+            exif,
+        );
+        let template = MetadataTemplate {
+            name: "Job".to_string(),
+            tags: vec![
+                "client".to_string(),
+                "{camera}".to_string(),
+                "{lens}".to_string(),
+            ],
+            iptc: IptcPatch {
+                headline: Some("{name} by body {serial}".to_string()),
+                ..IptcPatch::default()
+            },
+            apply_on_import: false,
+        };
+        let mut metadata = PhotoMetadata {
+            tags: vec!["client".to_string()],
+            notes: String::new(),
+            rating: 0,
+            flag: "none".to_string(),
+            color_label: String::new(),
+            iptc: IptcMetadata {
+                city: "Oslo".to_string(),
+                ..IptcMetadata::default()
+            },
+        };
+        template.apply(&mut metadata, &photo);
+        assert_eq!(metadata.tags, ["client", "Z 8"]);
+        assert_eq!(metadata.iptc.headline, "DSC_0042 by body 3012345");
+        assert_eq!(metadata.iptc.city, "Oslo");
+    }
+}
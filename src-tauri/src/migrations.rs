@@ -0,0 +1,278 @@
+use rusqlite::Transaction;
+
+type Step = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// The catalog schema, one step per version. A catalog's version is kept in
+/// SQLite's `user_version`; `migrate` runs the steps it has not had yet.
+/// Steps are only ever appended.
+const MIGRATIONS: &[(&str, Step)] = &[
+    ("initial schema", initial_schema),
+    ("photo tags table and metadata indexes", photo_tags),
+    ("scan index group membership", scan_index_groups),
+    (
+        "keyword changes in the metadata history",
+        history_keyword_changes,
+    ),
+];
+
+/// Bring the catalog to the latest schema. Each step runs in its own
+/// transaction, so a failed step leaves the catalog at the version before.
+pub fn migrate(conn: &mut rusqlite::Connection) -> Result<(), String> {
+    let version: usize = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if version > MIGRATIONS.len() {
+        return Err("This catalog was saved by a newer version of Hologram".to_string());
+    }
+    for (index, (name, step)) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        step(&tx)
+            .and_then(|_| tx.pragma_update(None, "user_version", index + 1))
+            .map_err(|e| format!("Catalog migration {} ({name}) failed: {e}", index + 1))?;
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Add `column` to `table` unless the table already has it.
+fn add_column(
+    tx: &Transaction,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let exists = tx
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1"
+        ))?
+        .exists([column])?;
+    if !exists {
+        tx.execute(
+            &format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// The schema as it stood before catalogs were versioned. Catalogs from
+/// then have some of these tables already, possibly without later columns.
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS photo_metadata (
+            photo_id TEXT PRIMARY KEY,
+            tags TEXT NOT NULL DEFAULT '[]',
+            notes TEXT NOT NULL DEFAULT ''
+        );
+        CREATE TABLE IF NOT EXISTS scan_index (
+            file_path TEXT PRIMARY KEY,
+            root_path TEXT NOT NULL,
+            photo_id TEXT NOT NULL,
+            file_size INTEGER NOT NULL,
+            modified_at TEXT NOT NULL,
+            exif_json TEXT NOT NULL,
+            scanned_at TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS scan_index_root_path ON scan_index(root_path);
+        CREATE TABLE IF NOT EXISTS catalog_roots (
+            root_path TEXT PRIMARY KEY,
+            added_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS pending_sidecars (
+            sidecar_path TEXT PRIMARY KEY,
+            contents TEXT NOT NULL,
+            queued_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS catalog_settings (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS capture_time_shifts (
+            photo_id TEXT PRIMARY KEY,
+            shift_seconds INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS metadata_history (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            label TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            photo_count INTEGER NOT NULL,
+            changes TEXT NOT NULL,
+            undone INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE IF NOT EXISTS keywords (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            parent_id INTEGER,
+            name TEXT NOT NULL,
+            synonyms TEXT NOT NULL DEFAULT '[]',
+            export INTEGER NOT NULL DEFAULT 1
+        );
+        CREATE INDEX IF NOT EXISTS keywords_parent_id ON keywords(parent_id);
+        CREATE TABLE IF NOT EXISTS ingest_history (
+            content_hash TEXT PRIMARY KEY,
+            source_path TEXT NOT NULL,
+            destination_path TEXT NOT NULL,
+            ingested_at TEXT NOT NULL
+        );",
+    )?;
+    add_column(tx, "photo_metadata", "rating", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(tx, "photo_metadata", "flag", "TEXT NOT NULL DEFAULT 'none'")?;
+    add_column(tx, "photo_metadata", "iptc", "TEXT NOT NULL DEFAULT '{}'")?;
+    add_column(
+        tx,
+        "photo_metadata",
+        "color_label",
+        "TEXT NOT NULL DEFAULT ''",
+    )?;
+    add_column(tx, "scan_index", "content_key", "TEXT")?;
+    add_column(tx, "scan_index", "health_json", "TEXT")?;
+    add_column(
+        tx,
+        "scan_index",
+        "exif_version",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column(tx, "catalog_roots", "volume_id", "TEXT")?;
+    tx.execute_batch(
+        "CREATE INDEX IF NOT EXISTS scan_index_content_key ON scan_index(content_key);",
+    )
+}
+
+/// Move tags out of the JSON column into `photo_tags`, one row per tag,
+/// and index the columns photos are filtered by.
+fn photo_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE photo_tags (
+            photo_id TEXT NOT NULL,
+            tag TEXT NOT NULL,
+            position INTEGER NOT NULL,
+            PRIMARY KEY (photo_id, tag)
+        ) WITHOUT ROWID;
+        CREATE INDEX photo_tags_tag ON photo_tags(tag COLLATE NOCASE);",
+    )?;
+    {
+        let mut select = tx.prepare("SELECT photo_id, tags FROM photo_metadata")?;
+        let mut insert = tx.prepare(
+            "INSERT OR IGNORE INTO photo_tags (photo_id, tag, position) VALUES (?1, ?2, ?3)",
+        )?;
+        let rows = select.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (photo_id, tags_json) = row?;
+            let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
+            for (position, tag) in tags.iter().enumerate() {
+                insert.execute(rusqlite::params![photo_id, tag, position])?;
+            }
+        }
+    }
+    tx.execute_batch(
+        "CREATE TABLE photo_metadata_new (
+            photo_id TEXT PRIMARY KEY,
+            notes TEXT NOT NULL DEFAULT '',
+            rating INTEGER NOT NULL DEFAULT 0,
+            flag TEXT NOT NULL DEFAULT 'none',
+            color_label TEXT NOT NULL DEFAULT '',
+            iptc TEXT NOT NULL DEFAULT '{}'
+        );
+        INSERT INTO photo_metadata_new (photo_id, notes, rating, flag, color_label, iptc)
+            SELECT photo_id, notes, rating, flag, color_label, iptc FROM photo_metadata;
+        DROP TABLE photo_metadata;
+        ALTER TABLE photo_metadata_new RENAME TO photo_metadata;
+        CREATE INDEX photo_metadata_rating ON photo_metadata(rating);
+        CREATE INDEX photo_metadata_flag ON photo_metadata(flag);
+        CREATE INDEX photo_metadata_color_label ON photo_metadata(color_label);",
+    )
+}
+
+/// Remember each file's group as last reported, so companions appearing or
+/// disappearing next to an unchanged photo are noticed.
+fn scan_index_groups(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(tx, "scan_index", "group_json", "TEXT")
+}
+
+/// Journal keyword renames and deletions with the retagging they cause.
+fn history_keyword_changes(tx: &Transaction) -> rusqlite::Result<()> {
+    add_column(
+        tx,
+        "metadata_history",
+        "keyword_changes",
+        "TEXT NOT NULL DEFAULT '[]'",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_photo_metadata;
+
+    /// A catalog as Hologram wrote it before catalogs were versioned.
+    const BASELINE_SCHEMA: &str = "CREATE TABLE photo_metadata (
+        photo_id TEXT PRIMARY KEY,
+        tags TEXT NOT NULL DEFAULT '[]',
+        notes TEXT NOT NULL DEFAULT '',
+        rating INTEGER NOT NULL DEFAULT 0,
+        flag TEXT NOT NULL DEFAULT 'none'
+    );
+    INSERT INTO photo_metadata (photo_id, tags, notes, rating, flag) VALUES
+        ('a', '[\"portfolio\",\"Places|Oslo\"]', 'hero', 5, 'pick'),
+        ('b', '[]', '', 0, 'reject'),
+        ('c', 'not json', 'kept', 2, 'none');";
+
+    #[test]
+    fn baseline_catalogs_keep_their_metadata() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_SCHEMA).unwrap();
+        migrate(&mut conn).unwrap();
+
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        let metadata = read_photo_metadata(&conn, &["a".into(), "b".into(), "c".into()]);
+        let a = &metadata["a"];
+        assert_eq!(a.tags, ["portfolio", "Places|Oslo"]);
+        assert_eq!(
+            (a.notes.as_str(), a.rating, a.flag.as_str()),
+            ("hero", 5, "pick")
+        );
+        assert_eq!(metadata["b"].flag, "reject");
+        assert!(metadata["b"].tags.is_empty());
+        // Unreadable tags are dropped, the rest of the row is kept.
+        assert_eq!(
+            (metadata["c"].notes.as_str(), metadata["c"].rating),
+            ("kept", 2)
+        );
+
+        // Migrating again is a no-op.
+        migrate(&mut conn).unwrap();
+        assert_eq!(read_photo_metadata(&conn, &["a".into()])["a"].tags.len(), 2);
+    }
+
+    #[test]
+    fn catalogs_from_before_ratings_gain_the_columns() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE photo_metadata (
+                photo_id TEXT PRIMARY KEY,
+                tags TEXT NOT NULL DEFAULT '[]',
+                notes TEXT NOT NULL DEFAULT ''
+            );
+            INSERT INTO photo_metadata (photo_id, tags, notes) VALUES ('a', '[\"x\"]', 'n');",
+        )
+        .unwrap();
+        migrate(&mut conn).unwrap();
+        let a = &read_photo_metadata(&conn, &["a".into()])["a"];
+        assert_eq!(
+            (a.tags.as_slice(), a.rating, a.flag.as_str()),
+            (&["x".to_string()][..], 0, "none")
+        );
+    }
+
+    #[test]
+    fn refuses_catalogs_from_a_newer_version() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(migrate(&mut conn).is_err());
+    }
+}
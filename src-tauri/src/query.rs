@@ -0,0 +1,1435 @@
+use crate::capture_time::{capture_instant, local_capture_time, sun_elevation};
+use crate::keywords;
+use crate::labels::{self, LabelSet};
+use crate::{Photo, PhotoFilter};
+use chrono::{DateTime, Datelike, Months, NaiveDate, NaiveDateTime, Timelike, Utc, Weekday};
+use rayon::prelude::*;
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::iter::Peekable;
+use std::ops::Range;
+use std::str::Chars;
+use std::sync::OnceLock;
+
+/// A search over the catalog, built by the frontend or parsed from the query
+/// syntax with `parse`. An empty `All` matches every photo.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    All(Vec<Query>),
+    Any(Vec<Query>),
+    Not(Box<Query>),
+    Term(Term),
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query::All(Vec::new())
+    }
+}
+
+/// One condition on a field, e.g. `iso` `gt` `"3200"`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Term {
+    pub field: Field,
+    #[serde(default)]
+    pub op: Op,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    /// Words anywhere in the file name, EXIF, tags or notes.
+    Text,
+    Name,
+    Type,
+    Make,
+    Camera,
+    Lens,
+    Iso,
+    Aperture,
+    Focal,
+    /// Exposure time, compared in seconds: `1/250`, `2s`.
+    Shutter,
+    ExposureMode,
+    Flash,
+    WhiteBalance,
+    DriveMode,
+    FocusMode,
+    AfPoint,
+    MeteringMode,
+    ImageStabilization,
+    CameraSerial,
+    LensSerial,
+    Artist,
+    Copyright,
+    Rating,
+    /// `pick`, `reject`, `none` or a catalog flag state by name.
+    Flag,
+    /// A color, or a catalog's name for one; `none` for unlabeled.
+    Label,
+    /// A keyword path, a keyword under it or, for one word, any level.
+    Tag,
+    Notes,
+    /// The local capture date: `2024`, `2024-06` or `2024-06-01`.
+    Date,
+    /// The local capture time of day: an hour (`14`) or minute (`14:30`),
+    /// a window (`14:00..15:30`, past midnight when the end comes first),
+    /// or the light: `golden`, `blue`, `day` or `night`.
+    Time,
+    /// Capture weekdays: `sat,sun`, `weekend` or `weekdays`.
+    Weekday,
+    /// Taken within some minutes of another photo, named by ID or file
+    /// name: `IMG_0412.CR3~30`.
+    Near,
+    /// The photo has `gps`, `tags`, `notes`, a `rating`, `label` or `flag`.
+    Has,
+}
+
+/// How a term compares. `Match` is containment for text, equality for
+/// numbers and the whole period for dates; `Range` takes `low..high` with
+/// either end open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Op {
+    #[default]
+    Match,
+    Eq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Range,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Captured,
+    Name,
+    Modified,
+    Size,
+    Rating,
+}
+
+/// What `query_photos` is asked: structured terms from the filter panels
+/// and the search box's text, which must both match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhotoQuery {
+    #[serde(default)]
+    pub query: Query,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub sort: SortKey,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+/// Tolerance for numbers read back from EXIF rationals, e.g. f/2.8.
+const EPSILON: f64 = 1e-6;
+
+/// How far in time a photo without GPS borrows the position of another
+/// photo for working out the light.
+const BORROWED_POSITION_SECONDS: i64 = 12 * 3600;
+
+/// Sun elevations, in degrees, of the light `Field::Time` names.
+fn light(name: &str) -> Option<Range<f64>> {
+    Some(match name {
+        "golden" | "golden-hour" => -4.0..6.0,
+        "blue" | "blue-hour" => -6.0..-4.0,
+        "day" | "daylight" => 6.0..90.0,
+        "night" => -90.0..-6.0,
+        _ => return None,
+    })
+}
+
+/// What terms look up beyond the photo itself: the other photos' capture
+/// times and positions, built when first needed.
+pub struct Context<'a> {
+    photos: &'a [Photo],
+    references: OnceLock<HashMap<String, DateTime<Utc>>>,
+    positions: OnceLock<Vec<(DateTime<Utc>, f64, f64)>>,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(photos: &'a [Photo]) -> Self {
+        Self {
+            photos,
+            references: OnceLock::new(),
+            positions: OnceLock::new(),
+        }
+    }
+
+    /// The capture instant of the photo `name` names: its ID, file name or
+    /// file name without the extension.
+    fn reference(&self, name: &str) -> Option<DateTime<Utc>> {
+        let references = self.references.get_or_init(|| {
+            let mut references = HashMap::new();
+            for photo in self.photos {
+                let taken = capture_instant(photo);
+                references.insert(photo.id.clone(), taken);
+                let file_name = photo.file_name.to_lowercase();
+                if let Some((stem, _)) = file_name.rsplit_once('.') {
+                    references.entry(stem.to_string()).or_insert(taken);
+                }
+                references.entry(file_name).or_insert(taken);
+            }
+            references
+        });
+        references
+            .get(name)
+            .or_else(|| references.get(&name.to_lowercase()))
+            .copied()
+    }
+
+    /// Where `photo` was taken: its GPS position or, without one, that of
+    /// the photo with one taken closest in time, e.g. by a phone on the same
+    /// shoot.
+    fn position(&self, photo: &Photo) -> Option<(f64, f64)> {
+        if let (Some(latitude), Some(longitude)) = (photo.exif.latitude, photo.exif.longitude) {
+            return Some((latitude, longitude));
+        }
+        let positions = self.positions.get_or_init(|| {
+            let mut positions: Vec<_> = self
+                .photos
+                .iter()
+                .filter_map(|photo| {
+                    Some((
+                        photo.exif.date_taken?,
+                        photo.exif.latitude?,
+                        photo.exif.longitude?,
+                    ))
+                })
+                .collect();
+            positions.sort_by_key(|(taken, ..)| *taken);
+            positions
+        });
+        let taken = photo.exif.date_taken?;
+        let index = positions.partition_point(|(at, ..)| *at < taken);
+        let distance = |at: &DateTime<Utc>| (*at - taken).num_seconds().abs();
+        [index.checked_sub(1), Some(index)]
+            .into_iter()
+            .flatten()
+            .filter_map(|index| positions.get(index))
+            .min_by_key(|(at, ..)| distance(at))
+            .filter(|(at, ..)| distance(at) <= BORROWED_POSITION_SECONDS)
+            .map(|(_, latitude, longitude)| (*latitude, *longitude))
+    }
+}
+
+/// The IDs of `photos` matching `query`, sorted. Conditions on the
+/// catalog's own fields run as SQL on `conn`, using its indexes; only what
+/// the files themselves say is matched here.
+pub fn run(
+    query: &PhotoQuery,
+    mut photos: Vec<Photo>,
+    conn: &Connection,
+) -> Result<Vec<String>, String> {
+    let filter = Query::All(vec![query.query.clone(), parse(&query.text)]);
+    let labels = labels::load(conn);
+    let plan = compile(&filter, conn, &labels)?;
+    if matches!(query.sort, SortKey::Rating) {
+        let ratings = ratings(conn)?;
+        for photo in &mut photos {
+            photo.rating = ratings.get(&photo.id).copied();
+        }
+    }
+    let context = Context::new(&photos);
+    let mut matched: Vec<&Photo> = photos
+        .par_iter()
+        .filter(|photo| plan.matches(photo, &context))
+        .collect();
+    matched.par_sort_by(|a, b| {
+        let order = compare(query.sort, a, b)
+            .then_with(|| local_capture_time(a).cmp(&local_capture_time(b)))
+            .then_with(|| a.file_name.cmp(&b.file_name));
+        if query.descending {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    Ok(matched.into_iter().map(|photo| photo.id.clone()).collect())
+}
+
+fn ratings(conn: &Connection) -> Result<HashMap<String, u8>, String> {
+    let mut statement = conn
+        .prepare("SELECT photo_id, rating FROM photo_metadata WHERE rating > 0")
+        .map_err(|e| e.to_string())?;
+    let rows = statement
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, u8>(1)?.min(5)))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<rusqlite::Result<_>>()
+        .map_err(|e| e.to_string())
+}
+
+/// The time conditions of a `PhotoFilter`, as terms.
+pub fn filter_time_terms(filter: &PhotoFilter) -> Vec<Term> {
+    let mut terms = Vec::new();
+    let mut term = |field, value: String| {
+        terms.push(Term {
+            field,
+            op: if value.contains("..") {
+                Op::Range
+            } else {
+                Op::Match
+            },
+            value,
+        })
+    };
+    if let Some(time) = &filter.time_of_day {
+        term(Field::Time, time.clone());
+    }
+    if let Some(weekdays) = &filter.weekdays {
+        term(Field::Weekday, weekdays.join(","));
+    }
+    if let Some(near) = &filter.near {
+        term(Field::Near, format!("{}~{}", near.photo_id, near.minutes));
+    }
+    terms
+}
+
+fn compare(sort: SortKey, a: &Photo, b: &Photo) -> Ordering {
+    match sort {
+        SortKey::Captured => Ordering::Equal,
+        SortKey::Name => a.file_name.to_lowercase().cmp(&b.file_name.to_lowercase()),
+        SortKey::Modified => a.modified_at.cmp(&b.modified_at),
+        SortKey::Size => a.file_size.cmp(&b.file_size),
+        SortKey::Rating => a.rating.unwrap_or(0).cmp(&b.rating.unwrap_or(0)),
+    }
+}
+
+impl Query {
+    fn is_empty(&self) -> bool {
+        matches!(self, Query::All(terms) if terms.is_empty())
+    }
+
+    /// The whole query as a condition on the catalog's fields, or `None`
+    /// when some term needs the files' own metadata.
+    fn condition(&self, labels: &LabelSet) -> Option<Condition> {
+        match self {
+            Query::All(queries) => Some(Condition::join(
+                queries
+                    .iter()
+                    .map(|query| query.condition(labels))
+                    .collect::<Option<_>>()?,
+                " AND ",
+                true,
+            )),
+            Query::Any(queries) => Some(Condition::join(
+                queries
+                    .iter()
+                    .map(|query| query.condition(labels))
+                    .collect::<Option<_>>()?,
+                " OR ",
+                false,
+            )),
+            Query::Not(query) => query.condition(labels).map(|condition| Condition {
+                sql: format!("NOT ({})", condition.sql),
+                params: condition.params,
+            }),
+            Query::Term(term) => term.condition(labels),
+        }
+    }
+}
+
+/// A condition on `photo_metadata AS m`, with its `?` parameters in order.
+struct Condition {
+    sql: String,
+    params: Vec<Value>,
+}
+
+impl Condition {
+    fn new(sql: impl Into<String>, params: Vec<Value>) -> Self {
+        Self {
+            sql: sql.into(),
+            params,
+        }
+    }
+
+    fn constant(matches: bool) -> Self {
+        Self::new(if matches { "1" } else { "0" }, Vec::new())
+    }
+
+    /// `conditions` joined by `operator`; `empty` when there are none.
+    fn join(conditions: Vec<Condition>, operator: &str, empty: bool) -> Self {
+        if conditions.is_empty() {
+            return Self::constant(empty);
+        }
+        let sql = conditions
+            .iter()
+            .map(|condition| format!("({})", condition.sql))
+            .collect::<Vec<_>>()
+            .join(operator);
+        let params = conditions
+            .into_iter()
+            .flat_map(|condition| condition.params)
+            .collect();
+        Self { sql, params }
+    }
+}
+
+/// A query ready to match photos: each group's terms on catalog fields were
+/// run as one SQL query, leaving the EXIF terms to match photo by photo.
+enum Plan {
+    All(Vec<Plan>),
+    Any(Vec<Plan>),
+    Not(Box<Plan>),
+    /// Photos with catalog metadata matching a condition. When photos
+    /// without any match it, `ids` holds those that do not instead.
+    Catalog {
+        ids: HashSet<String>,
+        default: bool,
+    },
+    Exif(Term),
+}
+
+impl Plan {
+    fn matches(&self, photo: &Photo, context: &Context) -> bool {
+        match self {
+            Plan::All(plans) => plans.iter().all(|plan| plan.matches(photo, context)),
+            Plan::Any(plans) => plans.iter().any(|plan| plan.matches(photo, context)),
+            Plan::Not(plan) => !plan.matches(photo, context),
+            Plan::Catalog { ids, default } => ids.contains(&photo.id) != *default,
+            Plan::Exif(term) => term.matches(photo, context),
+        }
+    }
+
+    fn catalog(conn: &Connection, condition: Condition) -> Result<Self, String> {
+        let default: bool = conn
+            .query_row(
+                &format!(
+                    "SELECT ({}) FROM (SELECT '' AS photo_id, '' AS notes, 0 AS rating,
+                       'none' AS flag, '' AS color_label) AS m",
+                    condition.sql
+                ),
+                params_from_iter(&condition.params),
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let mut statement = conn
+            .prepare(&format!(
+                "SELECT photo_id FROM photo_metadata AS m WHERE {}({})",
+                if default { "NOT " } else { "" },
+                condition.sql
+            ))
+            .map_err(|e| e.to_string())?;
+        let ids = statement
+            .query_map(params_from_iter(&condition.params), |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<rusqlite::Result<_>>()
+            .map_err(|e| e.to_string())?;
+        Ok(Plan::Catalog { ids, default })
+    }
+}
+
+fn compile(query: &Query, conn: &Connection, labels: &LabelSet) -> Result<Plan, String> {
+    if let Some(condition) = query.condition(labels) {
+        return Plan::catalog(conn, condition);
+    }
+    Ok(match query {
+        Query::All(queries) => Plan::All(compile_group(queries, " AND ", true, conn, labels)?),
+        Query::Any(queries) => Plan::Any(compile_group(queries, " OR ", false, conn, labels)?),
+        Query::Not(query) => Plan::Not(Box::new(compile(query, conn, labels)?)),
+        // Free text may be in the file's metadata or the catalog's.
+        Query::Term(term) if term.field == Field::Text => Plan::Any(vec![
+            Plan::Exif(term.clone()),
+            Plan::catalog(conn, text_condition(term.value.trim()))?,
+        ]),
+        Query::Term(term) => Plan::Exif(term.clone()),
+    })
+}
+
+/// The members of a group, with those only on catalog fields run together.
+fn compile_group(
+    queries: &[Query],
+    operator: &str,
+    empty: bool,
+    conn: &Connection,
+    labels: &LabelSet,
+) -> Result<Vec<Plan>, String> {
+    let mut conditions = Vec::new();
+    let mut plans = Vec::new();
+    for query in queries {
+        match query.condition(labels) {
+            Some(condition) => conditions.push(condition),
+            None => plans.push(compile(query, conn, labels)?),
+        }
+    }
+    if !conditions.is_empty() {
+        plans.push(Plan::catalog(
+            conn,
+            Condition::join(conditions, operator, empty),
+        )?);
+    }
+    Ok(plans)
+}
+
+impl Term {
+    /// Whether `photo`'s own metadata matches. Terms on catalog fields are
+    /// run as SQL instead, see `condition`.
+    pub fn matches(&self, photo: &Photo, context: &Context) -> bool {
+        let exif = &photo.exif;
+        let value = self.value.trim();
+        match self.field {
+            Field::Text => search_text(photo).contains(&value.to_lowercase()),
+            Field::Name => text_matches(Some(&photo.file_name), self.op, value),
+            Field::Type => photo
+                .file_type
+                .eq_ignore_ascii_case(value.trim_start_matches('.')),
+            Field::Make => text_matches(exif.camera_make.as_deref(), self.op, value),
+            Field::Camera => text_matches(exif.camera_model.as_deref(), self.op, value),
+            Field::Lens => text_matches(exif.lens_model.as_deref(), self.op, value),
+            Field::Iso => number_matches(exif.iso.map(f64::from), self.op, value, parse_number),
+            Field::Aperture => number_matches(exif.aperture, self.op, value, parse_aperture),
+            Field::Focal => number_matches(exif.focal_length, self.op, value, parse_focal),
+            Field::Shutter => number_matches(
+                exif.shutter_speed.as_deref().and_then(parse_seconds),
+                self.op,
+                value,
+                parse_seconds,
+            ),
+            Field::ExposureMode => text_matches(exif.exposure_mode.as_deref(), self.op, value),
+            Field::Flash => text_matches(exif.flash.as_deref(), self.op, value),
+            Field::WhiteBalance => text_matches(exif.white_balance.as_deref(), self.op, value),
+            Field::DriveMode => text_matches(exif.drive_mode.as_deref(), self.op, value),
+            Field::FocusMode => text_matches(exif.focus_mode.as_deref(), self.op, value),
+            Field::AfPoint => text_matches(exif.af_point.as_deref(), self.op, value),
+            Field::MeteringMode => text_matches(exif.metering_mode.as_deref(), self.op, value),
+            Field::ImageStabilization => {
+                text_matches(exif.image_stabilization.as_deref(), self.op, value)
+            }
+            Field::CameraSerial => text_matches(exif.camera_serial.as_deref(), self.op, value),
+            Field::LensSerial => text_matches(exif.lens_serial.as_deref(), self.op, value),
+            Field::Artist => text_matches(exif.artist.as_deref(), self.op, value),
+            Field::Copyright => text_matches(exif.copyright.as_deref(), self.op, value),
+            Field::Rating | Field::Flag | Field::Label | Field::Tag | Field::Notes => {
+                unreachable!("catalog fields are matched in SQL")
+            }
+            Field::Date => date_matches(local_capture_time(photo), self.op, value),
+            Field::Time => match light(&value.to_lowercase()) {
+                Some(elevations) => context
+                    .position(photo)
+                    .and_then(|(latitude, longitude)| sun_elevation(photo, latitude, longitude))
+                    .is_some_and(|elevation| elevations.contains(&elevation)),
+                None => time_matches(local_capture_time(photo), self.op, value),
+            },
+            Field::Weekday => weekday_matches(local_capture_time(photo).weekday(), value),
+            Field::Near => value.rsplit_once('~').is_some_and(|(name, window)| {
+                match (context.reference(name.trim()), parse_minutes(window)) {
+                    (Some(reference), Some(minutes)) => {
+                        (capture_instant(photo) - reference).num_seconds().abs() as f64
+                            <= minutes * 60.0
+                    }
+                    _ => false,
+                }
+            }),
+            // Only `has:gps` is left to match here.
+            Field::Has => exif.latitude.is_some() && exif.longitude.is_some(),
+        }
+    }
+
+    /// The term as a condition on the catalog's fields, or `None` when it
+    /// needs the file's own metadata.
+    fn condition(&self, labels: &LabelSet) -> Option<Condition> {
+        let value = self.value.trim();
+        Some(match self.field {
+            Field::Rating => number_condition("m.rating", self.op, value),
+            Field::Flag => match labels::flag_id(value).as_str() {
+                "none" => Condition::new("m.flag IN ('none', '')", Vec::new()),
+                flag => Condition::new("m.flag = ?", vec![Value::Text(flag.to_string())]),
+            },
+            Field::Label if value.eq_ignore_ascii_case("none") => {
+                Condition::new("m.color_label = ''", Vec::new())
+            }
+            Field::Label => match labels.color_for_label(value) {
+                Some(color) => {
+                    Condition::new("m.color_label = ?", vec![Value::Text(color.to_string())])
+                }
+                None => Condition::constant(false),
+            },
+            Field::Tag => tag_condition(value),
+            Field::Notes => match self.op {
+                Op::Match => Condition::new(
+                    "m.notes LIKE ? ESCAPE '\\'",
+                    vec![Value::Text(containing(value))],
+                ),
+                Op::Eq => Condition::new(
+                    "trim(m.notes) = ? COLLATE NOCASE",
+                    vec![Value::Text(value.to_string())],
+                ),
+                _ => Condition::constant(false),
+            },
+            Field::Has => Condition::new(
+                match value.to_ascii_lowercase().as_str() {
+                    "gps" | "location" => return None,
+                    "tags" | "tag" => "m.photo_id IN (SELECT photo_id FROM photo_tags)",
+                    "notes" => "trim(m.notes) <> ''",
+                    "rating" => "m.rating > 0",
+                    "label" => "m.color_label <> ''",
+                    "flag" => "m.flag NOT IN ('none', '')",
+                    _ => "0",
+                },
+                Vec::new(),
+            ),
+            _ => return None,
+        })
+    }
+}
+
+/// Free text in the catalog's fields, as `search_text` looks for it in the
+/// file's.
+fn text_condition(value: &str) -> Condition {
+    let pattern = Value::Text(containing(value));
+    Condition::new(
+        "m.notes LIKE ? ESCAPE '\\'
+         OR (m.flag NOT IN ('none', '') AND m.flag LIKE ? ESCAPE '\\')
+         OR (m.rating > 0 AND CAST(m.rating AS TEXT) LIKE ? ESCAPE '\\')
+         OR m.photo_id IN (SELECT photo_id FROM photo_tags WHERE tag LIKE ? ESCAPE '\\')",
+        vec![pattern; 4],
+    )
+}
+
+/// Tags matching `path` as the tag filter matches them, ignoring case: the
+/// tag itself, a keyword under it or, for a single word, any level of a
+/// path. Paths are looked up in the tag index.
+fn tag_condition(path: &str) -> Condition {
+    if path.contains(keywords::SEPARATOR) {
+        // Keywords under `a|b` sort from `a|b|` up to `a|b}`.
+        Condition::new(
+            "m.photo_id IN (SELECT photo_id FROM photo_tags
+               WHERE tag = ? COLLATE NOCASE
+                 OR (tag >= ? COLLATE NOCASE AND tag < ? COLLATE NOCASE))",
+            vec![
+                Value::Text(path.to_string()),
+                Value::Text(format!("{path}|")),
+                Value::Text(format!("{path}}}")),
+            ],
+        )
+    } else {
+        Condition::new(
+            "m.photo_id IN (SELECT photo_id FROM photo_tags
+               WHERE '|' || tag || '|' LIKE ? ESCAPE '\\')",
+            vec![Value::Text(format!("%|{}|%", escape_like(path)))],
+        )
+    }
+}
+
+fn number_condition(column: &str, op: Op, value: &str) -> Condition {
+    let compare = |operator: &str, number: f64| {
+        Condition::new(format!("{column} {operator} ?"), vec![Value::Real(number)])
+    };
+    if op == Op::Range {
+        let Some((low, high)) = value.split_once("..") else {
+            return Condition::constant(false);
+        };
+        let mut bounds = Vec::new();
+        for (bound, operator) in [(low, ">="), (high, "<=")] {
+            if bound.trim().is_empty() {
+                continue;
+            }
+            match parse_number(bound) {
+                Some(number) => bounds.push(compare(operator, number)),
+                None => return Condition::constant(false),
+            }
+        }
+        return Condition::join(bounds, " AND ", true);
+    }
+    let Some(number) = parse_number(value) else {
+        return Condition::constant(false);
+    };
+    let operator = match op {
+        Op::Match | Op::Eq => "=",
+        Op::Gt => ">",
+        Op::Gte => ">=",
+        Op::Lt => "<",
+        Op::Lte => "<=",
+        Op::Range => unreachable!(),
+    };
+    compare(operator, number)
+}
+
+/// A `LIKE` pattern for text containing `value`.
+fn containing(value: &str) -> String {
+    format!("%{}%", escape_like(value))
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Everything in the file's own metadata free text is matched against,
+/// lowercased. The catalog's fields are searched by `text_condition`.
+fn search_text(photo: &Photo) -> String {
+    let exif = &photo.exif;
+    let date = exif.date_taken.map(|_| {
+        local_capture_time(photo)
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    });
+    [
+        Some(photo.file_name.as_str()),
+        exif.camera_make.as_deref(),
+        exif.camera_model.as_deref(),
+        exif.lens_model.as_deref(),
+        exif.shutter_speed.as_deref(),
+        exif.exposure_mode.as_deref(),
+        exif.flash.as_deref(),
+        exif.white_balance.as_deref(),
+        exif.drive_mode.as_deref(),
+        exif.focus_mode.as_deref(),
+        exif.metering_mode.as_deref(),
+        exif.camera_serial.as_deref(),
+        exif.lens_serial.as_deref(),
+        exif.artist.as_deref(),
+        exif.copyright.as_deref(),
+        date.as_deref(),
+        Some(photo.file_type.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("\u{1}")
+    .to_lowercase()
+}
+
+fn text_matches(actual: Option<&str>, op: Op, value: &str) -> bool {
+    let Some(actual) = actual else {
+        return false;
+    };
+    match op {
+        Op::Match => actual.to_lowercase().contains(&value.to_lowercase()),
+        Op::Eq => actual.trim().eq_ignore_ascii_case(value),
+        _ => false,
+    }
+}
+
+fn number_matches(
+    actual: Option<f64>,
+    op: Op,
+    value: &str,
+    parse: fn(&str) -> Option<f64>,
+) -> bool {
+    let Some(actual) = actual else {
+        return false;
+    };
+    if op == Op::Range {
+        let Some((low, high)) = value.split_once("..") else {
+            return false;
+        };
+        let bound = |text: &str| {
+            if text.trim().is_empty() {
+                Some(None)
+            } else {
+                parse(text).map(Some)
+            }
+        };
+        let (Some(low), Some(high)) = (bound(low), bound(high)) else {
+            return false;
+        };
+        return low.is_none_or(|low| actual >= low - EPSILON)
+            && high.is_none_or(|high| actual <= high + EPSILON);
+    }
+    let Some(wanted) = parse(value) else {
+        return false;
+    };
+    match op {
+        Op::Match | Op::Eq => (actual - wanted).abs() < EPSILON,
+        Op::Gt => actual > wanted + EPSILON,
+        Op::Gte => actual >= wanted - EPSILON,
+        Op::Lt => actual < wanted - EPSILON,
+        Op::Lte => actual <= wanted + EPSILON,
+        Op::Range => unreachable!(),
+    }
+}
+
+fn date_matches(actual: NaiveDateTime, op: Op, value: &str) -> bool {
+    if op == Op::Range {
+        let Some((low, high)) = value.split_once("..") else {
+            return false;
+        };
+        let low = if low.trim().is_empty() {
+            None
+        } else {
+            match parse_period(low) {
+                Some((start, _)) => Some(start),
+                None => return false,
+            }
+        };
+        let high = if high.trim().is_empty() {
+            None
+        } else {
+            match parse_period(high) {
+                Some((_, end)) => Some(end),
+                None => return false,
+            }
+        };
+        return low.is_none_or(|low| actual >= low) && high.is_none_or(|high| actual < high);
+    }
+    let Some((start, end)) = parse_period(value) else {
+        return false;
+    };
+    match op {
+        Op::Match | Op::Eq => start <= actual && actual < end,
+        Op::Gt => actual >= end,
+        Op::Gte => actual >= start,
+        Op::Lt => actual < start,
+        Op::Lte => actual < end,
+        Op::Range => unreachable!(),
+    }
+}
+
+fn time_matches(actual: NaiveDateTime, op: Op, value: &str) -> bool {
+    let seconds = actual.num_seconds_from_midnight();
+    let (start, end) = if op == Op::Range {
+        let Some((low, high)) = value.split_once("..") else {
+            return false;
+        };
+        let start = if low.trim().is_empty() {
+            Some(0)
+        } else {
+            parse_clock(low).map(|(start, _)| start)
+        };
+        let end = if high.trim().is_empty() {
+            Some(DAY_SECONDS)
+        } else {
+            parse_clock(high).map(|(_, end)| end)
+        };
+        let (Some(start), Some(end)) = (start, end) else {
+            return false;
+        };
+        (start, end)
+    } else {
+        let Some((start, end)) = parse_clock(value) else {
+            return false;
+        };
+        match op {
+            Op::Gt => (end, DAY_SECONDS),
+            Op::Gte => (start, DAY_SECONDS),
+            Op::Lt => (0, start),
+            Op::Lte => (0, end),
+            _ => (start, end),
+        }
+    };
+    // A window ending before it starts runs past midnight.
+    if start < end {
+        start <= seconds && seconds < end
+    } else {
+        op == Op::Range && (seconds >= start || seconds < end)
+    }
+}
+
+const DAY_SECONDS: u32 = 24 * 3600;
+
+/// The hour (`14`) or minute (`14:30`) `value` names, as a half-open span
+/// of seconds since midnight.
+fn parse_clock(value: &str) -> Option<(u32, u32)> {
+    let value = value.trim();
+    let (hour, minute) = match value.split_once(':') {
+        Some((hour, minute)) => (hour.parse::<u32>().ok()?, Some(minute.parse::<u32>().ok()?)),
+        None => (value.parse::<u32>().ok()?, None),
+    };
+    if hour > 23 || minute.is_some_and(|minute| minute > 59) {
+        return None;
+    }
+    let start = hour * 3600 + minute.unwrap_or(0) * 60;
+    Some((start, start + if minute.is_some() { 60 } else { 3600 }))
+}
+
+fn weekday_matches(day: Weekday, value: &str) -> bool {
+    let weekend = matches!(day, Weekday::Sat | Weekday::Sun);
+    value
+        .split(',')
+        .map(|part| part.trim().to_lowercase())
+        .filter(|part| !part.is_empty())
+        .any(|part| match part.as_str() {
+            "weekend" => weekend,
+            "weekday" | "weekdays" => !weekend,
+            part => part.parse::<Weekday>().is_ok_and(|wanted| wanted == day),
+        })
+}
+
+/// `30`, `30m`, `90s` or `2h`, in minutes.
+fn parse_minutes(value: &str) -> Option<f64> {
+    let value = value.trim().to_lowercase();
+    if let Some(hours) = value.strip_suffix('h') {
+        return parse_number(hours).map(|hours| hours * 60.0);
+    }
+    if let Some(seconds) = value.strip_suffix('s') {
+        return parse_number(seconds).map(|seconds| seconds / 60.0);
+    }
+    parse_number(value.trim_end_matches("min").trim_end_matches('m'))
+}
+
+/// The year, month or day `value` names, as a half-open span.
+fn parse_period(value: &str) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let parts: Vec<&str> = value.trim().split('-').collect();
+    let number = |index: usize| parts.get(index)?.parse::<u32>().ok();
+    let year = parts.first()?.parse::<i32>().ok()?;
+    let (start, end) = match parts.len() {
+        1 => {
+            let start = NaiveDate::from_ymd_opt(year, 1, 1)?;
+            (start, start.checked_add_months(Months::new(12))?)
+        }
+        2 => {
+            let start = NaiveDate::from_ymd_opt(year, number(1)?, 1)?;
+            (start, start.checked_add_months(Months::new(1))?)
+        }
+        3 => {
+            let start = NaiveDate::from_ymd_opt(year, number(1)?, number(2)?)?;
+            (start, start.succ_opt()?)
+        }
+        _ => return None,
+    };
+    Some((start.and_hms_opt(0, 0, 0)?, end.and_hms_opt(0, 0, 0)?))
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value.trim().parse().ok()
+}
+
+/// `f/2.8`, `f2.8` or `2.8`.
+fn parse_aperture(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix("f/")
+        .or_else(|| value.strip_prefix('f'))
+        .unwrap_or(value);
+    parse_number(value)
+}
+
+/// `50mm` or `50`.
+fn parse_focal(value: &str) -> Option<f64> {
+    parse_number(value.trim().trim_end_matches("mm"))
+}
+
+/// An exposure time in seconds from `1/250`, `1/250 s`, `2s` or `0.5`.
+fn parse_seconds(value: &str) -> Option<f64> {
+    let value = value.trim().trim_end_matches('s').trim();
+    match value.split_once('/') {
+        Some((numerator, denominator)) => {
+            let denominator = parse_number(denominator)?;
+            (denominator != 0.0).then_some(parse_number(numerator)? / denominator)
+        }
+        None => parse_number(value),
+    }
+}
+
+/// The field a `name:` prefix in the query syntax stands for.
+fn field_named(name: &str) -> Option<Field> {
+    Some(match name.to_lowercase().as_str() {
+        "name" | "file" | "filename" => Field::Name,
+        "type" | "ext" => Field::Type,
+        "make" => Field::Make,
+        "camera" | "model" => Field::Camera,
+        "lens" => Field::Lens,
+        "iso" => Field::Iso,
+        "f" | "aperture" => Field::Aperture,
+        "focal" | "mm" => Field::Focal,
+        "shutter" | "ss" => Field::Shutter,
+        "exposure" | "mode" => Field::ExposureMode,
+        "flash" => Field::Flash,
+        "wb" | "whitebalance" => Field::WhiteBalance,
+        "drive" => Field::DriveMode,
+        "focus" => Field::FocusMode,
+        "af" => Field::AfPoint,
+        "metering" => Field::MeteringMode,
+        "is" | "stabilization" => Field::ImageStabilization,
+        "serial" => Field::CameraSerial,
+        "lensserial" => Field::LensSerial,
+        "artist" => Field::Artist,
+        "copyright" => Field::Copyright,
+        "rating" | "stars" => Field::Rating,
+        "flag" => Field::Flag,
+        "label" | "color" => Field::Label,
+        "tag" | "keyword" => Field::Tag,
+        "notes" | "note" => Field::Notes,
+        "date" => Field::Date,
+        "time" | "hour" => Field::Time,
+        "day" | "weekday" => Field::Weekday,
+        "near" | "within" => Field::Near,
+        "has" => Field::Has,
+        _ => return None,
+    })
+}
+
+enum Token {
+    Open,
+    Close,
+    Or,
+    Not,
+    Word {
+        field: Option<String>,
+        op: Op,
+        value: String,
+        quoted: bool,
+        raw: String,
+    },
+}
+
+/// Parse the search box syntax: words and `"quoted phrases"` match any
+/// text, `field:value` a field (`iso:>3200`, `f:1.4..2.8`,
+/// `lens:"24-70"`), `-` negates, `OR` and parentheses group. Terms are
+/// and-ed. Unknown fields are searched as text, so `12:30` still finds a
+/// note, and unfinished input matches what it has so far.
+pub fn parse(text: &str) -> Query {
+    let tokens = tokenize(text);
+    let mut position = 0;
+    let mut terms = Vec::new();
+    while position < tokens.len() {
+        let query = parse_any(&tokens, &mut position);
+        if !query.is_empty() {
+            terms.push(query);
+        }
+        // A stray `)` or `OR` at the top level is skipped.
+        if position < tokens.len() && matches!(tokens[position], Token::Close | Token::Or) {
+            position += 1;
+        }
+    }
+    simplify(Query::All, terms)
+}
+
+fn simplify(group: fn(Vec<Query>) -> Query, mut terms: Vec<Query>) -> Query {
+    if terms.len() == 1 {
+        terms.remove(0)
+    } else {
+        group(terms)
+    }
+}
+
+fn parse_any(tokens: &[Token], position: &mut usize) -> Query {
+    let mut alternatives = Vec::new();
+    loop {
+        let query = parse_all(tokens, position);
+        if !query.is_empty() {
+            alternatives.push(query);
+        }
+        if *position < tokens.len() && matches!(tokens[*position], Token::Or) {
+            *position += 1;
+        } else {
+            break;
+        }
+    }
+    if alternatives.is_empty() {
+        Query::default()
+    } else {
+        simplify(Query::Any, alternatives)
+    }
+}
+
+fn parse_all(tokens: &[Token], position: &mut usize) -> Query {
+    let mut terms = Vec::new();
+    while *position < tokens.len() && !matches!(tokens[*position], Token::Close | Token::Or) {
+        let query = parse_unary(tokens, position);
+        if !query.is_empty() {
+            terms.push(query);
+        }
+    }
+    simplify(Query::All, terms)
+}
+
+fn parse_unary(tokens: &[Token], position: &mut usize) -> Query {
+    let token = &tokens[*position];
+    *position += 1;
+    match token {
+        Token::Not => {
+            if *position < tokens.len() && !matches!(tokens[*position], Token::Close | Token::Or) {
+                let query = parse_unary(tokens, position);
+                if query.is_empty() {
+                    query
+                } else {
+                    Query::Not(Box::new(query))
+                }
+            } else {
+                Query::default()
+            }
+        }
+        Token::Open => {
+            let query = parse_any(tokens, position);
+            if *position < tokens.len() && matches!(tokens[*position], Token::Close) {
+                *position += 1;
+            }
+            query
+        }
+        Token::Close | Token::Or => Query::default(),
+        Token::Word {
+            field,
+            op,
+            value,
+            quoted,
+            raw,
+        } => {
+            let term = match field.as_deref().map(field_named) {
+                Some(Some(field)) => {
+                    let op = if *op == Op::Match && !quoted && value.contains("..") {
+                        Op::Range
+                    } else {
+                        *op
+                    };
+                    Term {
+                        field,
+                        op,
+                        value: value.clone(),
+                    }
+                }
+                Some(None) => Term {
+                    field: Field::Text,
+                    op: Op::Match,
+                    value: raw.replace('"', ""),
+                },
+                None => Term {
+                    field: Field::Text,
+                    op: Op::Match,
+                    value: value.clone(),
+                },
+            };
+            if term.value.trim().is_empty() {
+                Query::default()
+            } else {
+                Query::Term(term)
+            }
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            ch if ch.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => tokens.push(word(&mut chars)),
+        }
+    }
+    tokens
+}
+
+fn word(chars: &mut Peekable<Chars>) -> Token {
+    let mut field = None;
+    let mut op = Op::Match;
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut raw = String::new();
+    while let Some(&ch) = chars.peek() {
+        if ch.is_whitespace() || ch == ')' {
+            break;
+        }
+        chars.next();
+        raw.push(ch);
+        if ch == '"' {
+            quoted = true;
+            for ch in chars.by_ref() {
+                raw.push(ch);
+                if ch == '"' {
+                    break;
+                }
+                value.push(ch);
+            }
+        } else if ch == ':' && field.is_none() && !quoted && !value.is_empty() {
+            field = Some(std::mem::take(&mut value));
+            op = comparison(chars, &mut raw);
+        } else {
+            value.push(ch);
+        }
+    }
+    if field.is_none() && !quoted && value == "OR" {
+        return Token::Or;
+    }
+    Token::Word {
+        field,
+        op,
+        value,
+        quoted,
+        raw,
+    }
+}
+
+/// The comparison after a field's colon, e.g. `>=` in `rating:>=3`.
+fn comparison(chars: &mut Peekable<Chars>, raw: &mut String) -> Op {
+    let mut symbol = String::new();
+    while let Some(&ch) = chars.peek() {
+        if !matches!(ch, '<' | '>' | '=') {
+            break;
+        }
+        chars.next();
+        raw.push(ch);
+        symbol.push(ch);
+    }
+    match symbol.as_str() {
+        "" => Op::Match,
+        ">" => Op::Gt,
+        ">=" | "=>" => Op::Gte,
+        "<" => Op::Lt,
+        "<=" | "=<" => Op::Lte,
+        _ => Op::Eq,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{photo_from_exif, write_photo_metadata, ExifData, PhotoMetadata};
+    use std::path::Path;
+
+    fn term(field: Field, op: Op, value: &str) -> Query {
+        Query::Term(Term {
+            field,
+            op,
+            value: value.to_string(),
+        })
+    }
+
+    fn text(value: &str) -> Query {
+        term(Field::Text, Op::Match, value)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("a b OR c"),
+            Query::Any(vec![Query::All(vec![text("a"), text("b")]), text("c")])
+        );
+        assert_eq!(
+            parse("a (b OR c)"),
+            Query::All(vec![text("a"), Query::Any(vec![text("b"), text("c")])])
+        );
+        // Unfinished input keeps what it has.
+        assert_eq!(parse("(a OR"), text("a"));
+        assert_eq!(parse("a OR OR b)"), Query::Any(vec![text("a"), text("b")]));
+    }
+
+    #[test]
+    fn minus_negates_the_next_term_or_group() {
+        assert_eq!(
+            parse("-flag:reject"),
+            Query::Not(Box::new(term(Field::Flag, Op::Match, "reject")))
+        );
+        assert_eq!(
+            parse("a -(b OR c)"),
+            Query::All(vec![
+                text("a"),
+                Query::Not(Box::new(Query::Any(vec![text("b"), text("c")])))
+            ])
+        );
+        assert_eq!(parse("a -"), text("a"));
+    }
+
+    #[test]
+    fn quotes_keep_spaces_and_dots() {
+        assert_eq!(parse("\"golden gate\""), text("golden gate"));
+        assert_eq!(
+            parse("lens:\"24-70 f/2.8\""),
+            term(Field::Lens, Op::Match, "24-70 f/2.8")
+        );
+        // A quoted `..` is text, not a range.
+        assert_eq!(
+            parse("notes:\"1..2\""),
+            term(Field::Notes, Op::Match, "1..2")
+        );
+        assert_eq!(parse("\"OR\""), text("OR"));
+    }
+
+    #[test]
+    fn ranges_and_comparisons() {
+        assert_eq!(
+            parse("f:1.4..2.8"),
+            term(Field::Aperture, Op::Range, "1.4..2.8")
+        );
+        assert_eq!(parse("iso:3200.."), term(Field::Iso, Op::Range, "3200.."));
+        assert_eq!(parse("rating:>=3"), term(Field::Rating, Op::Gte, "3"));
+        assert_eq!(parse("iso:<800"), term(Field::Iso, Op::Lt, "800"));
+        assert_eq!(parse("date:=2024"), term(Field::Date, Op::Eq, "2024"));
+    }
+
+    #[test]
+    fn unknown_fields_are_searched_as_text() {
+        assert_eq!(parse("12:30"), text("12:30"));
+        assert_eq!(parse("foo:\"bar baz\""), text("foo:bar baz"));
+        assert_eq!(
+            parse("foo:bar iso:100"),
+            Query::All(vec![text("foo:bar"), term(Field::Iso, Op::Match, "100")])
+        );
+    }
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(hour, minute, 30)
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_hours_and_minutes() {
+        assert_eq!(parse_clock("14"), Some((14 * 3600, 15 * 3600)));
+        assert_eq!(parse_clock(" 14:30 "), Some((52200, 52260)));
+        assert_eq!(parse_clock("0:05"), Some((300, 360)));
+        for invalid in ["", "24", "12:60", "-1", "noon", "12:", "1:2:3"] {
+            assert_eq!(parse_clock(invalid), None, "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn time_windows_may_run_past_midnight() {
+        let night = |time| time_matches(time, Op::Range, "22:00..02:00");
+        assert!(night(at(22, 0)));
+        assert!(night(at(23, 59)));
+        assert!(night(at(0, 0)));
+        assert!(night(at(2, 0)));
+        assert!(!night(at(2, 1)));
+        assert!(!night(at(21, 59)));
+        assert!(!night(at(12, 0)));
+
+        assert!(time_matches(at(14, 59), Op::Match, "14"));
+        assert!(!time_matches(at(15, 0), Op::Match, "14"));
+        assert!(time_matches(at(15, 0), Op::Gt, "14"));
+        assert!(!time_matches(at(14, 30), Op::Gt, "14"));
+        assert!(time_matches(at(14, 29), Op::Lt, "14:30"));
+        assert!(!time_matches(at(14, 30), Op::Lt, "14:30"));
+        assert!(time_matches(at(6, 59), Op::Range, "..06"));
+        assert!(time_matches(at(23, 0), Op::Range, "18.."));
+        assert!(!time_matches(at(14, 0), Op::Range, "18..soon"));
+    }
+
+    #[test]
+    fn weekdays_by_name_or_kind() {
+        assert!(weekday_matches(Weekday::Sat, "weekend"));
+        assert!(weekday_matches(Weekday::Sun, "Weekend"));
+        assert!(!weekday_matches(Weekday::Fri, "weekend"));
+        assert!(weekday_matches(Weekday::Mon, "weekdays"));
+        assert!(!weekday_matches(Weekday::Sun, "weekday"));
+        assert!(weekday_matches(Weekday::Tue, "mon, tuesday"));
+        assert!(!weekday_matches(Weekday::Wed, "mon,tue,,someday"));
+    }
+
+    #[test]
+    fn golden_hour_borrows_a_position_taken_within_twelve_hours() {
+        let photo = |id: &str, hour: u32, position: Option<(f64, f64)>| {
+            // 16:30 PST on the winter solstice in San Francisco is golden.
+            let local = NaiveDate::from_ymd_opt(2024, 12, 21)
+                .unwrap()
+                .and_hms_opt(hour, 30, 0)
+                .unwrap();
+            let exif = ExifData {
+                date_taken_local: Some(local),
+                date_taken: Some(local.and_utc()),
+                latitude: position.map(|(latitude, _)| latitude),
+                longitude: position.map(|(_, longitude)| longitude),
+                ..ExifData::default()
+            };
+            let path = format!("/shoot/{id}.jpg");
+            photo_from_exif(Path::new(&path), id.to_string(), 0, local.and_utc(), exif)
+        };
+        let golden = Term {
+            field: Field::Time,
+            op: Op::Match,
+            value: "golden".to_string(),
+        };
+        let phone = photo("phone", 4, Some((37.77, -122.42)));
+        let camera = photo("camera", 16, None);
+        let photos = [phone, camera.clone()];
+        assert!(golden.matches(&camera, &Context::new(&photos)));
+
+        // A position from longer ago than twelve hours is not borrowed.
+        let photos = [photo("phone", 3, Some((37.77, -122.42))), camera.clone()];
+        assert!(!golden.matches(&camera, &Context::new(&photos)));
+    }
+
+    #[test]
+    fn catalog_fields_are_matched_in_sql_and_exif_in_memory() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        let metadata = [
+            (
+                "a",
+                PhotoMetadata {
+                    tags: vec!["Places|USA|California".to_string()],
+                    rating: 4,
+                    flag: "pick".to_string(),
+                    ..PhotoMetadata::default()
+                },
+            ),
+            (
+                "b",
+                PhotoMetadata {
+                    notes: "Sunset 100% beach".to_string(),
+                    rating: 1,
+                    color_label: "red".to_string(),
+                    ..PhotoMetadata::default()
+                },
+            ),
+        ];
+        for (id, metadata) in &metadata {
+            write_photo_metadata(&conn, id, metadata).unwrap();
+        }
+        let photos: Vec<Photo> = [("a", 100), ("b", 400), ("c", 6400)]
+            .into_iter()
+            .map(|(id, iso)| {
+                let exif = ExifData {
+                    camera_make: Some("Canon".to_string()),
+                    iso: Some(iso),
+                    ..ExifData::default()
+                };
+                let path = format!("/shoot/{id}.jpg");
+                photo_from_exif(Path::new(&path), id.to_string(), 0, Utc::now(), exif)
+            })
+            .collect();
+        let search = |text: &str| {
+            let query = PhotoQuery {
+                text: text.to_string(),
+                ..PhotoQuery::default()
+            };
+            run(&query, photos.clone(), &conn).unwrap()
+        };
+
+        assert_eq!(search("rating:>=3"), ["a"]);
+        // Photos without metadata match as if it were all defaults.
+        assert_eq!(search("rating:<2"), ["b", "c"]);
+        assert_eq!(search("-flag:pick"), ["b", "c"]);
+        assert_eq!(search("label:none"), ["a", "c"]);
+        assert_eq!(search("tag:usa"), ["a"]);
+        assert_eq!(search("tag:places|usa"), ["a"]);
+        assert!(search("tag:places|us").is_empty());
+        assert_eq!(search("has:notes"), ["b"]);
+        assert_eq!(search("notes:100%"), ["b"]);
+        assert!(search("notes:100_").is_empty());
+        // Free text finds catalog fields and the files' own metadata.
+        assert_eq!(search("beach"), ["b"]);
+        assert_eq!(search("california"), ["a"]);
+        assert_eq!(search("canon").len(), 3);
+        // Catalog and EXIF terms combine under either group.
+        assert_eq!(search("label:red OR iso:>3200"), ["b", "c"]);
+        assert_eq!(search("-(rating:>0 iso:<1000)"), ["c"]);
+
+        let query = PhotoQuery {
+            sort: SortKey::Rating,
+            descending: true,
+            ..PhotoQuery::default()
+        };
+        assert_eq!(run(&query, photos.clone(), &conn).unwrap(), ["a", "b", "c"]);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::exif_details::{Cr3Metadata, ExifTag};
+use crate::video::{boxes, find_box, read_moov};
+use anyhow::{bail, Context, Result};
+use exif::{Exif, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const RAF_MAGIC: &[u8] = b"FUJIFILMCCD-RAW";
+/// The `uuid` box in a CR3 `moov` holding Canon's metadata boxes.
+const CANON_METADATA_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+/// EXIF of a RAW format that is not a TIFF structure, so the EXIF reader
+/// cannot open the file itself.
+pub enum RawExif {
+    /// Fujifilm RAF: the embedded camera JPEG carries the full EXIF.
+    Raf(Exif),
+    /// Canon CR3: the `moov` box, holding one TIFF structure per IFD.
+    Cr3(Vec<u8>),
+}
+
+impl RawExif {
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; 92];
+        file.read_exact(&mut header)?;
+        if header.starts_with(RAF_MAGIC) {
+            let offset = u32::from_be_bytes(header[84..88].try_into()?);
+            let length = u32::from_be_bytes(header[88..92].try_into()?);
+            let file_len = file.metadata()?.len();
+            if u64::from(offset) + u64::from(length) > file_len {
+                bail!("Embedded JPEG runs past the end of the file");
+            }
+            file.seek(SeekFrom::Start(u64::from(offset)))?;
+            let mut jpeg = vec![0u8; length as usize];
+            file.read_exact(&mut jpeg)
+                .context("Embedded JPEG ends early")?;
+            let exif = Reader::new().read_from_container(&mut Cursor::new(jpeg))?;
+            return Ok(Self::Raf(exif));
+        }
+        if &header[4..8] == b"ftyp" && &header[8..12] == b"crx " {
+            return Ok(Self::Cr3(read_moov(path)?));
+        }
+        bail!("Not a RAF or CR3 file")
+    }
+
+    fn cr3_metadata(moov: &[u8]) -> Option<Cr3Metadata<'_>> {
+        let canon = boxes(moov)
+            .filter(|(kind, _)| kind == b"uuid")
+            .find_map(|(_, body)| body.strip_prefix(&CANON_METADATA_UUID))?;
+        Some(Cr3Metadata {
+            ifd0: find_box(canon, b"CMT1")?,
+            exif: find_box(canon, b"CMT2")?,
+            maker_note: find_box(canon, b"CMT3"),
+            gps: find_box(canon, b"CMT4"),
+        })
+    }
+
+    pub fn exif_data(&self) -> crate::ExifData {
+        match self {
+            Self::Raf(exif) => crate::exif_data_from(exif),
+            Self::Cr3(moov) => Self::cr3_metadata(moov)
+                .map(|metadata| metadata.exif_data())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn tags(&self) -> Vec<ExifTag> {
+        match self {
+            Self::Raf(exif) => crate::exif_tags_from(exif),
+            Self::Cr3(moov) => Self::cr3_metadata(moov)
+                .map(|metadata| metadata.tags())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_an_embedded_jpeg_past_the_end_of_the_file() {
+        let path = std::env::temp_dir().join(format!("hologram-raf-{}.raf", std::process::id()));
+        let mut header = [0u8; 92];
+        header[..RAF_MAGIC.len()].copy_from_slice(RAF_MAGIC);
+        header[84..88].copy_from_slice(&92u32.to_be_bytes());
+        header[88..92].copy_from_slice(&u32::MAX.to_be_bytes());
+        std::fs::write(&path, header).unwrap();
+        let error = RawExif::read(&path).err().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.to_string().contains("past the end"));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::video;
 use anyhow::Result;
 use base64::Engine;
 use exif::{Exif, In, Reader, Tag, Value};
@@ -5,6 +6,7 @@ use image::codecs::jpeg::JpegEncoder;
 use image::DynamicImage;
 #[cfg(not(target_env = "msvc"))]
 use image::RgbImage;
+use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
 #[cfg(not(target_env = "msvc"))]
 use rsraw::{ImageFormat as RawImageFormat, RawImage, ThumbFormat, BIT_DEPTH_8};
 use serde::{Deserialize, Serialize};
@@ -17,11 +19,19 @@ static RAW_EXTENSIONS: &[&str] = &[
     "orf", "pef", "ptx", "pxn", "raf", "raw", "rw2", "rwl", "rwz", "sr2", "srf", "srw", "x3f",
 ];
 
+/// Decoded with libheif rather than the `image` crate.
+static HEIF_EXTENSIONS: &[&str] = &["heic", "heif", "hif", "avif"];
+
+/// Video clips, described from their container and previewed by a poster
+/// frame.
+static VIDEO_EXTENSIONS: &[&str] = &["mp4", "mov", "m4v"];
+
 static SUPPORTED_EXTENSIONS: &[&str] = &[
-    "jpg", "jpeg", "tiff", "tif", "png", "3fr", "ari", "arw", "bay", "cap", "crw", "cr2", "cr3",
-    "data", "dcs", "dcr", "dng", "drf", "erf", "fff", "gpr", "iiq", "k25", "kdc", "mdc", "mef",
-    "mos", "mrw", "nef", "nrw", "obm", "orf", "pef", "ptx", "pxn", "raf", "raw", "rw2", "rwl",
-    "rwz", "sr2", "srf", "srw", "x3f",
+    "jpg", "jpeg", "tiff", "tif", "png", "webp", "heic", "heif", "hif", "avif", "mp4", "mov",
+    "m4v", "3fr", "ari", "arw", "bay", "cap", "crw", "cr2", "cr3", "data", "dcs", "dcr", "dng",
+    "drf", "erf", "fff", "gpr", "iiq", "k25", "kdc", "mdc", "mef", "mos", "mrw", "nef", "nrw",
+    "obm", "orf", "pef", "ptx", "pxn", "raf", "raw", "rw2", "rwl", "rwz", "sr2", "srf", "srw",
+    "x3f",
 ];
 
 const RAW_PREVIEW_CACHE_VERSION: &str = "raw-preview-orientation-v2";
@@ -58,6 +68,14 @@ pub fn is_raw_file(path: &Path) -> bool {
     extension_matches(path, RAW_EXTENSIONS)
 }
 
+pub fn is_heif_file(path: &Path) -> bool {
+    extension_matches(path, HEIF_EXTENSIONS)
+}
+
+pub fn is_video_file(path: &Path) -> bool {
+    extension_matches(path, VIDEO_EXTENSIONS)
+}
+
 fn extension_matches(path: &Path, extensions: &[&str]) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
@@ -169,9 +187,15 @@ pub fn generate_thumbnail_with_info(file_path: &Path) -> Result<GeneratedThumbna
             embedded_jpeg_preview: preview.embedded_jpeg_preview,
         });
     }
+    if is_video_file(file_path) {
+        let poster = video::poster_frame_jpeg(file_path, 400)?;
+        return Ok(GeneratedThumbnail {
+            thumbnail: base64::engine::general_purpose::STANDARD.encode(poster),
+            embedded_jpeg_preview: None,
+        });
+    }
 
-    let img = image::open(file_path)?;
-    let img = apply_exif_orientation(img, read_exif_orientation(file_path));
+    let img = open_upright(file_path)?;
     let thumbnail = img.thumbnail(400, 400);
     let buffer = encode_jpeg(&thumbnail, 90)?;
 
@@ -181,6 +205,62 @@ pub fn generate_thumbnail_with_info(file_path: &Path) -> Result<GeneratedThumbna
     })
 }
 
+/// A display JPEG bounded to `max_dimension`, oriented upright. RAWs use
+/// their embedded (or rendered) display preview, videos a poster frame.
+pub fn generate_preview_jpeg(file_path: &Path, max_dimension: u32) -> Result<Vec<u8>> {
+    if is_raw_file(file_path) {
+        return convert_raw_display_preview_to_jpeg(file_path, max_dimension);
+    }
+    if is_video_file(file_path) {
+        return video::poster_frame_jpeg(file_path, max_dimension);
+    }
+
+    bounded_jpeg_from_image(open_upright(file_path)?, max_dimension)
+}
+
+/// Full-size JPEG of a HEIF/AVIF file for the viewer, which cannot display
+/// those formats directly.
+pub fn heif_to_jpeg(file_path: &Path) -> Result<Vec<u8>> {
+    encode_jpeg(&decode_heif(file_path)?, 95)
+}
+
+/// Decode a non-RAW image the right way up. libheif already applies the
+/// HEIF rotation and mirror properties, so only other formats are rotated
+/// from their EXIF orientation.
+fn open_upright(file_path: &Path) -> Result<DynamicImage> {
+    if is_heif_file(file_path) {
+        return decode_heif(file_path);
+    }
+    let img = image::open(file_path)?;
+    Ok(apply_exif_orientation(
+        img,
+        read_exif_orientation(file_path),
+    ))
+}
+
+fn decode_heif(file_path: &Path) -> Result<DynamicImage> {
+    let path = file_path
+        .to_str()
+        .ok_or_else(|| anyhow::anyhow!("Invalid HEIF path"))?;
+    let context = HeifContext::read_from_file(path)?;
+    let handle = context.primary_image_handle()?;
+    let image = LibHeif::new().decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
+    let plane = image
+        .planes()
+        .interleaved
+        .ok_or_else(|| anyhow::anyhow!("HEIF image has no RGB plane"))?;
+
+    // Rows may be padded beyond `width * 3` bytes.
+    let row_bytes = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_bytes * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_bytes]);
+    }
+    let rgb = image::RgbImage::from_raw(plane.width, plane.height, pixels)
+        .ok_or_else(|| anyhow::anyhow!("HEIF image has an unexpected size"))?;
+    Ok(DynamicImage::ImageRgb8(rgb))
+}
+
 pub fn generate_embedded_thumbnail(file_path: &Path) -> Option<String> {
     extract_embedded_jpeg_thumbnail(file_path)
         .ok()
@@ -0,0 +1,383 @@
+use crate::raw_preview::is_supported_file;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use walkdir::WalkDir;
+
+const SCAN_OPTIONS_KEY: &str = "scan_options";
+/// Per-folder ignore file: one glob per line, `#` starts a comment. Patterns
+/// apply to the folder holding the file and everything below it.
+pub const IGNORE_FILE_NAME: &str = ".hologramignore";
+
+/// Never photos: OS volume metadata, AppleDouble resource forks, Lightroom
+/// preview caches and our own Lightroom exports.
+const BUILTIN_IGNORE_PATTERNS: &[&str] = &[
+    ".Trashes",
+    ".Spotlight-V100",
+    ".fseventsd",
+    "._*",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+    "*.lrdata",
+    "hologram-lightroom-export",
+];
+
+fn default_true() -> bool {
+    true
+}
+
+/// How a catalog's folders are walked. Stored per catalog, so scans,
+/// rescans and the folder watcher all see the same files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanOptions {
+    /// Globs relative to the scanned folder. A pattern without `/` matches a
+    /// file or folder name at any depth.
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+    /// Leave out dot-files and dot-folders. Off unless chosen, as scans
+    /// always included them.
+    #[serde(default)]
+    pub skip_hidden: bool,
+    /// Folder levels below the root to descend into; `None` is unlimited.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default = "default_true")]
+    pub follow_symlinks: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            ignore_patterns: Vec::new(),
+            skip_hidden: false,
+            max_depth: None,
+            follow_symlinks: true,
+        }
+    }
+}
+
+pub fn load_options(conn: &rusqlite::Connection) -> ScanOptions {
+    conn.query_row(
+        "SELECT value FROM catalog_settings WHERE key = ?1",
+        [SCAN_OPTIONS_KEY],
+        |row| row.get::<_, String>(0),
+    )
+    .ok()
+    .and_then(|json| serde_json::from_str(&json).ok())
+    .unwrap_or_default()
+}
+
+pub fn save_options(conn: &rusqlite::Connection, options: &ScanOptions) -> Result<(), String> {
+    build_globs(options.ignore_patterns.iter().map(String::as_str))?;
+    let json = serde_json::to_string(options).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO catalog_settings (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value=excluded.value",
+        [SCAN_OPTIONS_KEY, &json],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Compiled ignore patterns. Patterns ending in `/` only match folders.
+struct Globs {
+    any: GlobSet,
+    dirs: GlobSet,
+}
+
+impl Globs {
+    fn empty() -> Self {
+        Self {
+            any: GlobSet::empty(),
+            dirs: GlobSet::empty(),
+        }
+    }
+
+    fn is_match(&self, path: &Path, is_dir: bool) -> bool {
+        self.any.is_match(path) || (is_dir && self.dirs.is_match(path))
+    }
+}
+
+/// Compile ignore patterns gitignore-style: patterns containing `/` are
+/// anchored to the folder they belong to, others match any name below it,
+/// and a trailing `/` matches folders only.
+fn build_globs<'a>(patterns: impl IntoIterator<Item = &'a str>) -> Result<Globs, String> {
+    let mut any = GlobSetBuilder::new();
+    let mut dirs = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+        let dir_only = pattern.ends_with('/');
+        let pattern = pattern.trim_end_matches('/');
+        let anchored = match pattern.strip_prefix('/') {
+            Some(anchored) => anchored.to_string(),
+            None if pattern.contains('/') => pattern.to_string(),
+            None => format!("**/{pattern}"),
+        };
+        let glob = Glob::new(&anchored).map_err(|e| format!("Invalid pattern {pattern}: {e}"))?;
+        if dir_only {
+            dirs.add(glob);
+        } else {
+            any.add(glob);
+        }
+        // A matched folder excludes everything inside it, and whatever has
+        // something inside it is a folder.
+        any.add(Glob::new(&format!("{anchored}/**")).map_err(|e| e.to_string())?);
+    }
+    Ok(Globs {
+        any: any.build().map_err(|e| e.to_string())?,
+        dirs: dirs.build().map_err(|e| e.to_string())?,
+    })
+}
+
+fn read_ignore_file(dir: &Path) -> Option<Globs> {
+    let contents = fs::read_to_string(dir.join(IGNORE_FILE_NAME)).ok()?;
+    build_globs(contents.lines()).ok()
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Decides which files under one root folder are part of the library.
+/// Ignore files are read once per folder for the life of the filter, so a
+/// filter should not outlive the batch of paths it checks.
+pub struct ScanFilter {
+    root: PathBuf,
+    options: ScanOptions,
+    globs: Globs,
+    ignore_files: Mutex<HashMap<PathBuf, Option<Arc<Globs>>>>,
+}
+
+impl ScanFilter {
+    pub fn new(root: &str, options: &ScanOptions) -> Self {
+        let patterns = BUILTIN_IGNORE_PATTERNS
+            .iter()
+            .copied()
+            .chain(options.ignore_patterns.iter().map(String::as_str));
+        // Invalid user patterns are rejected when saved; fall back to the
+        // built-in set rather than failing a scan.
+        let globs = build_globs(patterns)
+            .or_else(|_| build_globs(BUILTIN_IGNORE_PATTERNS.iter().copied()))
+            .unwrap_or_else(|_| Globs::empty());
+        Self {
+            root: PathBuf::from(root),
+            options: options.clone(),
+            globs,
+            ignore_files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn ignore_file(&self, dir: &Path) -> Option<Arc<Globs>> {
+        let mut ignore_files = self.ignore_files.lock().unwrap_or_else(|e| e.into_inner());
+        ignore_files
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| read_ignore_file(dir).map(Arc::new))
+            .clone()
+    }
+
+    fn excluded(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        (self.options.skip_hidden && is_hidden(path)) || self.globs.is_match(relative, is_dir)
+    }
+
+    /// Walk the root for supported files, calling `on_found` with the
+    /// running count and each path as it is discovered. The walk stops early
+    /// once `on_found` returns false. Symlinked folders are followed at most
+    /// once, so links back into the tree cannot loop or duplicate photos.
+    pub fn walk(&self, on_found: impl FnMut(usize, &Path) -> bool) -> Vec<PathBuf> {
+        self.walk_from(&self.root.clone(), on_found)
+    }
+
+    /// Like `walk`, but only the part of the tree below `start`, a folder
+    /// inside the root.
+    pub fn walk_from(
+        &self,
+        start: &Path,
+        mut on_found: impl FnMut(usize, &Path) -> bool,
+    ) -> Vec<PathBuf> {
+        let Ok(start_relative) = start.strip_prefix(&self.root) else {
+            return Vec::new();
+        };
+        if !self.allows(start) {
+            return Vec::new();
+        }
+        let start_depth = start_relative.components().count();
+        let mut walker = WalkDir::new(start).follow_links(self.options.follow_symlinks);
+        if let Some(max_depth) = self.options.max_depth {
+            match (max_depth + 1).checked_sub(start_depth) {
+                Some(remaining) => walker = walker.max_depth(remaining),
+                None => return Vec::new(),
+            }
+        }
+        let mut entries = walker.into_iter();
+        // Ignore files of the folders above `start` still apply below it.
+        let mut ignore_files: Vec<(PathBuf, Arc<Globs>)> = start
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.root))
+            .filter_map(|dir| Some((dir.to_path_buf(), self.ignore_file(dir)?)))
+            .collect();
+        let mut visited_dirs: HashSet<PathBuf> = HashSet::new();
+        let mut paths = Vec::new();
+
+        while let Some(entry) = entries.next() {
+            let Ok(entry) = entry else {
+                continue;
+            };
+            let path = entry.path();
+            let is_dir = entry.file_type().is_dir();
+            ignore_files.retain(|(dir, _)| path.starts_with(dir) && path != dir);
+
+            let ignored = self.excluded(path, is_dir)
+                || ignore_files.iter().any(|(dir, globs)| {
+                    path.strip_prefix(dir)
+                        .is_ok_and(|relative| globs.is_match(relative, is_dir))
+                });
+            if ignored {
+                if is_dir {
+                    entries.skip_current_dir();
+                }
+                continue;
+            }
+
+            if is_dir {
+                let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                if !visited_dirs.insert(canonical) {
+                    entries.skip_current_dir();
+                    continue;
+                }
+                if let Some(globs) = self.ignore_file(path) {
+                    ignore_files.push((path.to_path_buf(), globs));
+                }
+                continue;
+            }
+            if !is_supported_file(path) {
+                continue;
+            }
+            let keep_walking = on_found(paths.len() + 1, path);
+            paths.push(path.to_path_buf());
+            if !keep_walking {
+                break;
+            }
+        }
+        paths
+    }
+
+    /// Whether a single path reported by the folder watcher would have been
+    /// picked up by `walk`.
+    pub fn allows(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if let Some(max_depth) = self.options.max_depth {
+            if relative.components().count() > max_depth + 1 {
+                return false;
+            }
+        }
+        let is_dir = path.is_dir();
+        let mut current = self.root.clone();
+        for component in relative.components() {
+            if let Some(globs) = self.ignore_file(&current) {
+                if path
+                    .strip_prefix(&current)
+                    .is_ok_and(|relative| globs.is_match(relative, is_dir))
+                {
+                    return false;
+                }
+            }
+            current.push(component);
+            // Every component above the path itself is a folder.
+            if self.excluded(&current, is_dir || current != path) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_honors_nested_ignore_files_and_opt_in_hidden() {
+        let root = std::env::temp_dir().join(format!("hologram-filter-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("shoot").join("rejects")).unwrap();
+        fs::create_dir_all(root.join(".hidden")).unwrap();
+        fs::write(
+            root.join("shoot").join(IGNORE_FILE_NAME),
+            "rejects/\n*.tmp.jpg\n",
+        )
+        .unwrap();
+        let root_str = root.to_string_lossy().to_string();
+
+        let filter = ScanFilter::new(&root_str, &ScanOptions::default());
+        assert!(filter.allows(&root.join("shoot").join("IMG_1.JPG")));
+        assert!(!filter.allows(&root.join("shoot").join("rejects").join("IMG_2.JPG")));
+        assert!(!filter.allows(&root.join("shoot").join("a").join("IMG_3.tmp.jpg")));
+        assert!(filter.allows(&root.join("IMG_3.tmp.jpg")));
+        assert!(filter.allows(&root.join(".hidden").join("IMG_4.JPG")));
+
+        let options = ScanOptions {
+            skip_hidden: true,
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(&root_str, &options);
+        assert!(!filter.allows(&root.join(".hidden").join("IMG_4.JPG")));
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn trailing_slash_patterns_only_match_folders() {
+        let root = std::env::temp_dir().join(format!("hologram-dir-only-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("shoot").join("rejects")).unwrap();
+        fs::create_dir_all(root.join("outtakes.jpg")).unwrap();
+        fs::write(root.join("shoot").join("rejects").join("IMG_1.JPG"), b"").unwrap();
+        fs::write(root.join("shoot").join("IMG_2.JPG"), b"").unwrap();
+        // A file named like the folder pattern is still a photo.
+        fs::write(root.join("rejects"), b"").unwrap();
+        fs::write(root.join("outtakes.jpg").join("IMG_3.JPG"), b"").unwrap();
+        fs::write(root.join("shoot").join("outtakes.jpg"), b"").unwrap();
+        let options = ScanOptions {
+            ignore_patterns: vec!["rejects/".to_string(), "outtakes.jpg/".to_string()],
+            ..ScanOptions::default()
+        };
+        let filter = ScanFilter::new(&root.to_string_lossy(), &options);
+
+        let mut found: Vec<PathBuf> = filter
+            .walk(|_, _| true)
+            .into_iter()
+            .map(|path| path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect();
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                Path::new("shoot").join("IMG_2.JPG"),
+                Path::new("shoot").join("outtakes.jpg")
+            ]
+        );
+        assert!(filter.allows(&root.join("rejects")));
+        assert!(filter.allows(&root.join("shoot").join("outtakes.jpg")));
+        assert!(!filter.allows(&root.join("shoot").join("rejects")));
+        assert!(!filter.allows(&root.join("shoot").join("rejects").join("IMG_1.JPG")));
+        assert!(!filter.allows(&root.join("outtakes.jpg").join("IMG_3.JPG")));
+        assert!(!filter.allows(&root.join("outtakes.jpg")));
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,897 @@
+use crate::capture_time;
+use crate::groups::{self, InferredPair, PairingOptions, PhotoGroup};
+use crate::health::{self, FileHealth};
+use crate::identity::{self, IdentityScheme, Relink};
+use crate::metadata_templates;
+use crate::raw_preview::is_supported_file;
+use crate::scan_filter::ScanFilter;
+use crate::{
+    collect_photo_metadata, file_fingerprint, photo_from_exif, stable_photo_id, ExifData, Photo,
+};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Bumped when `ExifData` gains fields that existing index rows lack, so
+/// files indexed before are read again on their next scan.
+const EXIF_LAYOUT_VERSION: i64 = 1;
+
+/// What the catalog remembers about a file from the last scan of its folder.
+struct IndexEntry {
+    photo_id: String,
+    file_size: u64,
+    modified_at: String,
+    content_key: Option<String>,
+    exif: Option<ExifData>,
+    /// `exif` was extracted with the current layout.
+    exif_current: bool,
+    health: Option<FileHealth>,
+    /// The group the file was last reported in; `None` for entries indexed
+    /// before groups were stored.
+    group: Option<Option<PhotoGroup>>,
+}
+
+/// Outcome of scanning a folder against its persisted scan index. `photos`
+/// is the complete library, grouped once `group_catalog` ran; the ID lists
+/// describe how it differs from the previous scan. `relinked` lists files
+/// recognised by content at a new path, whose catalog metadata now lives
+/// under their current ID. `inferred_pairs` lists groups formed across
+/// folders by capture metadata.
+#[derive(Default)]
+pub struct IndexedScan {
+    pub photos: Vec<Photo>,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+    pub relinked: Vec<Relink>,
+    pub inferred_pairs: Vec<InferredPair>,
+}
+
+impl IndexedScan {
+    /// Combine the scan of another root of the same catalog into this one.
+    pub fn extend(&mut self, other: IndexedScan) {
+        self.photos.extend(other.photos);
+        self.added.extend(other.added);
+        self.changed.extend(other.changed);
+        self.removed.extend(other.removed);
+        self.relinked.extend(other.relinked);
+        self.inferred_pairs.extend(other.inferred_pairs);
+    }
+}
+
+/// One file's result from the parallel read, before grouping.
+struct Scanned {
+    photo: Photo,
+    state: ScanState,
+}
+
+struct ScanState {
+    content_key: Option<String>,
+    /// The file's metadata was read from disk rather than the index.
+    reread: bool,
+    /// The index row must be rewritten (re-read, or a content key or health
+    /// check was added).
+    dirty: bool,
+}
+
+fn load_entries(conn: &rusqlite::Connection, root_path: &str) -> HashMap<String, IndexEntry> {
+    let mut entries = HashMap::new();
+    let Ok(mut statement) = conn.prepare(
+        "SELECT file_path, photo_id, file_size, modified_at, exif_json, content_key, health_json,
+           exif_version, group_json
+         FROM scan_index WHERE root_path = ?1",
+    ) else {
+        return entries;
+    };
+    let Ok(rows) = statement.query_map([root_path], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, u64>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, i64>(7)?,
+            row.get::<_, Option<String>>(8)?,
+        ))
+    }) else {
+        return entries;
+    };
+    for (
+        file_path,
+        photo_id,
+        file_size,
+        modified_at,
+        exif_json,
+        content_key,
+        health_json,
+        exif_version,
+        group_json,
+    ) in rows.flatten()
+    {
+        // Entries written by an older ExifData layout are re-read on this scan.
+        let exif = serde_json::from_str(&exif_json).ok();
+        let health = health_json.and_then(|json| serde_json::from_str(&json).ok());
+        let group = group_json.and_then(|json| serde_json::from_str(&json).ok());
+        entries.insert(
+            file_path,
+            IndexEntry {
+                photo_id,
+                file_size,
+                modified_at,
+                content_key,
+                exif,
+                exif_current: exif_version >= EXIF_LAYOUT_VERSION,
+                health,
+                group,
+            },
+        );
+    }
+    entries
+}
+
+/// Index rows for files seen elsewhere in the catalog (another root, e.g.
+/// the folder's old location) that have since vanished from disk, by
+/// content key.
+fn orphaned_entries(
+    conn: &rusqlite::Connection,
+    root_path: &str,
+    content_key: &str,
+) -> Vec<(String, String)> {
+    let Ok(mut statement) = conn.prepare(
+        "SELECT file_path, photo_id FROM scan_index
+         WHERE content_key = ?1 AND root_path != ?2",
+    ) else {
+        return Vec::new();
+    };
+    statement
+        .query_map([content_key, root_path], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map(|rows| {
+            rows.flatten()
+                .filter(|(file_path, _)| !Path::new(file_path).exists())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn write_entries(
+    conn: &mut rusqlite::Connection,
+    root_path: &str,
+    written: &[(&Photo, Option<&str>)],
+    removed_paths: &[String],
+    relinked: &[Relink],
+) -> Result<(), String> {
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    for relink in relinked {
+        if relink.old_id != relink.new_id {
+            identity::migrate_photo_id(&transaction, &relink.old_id, &relink.new_id)?;
+        }
+    }
+    {
+        let mut upsert = transaction
+            .prepare(
+                "INSERT INTO scan_index
+                 (file_path, root_path, photo_id, file_size, modified_at, exif_json, scanned_at,
+                  content_key, health_json, exif_version)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                 ON CONFLICT(file_path) DO UPDATE SET root_path=excluded.root_path,
+                   photo_id=excluded.photo_id, file_size=excluded.file_size,
+                   modified_at=excluded.modified_at, exif_json=excluded.exif_json,
+                   scanned_at=excluded.scanned_at, content_key=excluded.content_key,
+                   health_json=excluded.health_json, exif_version=excluded.exif_version",
+            )
+            .map_err(|e| e.to_string())?;
+        let scanned_at = Utc::now().to_rfc3339();
+        for (photo, content_key) in written {
+            let exif_json = serde_json::to_string(&photo.exif).map_err(|e| e.to_string())?;
+            let health_json = serde_json::to_string(&photo.health).map_err(|e| e.to_string())?;
+            upsert
+                .execute(rusqlite::params![
+                    photo.file_path,
+                    root_path,
+                    photo.id,
+                    photo.file_size,
+                    photo.modified_at.to_rfc3339(),
+                    exif_json,
+                    scanned_at,
+                    content_key,
+                    health_json,
+                    EXIF_LAYOUT_VERSION,
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+
+        let mut delete = transaction
+            .prepare("DELETE FROM scan_index WHERE file_path = ?1")
+            .map_err(|e| e.to_string())?;
+        for file_path in removed_paths {
+            delete.execute([file_path]).map_err(|e| e.to_string())?;
+        }
+    }
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+/// Callbacks a scan invokes from its worker threads.
+pub struct ScanHooks<'a> {
+    /// Called as each file's metadata becomes available.
+    pub on_photo: &'a (dyn Fn(&Photo) + Sync),
+    /// Polled between files; once it returns true the scan stops early and
+    /// leaves the index untouched.
+    pub is_cancelled: &'a (dyn Fn() -> bool + Sync),
+}
+
+impl ScanHooks<'static> {
+    pub fn none() -> Self {
+        ScanHooks {
+            on_photo: &|_| {},
+            is_cancelled: &|| false,
+        }
+    }
+}
+
+impl IndexEntry {
+    fn modified_at(&self) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&self.modified_at)
+            .map(|value| value.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now())
+    }
+}
+
+/// Scan `paths` (everything currently under `root_path`), reusing indexed
+/// EXIF for files whose size and modification time are unchanged. Without a
+/// catalog connection every file is read, as in a first scan. Returns `None`
+/// if the scan was cancelled through `hooks`.
+pub fn scan_paths(
+    conn: Option<&mut rusqlite::Connection>,
+    root_path: &str,
+    paths: Vec<PathBuf>,
+    hooks: &ScanHooks,
+) -> Option<IndexedScan> {
+    let entries = conn
+        .as_deref()
+        .map(|conn| load_entries(conn, root_path))
+        .unwrap_or_default();
+    reconcile(conn, root_path, entries, paths, None, hooks)
+}
+
+fn indexed_roots(conn: &rusqlite::Connection) -> Vec<String> {
+    conn.prepare("SELECT DISTINCT root_path FROM scan_index")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get(0))
+                .map(|rows| rows.flatten().collect())
+        })
+        .unwrap_or_default()
+}
+
+/// Every photo the catalog indexed, across its roots, as of each root's
+/// last scan and grouped as that scan left them. Queries run against these
+/// rather than the files.
+pub fn catalog_photos(conn: &rusqlite::Connection) -> Vec<Photo> {
+    let mut photos = Vec::new();
+    let mut grouped = true;
+    for root in indexed_roots(conn) {
+        let (root_photos, root_grouped) = indexed_photos(conn, &root, !Path::new(&root).is_dir());
+        photos.extend(root_photos);
+        grouped &= root_grouped;
+    }
+    // Catalogs indexed before groups were stored are grouped here until
+    // their next scan.
+    if !grouped {
+        groups::group_library(&mut photos, &groups::load_pairing_options(conn));
+    }
+    photos
+}
+
+/// The photos indexed under `root_path` with their stored groups, and
+/// whether every entry had one.
+fn indexed_photos(
+    conn: &rusqlite::Connection,
+    root_path: &str,
+    offline: bool,
+) -> (Vec<Photo>, bool) {
+    let mut grouped = true;
+    let mut photos: Vec<Photo> = load_entries(conn, root_path)
+        .into_iter()
+        .map(|(file_path, entry)| {
+            let mut photo = photo_from_exif(
+                Path::new(&file_path),
+                entry.photo_id.clone(),
+                entry.file_size,
+                entry.modified_at(),
+                entry.exif.clone().unwrap_or_default(),
+            );
+            photo.offline = offline;
+            photo.health = entry.health.clone().unwrap_or_default();
+            grouped &= entry.group.is_some();
+            photo.group = entry.group.flatten();
+            photo
+        })
+        .collect();
+    capture_time::apply_shifts(conn, &mut photos);
+    (photos, grouped)
+}
+
+/// Group a scan the way every view of the catalog sees it: by file name,
+/// then across folders when the catalog pairs by capture metadata. Photos of
+/// roots not in `scanned_roots` (unplugged drives, roots the watcher saw no
+/// change in) join from the index, so pairs can span roots. Photos whose
+/// group differs from the one last reported are added to `changed`, and the
+/// new groups are stored.
+pub fn group_catalog(
+    conn: Option<&mut rusqlite::Connection>,
+    scanned_roots: &[String],
+    scan: &mut IndexedScan,
+) {
+    let Some(conn) = conn else {
+        scan.inferred_pairs = groups::group_library(&mut scan.photos, &PairingOptions::default());
+        return;
+    };
+    for root in indexed_roots(conn) {
+        if !scanned_roots.contains(&root) {
+            let offline = !Path::new(&root).is_dir();
+            scan.photos.extend(indexed_photos(conn, &root, offline).0);
+        }
+    }
+    scan.inferred_pairs =
+        groups::group_library(&mut scan.photos, &groups::load_pairing_options(conn));
+
+    let stored: HashMap<String, Option<String>> = conn
+        .prepare("SELECT file_path, group_json FROM scan_index")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map(|rows| rows.flatten().collect())
+        })
+        .unwrap_or_default();
+    let added: HashSet<&str> = scan.added.iter().map(String::as_str).collect();
+    let mut reported: HashSet<String> = scan.changed.iter().cloned().collect();
+    let mut regrouped = Vec::new();
+    for photo in &scan.photos {
+        // An unplugged drive's companions cannot be listed; its photos keep
+        // their stored group until it is back.
+        if photo.offline {
+            continue;
+        }
+        let Some(previous) = stored.get(&photo.file_path) else {
+            continue;
+        };
+        let group_json = serde_json::to_string(&photo.group).unwrap_or_default();
+        if previous.as_deref() == Some(group_json.as_str()) {
+            continue;
+        }
+        // Entries indexed before groups were stored take the current group
+        // as their baseline.
+        let previous: Option<Option<PhotoGroup>> = previous
+            .as_deref()
+            .and_then(|json| serde_json::from_str(json).ok());
+        if previous.is_some_and(|previous| previous != photo.group)
+            && !added.contains(photo.id.as_str())
+            && reported.insert(photo.id.clone())
+        {
+            scan.changed.push(photo.id.clone());
+        }
+        regrouped.push((photo.file_path.as_str(), group_json));
+    }
+    if let Err(error) = write_groups(conn, &regrouped) {
+        eprintln!("Failed to store photo groups: {error}");
+    }
+}
+
+fn write_groups(
+    conn: &mut rusqlite::Connection,
+    regrouped: &[(&str, String)],
+) -> Result<(), String> {
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut update = transaction
+            .prepare("UPDATE scan_index SET group_json = ?2 WHERE file_path = ?1")
+            .map_err(|e| e.to_string())?;
+        for (file_path, group_json) in regrouped {
+            update
+                .execute([file_path, group_json.as_str()])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+/// Apply filesystem changes reported for `touched` paths without walking the
+/// whole folder: every other indexed file is trusted as it was last scanned.
+/// A touched directory is walked, and a vanished one removes everything
+/// indexed beneath it. Paths `filter` excludes are ignored.
+pub fn rescan_touched(
+    conn: Option<&mut rusqlite::Connection>,
+    root_path: &str,
+    touched: &[PathBuf],
+    filter: &ScanFilter,
+) -> Option<IndexedScan> {
+    let entries = conn
+        .as_deref()
+        .map(|conn| load_entries(conn, root_path))
+        .unwrap_or_default();
+    let scheme = conn
+        .as_deref()
+        .map(identity::load_scheme)
+        .unwrap_or_default();
+
+    let (present, gone): (Vec<&PathBuf>, Vec<&PathBuf>) =
+        touched.iter().partition(|path| path.exists());
+    let mut verify = HashSet::new();
+    let mut walked_dirs = Vec::new();
+    for path in present {
+        if path.is_dir() {
+            verify.extend(filter.walk_from(path, |_, _| true));
+            walked_dirs.push(path);
+        } else if is_supported_file(path) && filter.allows(path) {
+            verify.insert(path.clone());
+        }
+    }
+
+    // A walked folder is authoritative for what lies below it, so files
+    // excluded since they were indexed (e.g. by a new `.hologramignore`)
+    // drop out of the library.
+    let mut paths: Vec<PathBuf> = entries
+        .keys()
+        .map(PathBuf::from)
+        .filter(|path| !gone.iter().any(|gone| path.starts_with(gone)))
+        .filter(|path| !walked_dirs.iter().any(|dir| path.starts_with(dir)))
+        .filter(|path| !verify.contains(path) && filter.allows(path))
+        .collect();
+    paths.extend(verify.iter().cloned());
+
+    // Without an index entry there is no record of what a deleted file was
+    // called; fall back to the ID it would have been given when scanned. A
+    // content ID cannot be recovered once the file is gone.
+    let unindexed_gone: Vec<String> = gone
+        .iter()
+        .filter(|_| scheme == IdentityScheme::Path)
+        .filter(|path| is_supported_file(path))
+        .filter(|path| !entries.contains_key(path.to_string_lossy().as_ref()))
+        .map(|path| stable_photo_id(path))
+        .collect();
+
+    let mut scan = reconcile(
+        conn,
+        root_path,
+        entries,
+        paths,
+        Some(&verify),
+        &ScanHooks::none(),
+    )?;
+    scan.removed.extend(unindexed_gone);
+    Some(scan)
+}
+
+/// Build the library for `paths`. Files outside `verify` (when given) are
+/// taken from the index without touching the disk; all others are re-read
+/// unless their size and modification time still match the index.
+fn reconcile(
+    conn: Option<&mut rusqlite::Connection>,
+    root_path: &str,
+    entries: HashMap<String, IndexEntry>,
+    paths: Vec<PathBuf>,
+    verify: Option<&HashSet<PathBuf>>,
+    hooks: &ScanHooks,
+) -> Option<IndexedScan> {
+    let scheme = conn
+        .as_deref()
+        .map(identity::load_scheme)
+        .unwrap_or_default();
+    // Sampling a file for its content key costs a read of both ends and an
+    // EXIF parse, so it is only done for catalogs that identify by content.
+    let content_key = |path: &Path| match scheme {
+        IdentityScheme::Content => identity::content_key(path),
+        IdentityScheme::Path => None,
+    };
+    let mut scanned: Vec<Scanned> = paths
+        .par_iter()
+        .filter(|_| !(hooks.is_cancelled)())
+        .filter_map(|path| {
+            let key = path.to_string_lossy();
+            let entry = entries.get(key.as_ref());
+            if let Some((entry, exif)) = entry.and_then(|entry| Some((entry, entry.exif.clone()?)))
+            {
+                if verify.is_some_and(|verify| !verify.contains(path)) {
+                    let mut photo = photo_from_exif(
+                        path,
+                        entry.photo_id.clone(),
+                        entry.file_size,
+                        entry.modified_at(),
+                        exif,
+                    );
+                    photo.health = entry.health.clone().unwrap_or_default();
+                    return Some(Scanned {
+                        photo,
+                        state: ScanState {
+                            content_key: entry.content_key.clone(),
+                            reread: false,
+                            dirty: false,
+                        },
+                    });
+                }
+                let (file_size, modified_at) = file_fingerprint(path)?;
+                if entry.exif_current
+                    && entry.file_size == file_size
+                    && entry.modified_at == modified_at.to_rfc3339()
+                {
+                    let mut photo =
+                        photo_from_exif(path, entry.photo_id.clone(), file_size, modified_at, exif);
+                    // Entries indexed before content keys or health checks
+                    // existed get them now, so later moves of the file can be
+                    // recognised and damage is reported.
+                    let (content_key, has_key) = match &entry.content_key {
+                        Some(content_key) => (Some(content_key.clone()), true),
+                        None if scheme == IdentityScheme::Content => (content_key(path), false),
+                        None => (None, true),
+                    };
+                    let (health, has_health) = match &entry.health {
+                        Some(health) => (health.clone(), true),
+                        None => (health::check(path), false),
+                    };
+                    photo.health = health;
+                    return Some(Scanned {
+                        photo,
+                        state: ScanState {
+                            content_key,
+                            reread: false,
+                            dirty: !(has_key && has_health),
+                        },
+                    });
+                }
+            }
+            let mut photo = collect_photo_metadata(path)?;
+            let content_key = content_key(path);
+            // A file edited in place keeps the ID it was indexed under.
+            photo.id = match entry {
+                Some(entry) => entry.photo_id.clone(),
+                None => identity::photo_id(scheme, path, content_key.as_deref()),
+            };
+            Some(Scanned {
+                photo,
+                state: ScanState {
+                    content_key,
+                    reread: true,
+                    dirty: true,
+                },
+            })
+        })
+        .inspect(|scanned| (hooks.on_photo)(&scanned.photo))
+        .collect();
+    if (hooks.is_cancelled)() {
+        return None;
+    }
+    dedupe_content_ids(&mut scanned, &entries);
+
+    // The same file at a new path: a removed index entry of this root, or a
+    // vanished one left under another root when the folder itself moved.
+    let present: HashSet<&str> = scanned
+        .iter()
+        .map(|scanned| scanned.photo.file_path.as_str())
+        .collect();
+    let mut gone_by_key: HashMap<&str, (&String, &IndexEntry)> = HashMap::new();
+    for (file_path, entry) in &entries {
+        if let (false, Some(content_key)) = (
+            present.contains(file_path.as_str()),
+            entry.content_key.as_deref(),
+        ) {
+            gone_by_key.insert(content_key, (file_path, entry));
+        }
+    }
+    let mut relinked = Vec::new();
+    let mut relinked_paths: HashSet<String> = HashSet::new();
+    let mut orphan_paths = Vec::new();
+    for scanned in &scanned {
+        if entries.contains_key(&scanned.photo.file_path) {
+            continue;
+        }
+        let Some(content_key) = scanned.state.content_key.as_deref() else {
+            continue;
+        };
+        let old_id = if let Some((file_path, entry)) = gone_by_key.remove(content_key) {
+            relinked_paths.insert(file_path.clone());
+            Some(entry.photo_id.clone())
+        } else {
+            conn.as_deref().and_then(|conn| {
+                let (file_path, photo_id) = orphaned_entries(conn, root_path, content_key)
+                    .into_iter()
+                    .next()?;
+                orphan_paths.push(file_path);
+                Some(photo_id)
+            })
+        };
+        if let Some(old_id) = old_id {
+            relinked.push(Relink {
+                old_id,
+                new_id: scanned.photo.id.clone(),
+                file_path: scanned.photo.file_path.clone(),
+            });
+        }
+    }
+    drop(present);
+
+    let (mut photos, states): (Vec<Photo>, Vec<ScanState>) = scanned
+        .into_iter()
+        .map(|scanned| (scanned.photo, scanned.state))
+        .unzip();
+
+    // Under content IDs a moved file keeps its ID, so it is a change rather
+    // than a removal plus an addition. Changes of group are found once the
+    // whole catalog is grouped (see `group_catalog`).
+    let moved_ids: HashSet<&str> = relinked
+        .iter()
+        .filter(|relink| relink.old_id == relink.new_id)
+        .map(|relink| relink.new_id.as_str())
+        .collect();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    let mut written = Vec::new();
+    for (photo, state) in photos.iter().zip(&states) {
+        if !entries.contains_key(&photo.file_path) {
+            if moved_ids.contains(photo.id.as_str()) {
+                changed.push(photo.id.clone());
+            } else {
+                added.push(photo.id.clone());
+            }
+        } else if state.reread {
+            changed.push(photo.id.clone());
+        }
+        if state.dirty {
+            written.push((photo, state.content_key.as_deref()));
+        }
+    }
+
+    let present: HashSet<&str> = photos
+        .iter()
+        .map(|photo| photo.file_path.as_str())
+        .collect();
+    let mut removed = Vec::new();
+    let mut removed_paths = orphan_paths;
+    for (file_path, entry) in &entries {
+        if !present.contains(file_path.as_str()) {
+            let moved =
+                relinked_paths.contains(file_path) && moved_ids.contains(entry.photo_id.as_str());
+            if !moved {
+                removed.push(entry.photo_id.clone());
+            }
+            removed_paths.push(file_path.clone());
+        }
+    }
+
+    // Failing to persist the index only costs the next scan its shortcut,
+    // so the scan goes on, but the failure is reported.
+    if let Some(conn) = conn {
+        if let Err(error) = write_entries(conn, root_path, &written, &removed_paths, &relinked) {
+            eprintln!("Failed to update the scan index for {root_path}: {error}");
+        }
+        capture_time::apply_shifts(conn, &mut photos);
+        // Files recognised at a new path keep the metadata they had.
+        let relinked_ids: HashSet<&str> = relinked
+            .iter()
+            .map(|relink| relink.new_id.as_str())
+            .collect();
+        let new_ids: HashSet<&str> = added
+            .iter()
+            .map(String::as_str)
+            .filter(|id| !relinked_ids.contains(id))
+            .collect();
+        metadata_templates::apply_on_import(conn, &photos, &new_ids);
+    }
+
+    Some(IndexedScan {
+        photos,
+        added,
+        changed,
+        removed,
+        relinked,
+        inferred_pairs: Vec::new(),
+    })
+}
+
+/// Two copies of the same file would share a content ID. The copy the index
+/// already knows under that ID keeps it (or the first by path, for new
+/// files); the others fall back to path IDs.
+fn dedupe_content_ids(scanned: &mut [Scanned], entries: &HashMap<String, IndexEntry>) {
+    let mut order: Vec<usize> = (0..scanned.len()).collect();
+    order.sort_by(|&a, &b| {
+        let is_new = |index: usize| !entries.contains_key(&scanned[index].photo.file_path);
+        is_new(a)
+            .cmp(&is_new(b))
+            .then_with(|| scanned[a].photo.file_path.cmp(&scanned[b].photo.file_path))
+    });
+    let mut seen = HashSet::new();
+    for index in order {
+        let scanned = &mut scanned[index];
+        if !seen.insert(scanned.photo.id.clone()) {
+            scanned.photo.id = stable_photo_id(Path::new(&scanned.photo.file_path));
+            scanned.state.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::groups::GroupRole;
+    use crate::migrations;
+    use std::fs;
+
+    fn catalog(scheme: IdentityScheme) -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        migrations::migrate(&mut conn).unwrap();
+        identity::save_scheme(&conn, scheme).unwrap();
+        conn
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hologram-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn scan(conn: &mut rusqlite::Connection, root: &Path, paths: &[PathBuf]) -> IndexedScan {
+        let root = root.to_string_lossy().to_string();
+        let mut scan = scan_paths(Some(conn), &root, paths.to_vec(), &ScanHooks::none()).unwrap();
+        group_catalog(Some(conn), &[root], &mut scan);
+        scan
+    }
+
+    fn stored_key(conn: &rusqlite::Connection, path: &Path) -> Option<String> {
+        conn.query_row(
+            "SELECT content_key FROM scan_index WHERE file_path = ?1",
+            [path.to_string_lossy()],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn content_ids_follow_a_renamed_file() {
+        let root = temp_dir("relink-rename");
+        let mut conn = catalog(IdentityScheme::Content);
+        let before = root.join("IMG_0001.JPG");
+        fs::write(&before, b"not really a jpeg").unwrap();
+        let first = scan(&mut conn, &root, std::slice::from_ref(&before));
+        let id = first.photos[0].id.clone();
+        assert!(stored_key(&conn, &before).is_some());
+
+        let after = root.join("renamed.jpg");
+        fs::rename(&before, &after).unwrap();
+        let second = scan(&mut conn, &root, std::slice::from_ref(&after));
+        assert_eq!(second.photos[0].id, id);
+        assert_eq!(second.relinked.len(), 1);
+        assert_eq!(second.relinked[0].old_id, id);
+        assert_eq!(second.changed, vec![id]);
+        assert!(second.added.is_empty() && second.removed.is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn content_ids_follow_a_moved_root() {
+        let old_root = temp_dir("relink-old-root");
+        let new_root = temp_dir("relink-new-root");
+        let mut conn = catalog(IdentityScheme::Content);
+        let before = old_root.join("IMG_0002.JPG");
+        fs::write(&before, b"another photo").unwrap();
+        let id = scan(&mut conn, &old_root, std::slice::from_ref(&before)).photos[0]
+            .id
+            .clone();
+
+        let after = new_root.join("IMG_0002.JPG");
+        fs::rename(&before, &after).unwrap();
+        let moved = scan(&mut conn, &new_root, std::slice::from_ref(&after));
+        assert_eq!(moved.photos[0].id, id);
+        assert_eq!(moved.relinked.len(), 1);
+        // The vanished row of the old root is dropped with the relink.
+        assert!(stored_key(&conn, &after).is_some());
+        let old_rows: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM scan_index WHERE root_path = ?1",
+                [old_root.to_string_lossy()],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(old_rows, 0);
+        let _ = fs::remove_dir_all(&old_root);
+        let _ = fs::remove_dir_all(&new_root);
+    }
+
+    #[test]
+    fn path_ids_do_not_sample_content() {
+        let root = temp_dir("relink-path");
+        let mut conn = catalog(IdentityScheme::Path);
+        let before = root.join("IMG_0003.JPG");
+        fs::write(&before, b"a third photo").unwrap();
+        let first = scan(&mut conn, &root, std::slice::from_ref(&before));
+        assert_eq!(first.photos[0].id, stable_photo_id(&before));
+        assert_eq!(stored_key(&conn, &before), None);
+
+        let after = root.join("IMG_0004.JPG");
+        fs::rename(&before, &after).unwrap();
+        let second = scan(&mut conn, &root, std::slice::from_ref(&after));
+        assert!(second.relinked.is_empty());
+        assert_eq!(second.added, vec![stable_photo_id(&after)]);
+        assert_eq!(second.removed, vec![stable_photo_id(&before)]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn reports_companions_appearing_and_disappearing() {
+        let root = temp_dir("companions");
+        let mut conn = catalog(IdentityScheme::Path);
+        let photo = root.join("IMG_0005.JPG");
+        fs::write(&photo, b"a photo with a sidecar").unwrap();
+        let paths = [photo.clone()];
+        let id = scan(&mut conn, &root, &paths).photos[0].id.clone();
+
+        let sidecar = root.join("IMG_0005.xmp");
+        fs::write(&sidecar, "<x:xmpmeta/>").unwrap();
+        let with_sidecar = scan(&mut conn, &root, &paths);
+        assert_eq!(with_sidecar.changed, vec![id.clone()]);
+        // A sidecar joins the photo's group without making it a multi-photo
+        // group.
+        let group = with_sidecar.photos[0].group.as_ref().unwrap();
+        assert!(group.member(GroupRole::XmpSidecar).is_some());
+        assert_eq!(group.other_photo_ids(&id).count(), 0);
+
+        assert!(scan(&mut conn, &root, &paths).changed.is_empty());
+        fs::remove_file(&sidecar).unwrap();
+        let without_sidecar = scan(&mut conn, &root, &paths);
+        assert_eq!(without_sidecar.changed, vec![id]);
+        assert!(without_sidecar.photos[0].group.is_none());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn pairs_across_roots_and_reports_only_new_pairings() {
+        let slot1 = temp_dir("pairing-slot1");
+        let slot2 = temp_dir("pairing-slot2");
+        let mut conn = catalog(IdentityScheme::Path);
+        groups::save_pairing_options(
+            &conn,
+            &PairingOptions {
+                cross_folder: true,
+                match_file_number: false,
+                ..PairingOptions::default()
+            },
+        )
+        .unwrap();
+        // The files carry no EXIF; give both the same camera and capture
+        // time through the index.
+        let raw = slot1.join("DSCF0001.RAF");
+        let jpeg = slot2.join("DSCF0001.JPG");
+        fs::write(&raw, b"raw").unwrap();
+        fs::write(&jpeg, b"jpeg").unwrap();
+        scan(&mut conn, &slot1, std::slice::from_ref(&raw));
+        scan(&mut conn, &slot2, std::slice::from_ref(&jpeg));
+        conn.execute(
+            "UPDATE scan_index SET exif_json = ?1, exif_version = ?2",
+            rusqlite::params![
+                r#"{"camera_model":"X-T5","date_taken":"2024-05-01T10:00:00Z"}"#,
+                EXIF_LAYOUT_VERSION
+            ],
+        )
+        .unwrap();
+
+        // The watcher only rescans the root it saw a change in; the other
+        // root joins from the index.
+        let paired = scan(&mut conn, &slot2, std::slice::from_ref(&jpeg));
+        assert_eq!(paired.inferred_pairs.len(), 1);
+        assert_eq!(paired.changed.len(), 2);
+        let stored = catalog_photos(&conn);
+        assert!(stored
+            .iter()
+            .all(|photo| photo.group.as_ref().is_some_and(|group| group.inferred)));
+
+        let again = scan(&mut conn, &slot1, std::slice::from_ref(&raw));
+        assert_eq!(again.inferred_pairs.len(), 1);
+        assert!(again.changed.is_empty());
+        let _ = fs::remove_dir_all(&slot1);
+        let _ = fs::remove_dir_all(&slot2);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{Photo, ScanProgress};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+/// Minimum spacing between progress events, so a fast local scan doesn't
+/// flood the IPC bridge with one event per file.
+const EMIT_INTERVAL: Duration = Duration::from_millis(150);
+/// Flush a partial batch early once it gets this large, even if the interval
+/// has not elapsed yet.
+const MAX_BATCH_LEN: usize = 500;
+
+struct ReporterState {
+    last_emit: Instant,
+    batch: Vec<Photo>,
+}
+
+/// Streams the progress of a folder scan to the frontend:
+/// - "scan-discovery": files found so far while walking (`total` is 0
+///   because it is not known yet)
+/// - "scan-progress": files whose metadata has been read out of `total`
+/// - "scan-batch": the unpaired `Photo`s read since the previous batch, so
+///   the grid can fill while the scan continues
+///
+/// The final paired result is still returned by the scan command itself.
+pub struct ScanReporter {
+    app: AppHandle,
+    total: AtomicUsize,
+    read: AtomicUsize,
+    state: Mutex<ReporterState>,
+}
+
+impl ScanReporter {
+    pub fn new(app: AppHandle) -> Self {
+        Self {
+            app,
+            total: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            state: Mutex::new(ReporterState {
+                last_emit: Instant::now(),
+                batch: Vec::new(),
+            }),
+        }
+    }
+
+    fn due(state: &mut ReporterState) -> bool {
+        if state.last_emit.elapsed() < EMIT_INTERVAL {
+            return false;
+        }
+        state.last_emit = Instant::now();
+        true
+    }
+
+    pub fn discovered(&self, count: usize, path: &Path) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        if !Self::due(&mut state) {
+            return;
+        }
+        drop(state);
+        let _ = self.app.emit(
+            "scan-discovery",
+            &ScanProgress {
+                current: count,
+                total: 0,
+                percentage: 0.0,
+                current_file: Some(path.to_string_lossy().to_string()),
+            },
+        );
+    }
+
+    pub fn start_reading(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.emit_progress(0, None);
+    }
+
+    /// Record one file whose metadata is ready. Safe to call from rayon
+    /// workers concurrently.
+    pub fn photo_read(&self, photo: &Photo) {
+        let current = self.read.fetch_add(1, Ordering::Relaxed) + 1;
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.batch.push(photo.clone());
+        if state.batch.len() < MAX_BATCH_LEN && !Self::due(&mut state) {
+            return;
+        }
+        state.last_emit = Instant::now();
+        let batch = std::mem::take(&mut state.batch);
+        drop(state);
+
+        let _ = self.app.emit("scan-batch", &batch);
+        self.emit_progress(current, Some(photo.file_path.clone()));
+    }
+
+    /// Flush whatever is left of the last partial batch.
+    pub fn finish(&self) {
+        let batch = match self.state.lock() {
+            Ok(mut state) => std::mem::take(&mut state.batch),
+            Err(_) => return,
+        };
+        if !batch.is_empty() {
+            let _ = self.app.emit("scan-batch", &batch);
+        }
+        self.emit_progress(self.read.load(Ordering::Relaxed), None);
+    }
+
+    fn emit_progress(&self, current: usize, current_file: Option<String>) {
+        let total = self.total.load(Ordering::Relaxed);
+        let _ = self.app.emit(
+            "scan-progress",
+            &ScanProgress {
+                current,
+                total,
+                percentage: if total == 0 {
+                    0.0
+                } else {
+                    current as f64 / total as f64 * 100.0
+                },
+                current_file,
+            },
+        );
+    }
+}
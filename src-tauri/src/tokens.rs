@@ -0,0 +1,108 @@
+use crate::{capture_time, Photo};
+use std::path::Path;
+
+/// Fill in the `{token}`s of `text` for `photo`, as export and ingest
+/// renames and metadata templates do: `{name}` (file name without
+/// extension), `{type}`, `{date}` (`YYYYMMDD`), `{year}`, `{camera}`,
+/// `{make}`, `{lens}`, `{serial}` (camera body serial number) and, where
+/// photos are numbered, `{index}`. Values a photo lacks expand to nothing;
+/// unknown tokens are kept as written. Text is scanned once, so braces in
+/// a substituted value are never expanded again.
+pub fn expand(text: &str, photo: &Photo, index: Option<usize>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let expanded = rest
+            .find('}')
+            .and_then(|close| Some((value(&rest[1..close], photo, index)?, close)));
+        match expanded {
+            Some((value, close)) => {
+                out.push_str(&value);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn value(token: &str, photo: &Photo, index: Option<usize>) -> Option<String> {
+    let exif = &photo.exif;
+    let text = |value: Option<&str>| value.unwrap_or_default().to_string();
+    Some(match token {
+        "name" => text(
+            Path::new(&photo.file_name)
+                .file_stem()
+                .and_then(|value| value.to_str()),
+        ),
+        "type" => photo.file_type.clone(),
+        "date" => capture_time::local_capture_time(photo)
+            .format("%Y%m%d")
+            .to_string(),
+        "year" => capture_time::local_capture_time(photo)
+            .format("%Y")
+            .to_string(),
+        "camera" => text(exif.camera_model.as_deref().or(exif.camera_make.as_deref())),
+        "make" => text(exif.camera_make.as_deref()),
+        "lens" => text(exif.lens_model.as_deref()),
+        "serial" => text(exif.camera_serial.as_deref()),
+        "index" => format!("{:04}", index? + 1),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{photo_from_exif, ExifData};
+    use chrono::NaiveDate;
+
+    fn photo() -> Photo {
+        let taken = NaiveDate::from_ymd_opt(2024, 6, 1)
+            .unwrap()
+            .and_hms_opt(14, 30, 0)
+            .unwrap();
+        let exif = ExifData {
+            date_taken_local: Some(taken),
+            date_taken: Some(taken.and_utc()),
+            camera_make: Some("FUJIFILM".to_string()),
+            camera_model: Some("X-T5 {name}".to_string()),
+            ..ExifData::default()
+        };
+        photo_from_exif(
+            Path::new("/shoot/DSCF0001.RAF"),
+            "DSCF0001".to_string(),
+            0,
+            taken.and_utc(),
+            exif,
+        )
+    }
+
+    #[test]
+    fn expands_each_token_once() {
+        let photo = photo();
+        assert_eq!(
+            expand("{date}-{index}-{name}.{type}", &photo, Some(6)),
+            "20240601-0007-DSCF0001.RAF"
+        );
+        // A value containing a token is not expanded again.
+        assert_eq!(expand("{camera}", &photo, None), "X-T5 {name}");
+        assert_eq!(expand("{make} {year}", &photo, None), "FUJIFILM 2024");
+    }
+
+    #[test]
+    fn keeps_unknown_tokens_and_stray_braces() {
+        let photo = photo();
+        assert_eq!(expand("{lens}{serial}", &photo, None), "");
+        assert_eq!(expand("{index}", &photo, None), "{index}");
+        assert_eq!(expand("{nope} {name", &photo, None), "{nope} {name");
+        assert_eq!(expand("{{name}}", &photo, None), "{DSCF0001}");
+        assert_eq!(expand("plain", &photo, None), "plain");
+    }
+}
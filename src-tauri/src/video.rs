@@ -0,0 +1,480 @@
+use crate::ExifData;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+/// Largest `moov` box read into memory. Camera clips keep theirs well
+/// under a megabyte; anything bigger is not a clip we can describe.
+const MAX_MOOV_BYTES: u64 = 64 * 1024 * 1024;
+/// Where poster frames are taken from: a second in, past fades from black,
+/// then the first frame for clips shorter than that.
+const POSTER_SEEK_SECONDS: [&str; 2] = ["1", "0"];
+/// A damaged clip can stall ffmpeg; its thumbnail is given up on instead.
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Container metadata of a video clip, stored with the clip's EXIF. The
+/// frame size and creation date go into the usual `width`, `height` and
+/// `date_taken` fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoMetadata {
+    pub duration_ms: u64,
+    pub codec: Option<String>,
+    pub frame_rate: Option<f64>,
+}
+
+/// Read duration, codec, frame rate, frame size and creation date from an
+/// MP4 or QuickTime container without decoding any video.
+pub fn read_metadata(path: &Path) -> Result<ExifData> {
+    let moov = read_moov(path)?;
+    let mvhd = find_box(&moov, b"mvhd").context("Missing movie header")?;
+    let header = MovieHeader::parse(mvhd).context("Invalid movie header")?;
+
+    let mut exif = ExifData {
+        date_taken: header.creation_time,
+        ..ExifData::default()
+    };
+    let mut video = VideoMetadata {
+        duration_ms: header.duration_ms(),
+        codec: None,
+        frame_rate: None,
+    };
+    if let Some(track) = boxes(&moov)
+        .filter(|(kind, _)| kind == b"trak")
+        .find_map(|(_, trak)| VideoTrack::parse(trak))
+    {
+        exif.width = Some(track.width);
+        exif.height = Some(track.height);
+        video.codec = track.codec;
+        video.frame_rate = track.frame_rate;
+    }
+    exif.video = Some(video);
+    Ok(exif)
+}
+
+/// A JPEG of a frame near the start of the clip, bounded to
+/// `max_dimension` and rotated per the track matrix. Decoding is left to
+/// ffmpeg: a binary shipped next to the app is preferred, then one on the
+/// `PATH`.
+pub fn poster_frame_jpeg(path: &Path, max_dimension: u32) -> Result<Vec<u8>> {
+    let scale = format!(
+        "scale='min(iw,{max_dimension})':'min(ih,{max_dimension})':force_original_aspect_ratio=decrease"
+    );
+    let mut stderr = Vec::new();
+    for seek in POSTER_SEEK_SECONDS {
+        let mut command = Command::new(ffmpeg_path());
+        command
+            .args(["-v", "error", "-ss", seek, "-i"])
+            .arg(path)
+            .args(["-frames:v", "1", "-vf", &scale])
+            .args(["-f", "image2pipe", "-c:v", "mjpeg", "-q:v", "3", "-"]);
+        let output = run_with_timeout(command, FFMPEG_TIMEOUT)?;
+        if output.status.success() && !output.stdout.is_empty() {
+            return Ok(output.stdout);
+        }
+        stderr = output.stderr;
+    }
+    bail!(
+        "ffmpeg could not decode a frame: {}",
+        String::from_utf8_lossy(&stderr).trim()
+    )
+}
+
+/// Run `command` to completion, killing it once `timeout` has passed.
+fn run_with_timeout(mut command: Command, timeout: Duration) -> Result<Output> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("ffmpeg is needed for video poster frames")?;
+    // Drain both pipes while waiting, so a full pipe cannot stall the child.
+    let mut stdout = child.stdout.take().context("ffmpeg output unavailable")?;
+    let mut stderr = child.stderr.take().context("ffmpeg output unavailable")?;
+    let stdout = std::thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = stdout.read_to_end(&mut data);
+        data
+    });
+    let stderr = std::thread::spawn(move || {
+        let mut data = Vec::new();
+        let _ = stderr.read_to_end(&mut data);
+        data
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("ffmpeg took longer than {} seconds", timeout.as_secs());
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+fn ffmpeg_path() -> PathBuf {
+    let name = format!("ffmpeg{}", std::env::consts::EXE_SUFFIX);
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(&name)))
+        .filter(|bundled| bundled.is_file())
+        .unwrap_or_else(|| PathBuf::from(name))
+}
+
+/// Seek through the top-level boxes to `moov`, which may sit after
+/// gigabytes of `mdat`.
+pub fn read_moov(path: &Path) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset = 0u64;
+    while offset.checked_add(8).is_some_and(|end| end <= len) {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u64::from(u32::from_be_bytes(header[..4].try_into()?));
+        let mut header_len = 8u64;
+        if size == 1 {
+            file.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..].try_into()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - offset;
+        }
+        if size < header_len {
+            bail!("Invalid box size at offset {offset}");
+        }
+        if &header[4..8] == b"moov" {
+            let body_len = size - header_len;
+            if body_len > MAX_MOOV_BYTES {
+                bail!("Movie header is too large");
+            }
+            let mut body = vec![0u8; body_len as usize];
+            file.read_exact(&mut body)
+                .context("Movie header ends early")?;
+            return Ok(body);
+        }
+        offset = offset
+            .checked_add(size)
+            .with_context(|| format!("Invalid box size at offset {offset}"))?;
+    }
+    bail!("No movie header found")
+}
+
+/// The child boxes of a box body, as (type, body) pairs.
+pub fn boxes(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        let kind: [u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let (header_len, size) = match size {
+            0 => (8, rest.len()),
+            1 => (
+                16,
+                usize::try_from(u64::from_be_bytes(rest.get(8..16)?.try_into().ok()?)).ok()?,
+            ),
+            size => (8, size),
+        };
+        let body = rest.get(header_len..size)?;
+        rest = &rest[size..];
+        Some((kind, body))
+    })
+}
+
+pub fn find_box<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|(found, _)| found == kind)
+        .map(|(_, body)| body)
+}
+
+/// Follow a path of nested box types, e.g. `mdia/minf/stbl`.
+fn find_path<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+    path.iter()
+        .try_fold(data, |body, kind| find_box(body, kind))
+}
+
+fn be_u16(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Creation time, timescale and duration from an `mvhd` or `mdhd` body;
+/// both share this layout.
+struct MovieHeader {
+    creation_time: Option<DateTime<Utc>>,
+    timescale: u32,
+    duration: u64,
+}
+
+impl MovieHeader {
+    fn parse(body: &[u8]) -> Option<Self> {
+        let (created, timescale, duration) = match body.first()? {
+            1 => (be_u64(body, 4)?, be_u32(body, 20)?, be_u64(body, 24)?),
+            _ => (
+                u64::from(be_u32(body, 4)?),
+                be_u32(body, 12)?,
+                u64::from(be_u32(body, 16)?),
+            ),
+        };
+        Some(Self {
+            creation_time: quicktime_time(created),
+            timescale,
+            duration,
+        })
+    }
+
+    fn duration_ms(&self) -> u64 {
+        if self.timescale == 0 {
+            return 0;
+        }
+        self.duration.saturating_mul(1000) / u64::from(self.timescale)
+    }
+}
+
+/// Seconds since 1904-01-01 UTC; cameras that do not know the time write 0.
+fn quicktime_time(seconds: u64) -> Option<DateTime<Utc>> {
+    if seconds == 0 {
+        return None;
+    }
+    let epoch = Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).single()?;
+    epoch.checked_add_signed(TimeDelta::try_seconds(i64::try_from(seconds).ok()?)?)
+}
+
+struct VideoTrack {
+    width: u32,
+    height: u32,
+    codec: Option<String>,
+    frame_rate: Option<f64>,
+}
+
+impl VideoTrack {
+    /// `None` for tracks that are not video (sound, timecode, metadata).
+    fn parse(trak: &[u8]) -> Option<Self> {
+        let mdia = find_box(trak, b"mdia")?;
+        let hdlr = find_box(mdia, b"hdlr")?;
+        if hdlr.get(8..12)? != b"vide" {
+            return None;
+        }
+
+        // Track header dimensions are 16.16 fixed point, after the
+        // version-dependent times and the transformation matrix.
+        let tkhd = find_box(trak, b"tkhd")?;
+        let dimensions_at = if tkhd.first()? == &1 { 88 } else { 76 };
+        let mut width = be_u32(tkhd, dimensions_at)? >> 16;
+        let mut height = be_u32(tkhd, dimensions_at + 4)? >> 16;
+
+        let stbl = find_path(mdia, &[b"minf", b"stbl"]);
+        let sample_entry = stbl
+            .and_then(|stbl| find_box(stbl, b"stsd"))
+            .and_then(|stsd| stsd.get(8..));
+        let codec = sample_entry
+            .and_then(|entry| entry.get(4..8))
+            .map(codec_name);
+        if width == 0 || height == 0 {
+            // Fall back to the coded size in the visual sample entry.
+            let coded =
+                sample_entry.and_then(|entry| Some((be_u16(entry, 32)?, be_u16(entry, 34)?)));
+            if let Some((coded_width, coded_height)) = coded {
+                width = u32::from(coded_width);
+                height = u32::from(coded_height);
+            }
+        }
+        // Phones store portrait clips as landscape frames with a quarter-turn
+        // matrix, whose first coefficient is then zero.
+        if be_u32(tkhd, dimensions_at - 36)? == 0 {
+            std::mem::swap(&mut width, &mut height);
+        }
+
+        let frame_rate = find_box(mdia, b"mdhd")
+            .and_then(MovieHeader::parse)
+            .zip(stbl.and_then(|stbl| find_box(stbl, b"stts")))
+            .and_then(|(mdhd, stts)| average_frame_rate(mdhd.timescale, stts));
+
+        Some(Self {
+            width,
+            height,
+            codec,
+            frame_rate,
+        })
+    }
+}
+
+/// Frames per second from the sample time table: samples over their total
+/// duration, in the track's timescale.
+fn average_frame_rate(timescale: u32, stts: &[u8]) -> Option<f64> {
+    let entries = be_u32(stts, 4)? as usize;
+    let (mut samples, mut ticks) = (0u64, 0u64);
+    for index in 0..entries {
+        let at = 8 + index * 8;
+        let count = u64::from(be_u32(stts, at)?);
+        samples = samples.saturating_add(count);
+        ticks = ticks.saturating_add(count * u64::from(be_u32(stts, at + 4)?));
+    }
+    if samples == 0 || ticks == 0 {
+        return None;
+    }
+    let rate = samples as f64 * f64::from(timescale) / ticks as f64;
+    Some((rate * 1000.0).round() / 1000.0)
+}
+
+fn codec_name(fourcc: &[u8]) -> String {
+    match fourcc {
+        b"avc1" | b"avc3" => "H.264".to_string(),
+        b"hvc1" | b"hev1" => "HEVC".to_string(),
+        b"av01" => "AV1".to_string(),
+        b"vp09" => "VP9".to_string(),
+        b"mp4v" => "MPEG-4".to_string(),
+        b"apch" | b"apcn" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => "ProRes".to_string(),
+        b"jpeg" | b"mjpa" | b"mjpb" => "Motion JPEG".to_string(),
+        other => String::from_utf8_lossy(other).trim().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    /// Version 0 `mvhd`/`mdhd` body: created, modified, timescale, duration.
+    fn header_v0(created: u32, timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 4];
+        for value in [created, 0, timescale, duration] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        body.extend_from_slice(&[0u8; 80]);
+        body
+    }
+
+    fn stts(entries: &[(u32, u32)]) -> Vec<u8> {
+        let mut body = vec![0u8; 4];
+        body.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+        for (count, delta) in entries {
+            body.extend_from_slice(&count.to_be_bytes());
+            body.extend_from_slice(&delta.to_be_bytes());
+        }
+        body
+    }
+
+    fn video_trak(width: u32, height: u32, first_coefficient: u32) -> Vec<u8> {
+        let mut tkhd = vec![0u8; 76];
+        tkhd[40..44].copy_from_slice(&first_coefficient.to_be_bytes());
+        tkhd.extend_from_slice(&(width << 16).to_be_bytes());
+        tkhd.extend_from_slice(&(height << 16).to_be_bytes());
+
+        let mut hdlr = vec![0u8; 8];
+        hdlr.extend_from_slice(b"vide");
+        hdlr.extend_from_slice(&[0u8; 12]);
+        let mut stsd = vec![0u8; 8];
+        stsd.extend_from_slice(&mp4_box(b"hvc1", &[0u8; 78]));
+        let stbl = [
+            mp4_box(b"stsd", &stsd),
+            mp4_box(b"stts", &stts(&[(60, 1001)])),
+        ]
+        .concat();
+        let minf = mp4_box(b"stbl", &stbl);
+        let mdia = [
+            mp4_box(b"mdhd", &header_v0(0, 60000, 60060)),
+            mp4_box(b"hdlr", &hdlr),
+            mp4_box(b"minf", &minf),
+        ]
+        .concat();
+        [mp4_box(b"tkhd", &tkhd), mp4_box(b"mdia", &mdia)].concat()
+    }
+
+    #[test]
+    fn parses_version_0_and_1_movie_headers() {
+        let header = MovieHeader::parse(&header_v0(3_000_000_000, 600, 6300)).unwrap();
+        assert_eq!(header.duration_ms(), 10_500);
+        assert_eq!(
+            header.creation_time.unwrap().to_rfc3339(),
+            "1999-01-24T05:20:00+00:00"
+        );
+
+        let mut v1 = vec![1u8, 0, 0, 0];
+        v1.extend_from_slice(&0u64.to_be_bytes());
+        v1.extend_from_slice(&0u64.to_be_bytes());
+        v1.extend_from_slice(&1000u32.to_be_bytes());
+        v1.extend_from_slice(&u64::MAX.to_be_bytes());
+        let header = MovieHeader::parse(&v1).unwrap();
+        assert_eq!(header.creation_time, None);
+        assert_eq!(header.duration_ms(), u64::MAX / 1000);
+        assert!(MovieHeader::parse(&[0u8; 12]).is_none());
+        assert_eq!(quicktime_time(u64::MAX), None);
+    }
+
+    #[test]
+    fn averages_the_frame_rate_over_the_sample_table() {
+        assert_eq!(
+            average_frame_rate(30000, &stts(&[(300, 1001)])),
+            Some(29.97)
+        );
+        assert_eq!(
+            average_frame_rate(600, &stts(&[(10, 20), (10, 20), (5, 20)])),
+            Some(30.0)
+        );
+        assert_eq!(average_frame_rate(600, &stts(&[])), None);
+        // A table that claims more entries than it holds.
+        let mut truncated = stts(&[(10, 20)]);
+        truncated[4..8].copy_from_slice(&5u32.to_be_bytes());
+        assert_eq!(average_frame_rate(600, &truncated), None);
+    }
+
+    #[test]
+    fn parses_video_tracks_and_rotated_frames() {
+        let track = VideoTrack::parse(&video_trak(1920, 1080, 0x0001_0000)).unwrap();
+        assert_eq!((track.width, track.height), (1920, 1080));
+        assert_eq!(track.codec.as_deref(), Some("HEVC"));
+        assert_eq!(track.frame_rate, Some(59.94));
+
+        // A quarter-turn matrix stores a portrait clip as landscape.
+        let portrait = VideoTrack::parse(&video_trak(1920, 1080, 0)).unwrap();
+        assert_eq!((portrait.width, portrait.height), (1080, 1920));
+
+        let mut sound = video_trak(1920, 1080, 0x0001_0000);
+        let handler = sound.windows(4).position(|kind| kind == b"vide").unwrap();
+        sound[handler..handler + 4].copy_from_slice(b"soun");
+        assert!(VideoTrack::parse(&sound).is_none());
+    }
+
+    #[test]
+    fn rejects_box_sizes_that_overflow_the_offset() {
+        let path = std::env::temp_dir().join(format!("hologram-moov-{}.mp4", std::process::id()));
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(b"mdat");
+        data.extend_from_slice(&(u64::MAX - 4).to_be_bytes());
+        std::fs::write(&path, &data).unwrap();
+        assert!(read_moov(&path).is_err());
+
+        let moov = mp4_box(b"mvhd", &header_v0(0, 1000, 2000));
+        let data = [mp4_box(b"ftyp", b"isom"), mp4_box(b"moov", &moov)].concat();
+        std::fs::write(&path, &data).unwrap();
+        assert_eq!(read_moov(&path).unwrap(), moov);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::catalog::Relocation;
+use crate::jobs::{JobHandle, JOB_CANCELLED};
+use crate::raw_preview::generate_preview_jpeg;
+use crate::{open_cache_db, Photo};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+/// File written into a catalog root, when the user asks for it, identifying
+/// the root across mounts, so a drive that comes back under another mount
+/// point or drive letter is recognised.
+pub const VOLUME_MARKER: &str = ".hologram-volume";
+/// Long edge of the previews kept for browsing photos whose drive is
+/// unplugged.
+pub const OFFLINE_PREVIEW_MAX_DIMENSION: u32 = 2048;
+
+pub fn read_marker(root: &Path) -> Option<String> {
+    fs::read_to_string(root.join(VOLUME_MARKER))
+        .ok()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+/// The root's identifier, writing a new marker if it has none. Read-only
+/// media (e.g. a locked card) simply go without one.
+pub fn ensure_marker(root: &Path) -> Option<String> {
+    if let Some(id) = read_marker(root) {
+        return Some(id);
+    }
+    let id = Uuid::new_v4().to_string();
+    fs::write(root.join(VOLUME_MARKER), &id).ok()?;
+    Some(id)
+}
+
+/// Directories removable volumes are mounted under on each platform.
+fn mount_points() -> Vec<PathBuf> {
+    let mut mounts = Vec::new();
+    if cfg!(windows) {
+        mounts.extend((b'A'..=b'Z').map(|letter| PathBuf::from(format!("{}:\\", letter as char))));
+        return mounts;
+    }
+    let mut push_children = |dir: &Path| {
+        if let Ok(entries) = fs::read_dir(dir) {
+            mounts.extend(
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_dir()),
+            );
+        }
+    };
+    push_children(Path::new("/Volumes"));
+    push_children(Path::new("/mnt"));
+    for users_dir in ["/media", "/run/media"] {
+        push_children(Path::new(users_dir));
+        if let Ok(users) = fs::read_dir(users_dir) {
+            for user in users.flatten() {
+                push_children(&user.path());
+            }
+        }
+    }
+    mounts
+}
+
+/// Find where the root last seen at `old_root` is mounted now, by looking
+/// for its marker at the same path within every mounted volume.
+pub fn relocate(volume_id: &str, old_root: &Path) -> Option<PathBuf> {
+    find_root(&mount_points(), volume_id, old_root)
+}
+
+/// The path under one of `mounts` ending in a trailing part of `old_root`
+/// that holds the marker `volume_id`, longest part first.
+fn find_root(mounts: &[PathBuf], volume_id: &str, old_root: &Path) -> Option<PathBuf> {
+    let components: Vec<Component> = old_root
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    mounts.iter().find_map(|mount| {
+        (0..components.len()).find_map(|skip| {
+            let candidate: PathBuf = std::iter::once(mount.clone())
+                .chain(
+                    components[skip..]
+                        .iter()
+                        .map(|c| PathBuf::from(c.as_os_str())),
+                )
+                .collect();
+            (candidate != old_root && read_marker(&candidate).as_deref() == Some(volume_id))
+                .then_some(candidate)
+        })
+    })
+}
+
+/// Point cached thumbnails and previews of relocated roots at the new
+/// paths, so they stay valid without being regenerated.
+pub fn relocate_cache_paths(
+    app: &AppHandle,
+    folder_path: Option<&str>,
+    relocations: &[Relocation],
+) {
+    if relocations.is_empty() {
+        return;
+    }
+    let Ok(conn) = open_cache_db(app, folder_path) else {
+        return;
+    };
+    for relocation in relocations {
+        let prefix = format!("{}{MAIN_SEPARATOR}", relocation.old_root);
+        for table in ["thumbnail_cache", "preview_cache"] {
+            let _ = conn.execute(
+                &format!(
+                    "UPDATE {table} SET file_path = ?2 || substr(file_path, length(?1) + 1)
+                     WHERE substr(file_path, 1, length(?3)) = ?3"
+                ),
+                [&relocation.old_root, &relocation.new_root, &prefix],
+            );
+        }
+    }
+}
+
+pub fn read_offline_preview(conn: &rusqlite::Connection, photo_id: &str) -> Option<Vec<u8>> {
+    conn.query_row(
+        "SELECT preview FROM preview_cache WHERE photo_id = ?1",
+        [photo_id],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Store a mid-size preview of every online photo that lacks a current one,
+/// so the photos stay viewable after their drive is unplugged. Returns the
+/// number of previews written.
+pub fn cache_previews(
+    app: &AppHandle,
+    folder_path: Option<&str>,
+    photos: &[Photo],
+    job: &JobHandle,
+) -> Result<usize, String> {
+    let mut conn = open_cache_db(app, folder_path)?;
+    let pending: Vec<&Photo> = {
+        let mut statement = conn
+            .prepare(
+                "SELECT 1 FROM preview_cache
+                 WHERE photo_id = ?1 AND file_path = ?2 AND file_size = ?3 AND modified_at = ?4",
+            )
+            .map_err(|e| e.to_string())?;
+        photos
+            .iter()
+            .filter(|photo| !photo.offline && photo.health.is_ok())
+            .filter(|photo| {
+                !statement
+                    .exists(rusqlite::params![
+                        photo.id,
+                        photo.file_path,
+                        photo.file_size,
+                        photo.modified_at.to_rfc3339(),
+                    ])
+                    .unwrap_or(false)
+            })
+            .collect()
+    };
+
+    let total = pending.len();
+    let done = AtomicUsize::new(0);
+    let previews: Vec<(&Photo, Vec<u8>)> = pending
+        .into_par_iter()
+        .filter(|_| !job.is_cancelled())
+        .filter_map(|photo| {
+            let preview =
+                generate_preview_jpeg(Path::new(&photo.file_path), OFFLINE_PREVIEW_MAX_DIMENSION);
+            job.progress(done.fetch_add(1, Ordering::Relaxed) + 1, total);
+            preview.ok().map(|preview| (photo, preview))
+        })
+        .collect();
+
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut statement = transaction
+            .prepare(
+                "INSERT INTO preview_cache
+                 (photo_id, file_path, file_size, modified_at, preview, cached_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(photo_id) DO UPDATE SET file_path=excluded.file_path,
+                   file_size=excluded.file_size, modified_at=excluded.modified_at,
+                   preview=excluded.preview, cached_at=excluded.cached_at",
+            )
+            .map_err(|e| e.to_string())?;
+        let cached_at = Utc::now().to_rfc3339();
+        for (photo, preview) in &previews {
+            statement
+                .execute(rusqlite::params![
+                    photo.id,
+                    photo.file_path,
+                    photo.file_size,
+                    photo.modified_at.to_rfc3339(),
+                    preview,
+                    cached_at,
+                ])
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    transaction.commit().map_err(|e| e.to_string())?;
+    if job.is_cancelled() {
+        return Err(JOB_CANCELLED.to_string());
+    }
+    Ok(previews.len())
+}
+
+/// Remember a sidecar that could not be written because its drive is
+/// offline. The latest contents win.
+pub fn queue_sidecar(
+    conn: &rusqlite::Connection,
+    sidecar_path: &Path,
+    contents: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO pending_sidecars (sidecar_path, contents, queued_at) VALUES (?1, ?2, ?3)
+         ON CONFLICT(sidecar_path) DO UPDATE SET contents=excluded.contents,
+           queued_at=excluded.queued_at",
+        rusqlite::params![
+            sidecar_path.to_string_lossy().to_string(),
+            contents,
+            Utc::now().to_rfc3339(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The contents queued for `sidecar_path`, if any.
+pub fn queued_sidecar(conn: &rusqlite::Connection, sidecar_path: &Path) -> Option<String> {
+    conn.query_row(
+        "SELECT contents FROM pending_sidecars WHERE sidecar_path = ?1",
+        [sidecar_path.to_string_lossy()],
+        |row| row.get(0),
+    )
+    .ok()
+}
+
+/// Write the queued sidecars that belong under `root`, now that it is
+/// reachable again. A sidecar changed on disk after its entry was queued was
+/// edited elsewhere in the meantime and is kept as it is. Sidecars that
+/// still fail stay queued.
+pub fn flush_pending_sidecars(conn: &rusqlite::Connection, root: &str) {
+    let Ok(mut statement) =
+        conn.prepare("SELECT sidecar_path, contents, queued_at FROM pending_sidecars")
+    else {
+        return;
+    };
+    let Ok(rows) = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+        ))
+    }) else {
+        return;
+    };
+    let flushed: Vec<String> = rows
+        .flatten()
+        .filter(|(sidecar_path, ..)| Path::new(sidecar_path).starts_with(root))
+        .filter(|(sidecar_path, contents, queued_at)| {
+            if edited_since(Path::new(sidecar_path), queued_at) {
+                eprintln!("Kept {sidecar_path}: it changed after its update was queued");
+                return true;
+            }
+            fs::write(sidecar_path, contents).is_ok()
+        })
+        .map(|(sidecar_path, ..)| sidecar_path)
+        .collect();
+    for sidecar_path in flushed {
+        let _ = conn.execute(
+            "DELETE FROM pending_sidecars WHERE sidecar_path = ?1",
+            [sidecar_path],
+        );
+    }
+}
+
+/// Whether the file at `path` was modified after `queued_at`.
+fn edited_since(path: &Path, queued_at: &str) -> bool {
+    let Ok(queued_at) = DateTime::parse_from_rfc3339(queued_at) else {
+        return false;
+    };
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| DateTime::<Utc>::from(modified) > queued_at)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hologram-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn catalog() -> rusqlite::Connection {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn markers_are_written_once() {
+        let root = temp_dir("volume-marker");
+        assert_eq!(read_marker(&root), None);
+        let id = ensure_marker(&root).unwrap();
+        assert_eq!(ensure_marker(&root), Some(id.clone()));
+        assert_eq!(read_marker(&root), Some(id));
+
+        fs::write(root.join(VOLUME_MARKER), " \n").unwrap();
+        assert_eq!(read_marker(&root), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn finds_a_root_under_another_mount_point() {
+        let mounts = temp_dir("volume-mounts");
+        let old_root = Path::new("/Volumes/CARD/Photos/2024");
+        let other = mounts.join("other").join("Photos").join("2024");
+        let moved = mounts.join("card").join("Photos").join("2024");
+        fs::create_dir_all(&other).unwrap();
+        fs::create_dir_all(&moved).unwrap();
+        fs::write(other.join(VOLUME_MARKER), "another-volume").unwrap();
+        fs::write(moved.join(VOLUME_MARKER), "card-volume").unwrap();
+        let candidates = [
+            mounts.join("missing"),
+            mounts.join("other"),
+            mounts.join("card"),
+        ];
+
+        assert_eq!(
+            find_root(&candidates, "card-volume", old_root),
+            Some(moved.clone())
+        );
+        assert_eq!(find_root(&candidates, "unknown", old_root), None);
+        // A root that is still where it was is not relocated.
+        assert_eq!(find_root(&candidates, "card-volume", &moved), None);
+        // However deep the old mount point was.
+        assert_eq!(
+            find_root(
+                &candidates,
+                "card-volume",
+                Path::new("/run/media/alice/CARD/Photos/2024")
+            ),
+            Some(moved.clone())
+        );
+        fs::remove_dir_all(mounts).unwrap();
+    }
+
+    #[test]
+    fn queued_sidecars_are_written_when_their_root_is_back() {
+        let conn = catalog();
+        let root = temp_dir("volume-flush");
+        let other = temp_dir("volume-flush-other");
+        let sidecar = root.join("IMG_0001.xmp");
+        let elsewhere = other.join("IMG_0002.xmp");
+        fs::write(&sidecar, "old").unwrap();
+        queue_sidecar(&conn, &sidecar, "first").unwrap();
+        queue_sidecar(&conn, &sidecar, "latest").unwrap();
+        queue_sidecar(&conn, &elsewhere, "other").unwrap();
+        assert_eq!(queued_sidecar(&conn, &sidecar).as_deref(), Some("latest"));
+
+        flush_pending_sidecars(&conn, &root.to_string_lossy());
+        assert_eq!(fs::read_to_string(&sidecar).unwrap(), "latest");
+        assert_eq!(queued_sidecar(&conn, &sidecar), None);
+        // Sidecars under other roots wait for theirs.
+        assert!(!elsewhere.exists());
+        assert_eq!(queued_sidecar(&conn, &elsewhere).as_deref(), Some("other"));
+
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(other).unwrap();
+    }
+
+    #[test]
+    fn sidecars_edited_since_they_were_queued_are_kept() {
+        let conn = catalog();
+        let root = temp_dir("volume-edited");
+        let sidecar = root.join("IMG_0001.xmp");
+        queue_sidecar(&conn, &sidecar, "queued").unwrap();
+        conn.execute(
+            "UPDATE pending_sidecars SET queued_at = '2000-01-01T00:00:00+00:00'",
+            [],
+        )
+        .unwrap();
+        fs::write(&sidecar, "edited elsewhere").unwrap();
+
+        flush_pending_sidecars(&conn, &root.to_string_lossy());
+        assert_eq!(fs::read_to_string(&sidecar).unwrap(), "edited elsewhere");
+        assert_eq!(queued_sidecar(&conn, &sidecar), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
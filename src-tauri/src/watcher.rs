@@ -0,0 +1,193 @@
+use crate::raw_preview::is_supported_file;
+use crate::scan_filter::{self, ScanFilter};
+use crate::{identity, is_hologram_file, open_db, scan_index, Photo};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+
+/// How long the folder must stay quiet before a burst of events is handled.
+const QUIET_PERIOD: Duration = Duration::from_millis(750);
+/// Upper bound on how long a continuous burst (a card still copying) can
+/// delay delivery, so culling can start before the copy finishes.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(3);
+
+/// Managed state owning the filesystem watcher for the open library folder
+/// or catalog. Dropping the inner watcher closes its channel, which ends the
+/// worker thread that debounces and processes its events.
+#[derive(Default)]
+pub struct FolderWatcher(Mutex<Option<RecommendedWatcher>>);
+
+impl FolderWatcher {
+    /// Watch each of `roots` recursively, replacing any previous watch.
+    /// Changes are indexed in the catalog at `catalog_path`.
+    pub fn watch(
+        &self,
+        app: AppHandle,
+        catalog_path: String,
+        roots: Vec<String>,
+    ) -> Result<(), String> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| e.to_string())?;
+        for root in &roots {
+            watcher
+                .watch(Path::new(root), RecursiveMode::Recursive)
+                .map_err(|e| format!("Failed to watch {root}: {e}"))?;
+        }
+
+        let mut active = self.0.lock().map_err(|e| e.to_string())?;
+        *active = Some(watcher);
+        std::thread::spawn(move || process_events(app, catalog_path, roots, receiver));
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        if let Ok(mut active) = self.0.lock() {
+            *active = None;
+        }
+    }
+}
+
+fn collect_paths(event: notify::Result<Event>, touched: &mut HashSet<PathBuf>) {
+    let Ok(event) = event else {
+        return;
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    // Hologram's own files are skipped first: SQLite creates and deletes the
+    // catalog's journal on every commit, and a vanished path would otherwise
+    // pass as a deleted folder. Directories and vanished paths are resolved
+    // later; other files must be photos we would have scanned (this skips
+    // XMP sidecars). An edited ignore file re-walks the folder it applies to.
+    touched.extend(event.paths.into_iter().filter_map(|path| {
+        let file_name = path.file_name().and_then(|name| name.to_str());
+        if file_name.is_some_and(is_hologram_file) {
+            return None;
+        }
+        if file_name == Some(scan_filter::IGNORE_FILE_NAME) {
+            return path.parent().map(Path::to_path_buf);
+        }
+        (is_supported_file(&path) || !path.is_file()).then_some(path)
+    }));
+}
+
+fn process_events(
+    app: AppHandle,
+    catalog_path: String,
+    roots: Vec<String>,
+    receiver: Receiver<notify::Result<Event>>,
+) {
+    while let Ok(first) = receiver.recv() {
+        let started = Instant::now();
+        let mut touched = HashSet::new();
+        collect_paths(first, &mut touched);
+        loop {
+            let remaining = MAX_BATCH_DELAY.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                break;
+            }
+            match receiver.recv_timeout(QUIET_PERIOD.min(remaining)) {
+                Ok(event) => collect_paths(event, &mut touched),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if !touched.is_empty() {
+            apply_changes(&app, &catalog_path, &roots, touched);
+        }
+    }
+}
+
+fn apply_changes(app: &AppHandle, catalog_path: &str, roots: &[String], touched: HashSet<PathBuf>) {
+    let mut conn = open_db(app, Some(catalog_path)).ok();
+    let options = conn
+        .as_ref()
+        .map(scan_filter::load_options)
+        .unwrap_or_default();
+    for root in roots {
+        // An unplugged drive looks like every file was deleted; its photos
+        // stay in the index and are served offline instead.
+        if !Path::new(root).is_dir() {
+            continue;
+        }
+        let touched: Vec<PathBuf> = touched
+            .iter()
+            .filter(|path| path.starts_with(root))
+            .cloned()
+            .collect();
+        if touched.is_empty() {
+            continue;
+        }
+        let Some(mut scan) = scan_index::rescan_touched(
+            conn.as_mut(),
+            root,
+            &touched,
+            &ScanFilter::new(root, &options),
+        ) else {
+            continue;
+        };
+        scan_index::group_catalog(conn.as_mut(), std::slice::from_ref(root), &mut scan);
+        identity::relink_thumbnail_cache(app, Some(catalog_path), &scan.relinked);
+        emit_changes(app, &scan);
+    }
+}
+
+fn emit_changes(app: &AppHandle, scan: &scan_index::IndexedScan) {
+    let added: HashSet<&String> = scan.added.iter().collect();
+    let changed: HashSet<&String> = scan.changed.iter().collect();
+    let mut added_photos: Vec<&Photo> = Vec::new();
+    for photo in &scan.photos {
+        if added.contains(&photo.id) {
+            added_photos.push(photo);
+        } else if changed.contains(&photo.id) {
+            let _ = app.emit("photo-changed", photo);
+        }
+    }
+    if !added_photos.is_empty() {
+        let _ = app.emit("photos-added", &added_photos);
+    }
+    if !scan.relinked.is_empty() {
+        let _ = app.emit("photos-relinked", &scan.relinked);
+    }
+    if !scan.removed.is_empty() {
+        let _ = app.emit("photos-removed", &scan.removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{CreateKind, RemoveKind};
+
+    fn touched_by(kind: EventKind, path: &str) -> HashSet<PathBuf> {
+        let mut touched = HashSet::new();
+        collect_paths(
+            Ok(Event::new(kind).add_path(PathBuf::from(path))),
+            &mut touched,
+        );
+        touched
+    }
+
+    #[test]
+    fn skips_catalog_journals_and_volume_markers() {
+        let removed = EventKind::Remove(RemoveKind::File);
+        assert!(touched_by(removed, "/shoot/hologram.sql-journal").is_empty());
+        assert!(touched_by(removed, "/shoot/hologram-cache.sql-wal").is_empty());
+        assert!(touched_by(removed, "/shoot/.hologram-volume").is_empty());
+        assert_eq!(
+            touched_by(removed, "/shoot/IMG_0001.CR3"),
+            HashSet::from([PathBuf::from("/shoot/IMG_0001.CR3")])
+        );
+        assert_eq!(
+            touched_by(
+                EventKind::Create(CreateKind::File),
+                "/shoot/.hologramignore"
+            ),
+            HashSet::from([PathBuf::from("/shoot")])
+        );
+    }
+}
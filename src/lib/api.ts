@@ -1,15 +1,36 @@
 import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
-import { open } from "@tauri-apps/plugin-dialog";
+import { open, save } from "@tauri-apps/plugin-dialog";
 import type {
+  Catalog,
   CullFlag,
+  DamagedFile,
   ExportOptions,
   ExportResult,
+  HistoryEntry,
+  HistoryStep,
+  IdentityScheme,
+  IngestOptions,
+  IngestResult,
+  IptcMetadata,
+  JobInfo,
+  Keyword,
+  KeywordEdit,
+  LabelSet,
+  MetadataGroup,
+  MetadataPatch,
+  MetadataTemplate,
+  PairingOptions,
   Photo,
   PhotoFilter,
   PhotoMetadata,
+  PhotoQuery,
   PhotoStats,
   RawRenderReady,
+  Relink,
+  ScanDiff,
+  ScanOptions,
+  ScanProgress,
   ScanResult,
   ThumbnailReady,
   XmpSidecarResult,
@@ -59,12 +80,54 @@ export class HologramAPI {
    * 1. scan_folder_fast — returns metadata + EXIF instantly (no image decoding)
    * 2. generate_thumbnails — generates thumbnails in background, streaming
    *    each one to onThumbnail as it completes
+   *
+   * While phase 1 runs, onProgress receives discovery counts and per-file
+   * progress, and onBatch receives partial (not yet RAW/JPEG-paired) photos.
    */
   static async scanFolder(
     folderPath: string,
     onThumbnail?: (data: ThumbnailReady) => void,
+    onProgress?: (progress: ScanProgress) => void,
+    onBatch?: (photos: Photo[]) => void,
+  ): Promise<ScanResult> {
+    return await HologramAPI.scanLibrary(
+      () => invoke<ScanResult>("scan_folder_fast", { folderPath }),
+      folderPath,
+      onThumbnail,
+      onProgress,
+      onBatch,
+    );
+  }
+
+  /**
+   * Scan every online root folder of a catalog as one library, in the same
+   * phases as scanFolder. The catalog becomes the active folder, so ratings,
+   * tags and caches for all of its roots are kept in the catalog database.
+   */
+  static async scanCatalog(
+    catalogPath: string,
+    onThumbnail?: (data: ThumbnailReady) => void,
+    onProgress?: (progress: ScanProgress) => void,
+    onBatch?: (photos: Photo[]) => void,
+  ): Promise<ScanResult> {
+    return await HologramAPI.scanLibrary(
+      () => invoke<ScanResult>("scan_catalog", { catalogPath }),
+      catalogPath,
+      onThumbnail,
+      onProgress,
+      onBatch,
+    );
+  }
+
+  private static async scanLibrary(
+    scan: () => Promise<ScanResult>,
+    folderPath: string,
+    onThumbnail?: (data: ThumbnailReady) => void,
+    onProgress?: (progress: ScanProgress) => void,
+    onBatch?: (photos: Photo[]) => void,
   ): Promise<ScanResult> {
     let unlistenThumbnail: (() => void) | null = null;
+    const scanListeners: (() => void)[] = [];
     HologramAPI.setActiveFolderPath(folderPath);
 
     try {
@@ -75,10 +138,23 @@ export class HologramAPI {
         });
       }
 
+      if (onProgress) {
+        for (const name of ["scan-discovery", "scan-progress"]) {
+          scanListeners.push(
+            await listen<ScanProgress>(name, (event) => onProgress(event.payload)),
+          );
+        }
+      }
+      if (onBatch) {
+        scanListeners.push(
+          await listen<Photo[]>("scan-batch", (event) => onBatch(event.payload)),
+        );
+      }
+
       // Phase 1: Fast metadata scan (no image decoding)
-      const result = await invoke<ScanResult>("scan_folder_fast", {
-        folderPath,
-      });
+      const result = await scan().finally(() =>
+        scanListeners.forEach((unlisten) => unlisten()),
+      );
 
       // Phase 2: Kick off thumbnail generation in background (fire-and-forget)
       invoke("generate_thumbnails", { photos: result.photos, folderPath }).catch((err) => {
@@ -97,6 +173,80 @@ export class HologramAPI {
     }
   }
 
+  /**
+   * Re-scan an already loaded folder. Only files that were added, changed or
+   * removed since the previous scan come back; the rest keep their indexed
+   * metadata.
+   */
+  static async rescanFolder(folderPath: string): Promise<ScanDiff> {
+    return await invoke<ScanDiff>("rescan_folder", { folderPath });
+  }
+
+  static async rescanCatalog(catalogPath: string): Promise<ScanDiff> {
+    return await invoke<ScanDiff>("rescan_catalog", { catalogPath });
+  }
+
+  /**
+   * Create (or open) a catalog directory owning the given root folders.
+   * `markVolumes` writes a `.hologram-volume` file into each root so it is
+   * found again when its drive is remounted elsewhere.
+   */
+  static async createCatalog(
+    catalogPath: string,
+    rootPaths: string[],
+    markVolumes = false,
+  ): Promise<Catalog> {
+    return await invoke<Catalog>("create_catalog", { catalogPath, rootPaths, markVolumes });
+  }
+
+  static async getCatalog(catalogPath: string): Promise<Catalog> {
+    return await invoke<Catalog>("get_catalog", { catalogPath });
+  }
+
+  static async addCatalogRoot(
+    catalogPath: string,
+    rootPath: string,
+    markVolume = false,
+  ): Promise<Catalog> {
+    return await invoke<Catalog>("add_catalog_root", { catalogPath, rootPath, markVolume });
+  }
+
+  static async removeCatalogRoot(catalogPath: string, rootPath: string): Promise<Catalog> {
+    return await invoke<Catalog>("remove_catalog_root", { catalogPath, rootPath });
+  }
+
+  static async watchCatalog(catalogPath: string): Promise<void> {
+    await invoke("watch_catalog", { catalogPath });
+  }
+
+  /**
+   * Live-watch the open folder. Files copied in by a tethered camera or an
+   * ingest script arrive through onPhotosAdded while the copy is running.
+   */
+  static async watchFolder(folderPath: string): Promise<void> {
+    await invoke("watch_folder", { folderPath });
+  }
+
+  static async unwatchFolder(): Promise<void> {
+    await invoke("unwatch_folder");
+  }
+
+  static async onPhotosAdded(callback: (photos: Photo[]) => void): Promise<() => void> {
+    return await listen<Photo[]>("photos-added", (event) => callback(event.payload));
+  }
+
+  static async onPhotoChanged(callback: (photo: Photo) => void): Promise<() => void> {
+    return await listen<Photo>("photo-changed", (event) => callback(event.payload));
+  }
+
+  static async onPhotosRemoved(callback: (photoIds: string[]) => void): Promise<() => void> {
+    return await listen<string[]>("photos-removed", (event) => callback(event.payload));
+  }
+
+  static async onPhotosRelinked(callback: (relinks: Relink[]) => void): Promise<() => void> {
+    return await listen<Relink[]>("photos-relinked", (event) => callback(event.payload));
+  }
+
   static async onRawRenderReady(
     callback: (data: RawRenderReady) => void,
   ): Promise<() => void> {
@@ -130,6 +280,14 @@ export class HologramAPI {
     }
   }
 
+  /** IDs of the catalog's photos matching `query`, evaluated against the scan index and sorted. */
+  static async queryPhotos(query: PhotoQuery): Promise<string[]> {
+    return await invoke<string[]>("query_photos", {
+      folderPath: HologramAPI.activeFolderPath,
+      query,
+    });
+  }
+
   static async findMissingPhotoIds(photos: Photo[]): Promise<string[]> {
     if (photos.length === 0) return [];
     return await invoke<string[]>("find_missing_photo_ids", { photos });
@@ -204,6 +362,200 @@ export class HologramAPI {
     });
   }
 
+  /** Patch many photos in one undoable write; resolves to their metadata by ID. */
+  static async applyMetadataBatch(
+    photoIds: string[],
+    patch: MetadataPatch,
+    label?: string,
+  ): Promise<Record<string, PhotoMetadata>> {
+    return await invoke<Record<string, PhotoMetadata>>("apply_metadata_batch", {
+      photoIds,
+      patch,
+      label: label ?? null,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  static async undoMetadataChange(): Promise<HistoryStep | null> {
+    return await invoke<HistoryStep | null>("undo_metadata_change", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  static async redoMetadataChange(): Promise<HistoryStep | null> {
+    return await invoke<HistoryStep | null>("redo_metadata_change", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Recent metadata batches, newest first. */
+  static async getMetadataHistory(limit?: number): Promise<HistoryEntry[]> {
+    return await invoke<HistoryEntry[]>("get_metadata_history", {
+      limit: limit ?? null,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /**
+   * Set IPTC fields on several photos at once. Fields missing from `patch`
+   * keep each photo's value. Resolves to the resulting fields by photo ID.
+   */
+  static async setIptcMetadata(
+    photoIds: string[],
+    patch: Partial<IptcMetadata>,
+  ): Promise<Record<string, IptcMetadata>> {
+    return await invoke<Record<string, IptcMetadata>>("set_iptc_metadata", {
+      photoIds,
+      patch,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  static async getKeywords(): Promise<Keyword[]> {
+    return await invoke<Keyword[]>("get_keywords", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Create or change a keyword; returns the updated tree. */
+  static async saveKeyword(keyword: KeywordEdit): Promise<Keyword[]> {
+    return await invoke<Keyword[]>("save_keyword", {
+      keyword,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Delete a keyword and everything under it, untagging their photos. */
+  static async deleteKeyword(keywordId: number): Promise<Keyword[]> {
+    return await invoke<Keyword[]>("delete_keyword", {
+      keywordId,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Merge a Lightroom keyword list chosen by the user; null if cancelled. */
+  static async importKeywordList(): Promise<Keyword[] | null> {
+    const filePath = await open({
+      multiple: false,
+      title: "Import Keyword List",
+      filters: [{ name: "Keyword list", extensions: ["txt"] }],
+    });
+    if (!filePath) return null;
+    return await invoke<Keyword[]>("import_keyword_list", {
+      filePath,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Save the keywords as a Lightroom keyword list; false if cancelled. */
+  static async exportKeywordList(): Promise<boolean> {
+    const filePath = await save({
+      title: "Export Keyword List",
+      defaultPath: "keywords.txt",
+      filters: [{ name: "Keyword list", extensions: ["txt"] }],
+    });
+    if (!filePath) return false;
+    await invoke("export_keyword_list", {
+      filePath,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+    return true;
+  }
+
+  static async getLabelSet(): Promise<LabelSet> {
+    return await invoke<LabelSet>("get_label_set", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Rename the color labels and replace the extra flag states; returns what was stored. */
+  static async setLabelSet(labels: LabelSet): Promise<LabelSet> {
+    return await invoke<LabelSet>("set_label_set", {
+      labels,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  static async getMetadataTemplates(): Promise<MetadataTemplate[]> {
+    return await invoke<MetadataTemplate[]>("get_metadata_templates", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Replace the catalog's metadata templates. */
+  static async setMetadataTemplates(templates: MetadataTemplate[]): Promise<void> {
+    await invoke("set_metadata_templates", {
+      templates,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Stamp a template onto `photos`; resolves to their metadata by ID. */
+  static async applyMetadataTemplate(
+    photos: Photo[],
+    templateName: string,
+  ): Promise<Record<string, PhotoMetadata>> {
+    return await invoke<Record<string, PhotoMetadata>>("apply_metadata_template", {
+      photos,
+      templateName,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Every EXIF, maker note, IPTC and XMP field of a file, grouped by IFD or namespace. */
+  static async getFullMetadata(filePath: string): Promise<MetadataGroup[]> {
+    return await invoke<MetadataGroup[]>("get_full_metadata", { filePath });
+  }
+
+  /** Files found truncated or corrupt by the last scans, to re-copy from the card. */
+  static async listDamagedFiles(): Promise<DamagedFile[]> {
+    return await invoke<DamagedFile[]>("list_damaged_files", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  static async getPairingOptions(): Promise<PairingOptions> {
+    return await invoke<PairingOptions>("get_pairing_options", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Applies from the next scan. */
+  static async setPairingOptions(options: PairingOptions): Promise<void> {
+    await invoke("set_pairing_options", {
+      folderPath: HologramAPI.activeFolderPath,
+      options,
+    });
+  }
+
+  static async getScanOptions(): Promise<ScanOptions> {
+    return await invoke<ScanOptions>("get_scan_options", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Applies from the next scan; newly excluded files then leave the library. */
+  static async setScanOptions(options: ScanOptions): Promise<void> {
+    await invoke("set_scan_options", {
+      folderPath: HologramAPI.activeFolderPath,
+      options,
+    });
+  }
+
+  static async getIdentityScheme(): Promise<IdentityScheme> {
+    return await invoke<IdentityScheme>("get_identity_scheme", {
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Re-derives every photo ID and migrates metadata; rescan the folder afterwards. */
+  static async setIdentityScheme(scheme: IdentityScheme): Promise<Relink[]> {
+    return await invoke<Relink[]>("set_identity_scheme", {
+      folderPath: HologramAPI.activeFolderPath,
+      scheme,
+    });
+  }
+
   static async getPhotoMetadata(photoIds: string[]): Promise<Record<string, PhotoMetadata>> {
     return await invoke("get_photo_metadata", {
       photoIds,
@@ -211,11 +563,14 @@ export class HologramAPI {
     });
   }
 
-  static async loadFullResolutionImage(filePath: string): Promise<ArrayBuffer> {
+  /** With photoId, offline photos fall back to their cached mid-size preview. */
+  static async loadFullResolutionImage(filePath: string, photoId?: string): Promise<ArrayBuffer> {
     const imageData = await invoke<ArrayBuffer | string>(
       "load_full_resolution_image_command",
       {
         filePath,
+        photoId,
+        folderPath: HologramAPI.activeFolderPath,
       },
     );
     if (typeof imageData === "string") {
@@ -233,12 +588,57 @@ export class HologramAPI {
       photos,
       allPhotos,
       options,
+      folderPath: HologramAPI.activeFolderPath,
     });
   }
 
+  static async ingestCard(
+    options: IngestOptions,
+    onProgress?: (progress: ScanProgress) => void,
+  ): Promise<IngestResult> {
+    const unlisten = onProgress
+      ? await listen<ScanProgress>("ingest-progress", (event) => onProgress(event.payload))
+      : null;
+    try {
+      return await invoke<IngestResult>("ingest_card", { options });
+    } finally {
+      unlisten?.();
+    }
+  }
+
+  static async listJobs(): Promise<JobInfo[]> {
+    return await invoke<JobInfo[]>("list_jobs");
+  }
+
+  /** Scans, thumbnail generation, RAW pre-rendering, exports and ingests all run as jobs. */
+  static async cancelJob(jobId: string): Promise<void> {
+    await invoke("cancel_job", { jobId });
+  }
+
+  static async onJobStarted(callback: (job: JobInfo) => void): Promise<() => void> {
+    return await listen<JobInfo>("job-started", (event) => callback(event.payload));
+  }
+
+  static async onJobProgress(callback: (job: JobInfo) => void): Promise<() => void> {
+    return await listen<JobInfo>("job-progress", (event) => callback(event.payload));
+  }
+
+  static async onJobFinished(callback: (job: JobInfo) => void): Promise<() => void> {
+    return await listen<JobInfo>("job-finished", (event) => callback(event.payload));
+  }
+
   static async exportXmpSidecars(photos: Photo[]): Promise<XmpSidecarResult> {
     return await invoke<XmpSidecarResult>("export_xmp_sidecars", {
       photos,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
+
+  /** Keep photos viewable after their drive is unplugged. */
+  static async cacheOfflinePreviews(photos: Photo[]): Promise<number> {
+    return await invoke<number>("cache_offline_previews", {
+      photos,
+      folderPath: HologramAPI.activeFolderPath,
     });
   }
 
@@ -248,4 +648,17 @@ export class HologramAPI {
       folderPath: HologramAPI.activeFolderPath,
     });
   }
+
+  /**
+   * Correct the capture time of photos by a number of seconds, e.g. a camera
+   * clock left on the wrong zone. The correction is kept in the catalog and
+   * written to the XMP sidecars. Resolves to the corrected photos.
+   */
+  static async shiftCaptureTime(photos: Photo[], shiftSeconds: number): Promise<Photo[]> {
+    return await invoke<Photo[]>("shift_capture_time", {
+      photos,
+      shiftSeconds,
+      folderPath: HologramAPI.activeFolderPath,
+    });
+  }
 }
@@ -10,6 +10,7 @@ import type {
 } from "./types.ts";
 import { invoke } from "@tauri-apps/api/core";
 import { photoPreviewSrc } from "./photoPreview.ts";
+import { isGrouped } from "./photoGroups.ts";
 import { heavyMediaConcurrency } from "./concurrency.ts";
 
 const DINO_MODEL_ID = "onnx-community/dinov2-small";
@@ -1327,7 +1328,7 @@ function rankerFeatureVector(
     clamp((exposureBias + 5) / 10, 0, 1),
     ev100 ? clamp(ev100 / 18, 0, 1) : 0,
     ["CR2", "CR3", "NEF", "ARW", "DNG", "RAF", "ORF", "RW2"].includes(fileType) ? 1 : 0,
-    photo.paired_with ? 1 : 0,
+    isGrouped(photo) ? 1 : 0,
   ];
 
   for (let index = 0; index < EMBEDDING_RANKER_FEATURES; index++) {
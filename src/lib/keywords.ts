@@ -0,0 +1,21 @@
+/** Separates the levels of a keyword path: `Places|USA|California`. */
+export const KEYWORD_SEPARATOR = "|";
+
+/** The last level of a keyword path, which is what a photo's tag chip shows. */
+export function keywordLeaf(tag: string): string {
+  return tag.slice(tag.lastIndexOf(KEYWORD_SEPARATOR) + 1);
+}
+
+/**
+ * Whether `tags` include `query`, ignoring case: the tag itself, a keyword
+ * under it (`Places|USA` matches `Places|USA|California`) or, for a single
+ * word, any level of a path.
+ */
+export function hasKeyword(tags: string[], query: string): boolean {
+  const wanted = query.toLowerCase();
+  return tags.some((tag) => {
+    const path = tag.toLowerCase();
+    if (path === wanted || path.startsWith(wanted + KEYWORD_SEPARATOR)) return true;
+    return !wanted.includes(KEYWORD_SEPARATOR) && path.split(KEYWORD_SEPARATOR).includes(wanted);
+  });
+}
@@ -0,0 +1,26 @@
+import type { ColorLabel, CullFlag, LabelSet } from "./types.ts";
+
+/** Color labels in Lightroom's order, with the keys that set them (6-9 as in Lightroom). */
+export const COLOR_LABELS: { color: Exclude<ColorLabel, "">; swatch: string; key?: string }[] = [
+  { color: "red", swatch: "#f0616e", key: "6" },
+  { color: "yellow", swatch: "#f2c94c", key: "7" },
+  { color: "green", swatch: "#5ce8a4", key: "8" },
+  { color: "blue", swatch: "#5b9cf0", key: "9" },
+  { color: "purple", swatch: "#b27cf0" },
+];
+
+export function colorSwatch(color: ColorLabel | null | undefined): string | null {
+  return COLOR_LABELS.find((label) => label.color === color)?.swatch ?? null;
+}
+
+export function colorName(labels: LabelSet | null, color: ColorLabel): string {
+  const name = labels?.colors.find((label) => label.color === color)?.name;
+  return name || color.charAt(0).toUpperCase() + color.slice(1);
+}
+
+/** What a flag is shown as: Pick, Reject or the name of a catalog flag state. */
+export function flagName(labels: LabelSet | null, flag: CullFlag | undefined): string {
+  if (flag === "pick") return "Pick";
+  if (flag === "reject") return "Reject";
+  return labels?.flags.find((state) => state.id === flag)?.name ?? "";
+}
@@ -0,0 +1,34 @@
+import type { Photo } from "./types.ts";
+
+/** IDs of every photo shot alongside this one, itself first. */
+export function groupPhotoIds(photo: Photo): string[] {
+  const ids = [photo.id];
+  for (const member of photo.group?.members ?? []) {
+    if (member.photo_id && member.photo_id !== photo.id) ids.push(member.photo_id);
+  }
+  return ids;
+}
+
+/** Whether other photos belong to the same shot (RAW+JPEG, RAW+HEIF, ...). */
+export function isGrouped(photo: Photo): boolean {
+  return groupPhotoIds(photo).length > 1;
+}
+
+/**
+ * The other still rendition of the shot: the primary RAW for a JPEG or HEIF,
+ * and the group's representative for a RAW. Video clips are never a
+ * counterpart.
+ */
+export function counterpartId(photo: Photo): string | undefined {
+  const group = photo.group;
+  if (!group) return undefined;
+  const raw = group.members.find((member) => member.role === "primary_raw");
+  if (raw?.photo_id && raw.photo_id !== photo.id) return raw.photo_id;
+  const stills = group.members.filter(
+    (member) => member.photo_id && member.photo_id !== photo.id && member.role !== "video_clip",
+  );
+  if (stills.some((member) => member.photo_id === group.representative_id)) {
+    return group.representative_id;
+  }
+  return stills[0]?.photo_id ?? undefined;
+}
@@ -0,0 +1,57 @@
+import type { PhotoFilter, Query, QueryField } from "./types.ts";
+
+/** Filter panel fields matched exactly against the EXIF value they were picked from. */
+const EXACT_FIELDS: [keyof PhotoFilter, QueryField][] = [
+  ["shutter_speed", "shutter"],
+  ["exposure_mode", "exposure_mode"],
+  ["flash", "flash"],
+  ["white_balance", "white_balance"],
+  ["drive_mode", "drive_mode"],
+  ["focus_mode", "focus_mode"],
+  ["af_point", "af_point"],
+  ["metering_mode", "metering_mode"],
+  ["image_stabilization", "image_stabilization"],
+  ["camera_serial", "camera_serial"],
+  ["lens_serial", "lens_serial"],
+  ["artist", "artist"],
+  ["copyright", "copyright"],
+];
+
+const RANGE_FIELDS: [keyof PhotoFilter, QueryField][] = [
+  ["iso_range", "iso"],
+  ["aperture_range", "aperture"],
+  ["focal_length_range", "focal"],
+  ["date_range", "date"],
+];
+
+/**
+ * The structured query for the sidebar and toolbar filters. `search` is not
+ * included: it is sent as query text.
+ */
+export function filterQuery(filter: PhotoFilter, hideRejects = false): Query {
+  const terms: Query[] = [];
+  const term = (field: QueryField, value: string, op: "match" | "eq" | "gte" | "range" = "match") =>
+    terms.push({ term: { field, op, value } });
+
+  if (filter.camera_make) term("make", filter.camera_make);
+  if (filter.camera_model) term("camera", filter.camera_model);
+  if (filter.lens_model) term("lens", filter.lens_model);
+  if (filter.file_type) term("type", filter.file_type);
+  for (const [key, field] of EXACT_FIELDS) {
+    const value = filter[key];
+    if (typeof value === "string" && value) term(field, value, "eq");
+  }
+  for (const [key, field] of RANGE_FIELDS) {
+    const range = filter[key] as [number | string | null | undefined, number | string | null | undefined] | undefined;
+    if (range && (range[0] != null || range[1] != null)) term(field, `${range[0] ?? ""}..${range[1] ?? ""}`, "range");
+  }
+  for (const tag of filter.tags ?? []) term("tag", tag);
+  if (filter.rating_gte) term("rating", String(filter.rating_gte), "gte");
+  if (filter.flag) term("flag", filter.flag);
+  if (filter.color_label) term("label", filter.color_label);
+  if (filter.time_of_day) term("time", filter.time_of_day, filter.time_of_day.includes("..") ? "range" : "match");
+  if (filter.weekdays?.length) term("weekday", filter.weekdays.join(","));
+  if (filter.near) term("near", `${filter.near.photo_id}~${filter.near.minutes}`);
+  if (hideRejects && filter.flag !== "reject") terms.push({ not: { term: { field: "flag", value: "reject" } } });
+  return { all: terms };
+}
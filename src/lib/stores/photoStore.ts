@@ -2,20 +2,20 @@ import { writable, derived } from "svelte/store";
 import type {
   CullFlag,
   EmbeddedJpegPreview,
+  HistoryEntry,
+  IptcMetadata,
+  Keyword,
+  LabelSet,
+  MetadataPatch,
   Photo,
   PhotoFilter,
+  PhotoMetadata,
   PhotoStats,
   AppState,
   ScanProgress,
 } from "../types.ts";
 import { HologramAPI } from "../api.ts";
-
-const RAW_FILE_TYPES = new Set([
-  "3FR", "ARI", "ARW", "BAY", "CAP", "CRW", "CR2", "CR3", "DATA", "DCS", "DCR",
-  "DNG", "DRF", "ERF", "FFF", "GPR", "IIQ", "K25", "KDC", "MDC", "MEF", "MOS",
-  "MRW", "NEF", "NRW", "OBM", "ORF", "PEF", "PTX", "PXN", "RAF", "RAW", "RW2",
-  "RWL", "RWZ", "SR2", "SRF", "SRW", "X3F",
-]);
+import { groupPhotoIds } from "../photoGroups.ts";
 
 function createPhotoStore() {
   const initialState: AppState = {
@@ -54,7 +54,7 @@ function createPhotoStore() {
   }
 
   function normalizeFlag(flag: CullFlag | undefined): CullFlag {
-    return flag === "pick" || flag === "reject" ? flag : "none";
+    return flag || "none";
   }
 
   function flushThumbnails() {
@@ -72,7 +72,9 @@ function createPhotoStore() {
         const pi = photoIndex.get(id);
         const fi = filteredIndex.get(id);
         const source = pi !== undefined ? photos[pi] : fi !== undefined ? filteredPhotos[fi] : null;
-        const pairedId = source?.paired_with;
+        // A RAW's embedded preview also stands in for the rest of its group.
+        const memberIds =
+          source && patch.embeddedJpegPreview !== undefined ? groupPhotoIds(source).slice(1) : [];
 
         if (pi !== undefined) {
           photos[pi] = {
@@ -93,7 +95,7 @@ function createPhotoStore() {
           };
         }
 
-        if (patch.embeddedJpegPreview !== undefined && pairedId) {
+        for (const pairedId of memberIds) {
           const pairedPi = photoIndex.get(pairedId);
           if (pairedPi !== undefined) {
             photos[pairedPi] = {
@@ -163,6 +165,57 @@ function createPhotoStore() {
     }
   }
 
+  /** Replace the catalog metadata of the photos in `metadataMap`. */
+  function mergePhotoMetadata(metadataMap: Record<string, PhotoMetadata>) {
+    update((state) => {
+      const photos = [...state.photos];
+      const filteredPhotos = [...state.filteredPhotos];
+      for (const [id, meta] of Object.entries(metadataMap)) {
+        const pi = photoIndex.get(id);
+        const patch = {
+          tags: meta.tags ?? [],
+          notes: meta.notes ?? "",
+          rating: normalizeRating(meta.rating),
+          flag: normalizeFlag(meta.flag),
+          color_label: meta.color_label ?? "",
+          iptc: meta.iptc ?? null,
+        };
+        if (pi !== undefined) photos[pi] = { ...photos[pi], ...patch };
+        const fi = filteredIndex.get(id);
+        if (fi !== undefined) filteredPhotos[fi] = { ...filteredPhotos[fi], ...patch };
+      }
+      return { ...state, photos, filteredPhotos };
+    });
+  }
+
+  /** Show a batch's rating, flag and tag changes before the catalog confirms them. */
+  function previewBatch(photoIds: string[], patch: MetadataPatch) {
+    update((state) => {
+      const photos = [...state.photos];
+      const filteredPhotos = [...state.filteredPhotos];
+      const patchPhoto = (photo: Photo): Photo => {
+        let tags = patch.tags ?? photo.tags ?? [];
+        tags = [...tags, ...(patch.add_tags ?? []).filter((tag) => !tags.includes(tag))];
+        tags = tags.filter((tag) => !(patch.remove_tags ?? []).includes(tag));
+        return {
+          ...photo,
+          tags,
+          notes: patch.notes ?? photo.notes,
+          rating: normalizeRating(patch.rating ?? photo.rating),
+          flag: normalizeFlag(patch.flag ?? photo.flag),
+          color_label: patch.color_label ?? photo.color_label,
+        };
+      };
+      for (const id of photoIds) {
+        const pi = photoIndex.get(id);
+        if (pi !== undefined) photos[pi] = patchPhoto(photos[pi]);
+        const fi = filteredIndex.get(id);
+        if (fi !== undefined) filteredPhotos[fi] = patchPhoto(filteredPhotos[fi]);
+      }
+      return { ...state, photos, filteredPhotos };
+    });
+  }
+
   return {
     subscribe,
     get photos() {
@@ -230,30 +283,82 @@ function createPhotoStore() {
       patchPhotoMetadata(photoId, { flag: normalizeFlag(flag) }),
     clearPhotoCull: (photoId: string) => patchPhotoMetadata(photoId, { rating: 0, flag: "none" }),
     loadMetadata: async (photoIds: string[]) => {
+      if (photoIds.length === 0) return;
+      void loadLabelSet();
+      void loadKeywords();
+      try {
+        mergePhotoMetadata(await HologramAPI.getPhotoMetadata(photoIds));
+      } catch (e) {
+        console.error("loadMetadata failed:", e);
+      }
+    },
+    /** Stamp a metadata template onto `photos` and merge the result. */
+    applyMetadataTemplate: async (photos: Photo[], templateName: string) => {
+      if (photos.length === 0) return;
+      mergePhotoMetadata(await HologramAPI.applyMetadataTemplate(photos, templateName));
+    },
+    /**
+     * Patch several photos in one undoable catalog write. Ratings, flags and
+     * tags show at once; the catalog's result replaces them when it arrives.
+     */
+    applyMetadataBatch: async (photoIds: string[], patch: MetadataPatch, label?: string) => {
+      if (photoIds.length === 0) return;
+      previewBatch(photoIds, patch);
+      try {
+        mergePhotoMetadata(await HologramAPI.applyMetadataBatch(photoIds, patch, label));
+      } catch (e) {
+        console.error("applyMetadataBatch failed:", e);
+        mergePhotoMetadata(await HologramAPI.getPhotoMetadata(photoIds));
+      }
+    },
+    /** Revert the last metadata batch; resolves to it, or null if none. */
+    undoMetadata: async (): Promise<HistoryEntry | null> => {
+      const step = await HologramAPI.undoMetadataChange();
+      if (!step) return null;
+      mergePhotoMetadata(step.metadata);
+      if (step.keywords_changed) void loadKeywords();
+      return step.entry;
+    },
+    /** Reapply the last undone metadata batch; resolves to it, or null if none. */
+    redoMetadata: async (): Promise<HistoryEntry | null> => {
+      const step = await HologramAPI.redoMetadataChange();
+      if (!step) return null;
+      mergePhotoMetadata(step.metadata);
+      if (step.keywords_changed) void loadKeywords();
+      return step.entry;
+    },
+    /** Batch-edit IPTC fields; only the fields in `patch` change. */
+    setPhotoIptc: async (photoIds: string[], patch: Partial<IptcMetadata>) => {
       if (photoIds.length === 0) return;
       try {
-        const metadataMap = await HologramAPI.getPhotoMetadata(photoIds);
+        const updated = await HologramAPI.setIptcMetadata(photoIds, patch);
         update((state) => {
           const photos = [...state.photos];
           const filteredPhotos = [...state.filteredPhotos];
-          for (const [id, meta] of Object.entries(metadataMap)) {
+          for (const [id, iptc] of Object.entries(updated)) {
             const pi = photoIndex.get(id);
-            const patch = {
-              tags: meta.tags ?? [],
-              notes: meta.notes ?? "",
-              rating: normalizeRating(meta.rating),
-              flag: normalizeFlag(meta.flag),
-            };
-            if (pi !== undefined) photos[pi] = { ...photos[pi], ...patch };
+            if (pi !== undefined) photos[pi] = { ...photos[pi], iptc };
             const fi = filteredIndex.get(id);
-            if (fi !== undefined) filteredPhotos[fi] = { ...filteredPhotos[fi], ...patch };
+            if (fi !== undefined) filteredPhotos[fi] = { ...filteredPhotos[fi], iptc };
           }
           return { ...state, photos, filteredPhotos };
         });
       } catch (e) {
-        console.error("loadMetadata failed:", e);
+        console.error("setIptcMetadata failed:", e);
       }
     },
+    patchPhotoExif: (updated: Photo[]) =>
+      update((state) => {
+        const photos = [...state.photos];
+        const filteredPhotos = [...state.filteredPhotos];
+        for (const photo of updated) {
+          const pi = photoIndex.get(photo.id);
+          if (pi !== undefined) photos[pi] = { ...photos[pi], exif: photo.exif };
+          const fi = filteredIndex.get(photo.id);
+          if (fi !== undefined) filteredPhotos[fi] = { ...filteredPhotos[fi], exif: photo.exif };
+        }
+        return { ...state, photos, filteredPhotos };
+      }),
     reset: () => set(initialState),
   };
 }
@@ -267,27 +372,20 @@ export const filteredPhotos = derived(
   ($store) => $store.filteredPhotos,
 );
 
-// Display photos: collapse paired RAW+JPEG into single entries (prefer JPEG for display)
+// Display photos: collapse each photo group into its representative (the
+// camera JPEG when there is one, which has the better thumbnail)
 export const displayPhotos = derived(filteredPhotos, ($photos) => {
   const seen = new Set<string>();
   const result: Photo[] = [];
   for (const photo of $photos) {
     if (seen.has(photo.id)) continue;
-    seen.add(photo.id);
-    if (photo.paired_with) {
-      seen.add(photo.paired_with);
-      // For the grid, show the JPEG version (has better thumbnail)
-      const isRaw = RAW_FILE_TYPES.has(photo.file_type);
-      if (isRaw) {
-        // Find the JPEG pair and show that instead, but keep the RAW reference
-        const jpegPair = $photos.find((p) => p.id === photo.paired_with);
-        if (jpegPair) {
-          result.push(jpegPair);
-          continue;
-        }
-      }
-    }
-    result.push(photo);
+    for (const id of groupPhotoIds(photo)) seen.add(id);
+    const representativeId = photo.group?.representative_id;
+    const representative =
+      representativeId && representativeId !== photo.id
+        ? $photos.find((p) => p.id === representativeId)
+        : undefined;
+    result.push(representative ?? photo);
   }
   return result;
 });
@@ -301,3 +399,25 @@ export const scanProgress = derived(photoStore, ($store) => $store.scanProgress)
 export const stats = derived(photoStore, ($store) => $store.stats);
 export const viewMode = derived(photoStore, ($store) => $store.viewMode);
 export const selectedIndex = derived(photoStore, ($store) => $store.selectedIndex);
+
+/** The catalog's color label names and flag states, loaded with its metadata. */
+export const labelSet = writable<LabelSet | null>(null);
+
+export async function loadLabelSet() {
+  try {
+    labelSet.set(await HologramAPI.getLabelSet());
+  } catch (e) {
+    console.error("getLabelSet failed:", e);
+  }
+}
+
+/** The catalog's keyword tree, sorted by path. */
+export const keywords = writable<Keyword[]>([]);
+
+export async function loadKeywords() {
+  try {
+    keywords.set(await HologramAPI.getKeywords());
+  } catch (e) {
+    console.error("getKeywords failed:", e);
+  }
+}
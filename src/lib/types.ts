@@ -1,4 +1,6 @@
-export type CullFlag = "none" | "pick" | "reject";
+/** Built-in cull states, or the ID of one of the catalog's `FlagState`s. */
+export type CullFlag = "none" | "pick" | "reject" | (string & {});
+export type ColorLabel = "" | "red" | "yellow" | "green" | "blue" | "purple";
 export type AutoCullLabel = "SELECT" | "MAYBE" | "REJECT" | "NEEDS_REVIEW";
 
 export interface EmbeddedJpegPreview {
@@ -17,13 +19,69 @@ export interface Photo {
   exif: ExifData;
   created_at: string;
   modified_at: string;
-  paired_with?: string; // ID of paired RAW/JPEG
+  group?: PhotoGroup | null; // other files of the same shot
   embedded_jpeg_preview?: EmbeddedJpegPreview | null;
   paired_raw_embedded_jpeg_preview?: EmbeddedJpegPreview | null;
   tags?: string[];
   notes?: string;
   rating?: number; // 0-5 stars
   flag?: CullFlag;
+  color_label?: ColorLabel | null;
+  iptc?: IptcMetadata | null;
+  offline?: boolean; // drive unplugged; browsed from the catalog
+  health?: FileHealth; // validation result from the last scan
+}
+
+export type HealthStatus = "ok" | "truncated" | "corrupt" | "unreadable";
+
+export interface FileHealth {
+  status: HealthStatus;
+  reason?: string | null;
+}
+
+export interface DamagedFile {
+  photo_id: string;
+  file_path: string;
+  file_size: number;
+  health: FileHealth;
+}
+
+// Preference order for the representative: camera_jpeg, heif, primary_raw.
+export type GroupRole =
+  | "camera_jpeg"
+  | "heif"
+  | "primary_raw"
+  | "tiff"
+  | "image"
+  | "raw" // further RAWs, e.g. a DNG next to the original
+  | "video_clip"
+  | "xmp_sidecar";
+
+export interface GroupMember {
+  role: GroupRole;
+  photo_id?: string | null; // unset for companion clips and sidecars
+  file_path: string;
+}
+
+export interface PhotoGroup {
+  representative_id: string; // the member shown for the whole shot
+  members: GroupMember[];
+  inferred?: boolean; // matched across folders by capture time, not by name
+}
+
+// Pairing of RAW and JPEG files written to different folders or cards.
+export interface PairingOptions {
+  cross_folder: boolean;
+  tolerance_ms: number; // largest capture-time difference for one shot
+  match_file_number: boolean; // DSC_1234 only pairs with another 1234
+}
+
+export interface InferredPair {
+  raw_id: string;
+  photo_id: string;
+  raw_path: string;
+  photo_path: string;
+  time_delta_ms: number;
 }
 
 export interface ExifData {
@@ -37,7 +95,22 @@ export interface ExifData {
   exposure_mode?: string;
   flash?: string;
   white_balance?: string;
-  date_taken?: string;
+  date_taken?: string; // capture instant (UTC)
+  date_taken_local?: string; // camera wall-clock time, without zone
+  capture_time_shift?: number; // clock correction applied, in seconds
+  camera_serial?: string;
+  lens_serial?: string;
+  shutter_count?: number;
+  drive_mode?: string;
+  focus_mode?: string;
+  af_point?: string;
+  metering_mode?: string;
+  image_stabilization?: string;
+  subsec_time?: string;
+  offset_time?: string; // UTC offset of date_taken as recorded, e.g. "+02:00"
+  artist?: string;
+  copyright?: string;
+  camera_rating?: number; // star rating set in camera
   exposure_bias?: number;
   ev100?: number;
   latitude?: number;
@@ -46,6 +119,13 @@ export interface ExifData {
   width?: number;
   height?: number;
   orientation?: number;
+  video?: VideoMetadata | null; // video clips only
+}
+
+export interface VideoMetadata {
+  duration_ms: number;
+  codec?: string | null;
+  frame_rate?: number | null;
 }
 
 export interface PhotoFilter {
@@ -62,9 +142,65 @@ export interface PhotoFilter {
   exposure_mode?: string;
   flash?: string;
   white_balance?: string;
+  camera_serial?: string;
+  lens_serial?: string;
+  drive_mode?: string;
+  focus_mode?: string;
+  af_point?: string;
+  metering_mode?: string;
+  image_stabilization?: string;
+  artist?: string;
+  copyright?: string;
   tags?: string[]; // filter photos that have ALL of these tags
   rating_gte?: number;
   flag?: CullFlag;
+  color_label?: ColorLabel;
+  time_of_day?: string; // `golden`, `blue`, `day`, `night` or a window such as `14:00..15:30`
+  weekdays?: string[]; // "mon"..."sun", "weekend" or "weekdays"
+  near?: NearPhoto;
+}
+
+/** Photos taken within `minutes` of the photo `photo_id`. */
+export interface NearPhoto {
+  photo_id: string;
+  minutes: number;
+}
+
+/** A field `query_photos` can match; see `src-tauri/src/query.rs`. */
+export type QueryField =
+  | "text" | "name" | "type" | "make" | "camera" | "lens" | "iso" | "aperture" | "focal" | "shutter"
+  | "exposure_mode" | "flash" | "white_balance" | "drive_mode" | "focus_mode" | "af_point" | "metering_mode"
+  | "image_stabilization" | "camera_serial" | "lens_serial" | "artist" | "copyright"
+  | "rating" | "flag" | "label" | "tag" | "notes" | "date" | "time" | "weekday" | "near" | "has";
+
+/** `match` is containment for text, equality for numbers and the whole period for dates; `range` takes `low..high`. */
+export type QueryOp = "match" | "eq" | "gt" | "gte" | "lt" | "lte" | "range";
+
+export type Query =
+  | { all: Query[] }
+  | { any: Query[] }
+  | { not: Query }
+  | { term: { field: QueryField; op?: QueryOp; value: string } };
+
+export interface PhotoQuery {
+  query?: Query;
+  text?: string; // query syntax, e.g. `iso:>3200 lens:"24-70" rating:>=3 -flag:reject`
+  sort?: "captured" | "name" | "modified" | "size" | "rating";
+  descending?: boolean;
+}
+
+/** One field of a file's metadata, see `HologramAPI.getFullMetadata`. */
+export interface MetadataField {
+  id: string; // "0x829A" (EXIF), "2:105" (IPTC) or "dc:subject" (XMP)
+  name: string;
+  raw: string;
+  value: string;
+}
+
+/** The fields of one EXIF IFD, IPTC record or XMP namespace ("XMP-dc"). */
+export interface MetadataGroup {
+  namespace: string;
+  fields: MetadataField[];
 }
 
 export interface ImageAdjustmentSettings {
@@ -146,11 +282,135 @@ export interface ExportResult {
   metadata_path?: string;
 }
 
+export interface IngestOptions {
+  source_path: string;
+  destination_path: string;
+  backup_path?: string;
+  organize_by: "flat" | "date" | "camera";
+  rename_pattern?: string;
+}
+
+export interface IngestFailure {
+  file_path: string;
+  error: string;
+}
+
+export interface IngestResult {
+  copied_count: number;
+  skipped_count: number; // already ingested (same content hash)
+  failed: IngestFailure[];
+  destination_path: string;
+  backup_path?: string;
+}
+
+export type JobStatus = "running" | "completed" | "cancelled" | "failed";
+
+export interface JobInfo {
+  id: string;
+  kind: string; // "scan" | "thumbnails" | "raw-prerender" | "export" | "ingest"
+  status: JobStatus;
+  current: number;
+  total: number;
+  error?: string;
+  started_at: string;
+  finished_at?: string;
+}
+
 export interface PhotoMetadata {
   tags: string[];
   notes: string;
   rating: number;
   flag: CullFlag;
+  color_label?: ColorLabel;
+  iptc?: IptcMetadata;
+}
+
+/** A batch edit: only the fields present change on each photo. */
+export interface MetadataPatch {
+  tags?: string[]; // replaces the photo's tags
+  add_tags?: string[];
+  remove_tags?: string[];
+  notes?: string;
+  rating?: number;
+  flag?: CullFlag;
+  color_label?: ColorLabel; // "" clears it
+  iptc?: Partial<IptcMetadata>;
+}
+
+/** A cull state besides pick and reject; photos store its `id`. */
+export interface FlagState {
+  id: string;
+  name: string;
+}
+
+/** A node of the catalog's keyword tree; photos are tagged with its `path`. */
+export interface Keyword {
+  id: number;
+  parent_id: number | null;
+  name: string;
+  path: string; // e.g. "Places|USA|California"
+  synonyms: string[];
+  export: boolean; // written to sidecars and exports
+}
+
+/** A new keyword (no `id`) or changes to one; renames retag its photos. */
+export interface KeywordEdit {
+  id?: number;
+  parent_id: number | null;
+  name: string;
+  synonyms: string[];
+  export: boolean;
+}
+
+/** What the catalog calls its color labels and which extra flag states it has. */
+export interface LabelSet {
+  colors: { color: Exclude<ColorLabel, "">; name: string }[];
+  flags: FlagState[];
+}
+
+/** One undoable metadata batch in the catalog's history. */
+export interface HistoryEntry {
+  id: number;
+  label: string;
+  created_at: string;
+  photo_count: number;
+  undone: boolean;
+}
+
+export interface HistoryStep {
+  entry: HistoryEntry;
+  metadata: Record<string, PhotoMetadata>; // what each photo was left with
+  keywords_changed: boolean; // the step renamed, moved or deleted keywords
+}
+
+/** IPTC Core / Extension fields kept in the catalog and written to XMP. */
+export interface IptcMetadata {
+  headline: string;
+  caption: string;
+  creator: string;
+  contact_email: string;
+  contact_phone: string;
+  contact_url: string;
+  credit_line: string;
+  copyright_notice: string;
+  usage_terms: string;
+  city: string;
+  state: string;
+  country: string;
+  event: string;
+  person_shown: string[];
+}
+
+/**
+ * Named metadata stamped onto many photos at once. Text may contain
+ * `{name}`, `{type}`, `{date}` (YYYYMMDD), `{year}`, `{camera}`, `{make}`,
+ * `{lens}` and `{serial}`, filled in per photo as in export renames.
+ */
+export interface MetadataTemplate {
+  name: string;
+  tags: string[]; // added to each photo's tags
+  iptc: Partial<IptcMetadata>; // fields left out keep each photo's value
+  apply_on_import: boolean; // applied by scans to newly found files
 }
 
 export interface AutoCullPhoto {
@@ -203,12 +463,15 @@ export interface AutoCullSession {
 export interface XmpSidecarResult {
   processed_count: number;
   skipped_count: number;
+  queued_count: number; // offline photos, written when their drive returns
+  unknown_labels: string[]; // imported xmp:Label values naming no color label
 }
 
 export interface PhotoStats {
   total_photos: number;
   raw_count: number;
   jpeg_count: number;
+  video_count: number;
   paired_count: number;
   raw_embedded_jpeg_preview_count?: number;
   raw_jpeg_redundancy_count?: number;
@@ -219,6 +482,49 @@ export interface PhotoStats {
 export interface ScanResult {
   photos: Photo[];
   stats: PhotoStats;
+  inferred_pairs: InferredPair[];
+}
+
+export interface ScanDiff {
+  added: Photo[];
+  changed: Photo[];
+  removed: string[]; // photo IDs
+  relinked: Relink[]; // moved files whose metadata followed them
+  inferred_pairs: InferredPair[];
+  stats: PhotoStats;
+}
+
+export interface CatalogRoot {
+  root_path: string;
+  online: boolean; // false while the folder's drive is unplugged
+  volume_id?: string; // from an opt-in volume marker; finds the folder again if remounted elsewhere
+  photo_count: number;
+  added_at: string;
+}
+
+// A catalog directory whose library spans several root folders.
+export interface Catalog {
+  catalog_path: string;
+  roots: CatalogRoot[];
+}
+
+// "path": IDs derived from the file path; "content": from a partial content
+// hash, so they survive renames and moves.
+export type IdentityScheme = "path" | "content";
+
+export interface Relink {
+  old_id: string;
+  new_id: string;
+  file_path: string;
+}
+
+// Which files scans of a catalog pick up. Folders can also hold a
+// `.hologramignore` file with one glob per line.
+export interface ScanOptions {
+  ignore_patterns: string[]; // globs; without "/" they match a name at any depth
+  skip_hidden: boolean; // dot-files and dot-folders; off by default
+  max_depth?: number | null; // folder levels below each root; null is unlimited
+  follow_symlinks: boolean;
 }
 
 export interface ThumbnailReady {
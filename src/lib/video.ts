@@ -0,0 +1,14 @@
+import type { Photo } from "./types.ts";
+
+export function isVideo(photo: Photo): boolean {
+  return !!photo.exif.video;
+}
+
+/** Clip length as `m:ss`, or `h:mm:ss` for clips of an hour or more. */
+export function formatDuration(durationMs: number): string {
+  const totalSeconds = Math.round(durationMs / 1000);
+  const hours = Math.floor(totalSeconds / 3600);
+  const minutes = Math.floor((totalSeconds % 3600) / 60);
+  const seconds = String(totalSeconds % 60).padStart(2, "0");
+  return hours > 0 ? `${hours}:${String(minutes).padStart(2, "0")}:${seconds}` : `${minutes}:${seconds}`;
+}